@@ -0,0 +1,37 @@
+//! Asks a running `elkd --socket` for the strip's state
+//!
+//! Start the daemon with `elkd --socket /tmp/elkd.sock`, then run
+//! `cargo run --example elkd_socket_client -- /tmp/elkd.sock`. The client
+//! waits for the `OK` greeting, sends `state` and prints the reply.
+
+#[cfg(unix)]
+fn main() -> std::io::Result<()> {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "/run/elkd.sock".to_string());
+    let stream = UnixStream::connect(&path)?;
+    let mut lines = BufReader::new(stream.try_clone()?).lines();
+    let mut writer = stream;
+
+    // elkd greets each client once the strip is connected
+    let greeting = lines.next().transpose()?.unwrap_or_default();
+    if greeting != "OK" {
+        eprintln!("Unexpected greeting: {}", greeting);
+        std::process::exit(1);
+    }
+
+    writeln!(writer, "state")?;
+    let state = lines.next().transpose()?.unwrap_or_default();
+    println!("{}", state);
+
+    writeln!(writer, "quit")?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("Unix sockets are not available on this platform");
+}
@@ -0,0 +1,52 @@
+//! Runs lighting actions at set times from the host
+//!
+//! At 19:00 the strip switches to a warm white at 30%, and at 23:00 on
+//! weekdays it starts a slow red crossfade. Stop with Ctrl+C.
+
+use chrono::NaiveTime;
+use elk_led_controller::*;
+use std::sync::Arc;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt::init();
+
+    let device = Arc::new(BleLedDevice::new().await?);
+    let scheduler = LocalScheduler::new(device);
+
+    let evening = NaiveTime::from_hms_opt(19, 0, 0).expect("valid time");
+    scheduler.add("warm white", Days::ALL, evening, |device| async move {
+        device.set_color_temp_kelvin(2700).await?;
+        device.set_brightness(30).await
+    });
+
+    let night = NaiveTime::from_hms_opt(23, 0, 0).expect("valid time");
+    scheduler.add(
+        "slow red crossfade",
+        Days::WEEKDAYS,
+        night,
+        |device| async move {
+            device.set_effect(Effect::CrossfadeRed).await?;
+            device.set_effect_speed(10).await
+        },
+    );
+
+    for entry in scheduler.list() {
+        println!(
+            "{} {} on {} at {}",
+            entry.id, entry.name, entry.days, entry.time
+        );
+    }
+
+    // Stop the scheduler on Ctrl+C
+    let cancel = CancelHandle::new();
+    let ctrl_c_cancel = cancel.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            ctrl_c_cancel.cancel();
+        }
+    });
+
+    scheduler.run(cancel).await;
+    Ok(())
+}
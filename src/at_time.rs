@@ -0,0 +1,79 @@
+/*!
+ # One-off host timer
+
+ Runs a single action at a local wall-clock time, e.g. turning the strip off
+ at 20:00 for an event. Unlike the device schedules nothing is programmed
+ into the firmware, so this works on devices with a drifting clock, but the
+ host process has to keep running until the target time.
+*/
+
+use chrono::{DateTime, Local};
+use std::future::Future;
+use std::time::Duration;
+use tracing::{debug, info, instrument, warn};
+
+use crate::{BleLedDevice, CancelHandle, Error, LineCommand, Result};
+
+/// Longest single sleep before the remaining time is measured again
+///
+/// Keeps the target on the wall clock when it is adjusted (e.g. by NTP) or
+/// the host was suspended, which a single monotonic sleep would miss.
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+impl BleLedDevice {
+    /// Returns a timer that waits until the local time `when`, then sends `action`
+    ///
+    /// Nothing happens until the returned future is awaited. It resolves to
+    /// `Ok(false)` without sending anything when the returned handle is
+    /// cancelled first, and to `Ok(true)` once the action was applied. A time
+    /// in the past applies the action right away. Only commands that change
+    /// the device can be scheduled, not `sleep`, `status`, `metrics`,
+    /// `list_effects`, `help` or `quit`.
+    #[instrument(skip(self))]
+    pub fn at_time(
+        &self,
+        when: DateTime<Local>,
+        action: LineCommand,
+    ) -> Result<(CancelHandle, impl Future<Output = Result<bool>> + Send + '_)> {
+        if !action.uses_device() {
+            return Err(Error::InvalidCommand(format!(
+                "'{}' can't be scheduled, it doesn't change the device",
+                action.name()
+            )));
+        }
+
+        let cancel = CancelHandle::new();
+        let timer = {
+            let cancel = cancel.clone();
+            async move {
+                info!("Waiting until {} to apply {:?}", when, action);
+                if !wait_until(when, &cancel).await {
+                    info!("Timer for {} cancelled", when);
+                    return Ok(false);
+                }
+                if Local::now() - when > chrono::Duration::minutes(1) {
+                    warn!("Applying {:?} late, it was due at {}", action, when);
+                }
+                action.run(self).await?;
+                Ok(true)
+            }
+        };
+        Ok((cancel, timer))
+    }
+}
+
+/// Sleeps until the local time `when`, returning `false` if `cancel` fired first
+async fn wait_until(when: DateTime<Local>, cancel: &CancelHandle) -> bool {
+    loop {
+        let remaining = (when - Local::now()).to_std().unwrap_or(Duration::ZERO);
+        if remaining.is_zero() {
+            return !cancel.is_cancelled();
+        }
+        debug!("{:?} left until {}", remaining, when);
+
+        tokio::select! {
+            _ = tokio::time::sleep(remaining.min(MAX_SLEEP)) => {}
+            _ = cancel.cancelled() => return false,
+        }
+    }
+}
@@ -7,14 +7,20 @@ use std::{
     collections::VecDeque,
     sync::atomic::{AtomicBool, Ordering},
 };
-use tokio::sync::{mpsc, watch};
+use tokio::sync::watch;
 use tokio::time::{sleep, Duration};
-use tracing::{debug, error, info, instrument, warn};
+use tracing::{debug, error, info, instrument, trace, warn};
 
-use crate::{BleLedDevice, Error, Result, EFFECTS};
+use crate::color::hsv_to_rgb;
+use crate::{BleLedDevice, Color, Effect, Error, Result};
+use futures::Stream;
 
 /// Frequency ranges for audio analysis
+///
+/// With the `serde` feature these serialize as snake_case strings.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum FrequencyRange {
     /// Bass frequencies (20-250 Hz)
     Bass,
@@ -27,7 +33,11 @@ pub enum FrequencyRange {
 }
 
 /// Visualization modes for audio monitoring
+///
+/// With the `serde` feature these serialize as snake_case strings.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum VisualizationMode {
     /// Frequencies map to colors (bass=red, mid=green, high=blue)
     FrequencyColor,
@@ -41,6 +51,29 @@ pub enum VisualizationMode {
     EnhancedFrequencyColor,
     /// BPM synchronized effects
     BpmSync,
+    /// Dominant musical pitch class picks the hue around the color wheel
+    ///
+    /// The pitch class comes from a chromagram: spectral energy folded into
+    /// the 12 notes C through B over `pitch_octaves`. Also accepted as
+    /// `chroma` when deserializing.
+    #[cfg_attr(feature = "serde", serde(alias = "chroma"))]
+    PitchHue,
+}
+
+/// How band energy is scaled to the 0.0-1.0 range driving the LEDs
+///
+/// With the `serde` feature these serialize as snake_case strings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Normalization {
+    /// Divide by a slowly decaying peak (see `max_energy_decay`); a loud
+    /// transient keeps the output dim until the peak has decayed
+    #[default]
+    PeakDecay,
+    /// Divide by the RMS of the last couple of seconds, for steadier
+    /// brightness on tracks with a wide dynamic range
+    Rms,
 }
 
 /// Audio visualization settings and state
@@ -62,6 +95,41 @@ pub struct AudioVisualization {
     pub update_interval_ms: u32,
     /// Whether to sync state from audio directly to LED
     pub active: bool,
+    /// Power the device on before sending updates when it is off
+    ///
+    /// With `false`, colors are still sent to a device that is off and show
+    /// once it is turned on by other means.
+    pub power_on: bool,
+    /// How fast the peak markers fall back towards the current level (units/s)
+    pub peak_decay_rate: f32,
+    /// How long a new peak is held before it starts to decay (ms)
+    pub peak_hold_ms: u32,
+    /// Octave range used for pitch detection, inclusive (4 is the octave of middle C)
+    pub pitch_octaves: (u8, u8),
+    /// Lowest brightness the visualization will send (0-100, 0 keeps the full range)
+    pub min_brightness: u8,
+    /// Weight the spectrum by the A-weighting curve before measuring band energy
+    ///
+    /// Balances the bands by perceived loudness, so the wide high band no
+    /// longer dominates FrequencyColor on typical music.
+    pub a_weighting: bool,
+    /// Weight of the previous value when smoothing band energy (0.0-0.99)
+    ///
+    /// Higher is steadier but slower to react; 0.5-0.9 suits most music,
+    /// lower values help fast electronic tracks.
+    pub energy_smoothing: f32,
+    /// How much of the normalization peak is kept per analysis pass (0.0-1.0)
+    ///
+    /// Closer to 1.0 adapts more slowly to volume changes. 0.999-0.9999 is
+    /// reasonable; at the default 50ms interval 0.9995 halves in about a minute.
+    pub max_energy_decay: f32,
+    /// Energy jump over the previous pass that counts as a beat, per band
+    /// (bass, mid, high)
+    ///
+    /// Lower values detect more beats; 1.1-2.0 is a sensible range.
+    pub beat_thresholds: [f32; 3],
+    /// How band energy is normalized
+    pub normalization: Normalization,
 }
 
 impl Default for AudioVisualization {
@@ -75,10 +143,91 @@ impl Default for AudioVisualization {
             high_effect_trigger: true,
             update_interval_ms: 50, // 50ms = 20 updates per second
             active: false,
+            power_on: true,
+            peak_decay_rate: 1.0, // Full-scale peak falls to zero in 1 second
+            peak_hold_ms: 300,
+            pitch_octaves: (3, 6), // C3 (130 Hz) to B6 (1976 Hz)
+            min_brightness: 0,
+            a_weighting: false,
+            energy_smoothing: 0.7,
+            max_energy_decay: 0.9995,
+            beat_thresholds: [1.4, 1.3, 1.2],
+            normalization: Normalization::PeakDecay,
         }
     }
 }
 
+/// Number of analysis passes in the RMS normalization window (2s at 50ms)
+const RMS_WINDOW: usize = 40;
+
+/// RMS multiple that maps to full scale with [`Normalization::Rms`]
+///
+/// Typical levels land around 0.5, leaving room for peaks.
+const RMS_HEADROOM: f32 = 2.0;
+
+/// Time constant of the VU level rising towards a louder signal (seconds)
+const VU_ATTACK_SECS: f32 = 0.03;
+
+/// Time constant of the VU level falling back after the signal drops (seconds)
+const VU_RELEASE_SECS: f32 = 0.3;
+
+/// Minimum tempo confidence for BpmSync to lock effects to the estimated tempo
+///
+/// Below it BpmSync falls back to plain energy-driven colors.
+pub const BPM_CONFIDENCE_THRESHOLD: f32 = 0.5;
+
+/// How much stronger a new pitch class must be before PitchHue switches to it
+const PITCH_HYSTERESIS: f32 = 1.25;
+
+/// Lowest input sample rate the analyzer accepts
+///
+/// Below this the mid band would be cut off by the Nyquist limit.
+const MIN_SAMPLE_RATE: usize = 8000;
+
+/// Lowest frequency included in the analysis (Hz)
+const MIN_ANALYSIS_FREQUENCY: f32 = 20.0;
+
+/// Highest frequency included in the analysis, if the sample rate allows (Hz)
+const MAX_ANALYSIS_FREQUENCY: f32 = 20000.0;
+
+/// Analysed frequency range, with the upper bound clamped to the Nyquist frequency
+fn frequency_limit(sample_rate: usize) -> FrequencyLimit {
+    let nyquist = sample_rate as f32 / 2.0;
+    FrequencyLimit::Range(MIN_ANALYSIS_FREQUENCY, MAX_ANALYSIS_FREQUENCY.min(nyquist))
+}
+
+/// Linear gain of the A-weighting curve (IEC 61672) at `freq` Hz, 1.0 at 1 kHz
+fn a_weighting_gain(freq: f32) -> f32 {
+    let f2 = freq * freq;
+    let r_a = (12194.0f32.powi(2) * f2 * f2)
+        / ((f2 + 20.6f32.powi(2))
+            * ((f2 + 107.7f32.powi(2)) * (f2 + 737.9f32.powi(2))).sqrt()
+            * (f2 + 12194.0f32.powi(2)));
+    // +2.0 dB normalizes the curve to 0 dB at 1 kHz
+    r_a * 10f32.powf(2.0 / 20.0)
+}
+
+/// Snapshot of the analyzer state, published after every analysis pass
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AudioAnalysis {
+    /// Normalized energy per band [bass, mid, high] (0.0-1.0)
+    pub energy: [f32; 3],
+    /// Held, then decaying peak per band [bass, mid, high] (0.0-1.0)
+    pub peak: [f32; 3],
+    /// Smoothed overall loudness for level meters (0.0-1.0)
+    pub vu: f32,
+    /// Estimated tempo in beats per minute
+    pub bpm: f32,
+    /// Number of beats detected per band [bass, mid, high] since start
+    pub beat_counts: [usize; 3],
+    /// Confidence in the tempo estimate (0.0-1.0), based on beat interval regularity
+    pub beat_confidence: f32,
+    /// Seconds since the last bass beat, if any beat has been detected yet
+    pub seconds_since_last_beat: Option<f32>,
+    /// Dominant pitch class (0 = C, 1 = C#, ..., 11 = B), if any
+    pub pitch_class: Option<u8>,
+}
+
 /// Audio spectrum analyzer for LED visualization
 #[derive(Debug)]
 struct AudioAnalyzer {
@@ -114,6 +263,28 @@ struct AudioAnalyzer {
     beat_count: [usize; 3],
     /// Reusable buffer for FFT samples
     sample_buffer: Vec<f32>,
+    /// Peak normalized energy per band, held and then decaying over time
+    peak: [f32; 3],
+    /// Seconds since each band's peak was last raised
+    peak_age: [f32; 3],
+    /// Overall loudness with fast attack and slow release
+    vu: f32,
+    /// Spectral energy folded into the 12 pitch classes (chromagram)
+    chroma: [f32; 12],
+    /// Currently selected dominant pitch class
+    pitch_class: Option<usize>,
+    /// Octave range used for the chromagram
+    pitch_octaves: (u8, u8),
+    /// Whether band energy uses A-weighted magnitudes
+    a_weighting: bool,
+    /// Weight of the previous value in the smoothed energy average
+    energy_smoothing: f32,
+    /// Per-update decay factor of the max energy used for normalization
+    max_energy_decay: f32,
+    /// Normalization method
+    normalization: Normalization,
+    /// Recent band energies for RMS normalization
+    rms_window: [VecDeque<f32>; 3],
 }
 
 impl AudioAnalyzer {
@@ -141,9 +312,34 @@ impl AudioAnalyzer {
             ],
             beat_count: [0; 3],
             sample_buffer: Vec::with_capacity(sample_size),
+            peak: [0.0; 3],
+            peak_age: [0.0; 3],
+            vu: 0.0,
+            chroma: [0.0; 12],
+            pitch_class: None,
+            pitch_octaves: (3, 6),
+            a_weighting: false,
+            energy_smoothing: 0.7,
+            max_energy_decay: 0.9995,
+            normalization: Normalization::PeakDecay,
+            rms_window: [
+                VecDeque::with_capacity(RMS_WINDOW),
+                VecDeque::with_capacity(RMS_WINDOW),
+                VecDeque::with_capacity(RMS_WINDOW),
+            ],
         }
     }
 
+    /// Take over the tunable settings from the visualization config
+    fn configure(&mut self, config: &AudioVisualization) {
+        self.pitch_octaves = config.pitch_octaves;
+        self.a_weighting = config.a_weighting;
+        self.energy_smoothing = config.energy_smoothing.clamp(0.0, 0.99);
+        self.max_energy_decay = config.max_energy_decay.clamp(0.0, 1.0);
+        self.beat_thresholds = config.beat_thresholds;
+        self.normalization = config.normalization;
+    }
+
     /// Add a sample to the analyzer
     fn add_sample(&mut self, sample: f32) {
         self.samples.push_back(sample);
@@ -167,12 +363,13 @@ impl AudioAnalyzer {
         match samples_fft_to_spectrum(
             &self.sample_buffer,
             self.sample_rate as u32,
-            FrequencyLimit::Range(20.0, 20000.0),
+            frequency_limit(self.sample_rate),
             None, // No scaling function
         ) {
             Ok(spectrum) => {
                 // Extract energy in different frequency bands
                 self.extract_energy(&spectrum);
+                self.extract_chroma(&spectrum);
                 self.detect_beats();
             }
             Err(e) => {
@@ -185,9 +382,9 @@ impl AudioAnalyzer {
     fn extract_energy(&mut self, spectrum: &FrequencySpectrum) {
         // Define frequency bands
         let bands = [
-            (20.0, 250.0),     // Bass
-            (250.0, 2000.0),   // Mid
-            (2000.0, 20000.0), // High
+            (20.0, 250.0),                    // Bass
+            (250.0, 2000.0),                  // Mid
+            (2000.0, MAX_ANALYSIS_FREQUENCY), // High
         ];
 
         // Calculate energy for each band
@@ -197,7 +394,13 @@ impl AudioAnalyzer {
                 .data()
                 .iter()
                 .filter(|(freq, _)| freq.val() >= *low && freq.val() <= *high)
-                .map(|(_, magnitude)| magnitude.val())
+                .map(|(freq, magnitude)| {
+                    if self.a_weighting {
+                        magnitude.val() * a_weighting_gain(freq.val())
+                    } else {
+                        magnitude.val()
+                    }
+                })
                 .collect();
 
             if !band_values.is_empty() {
@@ -206,14 +409,60 @@ impl AudioAnalyzer {
                 self.energy[i] = band_energy * self.scaling;
 
                 // Update max energy (with dampening)
-                self.max_energy[i] = self.max_energy[i] * 0.9995 + self.energy[i] * 0.0005;
+                self.max_energy[i] = self.max_energy[i] * self.max_energy_decay
+                    + self.energy[i] * (1.0 - self.max_energy_decay);
                 if self.energy[i] > self.max_energy[i] {
                     self.max_energy[i] = self.energy[i];
                 }
 
+                self.rms_window[i].push_back(self.energy[i]);
+                if self.rms_window[i].len() > RMS_WINDOW {
+                    self.rms_window[i].pop_front();
+                }
+
                 // Smooth energy (exponential moving average)
-                self.smoothed_energy[i] = self.smoothed_energy[i] * 0.7 + self.energy[i] * 0.3;
+                self.smoothed_energy[i] = self.smoothed_energy[i] * self.energy_smoothing
+                    + self.energy[i] * (1.0 - self.energy_smoothing);
+            }
+        }
+    }
+
+    /// Fold the spectrum into 12 pitch classes and pick the dominant one
+    fn extract_chroma(&mut self, spectrum: &FrequencySpectrum) {
+        // MIDI note numbers: C of octave n is 12 * (n + 1), A4 (440 Hz) is 69
+        let (low_octave, high_octave) = self.pitch_octaves;
+        let lowest_note = 12.0 * (low_octave as f32 + 1.0) - 0.5;
+        let highest_note = 12.0 * (high_octave as f32 + 2.0) - 0.5;
+
+        self.chroma = [0.0; 12];
+        for (freq, magnitude) in spectrum.data() {
+            if freq.val() <= 0.0 {
+                continue;
+            }
+            let note = 69.0 + 12.0 * (freq.val() / 440.0).log2();
+            if note < lowest_note || note >= highest_note {
+                continue;
             }
+            let pitch_class = (note.round() as i32).rem_euclid(12) as usize;
+            self.chroma[pitch_class] += magnitude.val();
+        }
+
+        let Some((strongest, &strength)) = self
+            .chroma
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+        else {
+            return;
+        };
+        if strength <= 0.0 {
+            return;
+        }
+
+        // Hysteresis: only switch when the new class clearly dominates
+        match self.pitch_class {
+            Some(current) if self.chroma[current] * PITCH_HYSTERESIS >= strength => {}
+            _ => self.pitch_class = Some(strongest),
         }
     }
 
@@ -236,11 +485,7 @@ impl AudioAnalyzer {
             self.beat_detected[i] = false;
 
             // Normalize current energy
-            let normalized_energy = if self.max_energy[i] > 0.0 {
-                self.energy[i] / self.max_energy[i]
-            } else {
-                0.0
-            };
+            let normalized_energy = self.normalize(i, self.energy[i]);
 
             // Calculate local energy average (recent history)
             let local_energy_avg = if !self.energy_history[i].is_empty() {
@@ -306,11 +551,130 @@ impl AudioAnalyzer {
         }
     }
 
+    /// Update the per-band peaks
+    ///
+    /// A new peak is held for `hold_secs`, then decays by `decay_rate` units
+    /// per second until the level catches up with it.
+    fn update_peaks(&mut self, decay_rate: f32, hold_secs: f32, elapsed_secs: f32) {
+        let ranges = [
+            FrequencyRange::Bass,
+            FrequencyRange::Mid,
+            FrequencyRange::High,
+        ];
+        for (i, range) in ranges.iter().enumerate() {
+            let level = self.get_normalized_energy(*range).clamp(0.0, 1.0);
+            if level >= self.peak[i] {
+                self.peak[i] = level;
+                self.peak_age[i] = 0.0;
+                continue;
+            }
+
+            self.peak_age[i] += elapsed_secs;
+            if self.peak_age[i] > hold_secs {
+                // Only the part of this pass after the hold ran out counts
+                let decay_secs = (self.peak_age[i] - hold_secs).min(elapsed_secs);
+                self.peak[i] = (self.peak[i] - decay_rate * decay_secs).max(level);
+            }
+        }
+    }
+
+    /// Move the VU level towards the mean band energy
+    ///
+    /// Rises with [`VU_ATTACK_SECS`] so onsets show at once, and falls with
+    /// the slower [`VU_RELEASE_SECS`] so the meter doesn't flicker.
+    fn update_vu(&mut self, elapsed_secs: f32) {
+        let level = [
+            FrequencyRange::Bass,
+            FrequencyRange::Mid,
+            FrequencyRange::High,
+        ]
+        .iter()
+        .map(|range| self.get_normalized_energy(*range).clamp(0.0, 1.0))
+        .sum::<f32>()
+            / 3.0;
+        let time_constant = if level > self.vu {
+            VU_ATTACK_SECS
+        } else {
+            VU_RELEASE_SECS
+        };
+        let alpha = 1.0 - (-elapsed_secs / time_constant).exp();
+        self.vu += (level - self.vu) * alpha;
+    }
+
+    /// Confidence in the tempo estimate (0.0-1.0)
+    ///
+    /// Derived from the coefficient of variation of recent inter-beat intervals:
+    /// perfectly regular beats give 1.0, erratic or stale beats give 0.0.
+    fn beat_confidence(&self, current_time: f64) -> f32 {
+        // Beats older than the BPM window are stale (e.g. during a quiet section)
+        let recent: Vec<f64> = self
+            .beat_timestamps
+            .iter()
+            .copied()
+            .filter(|t| current_time - t <= 5.0)
+            .collect();
+        if recent.len() < 4 {
+            return 0.0;
+        }
+
+        let intervals: Vec<f64> = recent.windows(2).map(|w| w[1] - w[0]).collect();
+        let mean = intervals.iter().sum::<f64>() / intervals.len() as f64;
+        if mean <= 0.0 {
+            return 0.0;
+        }
+        let variance =
+            intervals.iter().map(|i| (i - mean).powi(2)).sum::<f64>() / intervals.len() as f64;
+        let coefficient_of_variation = variance.sqrt() / mean;
+
+        (1.0 - coefficient_of_variation as f32).clamp(0.0, 1.0)
+    }
+
+    /// Build a public snapshot of the current analysis
+    fn snapshot(&self, current_time: f64) -> AudioAnalysis {
+        AudioAnalysis {
+            energy: [
+                self.get_normalized_energy(FrequencyRange::Bass),
+                self.get_normalized_energy(FrequencyRange::Mid),
+                self.get_normalized_energy(FrequencyRange::High),
+            ],
+            peak: self.peak,
+            vu: self.vu,
+            bpm: self.estimated_bpm,
+            beat_counts: self.beat_count,
+            beat_confidence: self.beat_confidence(current_time),
+            seconds_since_last_beat: (self.last_beat_time > 0.0)
+                .then_some((current_time - self.last_beat_time) as f32),
+            pitch_class: self.pitch_class.map(|class| class as u8),
+        }
+    }
+
     /// Get the estimated BPM (beats per minute)
     fn get_bpm(&self) -> f32 {
         self.estimated_bpm
     }
 
+    /// The inputs of [`compute_color`] for the current analysis pass
+    fn color_inputs(&self, current_time: f64) -> ColorInputs {
+        ColorInputs {
+            energy: [
+                self.get_normalized_energy(FrequencyRange::Bass),
+                self.get_normalized_energy(FrequencyRange::Mid),
+                self.get_normalized_energy(FrequencyRange::High),
+            ],
+            overall: self.get_normalized_energy(FrequencyRange::Full),
+            beats: [
+                self.is_beat_detected(FrequencyRange::Bass),
+                self.is_beat_detected(FrequencyRange::Mid),
+                self.is_beat_detected(FrequencyRange::High),
+            ],
+            bpm: self.get_bpm(),
+            beat_confidence: self.beat_confidence(current_time),
+            on_beat: self.is_on_beat(current_time),
+            time: current_time,
+            pitch_class: self.pitch_class,
+        }
+    }
+
     /// Check if we're at a beat position according to BPM timing
     fn is_on_beat(&self, current_time: f64) -> bool {
         if self.estimated_bpm <= 0.0 {
@@ -328,40 +692,42 @@ impl AudioAnalyzer {
     /// Get normalized energy for a frequency range (0.0-1.0)
     fn get_normalized_energy(&self, range: FrequencyRange) -> f32 {
         match range {
-            FrequencyRange::Bass => {
-                if self.max_energy[0] > 0.0 {
-                    self.smoothed_energy[0] / self.max_energy[0]
-                } else {
-                    0.0
-                }
-            }
-            FrequencyRange::Mid => {
-                if self.max_energy[1] > 0.0 {
-                    self.smoothed_energy[1] / self.max_energy[1]
-                } else {
-                    0.0
-                }
-            }
-            FrequencyRange::High => {
-                if self.max_energy[2] > 0.0 {
-                    self.smoothed_energy[2] / self.max_energy[2]
-                } else {
-                    0.0
-                }
-            }
+            FrequencyRange::Bass => self.normalize(0, self.smoothed_energy[0]),
+            FrequencyRange::Mid => self.normalize(1, self.smoothed_energy[1]),
+            FrequencyRange::High => self.normalize(2, self.smoothed_energy[2]),
             FrequencyRange::Full => {
                 // Average of all bands
-                let sum = self
-                    .smoothed_energy
-                    .iter()
-                    .zip(self.max_energy.iter())
-                    .map(|(e, m)| if *m > 0.0 { e / m } else { 0.0 })
+                let sum = (0..3)
+                    .map(|i| self.normalize(i, self.smoothed_energy[i]))
                     .sum::<f32>();
                 sum / 3.0
             }
         }
     }
 
+    /// Scale an energy value of band `band` by the configured reference level
+    fn normalize(&self, band: usize, energy: f32) -> f32 {
+        let reference = match self.normalization {
+            Normalization::PeakDecay => self.max_energy[band],
+            Normalization::Rms => {
+                let window = &self.rms_window[band];
+                if window.is_empty() {
+                    0.0
+                } else {
+                    let mean_square =
+                        window.iter().map(|e| e * e).sum::<f32>() / window.len() as f32;
+                    mean_square.sqrt() * RMS_HEADROOM
+                }
+            }
+        };
+
+        if reference > 0.0 {
+            energy / reference
+        } else {
+            0.0
+        }
+    }
+
     /// Check if beat is detected in a specific range
     fn is_beat_detected(&self, range: FrequencyRange) -> bool {
         match range {
@@ -380,7 +746,7 @@ struct AudioColor {
     g: u8,
     b: u8,
     brightness: u8,
-    effect: Option<u8>,
+    effect: Option<Effect>,
 }
 
 impl Default for AudioColor {
@@ -395,15 +761,474 @@ impl Default for AudioColor {
     }
 }
 
+/// What the visualization modes read from one analysis pass
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct ColorInputs {
+    /// Normalized energy per band [bass, mid, high] (0.0-1.0)
+    energy: [f32; 3],
+    /// Average of the normalized band energies (0.0-1.0)
+    overall: f32,
+    /// Whether this pass detected a beat per band [bass, mid, high]
+    beats: [bool; 3],
+    /// Estimated tempo in beats per minute
+    bpm: f32,
+    /// Confidence in the tempo estimate (0.0-1.0)
+    beat_confidence: f32,
+    /// Whether the estimated tempo puts this pass on a beat
+    on_beat: bool,
+    /// Seconds since the Unix epoch, for time-based patterns
+    time: f64,
+    /// Dominant pitch class (0 = C, ..., 11 = B), if any
+    pitch_class: Option<usize>,
+}
+
+/// The color `mode` shows for `inputs`
+///
+/// A pure function of its arguments, so a mode's mapping doesn't depend on
+/// timing or channels. `previous` is the color of the last pass: modes keep
+/// what they don't set, e.g. FrequencyColor leaves the brightness alone and
+/// PitchHue holds its color while no pitch stands out.
+fn compute_color(
+    mode: VisualizationMode,
+    inputs: &ColorInputs,
+    config: &AudioVisualization,
+    previous: AudioColor,
+) -> AudioColor {
+    let sensitivity = config.sensitivity;
+    let (bass_trigger, mid_trigger, high_trigger) = (
+        config.bass_color_trigger,
+        config.mid_brightness_trigger,
+        config.high_effect_trigger,
+    );
+    let mut audio_color = previous;
+
+    match mode {
+        VisualizationMode::FrequencyColor => {
+            // Map frequency energies to RGB
+            let bass = inputs.energy[0];
+            let mid = inputs.energy[1];
+            let high = inputs.energy[2];
+
+            // Apply sensitivity
+            audio_color.r = (bass * 255.0 * sensitivity) as u8;
+            audio_color.g = (mid * 255.0 * sensitivity) as u8;
+            audio_color.b = (high * 255.0 * sensitivity) as u8;
+
+            // Ensure some minimum brightness when there's sound
+            let overall = inputs.overall;
+            if overall > 0.05 {
+                audio_color.r = audio_color.r.max(10);
+                audio_color.g = audio_color.g.max(10);
+                audio_color.b = audio_color.b.max(10);
+            }
+
+            // Reset effect
+            audio_color.effect = None;
+        }
+
+        VisualizationMode::EnergyBrightness => {
+            // Set color based on dominant frequency
+            let bass = inputs.energy[0];
+            let mid = inputs.energy[1];
+            let high = inputs.energy[2];
+
+            // Find dominant frequency
+            if bass > mid && bass > high && bass > 0.1 {
+                // Bass dominant - red
+                audio_color.r = 255;
+                audio_color.g = 0;
+                audio_color.b = 0;
+            } else if mid > bass && mid > high && mid > 0.1 {
+                // Mid dominant - green
+                audio_color.r = 0;
+                audio_color.g = 255;
+                audio_color.b = 0;
+            } else if high > bass && high > mid && high > 0.1 {
+                // High dominant - blue
+                audio_color.r = 0;
+                audio_color.g = 0;
+                audio_color.b = 255;
+            } else {
+                // No dominant frequency - white
+                audio_color.r = 255;
+                audio_color.g = 255;
+                audio_color.b = 255;
+            }
+
+            // Set brightness based on overall energy
+            let energy = inputs.overall;
+            audio_color.brightness = (energy * 100.0 * sensitivity) as u8;
+            audio_color.brightness = audio_color.brightness.clamp(5, 100);
+
+            // Reset effect
+            audio_color.effect = None;
+        }
+
+        VisualizationMode::BeatEffects => {
+            // Set different effects based on detected beats
+            if inputs.beats[0] && bass_trigger {
+                // Bass beat - set to red and use crossfade
+                audio_color.r = 255;
+                audio_color.g = 0;
+                audio_color.b = 0;
+                audio_color.effect = Some(Effect::CrossfadeRed);
+            } else if inputs.beats[1] && mid_trigger {
+                // Mid beat - set to green and use crossfade
+                audio_color.r = 0;
+                audio_color.g = 255;
+                audio_color.b = 0;
+                audio_color.effect = Some(Effect::CrossfadeGreen);
+            } else if inputs.beats[2] && high_trigger {
+                // High beat - set to blue and use crossfade
+                audio_color.r = 0;
+                audio_color.g = 0;
+                audio_color.b = 255;
+                audio_color.effect = Some(Effect::CrossfadeBlue);
+            } else {
+                // No beat - set to white with no effect
+                audio_color.r = 255;
+                audio_color.g = 255;
+                audio_color.b = 255;
+                audio_color.effect = None;
+            }
+
+            // Energy affects brightness
+            let energy = inputs.overall;
+            audio_color.brightness = (energy * 100.0 * sensitivity) as u8;
+            audio_color.brightness = audio_color.brightness.clamp(20, 100);
+        }
+
+        VisualizationMode::SpectralFlow => {
+            // Create flowing color pattern based on spectral content
+            let bass = inputs.energy[0];
+            let mid = inputs.energy[1];
+            let high = inputs.energy[2];
+
+            // Create color flow - smooth transitions between colors
+            let time = inputs.time as f32;
+
+            // Base hue shifts with time, energy modulates saturation and brightness
+            let energy = bass * 0.5 + mid * 0.3 + high * 0.2;
+
+            // Use simple time-based patterns when no sound
+            if energy < 0.05 {
+                // Gentle pulse with time when no sound
+                let pulse = (time * 0.5).sin() * 0.5 + 0.5;
+                audio_color.r = (pulse * 50.0) as u8;
+                audio_color.g = (pulse * 50.0) as u8;
+                audio_color.b = (pulse * 80.0) as u8;
+                audio_color.effect = Some(Effect::CrossfadeRgb);
+            } else {
+                // Sound present - create dynamic pattern
+
+                // When strong bass beat detected, temporarily switch to flash effect
+                if inputs.beats[0] && bass > 0.7 {
+                    audio_color.effect = Some(Effect::JumpAll);
+                } else {
+                    // Normal flow - energy levels modulate colors in a cycle
+                    let bass_phase = (time * 0.7).sin() * 0.5 + 0.5;
+                    let mid_phase = (time * 0.7 + 2.0).sin() * 0.5 + 0.5;
+                    let high_phase = (time * 0.7 + 4.0).sin() * 0.5 + 0.5;
+
+                    audio_color.r = (bass_phase * 255.0 * bass * sensitivity) as u8;
+                    audio_color.g = (mid_phase * 255.0 * mid * sensitivity) as u8;
+                    audio_color.b = (high_phase * 255.0 * high * sensitivity) as u8;
+
+                    // Set crossfade effect for subtle transitions
+                    audio_color.effect = Some(Effect::CrossfadeRgb);
+                }
+            }
+
+            // Adjust brightness based on overall energy
+            let brightness = (energy * 100.0 * sensitivity).max(20.0);
+            audio_color.brightness = brightness.min(100.0) as u8;
+        }
+
+        VisualizationMode::EnhancedFrequencyColor => {
+            // Get normalized energy values for each frequency range
+            let bass = inputs.energy[0];
+            let mid = inputs.energy[1];
+            let high = inputs.energy[2];
+
+            // Enhanced color mapping:
+            // - Bass dominant: warm red-yellow spectrum (255,0,0) to (255,200,0)
+            // - Mid dominant: green-cyan spectrum (0,255,0) to (0,255,200)
+            // - High dominant: cool blue-white spectrum (0,0,255) to (200,200,255)
+
+            // Start with black
+            let mut r: u8 = 0;
+            let mut g: u8 = 0;
+            let mut b: u8 = 0;
+
+            // Apply bass (red-orange-yellow warm colors)
+            if bass > 0.05 {
+                // Calculate bass contribution - more bass means more red
+                r = r.saturating_add((255.0 * bass * sensitivity) as u8);
+                // Yellow tint increases with stronger bass
+                g = g.saturating_add((150.0 * bass * bass * sensitivity) as u8);
+            }
+
+            // Apply mid (green-cyan colors)
+            if mid > 0.05 {
+                // Main green contribution
+                g = g.saturating_add((255.0 * mid * sensitivity) as u8);
+                // Some cyan tint for stronger mids
+                b = b.saturating_add((100.0 * mid * mid * sensitivity) as u8);
+            }
+
+            // Apply high (blue-white cool colors)
+            if high > 0.05 {
+                // Main blue contribution
+                b = b.saturating_add((255.0 * high * sensitivity) as u8);
+                // White tint (r,g components) increases with stronger highs
+                r = r.saturating_add((180.0 * high * high * sensitivity) as u8);
+                g = g.saturating_add((180.0 * high * high * sensitivity) as u8);
+            }
+
+            // Ensure some minimum brightness when there's sound
+            let overall = inputs.overall;
+            if overall > 0.05 {
+                r = r.max(10);
+                g = g.max(10);
+                b = b.max(10);
+            }
+
+            // Apply to audio color
+            audio_color.r = r;
+            audio_color.g = g;
+            audio_color.b = b;
+
+            // Adjust brightness based on energy
+            let energy = overall;
+            audio_color.brightness = (energy * 100.0 * sensitivity) as u8;
+            audio_color.brightness = audio_color.brightness.clamp(20, 100);
+
+            // No specific effect
+            audio_color.effect = None;
+
+            // For bass-heavy parts, add warmer tones
+            if bass > 0.7 && bass > 1.5 * mid && bass > 2.0 * high {
+                // Very bass heavy - make it more red-amber
+                audio_color.r = 255;
+                audio_color.g = (120.0 * bass * sensitivity) as u8;
+                audio_color.b = 0;
+            }
+
+            // For treble-heavy parts, add more white/light blue
+            if high > 0.7 && high > 1.5 * mid && high > 2.0 * bass {
+                // Very treble heavy - make it more white/light blue
+                audio_color.r = (210.0 * high * sensitivity) as u8;
+                audio_color.g = (220.0 * high * sensitivity) as u8;
+                audio_color.b = 255;
+            }
+        }
+
+        VisualizationMode::BpmSync => {
+            // Current tempo estimate
+            let bpm = inputs.bpm;
+            let bass = inputs.energy[0];
+            let mid = inputs.energy[1];
+            let high = inputs.energy[2];
+
+            // Calculate the base color based on frequency balance
+            // More bass = more red, more highs = more blue, etc.
+            let r = (bass * 255.0 * sensitivity * 1.2).min(255.0) as u8;
+            let g = (mid * 255.0 * sensitivity * 1.1).min(255.0) as u8;
+            let b = (high * 255.0 * sensitivity * 1.2).min(255.0) as u8;
+
+            // Only trust the tempo estimate once beats are regular enough
+            let confident = inputs.beat_confidence >= BPM_CONFIDENCE_THRESHOLD;
+
+            // Check if we're on a beat according to BPM timing
+            let on_beat = confident && inputs.on_beat;
+
+            // Different effects based on BPM
+            if !confident {
+                // Unreliable tempo - plain energy-driven color
+                audio_color.r = r;
+                audio_color.g = g;
+                audio_color.b = b;
+                audio_color.effect = None;
+            } else if bpm < 70.0 {
+                // Slow tempo - smooth color transitions
+                if on_beat && inputs.beats[0] {
+                    // On beat with bass - emphasize red
+                    audio_color.r = 255;
+                    audio_color.g = (g as f32 * 0.7) as u8;
+                    audio_color.b = (b as f32 * 0.6) as u8;
+                    audio_color.effect = Some(Effect::CrossfadeRed);
+                } else {
+                    // Normal color
+                    audio_color.r = r;
+                    audio_color.g = g;
+                    audio_color.b = b;
+                    audio_color.effect = Some(Effect::CrossfadeRgb);
+                }
+            } else if bpm < 120.0 {
+                // Medium tempo - more dynamic changes
+                if on_beat {
+                    // On beat pulses
+                    if inputs.beats[0] {
+                        // Bass hit - red pulse
+                        audio_color.r = 255;
+                        audio_color.g = 40;
+                        audio_color.b = 0;
+                        audio_color.effect = Some(Effect::JumpRgb);
+                    } else {
+                        // Regular beat - white pulse
+                        audio_color.r = 255;
+                        audio_color.g = 255;
+                        audio_color.b = 255;
+                        audio_color.effect = Some(Effect::CrossfadeWhite);
+                    }
+                } else {
+                    // Between beats - regular spectrum color
+                    audio_color.r = r;
+                    audio_color.g = g;
+                    audio_color.b = b;
+                    audio_color.effect = None;
+                }
+            } else {
+                // Fast tempo - flashy effects
+                if on_beat && inputs.beats[0] {
+                    // On beat with bass - bright flash
+                    audio_color.r = 255;
+                    audio_color.g = 255;
+                    audio_color.b = 255;
+                    audio_color.effect = Some(Effect::JumpAll);
+                } else if on_beat {
+                    // Regular beat - color based on spectrum
+                    audio_color.r = r;
+                    audio_color.g = g;
+                    audio_color.b = b;
+                    audio_color.effect = Some(Effect::BlinkAll);
+                } else {
+                    // Between beats - darker version of spectrum
+                    audio_color.r = (r as f32 * 0.7) as u8;
+                    audio_color.g = (g as f32 * 0.7) as u8;
+                    audio_color.b = (b as f32 * 0.7) as u8;
+                    audio_color.effect = None;
+                }
+            }
+
+            // Brightness pulses with the beat
+            let base_brightness = (60.0 * sensitivity).max(20.0) as u8;
+            let pulse_amplitude = (40.0 * sensitivity) as u8;
+
+            if !confident {
+                // Energy drives brightness without a reliable tempo
+                let energy = inputs.overall;
+                audio_color.brightness = ((energy * 100.0 * sensitivity) as u8).clamp(20, 100);
+            } else if on_beat {
+                // Brighter on beats
+                audio_color.brightness = base_brightness.saturating_add(pulse_amplitude).min(100);
+            } else {
+                // Normal brightness between beats
+                audio_color.brightness = base_brightness;
+            }
+
+            // Display estimated BPM in debug
+            debug!("Estimated BPM: {:.1}", bpm);
+        }
+
+        VisualizationMode::PitchHue => {
+            // Each pitch class gets its own 30 degree slice of the color wheel
+            if let Some(pitch_class) = inputs.pitch_class {
+                let energy = inputs.overall;
+                let value = (energy * sensitivity).clamp(0.1, 1.0);
+                let (r, g, b) = hsv_to_rgb(pitch_class as f32 * 30.0, 1.0, value);
+                audio_color.r = r;
+                audio_color.g = g;
+                audio_color.b = b;
+            }
+
+            // Energy is already folded into the color value
+            audio_color.brightness = 100;
+            audio_color.effect = None;
+        }
+    }
+
+    audio_color
+}
+
+/// Get an audio host by name, or the platform default
+fn select_host(host_name: Option<&str>) -> Result<cpal::Host> {
+    let Some(name) = host_name else {
+        return Ok(cpal::default_host());
+    };
+
+    let available = cpal::available_hosts();
+    let id = available
+        .iter()
+        .find(|id| id.name().eq_ignore_ascii_case(name))
+        .ok_or_else(|| {
+            let names: Vec<&str> = available.iter().map(|id| id.name()).collect();
+            Error::AudioCaptureError(format!(
+                "Audio host '{}' is not available (available: {})",
+                name,
+                names.join(", ")
+            ))
+        })?;
+
+    cpal::host_from_id(*id).map_err(|e| {
+        Error::AudioCaptureError(format!("Failed to open audio host {}: {}", id.name(), e))
+    })
+}
+
+/// Samples kept between analyzer passes; older ones are dropped when full
+///
+/// 8192 samples are about 170ms at 48kHz, far more than the analyzer falls
+/// behind normally (it drains every millisecond), while the FFT only looks
+/// at the newest 2048 anyway.
+const SAMPLE_RING_CAPACITY: usize = 8192;
+
+/// Most recent audio samples, shared by the capture callback and the analyzer
+///
+/// Unlike a bounded channel, a full ring drops its oldest samples, so a busy
+/// analyzer never loses the end of a capture buffer.
+struct SampleRing {
+    /// Buffered samples, oldest first
+    samples: parking_lot::Mutex<VecDeque<f32>>,
+    /// Maximum number of buffered samples
+    capacity: usize,
+}
+
+impl SampleRing {
+    /// Creates an empty ring holding up to `capacity` samples
+    fn new(capacity: usize) -> Self {
+        Self {
+            samples: parking_lot::Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    /// Appends samples, dropping the oldest ones beyond the capacity
+    fn push(&self, new_samples: impl Iterator<Item = f32>) {
+        let mut samples = self.samples.lock();
+        samples.extend(new_samples);
+        let excess = samples.len().saturating_sub(self.capacity);
+        if excess > 0 {
+            trace!("Audio analyzer behind, dropping {} old samples", excess);
+            samples.drain(..excess);
+        }
+    }
+
+    /// Hands all buffered samples to `f`, oldest first
+    ///
+    /// The lock is held meanwhile, so `f` must be cheap.
+    fn drain(&self, f: impl FnMut(f32)) {
+        self.samples.lock().drain(..).for_each(f);
+    }
+}
+
 /// Main audio monitoring system for LED control
 pub struct AudioMonitor {
     /// Current visualization configuration
     config: Arc<RwLock<AudioVisualization>>,
-    /// Channel for sending samples to analyzer
-    #[allow(dead_code)]
-    sample_tx: Option<mpsc::Sender<f32>>,
     /// Channel for receiving calculated colors
     color_rx: watch::Receiver<AudioColor>,
+    /// Channel for receiving analysis snapshots
+    analysis_rx: watch::Receiver<AudioAnalysis>,
     /// Flag to stop the audio monitor
     stop_flag: Arc<AtomicBool>,
     /// The audio capture stream
@@ -418,15 +1243,36 @@ impl AudioMonitor {
 
     /// Create a new audio monitor with a specified device name
     pub fn new_with_device(device_name: Option<String>) -> Result<Self> {
+        Self::new_with_host(device_name, None)
+    }
+
+    /// Names of the audio hosts (backends) compiled in and usable here, e.g. `ALSA`
+    pub fn available_hosts() -> Vec<&'static str> {
+        cpal::available_hosts()
+            .into_iter()
+            .map(|id| id.name())
+            .collect()
+    }
+
+    /// Create a new audio monitor on a specific audio host (backend)
+    ///
+    /// # Arguments
+    ///
+    /// * `device_name` - Part of the input device name, or `None` for the default device
+    /// * `host_name` - Host name from [`AudioMonitor::available_hosts`] (case-insensitive),
+    ///   or `None` for the platform default
+    pub fn new_with_host(device_name: Option<String>, host_name: Option<&str>) -> Result<Self> {
         let config = Arc::new(RwLock::new(AudioVisualization::default()));
         let stop_flag = Arc::new(AtomicBool::new(false));
 
-        // Create channels for audio samples and colors
-        let (sample_tx, sample_rx) = mpsc::channel::<f32>(4096);
+        // Create the sample buffer and channels for colors
+        let samples = Arc::new(SampleRing::new(SAMPLE_RING_CAPACITY));
         let (color_tx, color_rx) = watch::channel(AudioColor::default());
+        let (analysis_tx, analysis_rx) = watch::channel(AudioAnalysis::default());
 
         // Set up audio capture
-        let host = cpal::default_host();
+        let host = select_host(host_name)?;
+        debug!("Using audio host: {}", host.id().name());
 
         // Get input device by name or use default
         let input_device = if let Some(name) = device_name {
@@ -497,10 +1343,25 @@ impl AudioMonitor {
         // Get sample rate
         let sample_rate = config_range.sample_rate().0 as usize;
         debug!("Audio input sample rate: {} Hz", sample_rate);
+        if sample_rate < MIN_SAMPLE_RATE {
+            error!("Unusable audio input sample rate: {} Hz", sample_rate);
+            return Err(Error::AudioCaptureError(format!(
+                "Sample rate {} Hz is too low for analysis (at least {} Hz needed)",
+                sample_rate, MIN_SAMPLE_RATE
+            )));
+        }
+        if (sample_rate as f32) / 2.0 < MAX_ANALYSIS_FREQUENCY {
+            warn!(
+                "Audio input sample rate is {} Hz; frequencies above {} Hz are not analyzed",
+                sample_rate,
+                sample_rate / 2
+            );
+        }
 
         // Spawn analysis thread using std::thread since it doesn't need to be async
         let analyzer_stop_flag = stop_flag.clone();
         let analyzer_config = config.clone();
+        let analyzer_samples = samples.clone();
         std::thread::spawn(move || {
             // Use a blocking runtime for the analyzer
             let rt = tokio::runtime::Builder::new_current_thread()
@@ -510,8 +1371,9 @@ impl AudioMonitor {
 
             rt.block_on(async {
                 Self::run_analyzer(
-                    sample_rx,
+                    analyzer_samples,
                     color_tx,
+                    analysis_tx,
                     sample_rate,
                     analyzer_config,
                     analyzer_stop_flag,
@@ -528,19 +1390,19 @@ impl AudioMonitor {
             SampleFormat::F32 => Self::build_input_stream::<f32>(
                 &input_device,
                 &config_range.into(),
-                sample_tx.clone(),
+                samples.clone(),
                 err_fn,
             ),
             SampleFormat::I16 => Self::build_input_stream::<i16>(
                 &input_device,
                 &config_range.into(),
-                sample_tx.clone(),
+                samples.clone(),
                 err_fn,
             ),
             SampleFormat::U16 => Self::build_input_stream::<u16>(
                 &input_device,
                 &config_range.into(),
-                sample_tx.clone(),
+                samples.clone(),
                 err_fn,
             ),
             _ => {
@@ -567,8 +1429,8 @@ impl AudioMonitor {
 
         Ok(Self {
             config,
-            sample_tx: Some(sample_tx),
             color_rx,
+            analysis_rx,
             stop_flag,
             _stream: stream,
         })
@@ -578,14 +1440,12 @@ impl AudioMonitor {
     fn build_input_stream<T>(
         device: &cpal::Device,
         config: &cpal::StreamConfig,
-        sample_tx: mpsc::Sender<f32>,
+        samples: Arc<SampleRing>,
         err_fn: impl FnMut(cpal::StreamError) + Send + 'static,
     ) -> Result<cpal::Stream>
     where
         T: Sample<Float = f32> + cpal::SizedSample + Send + 'static,
     {
-        let tx = sample_tx.clone();
-
         debug!(
             "Building audio capture stream for device: {}",
             device.name().unwrap_or_default()
@@ -597,19 +1457,10 @@ impl AudioMonitor {
             .build_input_stream(
                 config,
                 move |data: &[T], _: &cpal::InputCallbackInfo| {
-                    // Process each sample
-                    for &sample in data {
-                        // Convert the sample to f32 (normalize between -1.0 and 1.0)
-                        let sample_f32 = sample.to_float_sample();
-
-                        // Apply some amplification to make sure we get signal
-                        let amplified = sample_f32 * 5.0;
-
-                        // Avoid blocking by using try_send; skip if channel is full
-                        if tx.try_send(amplified).is_err() {
-                            break;
-                        }
-                    }
+                    // Convert to f32 (between -1.0 and 1.0) and amplify to make sure we
+                    // get signal. The whole buffer is kept; if the analyzer falls behind,
+                    // the oldest samples are dropped instead.
+                    samples.push(data.iter().map(|&sample| sample.to_float_sample() * 5.0));
                 },
                 err_fn,
                 None,
@@ -622,8 +1473,9 @@ impl AudioMonitor {
 
     /// Run the audio analyzer in a background thread
     async fn run_analyzer(
-        mut sample_rx: mpsc::Receiver<f32>,
+        samples: Arc<SampleRing>,
         color_tx: watch::Sender<AudioColor>,
+        analysis_tx: watch::Sender<AudioAnalysis>,
         sample_rate: usize,
         config: Arc<RwLock<AudioVisualization>>,
         stop_flag: Arc<AtomicBool>,
@@ -635,32 +1487,21 @@ impl AudioMonitor {
         // Process audio samples
         while !stop_flag.load(Ordering::Relaxed) {
             // Collect samples
-            while let Ok(sample) = sample_rx.try_recv() {
-                analyzer.add_sample(sample);
-            }
+            samples.drain(|sample| analyzer.add_sample(sample));
 
             // Check if it's time to update the visualization
             let now = std::time::Instant::now();
 
             // Get config values inside a block to drop the guard before any await
-            let (
-                update_interval,
-                is_active,
-                vis_mode,
-                sensitivity,
-                bass_trigger,
-                mid_trigger,
-                high_trigger,
-            ) = {
+            let (update_interval, is_active, vis_mode, peak_decay_rate, peak_hold) = {
                 let config_guard = config.read();
+                analyzer.configure(&config_guard);
                 (
                     Duration::from_millis(config_guard.update_interval_ms as u64),
                     config_guard.active,
                     config_guard.mode,
-                    config_guard.sensitivity,
-                    config_guard.bass_color_trigger,
-                    config_guard.mid_brightness_trigger,
-                    config_guard.high_effect_trigger,
+                    config_guard.peak_decay_rate,
+                    config_guard.peak_hold_ms as f32 / 1000.0,
                 )
             };
 
@@ -668,339 +1509,21 @@ impl AudioMonitor {
                 // Analyze audio
                 analyzer.analyze();
 
+                // Update peak markers and publish the analysis snapshot
+                let elapsed = now.duration_since(last_update).as_secs_f32();
+                analyzer.update_peaks(peak_decay_rate, peak_hold, elapsed);
+                analyzer.update_vu(elapsed);
+                // Get current timestamp for timing-based effects
+                let current_time = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs_f64();
+                let _ = analysis_tx.send(analyzer.snapshot(current_time));
+
                 // Only update visuals if active
                 if is_active {
-                    // Get current timestamp for timing-based effects
-                    let current_time = std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap_or_default()
-                        .as_secs_f64();
-
-                    // Apply visualization based on the current mode
-                    match vis_mode {
-                        VisualizationMode::FrequencyColor => {
-                            // Map frequency energies to RGB
-                            let bass = analyzer.get_normalized_energy(FrequencyRange::Bass);
-                            let mid = analyzer.get_normalized_energy(FrequencyRange::Mid);
-                            let high = analyzer.get_normalized_energy(FrequencyRange::High);
-
-                            // Apply sensitivity
-                            audio_color.r = (bass * 255.0 * sensitivity) as u8;
-                            audio_color.g = (mid * 255.0 * sensitivity) as u8;
-                            audio_color.b = (high * 255.0 * sensitivity) as u8;
-
-                            // Ensure some minimum brightness when there's sound
-                            let overall = analyzer.get_normalized_energy(FrequencyRange::Full);
-                            if overall > 0.05 {
-                                audio_color.r = audio_color.r.max(10);
-                                audio_color.g = audio_color.g.max(10);
-                                audio_color.b = audio_color.b.max(10);
-                            }
-
-                            // Reset effect
-                            audio_color.effect = None;
-                        }
-
-                        VisualizationMode::EnergyBrightness => {
-                            // Set color based on dominant frequency
-                            let bass = analyzer.get_normalized_energy(FrequencyRange::Bass);
-                            let mid = analyzer.get_normalized_energy(FrequencyRange::Mid);
-                            let high = analyzer.get_normalized_energy(FrequencyRange::High);
-
-                            // Find dominant frequency
-                            if bass > mid && bass > high && bass > 0.1 {
-                                // Bass dominant - red
-                                audio_color.r = 255;
-                                audio_color.g = 0;
-                                audio_color.b = 0;
-                            } else if mid > bass && mid > high && mid > 0.1 {
-                                // Mid dominant - green
-                                audio_color.r = 0;
-                                audio_color.g = 255;
-                                audio_color.b = 0;
-                            } else if high > bass && high > mid && high > 0.1 {
-                                // High dominant - blue
-                                audio_color.r = 0;
-                                audio_color.g = 0;
-                                audio_color.b = 255;
-                            } else {
-                                // No dominant frequency - white
-                                audio_color.r = 255;
-                                audio_color.g = 255;
-                                audio_color.b = 255;
-                            }
-
-                            // Set brightness based on overall energy
-                            let energy = analyzer.get_normalized_energy(FrequencyRange::Full);
-                            audio_color.brightness = (energy * 100.0 * sensitivity) as u8;
-                            audio_color.brightness = audio_color.brightness.clamp(5, 100);
-
-                            // Reset effect
-                            audio_color.effect = None;
-                        }
-
-                        VisualizationMode::BeatEffects => {
-                            // Set different effects based on detected beats
-                            if analyzer.is_beat_detected(FrequencyRange::Bass) && bass_trigger {
-                                // Bass beat - set to red and use crossfade
-                                audio_color.r = 255;
-                                audio_color.g = 0;
-                                audio_color.b = 0;
-                                audio_color.effect = Some(EFFECTS.crossfade_red);
-                            } else if analyzer.is_beat_detected(FrequencyRange::Mid) && mid_trigger
-                            {
-                                // Mid beat - set to green and use crossfade
-                                audio_color.r = 0;
-                                audio_color.g = 255;
-                                audio_color.b = 0;
-                                audio_color.effect = Some(EFFECTS.crossfade_green);
-                            } else if analyzer.is_beat_detected(FrequencyRange::High)
-                                && high_trigger
-                            {
-                                // High beat - set to blue and use crossfade
-                                audio_color.r = 0;
-                                audio_color.g = 0;
-                                audio_color.b = 255;
-                                audio_color.effect = Some(EFFECTS.crossfade_blue);
-                            } else {
-                                // No beat - set to white with no effect
-                                audio_color.r = 255;
-                                audio_color.g = 255;
-                                audio_color.b = 255;
-                                audio_color.effect = None;
-                            }
-
-                            // Energy affects brightness
-                            let energy = analyzer.get_normalized_energy(FrequencyRange::Full);
-                            audio_color.brightness = (energy * 100.0 * sensitivity) as u8;
-                            audio_color.brightness = audio_color.brightness.clamp(20, 100);
-                        }
-
-                        VisualizationMode::SpectralFlow => {
-                            // Create flowing color pattern based on spectral content
-                            let bass = analyzer.get_normalized_energy(FrequencyRange::Bass);
-                            let mid = analyzer.get_normalized_energy(FrequencyRange::Mid);
-                            let high = analyzer.get_normalized_energy(FrequencyRange::High);
-
-                            // Create color flow - smooth transitions between colors
-                            let time = current_time as f32;
-
-                            // Base hue shifts with time, energy modulates saturation and brightness
-                            let energy = bass * 0.5 + mid * 0.3 + high * 0.2;
-
-                            // Use simple time-based patterns when no sound
-                            if energy < 0.05 {
-                                // Gentle pulse with time when no sound
-                                let pulse = (time * 0.5).sin() * 0.5 + 0.5;
-                                audio_color.r = (pulse * 50.0) as u8;
-                                audio_color.g = (pulse * 50.0) as u8;
-                                audio_color.b = (pulse * 80.0) as u8;
-                                audio_color.effect = Some(EFFECTS.crossfade_red_green_blue);
-                            } else {
-                                // Sound present - create dynamic pattern
-
-                                // When strong bass beat detected, temporarily switch to flash effect
-                                if analyzer.is_beat_detected(FrequencyRange::Bass) && bass > 0.7 {
-                                    audio_color.effect =
-                                        Some(EFFECTS.jump_red_green_blue_yellow_cyan_magenta_white);
-                                } else {
-                                    // Normal flow - energy levels modulate colors in a cycle
-                                    let bass_phase = (time * 0.7).sin() * 0.5 + 0.5;
-                                    let mid_phase = (time * 0.7 + 2.0).sin() * 0.5 + 0.5;
-                                    let high_phase = (time * 0.7 + 4.0).sin() * 0.5 + 0.5;
-
-                                    audio_color.r = (bass_phase * 255.0 * bass * sensitivity) as u8;
-                                    audio_color.g = (mid_phase * 255.0 * mid * sensitivity) as u8;
-                                    audio_color.b = (high_phase * 255.0 * high * sensitivity) as u8;
-
-                                    // Set crossfade effect for subtle transitions
-                                    audio_color.effect = Some(EFFECTS.crossfade_red_green_blue);
-                                }
-                            }
-
-                            // Adjust brightness based on overall energy
-                            let brightness = (energy * 100.0 * sensitivity).max(20.0);
-                            audio_color.brightness = brightness.min(100.0) as u8;
-                        }
-
-                        VisualizationMode::EnhancedFrequencyColor => {
-                            // Get normalized energy values for each frequency range
-                            let bass = analyzer.get_normalized_energy(FrequencyRange::Bass);
-                            let mid = analyzer.get_normalized_energy(FrequencyRange::Mid);
-                            let high = analyzer.get_normalized_energy(FrequencyRange::High);
-
-                            // Enhanced color mapping:
-                            // - Bass dominant: warm red-yellow spectrum (255,0,0) to (255,200,0)
-                            // - Mid dominant: green-cyan spectrum (0,255,0) to (0,255,200)
-                            // - High dominant: cool blue-white spectrum (0,0,255) to (200,200,255)
-
-                            // Start with black
-                            let mut r = 0;
-                            let mut g = 0;
-                            let mut b = 0;
-
-                            // Apply bass (red-orange-yellow warm colors)
-                            if bass > 0.05 {
-                                // Calculate bass contribution - more bass means more red
-                                r += (255.0 * bass * sensitivity) as u8;
-                                // Yellow tint increases with stronger bass
-                                g += (150.0 * bass * bass * sensitivity) as u8;
-                            }
-
-                            // Apply mid (green-cyan colors)
-                            if mid > 0.05 {
-                                // Main green contribution
-                                g += (255.0 * mid * sensitivity) as u8;
-                                // Some cyan tint for stronger mids
-                                b += (100.0 * mid * mid * sensitivity) as u8;
-                            }
-
-                            // Apply high (blue-white cool colors)
-                            if high > 0.05 {
-                                // Main blue contribution
-                                b += (255.0 * high * sensitivity) as u8;
-                                // White tint (r,g components) increases with stronger highs
-                                r += (180.0 * high * high * sensitivity) as u8;
-                                g += (180.0 * high * high * sensitivity) as u8;
-                            }
-
-                            // Ensure some minimum brightness when there's sound
-                            let overall = analyzer.get_normalized_energy(FrequencyRange::Full);
-                            if overall > 0.05 {
-                                r = r.max(10);
-                                g = g.max(10);
-                                b = b.max(10);
-                            }
-
-                            // Apply to audio color
-                            audio_color.r = r;
-                            audio_color.g = g;
-                            audio_color.b = b;
-
-                            // Adjust brightness based on energy
-                            let energy = overall;
-                            audio_color.brightness = (energy * 100.0 * sensitivity) as u8;
-                            audio_color.brightness = audio_color.brightness.clamp(20, 100);
-
-                            // No specific effect
-                            audio_color.effect = None;
-
-                            // For bass-heavy parts, add warmer tones
-                            if bass > 0.7 && bass > 1.5 * mid && bass > 2.0 * high {
-                                // Very bass heavy - make it more red-amber
-                                audio_color.r = 255;
-                                audio_color.g = (120.0 * bass * sensitivity) as u8;
-                                audio_color.b = 0;
-                            }
-
-                            // For treble-heavy parts, add more white/light blue
-                            if high > 0.7 && high > 1.5 * mid && high > 2.0 * bass {
-                                // Very treble heavy - make it more white/light blue
-                                audio_color.r = (210.0 * high * sensitivity) as u8;
-                                audio_color.g = (220.0 * high * sensitivity) as u8;
-                                audio_color.b = 255;
-                            }
-                        }
-
-                        VisualizationMode::BpmSync => {
-                            // Get current BPM from analyzer
-                            let bpm = analyzer.get_bpm();
-                            let bass = analyzer.get_normalized_energy(FrequencyRange::Bass);
-                            let mid = analyzer.get_normalized_energy(FrequencyRange::Mid);
-                            let high = analyzer.get_normalized_energy(FrequencyRange::High);
-
-                            // Calculate the base color based on frequency balance
-                            // More bass = more red, more highs = more blue, etc.
-                            let r = (bass * 255.0 * sensitivity * 1.2).min(255.0) as u8;
-                            let g = (mid * 255.0 * sensitivity * 1.1).min(255.0) as u8;
-                            let b = (high * 255.0 * sensitivity * 1.2).min(255.0) as u8;
-
-                            // Check if we're on a beat according to BPM timing
-                            let on_beat = analyzer.is_on_beat(current_time);
-
-                            // Different effects based on BPM
-                            if bpm < 70.0 {
-                                // Slow tempo - smooth color transitions
-                                if on_beat && analyzer.is_beat_detected(FrequencyRange::Bass) {
-                                    // On beat with bass - emphasize red
-                                    audio_color.r = 255;
-                                    audio_color.g = (g as f32 * 0.7) as u8;
-                                    audio_color.b = (b as f32 * 0.6) as u8;
-                                    audio_color.effect = Some(EFFECTS.crossfade_red);
-                                } else {
-                                    // Normal color
-                                    audio_color.r = r;
-                                    audio_color.g = g;
-                                    audio_color.b = b;
-                                    audio_color.effect = Some(EFFECTS.crossfade_red_green_blue);
-                                }
-                            } else if bpm < 120.0 {
-                                // Medium tempo - more dynamic changes
-                                if on_beat {
-                                    // On beat pulses
-                                    if analyzer.is_beat_detected(FrequencyRange::Bass) {
-                                        // Bass hit - red pulse
-                                        audio_color.r = 255;
-                                        audio_color.g = 40;
-                                        audio_color.b = 0;
-                                        audio_color.effect = Some(EFFECTS.jump_red_green_blue);
-                                    } else {
-                                        // Regular beat - white pulse
-                                        audio_color.r = 255;
-                                        audio_color.g = 255;
-                                        audio_color.b = 255;
-                                        audio_color.effect = Some(EFFECTS.crossfade_white);
-                                    }
-                                } else {
-                                    // Between beats - regular spectrum color
-                                    audio_color.r = r;
-                                    audio_color.g = g;
-                                    audio_color.b = b;
-                                    audio_color.effect = None;
-                                }
-                            } else {
-                                // Fast tempo - flashy effects
-                                if on_beat && analyzer.is_beat_detected(FrequencyRange::Bass) {
-                                    // On beat with bass - bright flash
-                                    audio_color.r = 255;
-                                    audio_color.g = 255;
-                                    audio_color.b = 255;
-                                    audio_color.effect =
-                                        Some(EFFECTS.jump_red_green_blue_yellow_cyan_magenta_white);
-                                } else if on_beat {
-                                    // Regular beat - color based on spectrum
-                                    audio_color.r = r;
-                                    audio_color.g = g;
-                                    audio_color.b = b;
-                                    audio_color.effect = Some(
-                                        EFFECTS.blink_red_green_blue_yellow_cyan_magenta_white,
-                                    );
-                                } else {
-                                    // Between beats - darker version of spectrum
-                                    audio_color.r = (r as f32 * 0.7) as u8;
-                                    audio_color.g = (g as f32 * 0.7) as u8;
-                                    audio_color.b = (b as f32 * 0.7) as u8;
-                                    audio_color.effect = None;
-                                }
-                            }
-
-                            // Brightness pulses with the beat
-                            let base_brightness = (60.0 * sensitivity).max(20.0) as u8;
-                            let pulse_amplitude = (40.0 * sensitivity) as u8;
-
-                            if on_beat {
-                                // Brighter on beats
-                                audio_color.brightness =
-                                    (base_brightness + pulse_amplitude).min(100);
-                            } else {
-                                // Normal brightness between beats
-                                audio_color.brightness = base_brightness;
-                            }
-
-                            // Display estimated BPM in debug
-                            debug!("Estimated BPM: {:.1}", bpm);
-                        }
-                    }
+                    let inputs = analyzer.color_inputs(current_time);
+                    audio_color = compute_color(vis_mode, &inputs, &config.read(), audio_color);
 
                     // Send the updated color
                     let _ = color_tx.send(audio_color);
@@ -1022,17 +1545,7 @@ impl AudioMonitor {
     /// Get the current visualization configuration
     pub fn get_config(&self) -> AudioVisualization {
         // Clone the configuration while holding the lock
-        let guard = self.config.read();
-        AudioVisualization {
-            range: guard.range,
-            mode: guard.mode,
-            sensitivity: guard.sensitivity,
-            bass_color_trigger: guard.bass_color_trigger,
-            mid_brightness_trigger: guard.mid_brightness_trigger,
-            high_effect_trigger: guard.high_effect_trigger,
-            update_interval_ms: guard.update_interval_ms,
-            active: guard.active,
-        }
+        self.config.read().clone()
     }
 
     /// Update visualization configuration
@@ -1051,11 +1564,11 @@ impl AudioMonitor {
         // Get the latest color from the analyzer
         let audio_color = *self.color_rx.borrow();
 
-        // Get current config for context
-        let config = self.config.read();
+        // Get current mode for context (don't hold the lock across awaits)
+        let mode = self.config.read().mode;
 
         // Create detailed log entry with audio characteristics
-        match config.mode {
+        match mode {
             VisualizationMode::FrequencyColor => {
                 info!(
                     "Audio viz [FrequencyColor] - RGB({}, {}, {}) - Bass: {:.2}, Mid: {:.2}, High: {:.2}, Brightness: {}%",
@@ -1120,6 +1633,7 @@ impl AudioMonitor {
             }
             VisualizationMode::BpmSync => {
                 let bpm = self.get_estimated_bpm();
+                let confidence = self.bpm_confidence();
                 let beat_info = if audio_color.effect.is_some() {
                     "On beat"
                 } else {
@@ -1127,20 +1641,35 @@ impl AudioMonitor {
                 };
 
                 info!(
-                    "Audio viz [BpmSync] - RGB({}, {}, {}) - BPM: {:.1}, {}, Effect: {:?}, Brightness: {}%",
+                    "Audio viz [BpmSync] - RGB({}, {}, {}) - BPM: {:.1} (confidence {:.2}), {}, Effect: {:?}, Brightness: {}%",
                     audio_color.r,
                     audio_color.g,
                     audio_color.b,
                     bpm,
+                    confidence,
                     beat_info,
                     audio_color.effect.map(|e| format!("{}", e)),
                     audio_color.brightness
                 );
             }
+            VisualizationMode::PitchHue => {
+                const PITCH_NAMES: [&str; 12] = [
+                    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+                ];
+                let pitch = self
+                    .get_analysis()
+                    .pitch_class
+                    .map(|class| PITCH_NAMES[class as usize]);
+
+                info!(
+                    "Audio viz [PitchHue] - RGB({}, {}, {}) - Pitch: {:?}",
+                    audio_color.r, audio_color.g, audio_color.b, pitch
+                );
+            }
         };
 
         // Ensure device is powered on
-        if !device.is_on {
+        if self.config.read().power_on && !device.is_on() {
             device.power_on().await?;
         }
 
@@ -1155,8 +1684,11 @@ impl AudioMonitor {
                 .await?;
         }
 
-        // Apply brightness
-        device.set_brightness(audio_color.brightness).await?;
+        // Apply brightness, keeping the strip from cutting out on quiet passages
+        let min_brightness = self.config.read().min_brightness;
+        device
+            .set_brightness(audio_color.brightness.max(min_brightness))
+            .await?;
 
         Ok(())
     }
@@ -1170,12 +1702,13 @@ impl AudioMonitor {
         let energy_high = self.get_energy(FrequencyRange::High);
         let energy_full = self.get_energy(FrequencyRange::Full);
         let bpm = self.get_estimated_bpm();
+        let analysis = self.get_analysis();
 
         // Get current config
         let config = self.config.read();
 
         debug!(
-            "Audio Analysis: Mode={:?}, Active={}, Sensitivity={:.2}, Bass={:.3}, Mid={:.3}, High={:.3}, Overall={:.3}, BPM={:.1}",
+            "Audio Analysis: Mode={:?}, Active={}, Sensitivity={:.2}, Bass={:.3}, Mid={:.3}, High={:.3}, Overall={:.3}, BPM={:.1}, BeatConfidence={:.2}, BeatCounts={:?}, SinceLastBeat={:?}",
             config.mode,
             config.active,
             config.sensitivity,
@@ -1183,7 +1716,10 @@ impl AudioMonitor {
             energy_mid,
             energy_high,
             energy_full,
-            bpm
+            bpm,
+            analysis.beat_confidence,
+            analysis.beat_counts,
+            analysis.seconds_since_last_beat
         );
 
         Ok(())
@@ -1198,7 +1734,7 @@ impl AudioMonitor {
         self.set_active(true);
 
         // Ensure device is on
-        if !device.is_on {
+        if self.config.read().power_on && !device.is_on() {
             device.power_on().await?;
         }
 
@@ -1242,22 +1778,80 @@ impl AudioMonitor {
         }
     }
 
+    /// Stream of computed colors as `(color, brightness, effect code)`
+    ///
+    /// For routing the visualization to several devices or to other outputs
+    /// instead of [`AudioMonitor::start_continuous_monitoring`]. Colors are
+    /// only computed while the monitor is active (see [`AudioMonitor::set_active`]),
+    /// at most once per `update_interval_ms` (50ms by default). Only the
+    /// latest color is kept, so a slow consumer skips intermediate colors
+    /// rather than falling behind. The stream ends when the monitor stops.
+    pub fn color_stream(&self) -> impl Stream<Item = (Color, u8, Option<u8>)> {
+        futures::stream::unfold(self.color_rx.clone(), |mut rx| async move {
+            rx.changed().await.ok()?;
+            let color = *rx.borrow_and_update();
+            let item = (
+                (color.r, color.g, color.b),
+                color.brightness,
+                color.effect.map(Effect::code),
+            );
+            Some((item, rx))
+        })
+    }
+
+    /// Get the latest analysis snapshot (band energies, peaks and tempo)
+    pub fn get_analysis(&self) -> AudioAnalysis {
+        *self.analysis_rx.borrow()
+    }
+
+    /// Get the held peak for a frequency range (0.0-1.0)
+    ///
+    /// A peak lingers for `peak_hold_ms`, then falls at `peak_decay_rate`,
+    /// which shows how close the input comes to clipping when tuning
+    /// `sensitivity`. [`FrequencyRange::Full`] gives the highest band peak.
+    pub fn get_peak(&self, range: FrequencyRange) -> f32 {
+        let peak = self.analysis_rx.borrow().peak;
+        match range {
+            FrequencyRange::Bass => peak[0],
+            FrequencyRange::Mid => peak[1],
+            FrequencyRange::High => peak[2],
+            FrequencyRange::Full => peak.into_iter().fold(0.0, f32::max),
+        }
+    }
+
+    /// Get a smoothed overall loudness (0-100) for driving a level meter
+    ///
+    /// Derived from the analyzed band energies rather than the output color,
+    /// so it is the same in every visualization mode.
+    pub fn vu_level(&self) -> u8 {
+        (self.analysis_rx.borrow().vu * 100.0)
+            .round()
+            .clamp(0.0, 100.0) as u8
+    }
+
     /// Get the estimated BPM if available (requires BpmSync mode)
     /// Returns 0.0 if BPM is not being calculated
+    ///
+    /// Until enough regular beats are heard this is the 120 BPM starting
+    /// guess; check [`AudioMonitor::bpm_confidence`] before relying on it.
     pub fn get_estimated_bpm(&self) -> f32 {
-        // This is a simple stub - the actual BPM is calculated internally
-        // and we don't have a way to access it directly from the public API
-        // The BPM value is used in the BpmSync mode internally
         let config = self.get_config();
         if config.mode == VisualizationMode::BpmSync {
-            // When in BPM mode, we can assume BPM is being calculated
-            // The specific value is used internally but not exposed
-            // We'll use a placeholder of 120 BPM here
-            120.0
+            self.analysis_rx.borrow().bpm
         } else {
             0.0
         }
     }
+
+    /// How far the tempo estimate can be trusted (0.0-1.0)
+    ///
+    /// Derived from how regular the bass beats of the last 5 seconds are, and
+    /// 0.0 with fewer than four of them, e.g. during an intro or a quiet
+    /// section. BpmSync only follows the tempo from
+    /// [`BPM_CONFIDENCE_THRESHOLD`] up.
+    pub fn bpm_confidence(&self) -> f32 {
+        self.analysis_rx.borrow().beat_confidence
+    }
 }
 
 impl Drop for AudioMonitor {
@@ -1266,3 +1860,303 @@ impl Drop for AudioMonitor {
         self.stop_flag.store(true, Ordering::Relaxed);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Full sensitivity, so expected channel values are plain `energy * 255`
+    fn config() -> AudioVisualization {
+        AudioVisualization {
+            sensitivity: 1.0,
+            ..Default::default()
+        }
+    }
+
+    fn color(mode: VisualizationMode, inputs: ColorInputs) -> AudioColor {
+        compute_color(mode, &inputs, &config(), AudioColor::default())
+    }
+
+    #[test]
+    fn frequency_color_maps_bands_to_channels() {
+        let previous = AudioColor {
+            brightness: 42,
+            effect: Some(Effect::JumpAll),
+            ..Default::default()
+        };
+        let inputs = ColorInputs {
+            energy: [1.0, 0.5, 0.0],
+            overall: 0.5,
+            ..Default::default()
+        };
+        let color = compute_color(
+            VisualizationMode::FrequencyColor,
+            &inputs,
+            &config(),
+            previous,
+        );
+        // Silent bands are lifted to 10 while there is sound
+        assert_eq!((color.r, color.g, color.b), (255, 127, 10));
+        assert_eq!(color.brightness, 42);
+        assert_eq!(color.effect, None);
+    }
+
+    #[test]
+    fn energy_brightness_shows_the_dominant_band() {
+        let color = color(
+            VisualizationMode::EnergyBrightness,
+            ColorInputs {
+                energy: [0.2, 0.8, 0.1],
+                overall: 0.4,
+                ..Default::default()
+            },
+        );
+        assert_eq!((color.r, color.g, color.b), (0, 255, 0));
+        assert_eq!(color.brightness, 40);
+
+        // Silence is dim white
+        let silent = self::color(VisualizationMode::EnergyBrightness, ColorInputs::default());
+        assert_eq!((silent.r, silent.g, silent.b), (255, 255, 255));
+        assert_eq!(silent.brightness, 5);
+    }
+
+    #[test]
+    fn beat_effects_crossfade_on_a_bass_beat() {
+        let color = color(
+            VisualizationMode::BeatEffects,
+            ColorInputs {
+                beats: [true, true, false],
+                overall: 0.6,
+                ..Default::default()
+            },
+        );
+        assert_eq!((color.r, color.g, color.b), (255, 0, 0));
+        assert_eq!(color.effect, Some(Effect::CrossfadeRed));
+        assert_eq!(color.brightness, 60);
+
+        // A disabled trigger ignores its band's beats
+        let inputs = ColorInputs {
+            beats: [false, false, true],
+            ..Default::default()
+        };
+        let config = AudioVisualization {
+            high_effect_trigger: false,
+            ..config()
+        };
+        let color = compute_color(
+            VisualizationMode::BeatEffects,
+            &inputs,
+            &config,
+            AudioColor::default(),
+        );
+        assert_eq!((color.r, color.g, color.b), (255, 255, 255));
+        assert_eq!(color.effect, None);
+        assert_eq!(color.brightness, 20);
+    }
+
+    #[test]
+    fn spectral_flow_pulses_when_quiet_and_jumps_on_strong_bass() {
+        let quiet = color(VisualizationMode::SpectralFlow, ColorInputs::default());
+        assert_eq!(quiet.effect, Some(Effect::CrossfadeRgb));
+        assert_eq!(quiet.brightness, 20);
+
+        let strong = color(
+            VisualizationMode::SpectralFlow,
+            ColorInputs {
+                energy: [0.9, 0.2, 0.1],
+                beats: [true, false, false],
+                ..Default::default()
+            },
+        );
+        assert_eq!(strong.effect, Some(Effect::JumpAll));
+    }
+
+    #[test]
+    fn enhanced_frequency_color_warms_bass_and_cools_treble() {
+        let bass = color(
+            VisualizationMode::EnhancedFrequencyColor,
+            ColorInputs {
+                energy: [0.9, 0.1, 0.1],
+                overall: 0.5,
+                ..Default::default()
+            },
+        );
+        assert_eq!((bass.r, bass.g, bass.b), (255, 108, 0));
+        assert_eq!(bass.brightness, 50);
+        assert_eq!(bass.effect, None);
+
+        let treble = color(
+            VisualizationMode::EnhancedFrequencyColor,
+            ColorInputs {
+                energy: [0.1, 0.1, 0.9],
+                overall: 0.5,
+                ..Default::default()
+            },
+        );
+        assert_eq!(treble.b, 255);
+        assert!(treble.r > 150 && treble.g > 150);
+    }
+
+    #[test]
+    fn bpm_sync_follows_the_tempo_only_when_confident() {
+        let beat = ColorInputs {
+            energy: [0.5, 0.0, 0.0],
+            overall: 0.3,
+            beats: [true, false, false],
+            bpm: 100.0,
+            on_beat: true,
+            ..Default::default()
+        };
+
+        // An unreliable tempo falls back to the spectrum color
+        let unsure = color(VisualizationMode::BpmSync, beat);
+        assert_eq!((unsure.r, unsure.g, unsure.b), (153, 0, 0));
+        assert_eq!(unsure.effect, None);
+        assert_eq!(unsure.brightness, 30);
+
+        let confident = ColorInputs {
+            beat_confidence: 1.0,
+            ..beat
+        };
+        let pulse = color(VisualizationMode::BpmSync, confident);
+        assert_eq!((pulse.r, pulse.g, pulse.b), (255, 40, 0));
+        assert_eq!(pulse.effect, Some(Effect::JumpRgb));
+        assert_eq!(pulse.brightness, 100);
+
+        let fast = color(
+            VisualizationMode::BpmSync,
+            ColorInputs {
+                bpm: 140.0,
+                ..confident
+            },
+        );
+        assert_eq!((fast.r, fast.g, fast.b), (255, 255, 255));
+        assert_eq!(fast.effect, Some(Effect::JumpAll));
+
+        let between = color(
+            VisualizationMode::BpmSync,
+            ColorInputs {
+                on_beat: false,
+                ..confident
+            },
+        );
+        assert_eq!(between.effect, None);
+        assert_eq!(between.brightness, 60);
+    }
+
+    #[test]
+    fn pitch_hue_picks_a_hue_per_pitch_class() {
+        // E is pitch class 4, 120 degrees: green
+        let e = color(
+            VisualizationMode::PitchHue,
+            ColorInputs {
+                overall: 1.0,
+                pitch_class: Some(4),
+                ..Default::default()
+            },
+        );
+        assert_eq!((e.r, e.g, e.b), (0, 255, 0));
+        assert_eq!(e.brightness, 100);
+        assert_eq!(e.effect, None);
+
+        // Without a clear pitch the previous color holds
+        let held = compute_color(
+            VisualizationMode::PitchHue,
+            &ColorInputs::default(),
+            &config(),
+            e,
+        );
+        assert_eq!((held.r, held.g, held.b), (0, 255, 0));
+    }
+
+    /// Dominant pitch class the analyzer finds for a pure tone
+    fn pitch_class_of(frequency: f32) -> Option<usize> {
+        let sample_rate = 44100;
+        let mut analyzer = AudioAnalyzer::new(sample_rate);
+        for i in 0..analyzer.sample_size {
+            let t = i as f32 / sample_rate as f32;
+            analyzer.add_sample((frequency * std::f32::consts::TAU * t).sin());
+        }
+        analyzer.analyze();
+        analyzer.pitch_class
+    }
+
+    #[test]
+    fn pure_tones_give_their_pitch_class() {
+        // A4
+        assert_eq!(pitch_class_of(440.0), Some(9));
+        // E5
+        assert_eq!(pitch_class_of(659.26), Some(4));
+    }
+
+    #[test]
+    fn frequency_limit_stops_at_nyquist() {
+        let bounds = |sample_rate| match frequency_limit(sample_rate) {
+            FrequencyLimit::Range(min, max) => (min, max),
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(bounds(44100), (20.0, 20000.0));
+        assert_eq!(bounds(40000), (20.0, 20000.0));
+        assert_eq!(bounds(MIN_SAMPLE_RATE), (20.0, 4000.0));
+
+        // The analyzer accepts the clamped range where the full one is refused
+        let samples: Vec<f32> = (0..2048)
+            .map(|i| (i as f32 * 3000.0 * std::f32::consts::TAU / 8000.0).sin())
+            .collect();
+        assert!(samples_fft_to_spectrum(
+            &samples,
+            8000,
+            FrequencyLimit::Range(MIN_ANALYSIS_FREQUENCY, MAX_ANALYSIS_FREQUENCY),
+            None,
+        )
+        .is_err());
+        let spectrum =
+            samples_fft_to_spectrum(&samples, 8000, frequency_limit(8000), None).unwrap();
+        assert!(spectrum.max_fr().val() <= 4000.0);
+        assert!((spectrum.max().0.val() - 3000.0).abs() < 10.0);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trips_modes_and_ranges() {
+        let modes = [
+            (VisualizationMode::FrequencyColor, "frequency_color"),
+            (VisualizationMode::EnergyBrightness, "energy_brightness"),
+            (VisualizationMode::BeatEffects, "beat_effects"),
+            (VisualizationMode::SpectralFlow, "spectral_flow"),
+            (
+                VisualizationMode::EnhancedFrequencyColor,
+                "enhanced_frequency_color",
+            ),
+            (VisualizationMode::BpmSync, "bpm_sync"),
+            (VisualizationMode::PitchHue, "pitch_hue"),
+        ];
+        for (mode, name) in modes {
+            let json = serde_json::to_string(&mode).unwrap();
+            assert_eq!(json, format!("\"{}\"", name));
+            assert_eq!(
+                serde_json::from_str::<VisualizationMode>(&json).unwrap(),
+                mode
+            );
+        }
+        assert_eq!(
+            serde_json::from_str::<VisualizationMode>("\"chroma\"").unwrap(),
+            VisualizationMode::PitchHue
+        );
+
+        let ranges = [
+            (FrequencyRange::Bass, "bass"),
+            (FrequencyRange::Mid, "mid"),
+            (FrequencyRange::High, "high"),
+            (FrequencyRange::Full, "full"),
+        ];
+        for (range, name) in ranges {
+            let json = serde_json::to_string(&range).unwrap();
+            assert_eq!(json, format!("\"{}\"", name));
+            assert_eq!(
+                serde_json::from_str::<FrequencyRange>(&json).unwrap(),
+                range
+            );
+        }
+    }
+}
@@ -1,61 +1,97 @@
 use clap::{Parser, Subcommand, ValueEnum};
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{Result, WrapErr};
+use elk_led_controller::console::{parse_duration, parse_script, parse_script_lines};
 use elk_led_controller::*;
+use serde_json::Value;
+use std::io::Write;
+use tokio::io::{AsyncBufReadExt, BufReader, Lines};
 use tokio::time::Duration;
 use tracing::{debug, error, info, instrument, trace, warn};
 
+#[path = "elkc/cache.rs"]
+mod cache;
+#[path = "elkc/config.rs"]
+mod config;
+#[path = "elkc/dry_run.rs"]
+mod dry_run;
+#[path = "elkc/spinner.rs"]
+mod spinner;
+
+/// Environment variable with the default device address
+const ADDRESS_VAR: &str = "ELK_DEVICE_ADDRESS";
+
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
+#[command(subcommand_required = true, arg_required_else_help = true)]
 struct Cli {
+    /// Device MAC address or ID to connect to (repeat to control a group of devices;
+    /// default: $ELK_DEVICE_ADDRESS)
+    #[arg(short, long = "address", global = true)]
+    addresses: Vec<String>,
+
+    /// Advertised name of the device to connect to (ignored when --address is given)
+    #[arg(short, long, global = true)]
+    name: Option<String>,
+
+    /// Run the command on every compatible device found within --timeout
+    /// (give it before the command, e.g. `--all off`)
+    #[arg(long = "all", conflicts_with_all = ["addresses", "name"])]
+    all_devices: bool,
+
+    /// How long to scan for the device, in seconds (default: 10)
+    #[arg(long, global = true)]
+    timeout: Option<u64>,
+
+    /// How many times to scan again when no device is found (default: 0)
+    #[arg(long, global = true)]
+    connect_retries: Option<u32>,
+
+    /// Never set the brightness above this level (0-100)
+    #[arg(long, global = true, value_parser = clap::value_parser!(u8).range(0..=100))]
+    max_brightness: Option<u8>,
+
+    /// Brightness gamma, e.g. 2.2 so 50% looks half as bright (default: linear)
+    #[arg(long, global = true, value_parser = parse_gamma)]
+    gamma: Option<f32>,
+
+    /// Config file with defaults for these flags
+    /// (default: ~/.config/elk-led-controller/config.toml, if it exists)
+    #[arg(long, global = true)]
+    config: Option<std::path::PathBuf>,
+
+    /// Scene file for the scene commands
+    /// (default: ~/.config/elk-led-controller/scenes.txt)
+    #[arg(long, global = true)]
+    scenes: Option<std::path::PathBuf>,
+
+    /// Print one JSON object with the result to stdout; logs go to stderr
+    #[arg(long, global = true, default_value_t = false)]
+    json: bool,
+
+    /// Print the frames a command would send instead of connecting
+    #[arg(long, global = true, default_value_t = false)]
+    dry_run: bool,
+
+    /// Leave the device off: don't power it on before commands that change its
+    /// light, so e.g. a color set now shows at the next power-on
+    #[arg(long, global = true, default_value_t = false)]
+    no_power_on: bool,
+
+    /// Don't show the connection's progress on the terminal
+    #[arg(short, long, global = true, default_value_t = false)]
+    quiet: bool,
+
+    /// Keep the connection open and re-apply the resulting look every SECS
+    /// seconds until Ctrl+C, for strips that forget it on a brief disconnect
+    #[arg(long, global = true, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    repeat: Option<u64>,
+
     #[command(subcommand)]
     command: Option<Commands>,
 }
 
-#[derive(Clone, ValueEnum, Debug)]
-enum EffectType {
-    /// Crossfade through red, green, blue, yellow, cyan, magenta, white
-    Rainbow,
-    /// Jump between red, green, blue
-    Jump,
-    /// Jump through red, green, blue, yellow, cyan, magenta, white
-    JumpAll,
-    /// Crossfade red
-    CrossfadeRed,
-    /// Crossfade green
-    CrossfadeGreen,
-    /// Crossfade blue
-    CrossfadeBlue,
-    /// Crossfade through red, green, blue
-    CrossfadeRgb,
-    /// Blink through red, green, blue, yellow, cyan, magenta, white
-    Blink,
-    /// Blink red
-    BlinkRed,
-    /// Blink green
-    BlinkGreen,
-    /// Blink blue
-    BlinkBlue,
-}
-
-impl std::fmt::Display for EffectType {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            EffectType::Rainbow => write!(f, "rainbow"),
-            EffectType::Jump => write!(f, "jump"),
-            EffectType::JumpAll => write!(f, "jump_all"),
-            EffectType::CrossfadeRed => write!(f, "crossfade_red"),
-            EffectType::CrossfadeGreen => write!(f, "crossfade_green"),
-            EffectType::CrossfadeBlue => write!(f, "crossfade_blue"),
-            EffectType::CrossfadeRgb => write!(f, "crossfade_rgb"),
-            EffectType::Blink => write!(f, "blink"),
-            EffectType::BlinkRed => write!(f, "blink_red"),
-            EffectType::BlinkGreen => write!(f, "blink_green"),
-            EffectType::BlinkBlue => write!(f, "blink_blue"),
-        }
-    }
-}
-
-#[derive(Clone, ValueEnum, Debug)]
+#[cfg(feature = "audio")]
+#[derive(Clone, Copy, ValueEnum, Debug)]
 enum AudioModeType {
     /// Map frequencies to colors (bass=red, mid=green, high=blue)
     FrequencyColor,
@@ -69,8 +105,12 @@ enum AudioModeType {
     EnhancedFrequencyColor,
     /// BPM synchronized effects
     BpmSync,
+    /// Dominant musical pitch picks the hue (chord changes change the color)
+    #[value(alias = "chroma")]
+    PitchHue,
 }
 
+#[cfg(feature = "audio")]
 impl From<AudioModeType> for VisualizationMode {
     fn from(mode: AudioModeType) -> Self {
         match mode {
@@ -80,11 +120,13 @@ impl From<AudioModeType> for VisualizationMode {
             AudioModeType::SpectralFlow => VisualizationMode::SpectralFlow,
             AudioModeType::EnhancedFrequencyColor => VisualizationMode::EnhancedFrequencyColor,
             AudioModeType::BpmSync => VisualizationMode::BpmSync,
+            AudioModeType::PitchHue => VisualizationMode::PitchHue,
         }
     }
 }
 
-#[derive(Clone, ValueEnum, Debug)]
+#[cfg(feature = "audio")]
+#[derive(Clone, Copy, ValueEnum, Debug)]
 enum AudioRangeType {
     /// Bass frequencies (20-250 Hz)
     Bass,
@@ -96,6 +138,7 @@ enum AudioRangeType {
     Full,
 }
 
+#[cfg(feature = "audio")]
 impl From<AudioRangeType> for FrequencyRange {
     fn from(range: AudioRangeType) -> Self {
         match range {
@@ -107,6 +150,56 @@ impl From<AudioRangeType> for FrequencyRange {
     }
 }
 
+#[derive(Clone, ValueEnum, Debug)]
+enum EasingType {
+    /// Constant rate of change
+    Linear,
+    /// Slow down near each color
+    Sine,
+}
+
+#[derive(Clone, Copy, ValueEnum, Debug)]
+enum CycleExit {
+    /// Go back to the first color
+    First,
+    /// Power the strip off
+    Off,
+}
+
+impl From<EasingType> for Easing {
+    fn from(easing: EasingType) -> Self {
+        match easing {
+            EasingType::Linear => Easing::Linear,
+            EasingType::Sine => Easing::Sine,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum, Debug, PartialEq, Eq)]
+enum DemoStep {
+    /// Static red, green and blue
+    Color,
+    /// Half and full brightness
+    Brightness,
+    /// Warm and cool white
+    ColorTemp,
+    /// Rainbow, jump and blink effects
+    Effects,
+    /// Slow and fast effect speed
+    Speed,
+}
+
+impl DemoStep {
+    /// Every step, in the order of the full demo
+    const ALL: [DemoStep; 5] = [
+        DemoStep::Color,
+        DemoStep::Brightness,
+        DemoStep::ColorTemp,
+        DemoStep::Effects,
+        DemoStep::Speed,
+    ];
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// Demonstration of LED features
@@ -114,6 +207,53 @@ enum Commands {
         /// Duration of each demo step in seconds
         #[arg(short, long, default_value_t = 5)]
         duration: u64,
+        /// Comma-separated step groups to run (default: all)
+        #[arg(long, alias = "steps", value_enum, value_delimiter = ',')]
+        only: Vec<DemoStep>,
+        /// Comma-separated step groups to leave out
+        #[arg(long, value_enum, value_delimiter = ',')]
+        skip: Vec<DemoStep>,
+        /// Repeat the demo until interrupted
+        #[arg(long = "loop", default_value_t = false)]
+        repeat: bool,
+    },
+    /// List available effects (with --address or --name, also whether that device supports them)
+    Effects,
+    /// Connect once and read commands from stdin until `quit` (try `help`)
+    #[command(alias = "interactive")]
+    Repl,
+    /// Run several commands over one connection, e.g. "on; color ff8800; sleep 2s; off"
+    Run {
+        /// Commands separated by ';' or new lines, as accepted by `repl`
+        script: String,
+        /// Go on with the next command after one fails
+        #[arg(long)]
+        keep_going: bool,
+    },
+    /// Run the commands of a file over one connection, one per line as accepted by `repl`
+    RunScript {
+        /// Script file; empty lines and lines starting with '#' are skipped
+        file: std::path::PathBuf,
+        /// Go on with the next command after one fails
+        #[arg(long)]
+        keep_going: bool,
+    },
+    /// Show the device's type, address and last known state
+    Status,
+    /// Print the frames the device pushes, e.g. after the IR remote or phone app changed it
+    Watch {
+        /// Stop after this long (e.g. 10m) instead of running until Ctrl+C
+        #[arg(short, long, value_parser = parse_duration)]
+        duration: Option<Duration>,
+    },
+    /// List nearby devices without connecting
+    Scan {
+        /// How long to scan, in seconds
+        #[arg(short, long, default_value_t = 5)]
+        duration: u64,
+        /// Also list peripherals that are not compatible
+        #[arg(long, default_value_t = false)]
+        all: bool,
     },
     /// Turn LED strip on
     On,
@@ -129,9 +269,15 @@ enum Commands {
     White,
     /// Set brightness
     Brightness {
-        /// Brightness level (0-100)
-        #[arg(short, long, default_value_t = 100)]
-        level: u8,
+        /// Brightness level (0-100), or a change such as +10 or -10
+        #[arg(short, long, default_value = "100", allow_hyphen_values = true, value_parser = parse_brightness)]
+        level: BrightnessLevel,
+        /// Raise the brightness by this much
+        #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100), conflicts_with_all = ["level", "down"])]
+        up: Option<u8>,
+        /// Lower the brightness by this much
+        #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100), conflicts_with_all = ["level", "up"])]
+        down: Option<u8>,
     },
     /// Set color temperature
     ColorTemp {
@@ -139,8 +285,11 @@ enum Commands {
         #[arg(short, long, default_value_t = 4000)]
         kelvin: u32,
     },
-    /// Set custom RGB color
+    /// Set a custom color, as hex or a name, RGB channels or HSV
     Color {
+        /// Hex color (e.g. '#ff69b4', ff69b4 or '#f0c') or a name such as orange
+        #[arg(value_parser = parse_color, conflicts_with_all = ["red", "green", "blue"])]
+        hex: Option<Color>,
         /// Red value (0-255)
         #[arg(short, long, default_value_t = 255)]
         red: u8,
@@ -150,212 +299,1054 @@ enum Commands {
         /// Blue value (0-255)
         #[arg(short, long, default_value_t = 255)]
         blue: u8,
+        /// Hue in degrees (0-360), instead of hex or RGB
+        #[arg(
+            long,
+            value_parser = clap::value_parser!(u16).range(0..=360),
+            conflicts_with_all = ["hex", "red", "green", "blue"]
+        )]
+        hue: Option<u16>,
+        /// Saturation with --hue (0-100)
+        #[arg(
+            long,
+            value_parser = clap::value_parser!(u8).range(0..=100),
+            default_value_t = 100,
+            requires = "hue"
+        )]
+        sat: u8,
+        /// Value (brightness of the color) with --hue (0-100)
+        #[arg(
+            long,
+            value_parser = clap::value_parser!(u8).range(0..=100),
+            default_value_t = 100,
+            requires = "hue"
+        )]
+        val: u8,
     },
     /// Set effect
     Effect {
-        /// Effect type (available options shown in description)
-        #[arg(short, long, value_enum, default_value_t = EffectType::Rainbow)]
-        effect_type: EffectType,
+        /// Effect name (e.g. rainbow, jump, blink, crossfade_red, blink_white) or hex code
+        #[arg(short, long, default_value = "rainbow")]
+        effect_type: Effect,
         /// Effect speed (0-100)
         #[arg(short, long, default_value_t = 50)]
         speed: u8,
+        /// Cycle through the device's effects in random order until interrupted
+        #[arg(long, default_value_t = false)]
+        shuffle: bool,
+        /// Seconds each effect runs when shuffling
+        #[arg(long, default_value_t = 30)]
+        dwell: u64,
+    },
+    /// Change the speed of the running effect without restarting it
+    Speed {
+        /// Effect speed (0-100)
+        #[arg(value_parser = clap::value_parser!(u8).range(0..=100))]
+        speed: u8,
+    },
+    /// Crossfade back and forth between two colors until interrupted
+    Crossfade {
+        /// First color as hex (e.g. '#00ffcc')
+        #[arg(long, value_parser = parse_color)]
+        from: Color,
+        /// Second color as hex (e.g. '#ff00aa')
+        #[arg(long, value_parser = parse_color)]
+        to: Color,
+        /// Time for a full cycle there and back (e.g. 6s, 1500ms, 2m)
+        #[arg(short, long, alias = "period-secs", default_value = "6s", value_parser = parse_duration)]
+        period: Duration,
+        /// Shape of the transition
+        #[arg(short, long, value_enum, default_value_t = EasingType::Sine)]
+        easing: EasingType,
+        /// Also dim the strip halfway between the colors
+        #[arg(short, long, default_value_t = false)]
+        breathe: bool,
+        /// Stop after this long (e.g. 10m) instead of running until Ctrl+C
+        #[arg(short, long, value_parser = parse_duration)]
+        duration: Option<Duration>,
+    },
+    /// Fade the color or the brightness to a new value, then stop (Ctrl+C stops early)
+    #[command(group(
+        clap::ArgGroup::new("target")
+            .required(true)
+            .args(["to_color", "to_brightness"])
+    ))]
+    Fade {
+        /// Color to fade to, as hex or a name (e.g. 112233, orange)
+        #[arg(long, value_parser = parse_color)]
+        to_color: Option<Color>,
+        /// Brightness to fade to (0-100)
+        #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+        to_brightness: Option<u8>,
+        /// How long the fade takes (e.g. 10s, 1500ms, 2m)
+        #[arg(short, long, value_parser = parse_duration)]
+        duration: Duration,
+        /// Number of writes to spread over the fade (default: one per command delay)
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        steps: Option<u32>,
+        /// Allow --steps that write faster than the device's command delay
+        #[arg(long)]
+        force: bool,
+    },
+    /// Loop through a list of colors until interrupted
+    Cycle {
+        /// Colors as hex or names, separated by commas (e.g. ff0000,green,#00f)
+        #[arg(short, long, required = true, value_delimiter = ',', value_parser = parse_color)]
+        colors: Vec<Color>,
+        /// How long each color is shown, including the fade (e.g. 10s, 1m)
+        #[arg(short, long, default_value = "10s", value_parser = parse_duration)]
+        interval: Duration,
+        /// Fade into each color over this long instead of switching at once (e.g. 2s)
+        #[arg(short, long, value_parser = parse_duration)]
+        fade: Option<Duration>,
+        /// Stop after going through the list this many times
+        #[arg(long)]
+        count: Option<u32>,
+        /// What to leave the strip at when stopped with Ctrl+C
+        #[arg(long, value_enum, default_value_t = CycleExit::First)]
+        on_exit: CycleExit,
+    },
+    /// Fade through random colors until interrupted
+    Random {
+        /// Time to fade from one color to the next (e.g. 8s, 1500ms)
+        #[arg(short, long, alias = "transition-secs", default_value = "8s", value_parser = parse_duration)]
+        transition: Duration,
+        /// Stop after this long (e.g. 1h) instead of running until Ctrl+C
+        #[arg(short, long, value_parser = parse_duration)]
+        duration: Option<Duration>,
+        /// Seed for the colors; the same seed gives the same sequence
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+    /// Flicker like a candle or fire (Ctrl+C to stop early)
+    Fire {
+        /// How long to flicker, in seconds
+        #[arg(short, long, default_value_t = 300)]
+        seconds: u64,
+        /// Color temperature of the flame in Kelvin (1000-4000)
+        #[arg(short, long, default_value_t = 1900, value_parser = clap::value_parser!(u32).range(1000..=4000))]
+        kelvin: u32,
+    },
+    /// Send a raw frame, for exploring the protocol; unknown frames can
+    /// confuse the device until it is power cycled
+    Raw {
+        /// Frame as hex, e.g. 7e00050300ff0000ef, '7e 00 05 03 ...' or 0x7e,0x00,...
+        #[arg(required_unless_present = "id", conflicts_with = "id")]
+        frame: Option<String>,
+        /// Allow frames that aren't 9 bytes long
+        #[arg(long, default_value_t = false)]
+        unchecked: bool,
+        /// Build the frame from a command ID instead, like `generic_command`
+        #[arg(long, value_parser = parse_code)]
+        id: Option<u8>,
+        /// Sub command ID for --id
+        #[arg(long, default_value = "0", value_parser = parse_code, requires = "id")]
+        sub_id: u8,
+        /// Up to three arguments for --id, separated by commas (missing ones are 0)
+        #[arg(long, value_delimiter = ',', value_parser = parse_code, requires = "id")]
+        args: Vec<u8>,
+    },
+    /// Step through effect codes and note what each one does
+    ProbeEffects {
+        /// First effect code to try (decimal or 0x-prefixed hex)
+        #[arg(long, default_value = "0x80", value_parser = parse_code)]
+        start: u8,
+        /// Last effect code to try (decimal or 0x-prefixed hex)
+        #[arg(long, default_value = "0xff", value_parser = parse_code)]
+        end: u8,
+        /// How long each code runs before asking for a description, in milliseconds
+        #[arg(long, default_value_t = 3000)]
+        dwell_ms: u64,
+        /// JSON file the notes are written to
+        #[arg(short, long, default_value = "effect-probe.json")]
+        output: std::path::PathBuf,
     },
     /// Schedule to turn on
     ScheduleOn {
         /// Hour (0-23)
-        #[arg(long, default_value_t = 8)]
+        #[arg(long, value_parser = clap::value_parser!(u8).range(0..=23), default_value_t = 8)]
         hour: u8,
         /// Minute (0-59)
-        #[arg(short, long, default_value_t = 30)]
+        #[arg(short, long, value_parser = clap::value_parser!(u8).range(0..=59), default_value_t = 30)]
         minute: u8,
-        /// Days (mon,tue,wed,thu,fri,sat,sun,all,weekdays,weekend)
+        /// Time instead of --hour/--minute, e.g. 8:30, 07:30 or 8:30pm
+        #[arg(long, value_parser = parse_time_of_day, conflicts_with_all = ["hour", "minute"])]
+        at: Option<(u8, u8)>,
+        /// Days, comma-separated (mon,tue,...,sun, ranges like mon-fri, all, weekdays, weekend)
         #[arg(short, long, default_value = "weekdays")]
-        days: String,
+        days: Days,
     },
     /// Schedule to turn off
     ScheduleOff {
         /// Hour (0-23)
-        #[arg(long, default_value_t = 23)]
+        #[arg(long, value_parser = clap::value_parser!(u8).range(0..=23), default_value_t = 23)]
         hour: u8,
         /// Minute (0-59)
-        #[arg(short, long, default_value_t = 45)]
+        #[arg(short, long, value_parser = clap::value_parser!(u8).range(0..=59), default_value_t = 45)]
         minute: u8,
-        /// Days (mon,tue,wed,thu,fri,sat,sun,all,weekdays,weekend)
+        /// Time instead of --hour/--minute, e.g. 23:45 or 11:45pm
+        #[arg(long, value_parser = parse_time_of_day, conflicts_with_all = ["hour", "minute"])]
+        at: Option<(u8, u8)>,
+        /// Days, comma-separated (mon,tue,...,sun, ranges like mon-fri, all, weekdays, weekend)
         #[arg(short, long, default_value = "weekdays")]
-        days: String,
+        days: Days,
+    },
+    /// Schedule to turn on and off, programming both schedules at once
+    Schedule {
+        /// Days, comma-separated (mon,tue,...,sun, ranges like mon-fri, all, weekdays, weekend)
+        #[arg(short, long, default_value = "weekdays")]
+        days: Days,
+        /// Time to turn on, e.g. 07:30 or 7:30am
+        #[arg(long, value_parser = parse_time_of_day)]
+        on: (u8, u8),
+        /// Time to turn off, e.g. 23:00 or 11pm
+        #[arg(long, value_parser = parse_time_of_day)]
+        off: (u8, u8),
+    },
+    /// Disable the device's on and/or off schedule (both unless --on or --off is given)
+    ScheduleClear {
+        /// Clear the on schedule
+        #[arg(long)]
+        on: bool,
+        /// Clear the off schedule
+        #[arg(long)]
+        off: bool,
+        /// Clear both schedules (the default)
+        #[arg(long, conflicts_with_all = ["on", "off"])]
+        all: bool,
+    },
+    /// Show the schedules as last written by this tool (the device can't report them)
+    ScheduleShow,
+    /// Turn the device off after a delay, waiting to clear the timer afterwards
+    Timer {
+        /// Delay before turning off (e.g. 45m, 1h30m, 90s)
+        #[arg(long, value_parser = parse_delay, required_unless_present = "on_in")]
+        off_in: Option<Duration>,
+        /// Delay before turning on instead
+        #[arg(long, value_parser = parse_delay, conflicts_with = "off_in")]
+        on_in: Option<Duration>,
+        /// Stay connected and send the command when the delay is over, instead
+        /// of programming the device's weekly schedule
+        #[arg(long)]
+        stay_connected: bool,
+    },
+    /// Wait until a local time, then apply an action (keeps running until then)
+    At {
+        /// When to act: 2024-01-01T20:00, or 20:00 / 8pm for the next time it is 20:00
+        #[arg(long, value_parser = parse_local_time)]
+        time: chrono::DateTime<chrono::Local>,
+        /// What to do: on, off, "color ff0000", "brightness 40" or "effect rainbow 60"
+        #[arg(long, default_value = "off")]
+        action: LineCommand,
+    },
+    /// Save, apply, list or delete named scenes
+    Scene {
+        #[command(subcommand)]
+        action: SceneAction,
+    },
+    /// Manage the config file with defaults for the flags
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
     },
     /// Start audio-reactive LED visualization
+    #[cfg(feature = "audio")]
     Audio {
-        /// Visualization mode
-        #[arg(short, long, value_enum, default_value_t = AudioModeType::FrequencyColor)]
-        mode: AudioModeType,
+        /// Visualization mode (default: frequency-color)
+        #[arg(short, long, value_enum)]
+        mode: Option<AudioModeType>,
 
-        /// Frequency range to monitor
-        #[arg(short, long, value_enum, default_value_t = AudioRangeType::Full)]
-        range: AudioRangeType,
+        /// Frequency range to monitor (default: full)
+        #[arg(short, long, value_enum)]
+        range: Option<AudioRangeType>,
 
-        /// Audio sensitivity (0-100)
-        #[arg(short, long, default_value_t = 70)]
-        sensitivity: u8,
+        /// Audio sensitivity (0-100, default: 70)
+        #[arg(short, long)]
+        sensitivity: Option<u8>,
 
-        /// Update interval in milliseconds
-        #[arg(short, long, default_value_t = 50)]
-        update_ms: u32,
+        /// Update interval in milliseconds (default: 50)
+        #[arg(short, long)]
+        update_ms: Option<u32>,
 
         /// Run in test mode (just display audio levels, don't control LEDs)
-        #[arg(short, long, default_value_t = false)]
+        #[arg(short, long, default_value_t = false, conflicts_with = "json")]
         test: bool,
 
         /// Audio device name to use (leave empty for default output device)
         #[arg(short, long)]
         device: Option<String>,
+
+        /// Audio host (backend) to use, e.g. ALSA or JACK (default: platform default)
+        #[arg(long)]
+        audio_host: Option<String>,
+
+        /// Don't wait for the device to acknowledge each update (lower latency, less reliable)
+        #[arg(long, default_value_t = false)]
+        fast: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Write a commented template to the config file
+    Init {
+        /// Replace an existing file
+        #[arg(long, default_value_t = false)]
+        force: bool,
+    },
+    /// Print where the config file is read from
+    Path,
+}
+
+#[derive(Subcommand)]
+enum SceneAction {
+    /// Save the device's last known look under a name, replacing a scene of that name
+    Save {
+        /// Scene name, a single word
+        name: String,
+    },
+    /// Apply a saved scene
+    Apply {
+        /// Scene name
+        name: String,
     },
+    /// List the saved scenes
+    List,
+    /// Delete a saved scene
+    Delete {
+        /// Scene name
+        name: String,
+    },
+}
+
+impl Commands {
+    /// Name reported in `--json` output, e.g. `set_color`
+    fn name(&self) -> &'static str {
+        match self {
+            Commands::Demo { .. } => "demo",
+            Commands::Effects => "effects",
+            Commands::Repl => "repl",
+            Commands::Run { .. } => "run",
+            Commands::RunScript { .. } => "run_script",
+            Commands::Status => "status",
+            Commands::Watch { .. } => "watch",
+            Commands::Scan { .. } => "scan",
+            Commands::On => "power_on",
+            Commands::Off => "power_off",
+            Commands::Red
+            | Commands::Green
+            | Commands::Blue
+            | Commands::White
+            | Commands::Color { .. } => "set_color",
+            Commands::Brightness { .. } => "set_brightness",
+            Commands::ColorTemp { .. } => "set_color_temp",
+            Commands::Effect { shuffle: true, .. } => "effect_shuffle",
+            Commands::Effect { .. } => "set_effect",
+            Commands::Speed { .. } => "set_effect_speed",
+            Commands::Crossfade { .. } => "crossfade",
+            Commands::Fade { .. } => "fade",
+            Commands::Cycle { .. } => "cycle",
+            Commands::Random { .. } => "random",
+            Commands::Fire { .. } => "fire",
+            Commands::Raw { .. } => "raw",
+            Commands::ProbeEffects { .. } => "probe_effects",
+            Commands::ScheduleOn { .. } => "schedule_on",
+            Commands::ScheduleOff { .. } => "schedule_off",
+            Commands::Schedule { .. } => "schedule",
+            Commands::ScheduleClear { .. } => "schedule_clear",
+            Commands::ScheduleShow => "schedule_show",
+            Commands::Timer { .. } => "timer",
+            Commands::At { .. } => "at",
+            Commands::Scene { action } => match action {
+                SceneAction::Save { .. } => "scene_save",
+                SceneAction::Apply { .. } => "scene_apply",
+                SceneAction::List => "scene_list",
+                SceneAction::Delete { .. } => "scene_delete",
+            },
+            Commands::Config { .. } => "config",
+            #[cfg(feature = "audio")]
+            Commands::Audio { .. } => "audio",
+        }
+    }
 }
 
 #[tokio::main]
 #[instrument]
 async fn main() -> Result<()> {
-    // Initialize tracing with pretty colors
-    tracing_subscriber::fmt().compact().init();
+    // Usage errors are invalid arguments (5); clap's own code 2 means "no adapter" here
+    let mut cli = Cli::try_parse().unwrap_or_else(|e| {
+        let _ = e.print();
+        std::process::exit(if e.use_stderr() { 5 } else { 0 });
+    });
+
+    // Initialize tracing with pretty colors, on stderr when stdout carries JSON
+    if cli.json {
+        tracing_subscriber::fmt()
+            .compact()
+            .with_writer(std::io::stderr)
+            .init();
+    } else {
+        tracing_subscriber::fmt()
+            .compact()
+            .with_writer(|| spinner::LogWriter(std::io::stdout()))
+            .init();
+    }
 
     // Initialize color-eyre for pretty error reporting
     color_eyre::install()?;
-
-    let cli = Cli::parse();
     debug!("Parsed command line arguments");
 
     // The info! macro doesn't work in main until after tracing_subscriber::fmt().init()
     // has been called, so it's safe to use it here
     info!("Starting LED controller");
 
+    let json = cli.json;
+    let command = cli.command.take().expect("clap requires a subcommand");
+    let name = command.name();
+
+    match run(cli, command).await {
+        Ok(outcome) => {
+            if json {
+                println!("{}", outcome.to_json(name));
+            } else {
+                info!("Command completed successfully");
+            }
+            if outcome.exit_code != 0 {
+                std::process::exit(outcome.exit_code);
+            }
+            Ok(())
+        }
+        Err(e) => {
+            // Let scripts tell the error categories apart (see Error::exit_code)
+            let code = e.downcast_ref::<Error>().map_or(1, Error::exit_code);
+            if json {
+                println!("{}", error_json(&e));
+                std::process::exit(code);
+            }
+            if code != 1 {
+                // The report main would print for Err, before exiting with our code
+                eprintln!("Error: {:?}", e);
+                std::process::exit(code);
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Connect as needed and run `command`
+async fn run(mut cli: Cli, mut command: Commands) -> Result<Outcome> {
+    // --at is another way to write --hour/--minute
+    if let Commands::ScheduleOn {
+        hour,
+        minute,
+        at: Some(at),
+        ..
+    }
+    | Commands::ScheduleOff {
+        hour,
+        minute,
+        at: Some(at),
+        ..
+    } = &mut command
+    {
+        (*hour, *minute) = *at;
+    }
+
+    let config_path = cli.config.clone().or_else(config::default_path);
+    if let Commands::Config { action } = &command {
+        let path = config_path
+            .ok_or_else(|| color_eyre::eyre::eyre!("No home directory, pass --config"))?;
+        if let ConfigAction::Init { force } = action {
+            config::init(&path, *force)?;
+            info!("Wrote config template to {}", path.display());
+        } else if !cli.json {
+            println!("{}", path.display());
+        }
+        let path = path.display().to_string();
+        return Ok(Outcome::default().field("path", json_string(Some(&path))));
+    }
+
+    // The environment picks the device when the command line doesn't, and
+    // wins over the config file
+    if cli.addresses.is_empty() && cli.name.is_none() && !cli.all_devices {
+        if let Some(address) = std::env::var(ADDRESS_VAR)
+            .ok()
+            .filter(|address| !address.is_empty())
+        {
+            debug!("Using {}={}", ADDRESS_VAR, address);
+            cli.addresses.push(address);
+        }
+    }
+
+    // An explicit --config must exist, the default one is optional
+    if let Some(path) = config_path.filter(|path| cli.config.is_some() || path.exists()) {
+        debug!("Reading defaults from {}", path.display());
+        let config = config::load(&path)?;
+        apply_config(&mut cli, &mut command, config);
+    }
+
+    if cli.repeat.is_some() {
+        if !sets_look(&command) {
+            return Err(Error::InvalidCommand(format!(
+                "--repeat only works with commands that set a look, not '{}'",
+                command.name()
+            ))
+            .into());
+        }
+        if cli.all_devices || cli.addresses.len() > 1 {
+            return Err(
+                Error::InvalidCommand("--repeat works with a single device".to_string()).into(),
+            );
+        }
+    }
+
+    if cli.dry_run {
+        return run_dry(&cli, command);
+    }
+
+    // Commands that don't need a device connection
+    if let Commands::Effects = command {
+        let device_type = match (cli.addresses.first(), cli.name.as_deref()) {
+            (None, None) => None,
+            (address, name) => {
+                let timeout = cli
+                    .timeout
+                    .map_or(Duration::from_secs(5), Duration::from_secs);
+                find_device_type(address.map(String::as_str), name, timeout).await
+            }
+        };
+        return Ok(print_effects(device_type, cli.json));
+    }
+    if let Commands::Scan { duration, all } = command {
+        return run_scan(Duration::from_secs(duration), all, cli.json).await;
+    }
+    if let Commands::ScheduleShow = command {
+        return show_schedules(cli.addresses.first().map(String::as_str), cli.json);
+    }
+    if let Commands::Scene {
+        action: SceneAction::List,
+    } = command
+    {
+        return list_scenes(&scene_store(&cli)?, cli.json);
+    }
+    if let Commands::Scene {
+        action: SceneAction::Delete { name },
+    } = &command
+    {
+        let mut scenes = scene_store(&cli)?;
+        if !scenes.remove(name) {
+            return Err(no_such_scene(&scenes, name));
+        }
+        scenes.save()?;
+        info!("Deleted scene '{}'", name);
+        return Ok(Outcome::default().field("scene", json_string(Some(name))));
+    }
+    // Look the scene up before spending time on connecting
+    let scene = match &command {
+        Commands::Scene {
+            action: SceneAction::Apply { name },
+        } => {
+            let scenes = scene_store(&cli)?;
+            match scenes.get(name) {
+                Some(scene) => Some(*scene),
+                None => return Err(no_such_scene(&scenes, name)),
+            }
+        }
+        _ => None,
+    };
+    if let Commands::Cycle {
+        colors,
+        interval,
+        fade,
+        ..
+    } = &command
+    {
+        if colors.len() < 2 {
+            return Err(Error::InvalidCommand(format!(
+                "cycle needs at least two colors, got {}",
+                colors.len()
+            ))
+            .into());
+        }
+        if interval.is_zero() {
+            return Err(
+                Error::InvalidCommand("--interval must be longer than zero".to_string()).into(),
+            );
+        }
+        if fade.is_some_and(|fade| fade > *interval) {
+            return Err(Error::InvalidCommand(
+                "--fade can't be longer than --interval".to_string(),
+            )
+            .into());
+        }
+    }
+    // Reject a typo in a script or frame before spending time on connecting
+    if let Commands::Run { script, .. } = &command {
+        parse_script(script)?;
+    }
+    if let Commands::RunScript { file, .. } = &command {
+        read_script(file)?;
+    }
+    if let Commands::Demo { only, skip, .. } = &command {
+        demo_steps(only, skip)?;
+    }
+    if let Commands::Raw {
+        frame,
+        unchecked,
+        id,
+        sub_id,
+        args,
+    } = &command
+    {
+        raw_frame(frame.as_deref(), *unchecked, *id, *sub_id, args)?;
+    }
+
+    // Every compatible device in range, or several addresses - control them together
+    if cli.all_devices {
+        let timeout = cli
+            .timeout
+            .map_or(DEFAULT_SCAN_TIMEOUT, Duration::from_secs);
+        let bluetooth = Bluetooth::open().await?;
+        let addresses: Vec<String> = bluetooth
+            .scan(timeout)
+            .await?
+            .into_iter()
+            .filter(DiscoveredDevice::is_compatible)
+            .map(|device| device.address)
+            .collect();
+        if addresses.is_empty() {
+            return Err(Error::NoCompatibleDevice.into());
+        }
+        info!("Found {} compatible devices", addresses.len());
+        return run_on_group(&cli, command, &addresses, &bluetooth).await;
+    }
+    if cli.addresses.len() > 1 {
+        let bluetooth = Bluetooth::open().await?;
+        return run_on_group(&cli, command, &cli.addresses, &bluetooth).await;
+    }
+
     // Initialize the device but don't automatically power it on
-    let mut device = match BleLedDevice::new_without_power().await {
+    let mut builder = BleLedDevice::builder()
+        .scan_timeout(
+            cli.timeout
+                .map_or(DEFAULT_SCAN_TIMEOUT, Duration::from_secs),
+        )
+        .connect_retries(cli.connect_retries.unwrap_or(0));
+    if let Some(max) = cli.max_brightness {
+        builder = builder.max_brightness(max);
+    }
+    if let Some(gamma) = cli.gamma {
+        builder = builder.brightness_curve(BrightnessCurve::Gamma(gamma));
+    }
+    if let Some(addr) = cli.addresses.first() {
+        builder = builder.address(addr);
+    }
+    if let Some(name) = &cli.name {
+        builder = builder.name(name);
+    }
+    // Show the connection's progress, unless the output is for scripts
+    let spinner = if cli.json || cli.quiet {
+        None
+    } else {
+        let (events, receiver) = tokio::sync::broadcast::channel(DEFAULT_EVENT_CAPACITY);
+        builder = builder.events(events);
+        spinner::Spinner::start(receiver)
+    };
+    let connected = builder.connect().await;
+    // Clear the progress line before anything else is printed
+    drop(spinner);
+    let mut device = match connected {
         Ok(dev) => dev,
         Err(e) => {
             error!("Failed to initialize device: {}", e);
             return Err(e.into());
         }
     };
+    restore_cached_state(&device);
 
-    match cli.command.unwrap_or(Commands::Demo { duration: 5 }) {
-        Commands::Demo { duration } => {
-            run_demo(&mut device, duration).await?;
+    // --repeat keeps the power as it is when the command didn't set it
+    let repeat_power = !cli.no_power_on || matches!(command, Commands::On | Commands::Off);
+    let mut outcome = Outcome::default();
+    match command {
+        Commands::Demo {
+            duration,
+            only,
+            skip,
+            repeat,
+        } => {
+            let steps = demo_steps(&only, &skip)?;
+            run_demo(&mut device, duration, &steps, repeat).await?;
+        }
+        Commands::Effects
+        | Commands::Scan { .. }
+        | Commands::Config { .. }
+        | Commands::ScheduleShow
+        | Commands::Scene {
+            action: SceneAction::List | SceneAction::Delete { .. },
+        } => {
+            unreachable!("runs before connecting")
+        }
+        Commands::Scene {
+            action: SceneAction::Save { name },
+        } => {
+            // A scene is a look to switch to, so applying it always powers on
+            let state = DeviceState {
+                is_on: true,
+                ..device.snapshot()
+            };
+            let mut scenes = scene_store(&cli)?;
+            scenes.insert(&name, state)?;
+            scenes.save()?;
+            info!("Saved scene '{}': {}", name, describe_scene(&state));
+            outcome = outcome.field("scene", json_string(Some(&name)));
+        }
+        Commands::Scene {
+            action: SceneAction::Apply { name },
+        } => {
+            let scene = scene.expect("looked up before connecting");
+            device
+                .apply_scene(&scene)
+                .await
+                .wrap_err_with(|| format!("Scene '{}' was not applied", name))?;
+            info!("Applied scene '{}': {}", name, describe_scene(&scene));
+            outcome = outcome.field("scene", json_string(Some(&name)));
+        }
+        Commands::Status => outcome = run_status(&device, cli.json).await?,
+        Commands::Watch { duration } => outcome = run_watch(&device, duration, cli.json).await?,
+        Commands::Repl => run_repl(&device, cli.json).await?,
+        Commands::Run { script, keep_going } => {
+            let steps: Vec<_> = (1..).zip(parse_script(&script)?).collect();
+            outcome = run_script(&device, &steps, "Step", keep_going, cli.json).await?
+        }
+        Commands::RunScript { file, keep_going } => {
+            let steps = read_script(&file)?;
+            outcome = run_script(&device, &steps, "Line", keep_going, cli.json).await?
+        }
+        Commands::Raw {
+            frame,
+            unchecked,
+            id,
+            sub_id,
+            args,
+        } => {
+            let frame = raw_frame(frame.as_deref(), unchecked, id, sub_id, &args)?;
+            let write_type = if device.acknowledged_writes() {
+                "with response"
+            } else {
+                "without response"
+            };
+            device.send_raw(&frame).await?;
+            if !cli.json {
+                println!(
+                    "Sent {} ({}, write {}): ok",
+                    protocol::format_frame(&frame),
+                    protocol::describe_frame(&frame),
+                    write_type
+                );
+            }
+            outcome = outcome
+                .field("frame", json_string(Some(&protocol::format_frame(&frame))))
+                .field("write_type", json_string(Some(write_type)));
         }
         Commands::On => {
-            if !device.is_on {
+            if !device.is_on() {
                 device.power_on().await?;
                 info!("Device powered on");
             }
         }
         Commands::Off => {
-            if device.is_on {
+            if device.is_on() {
                 device.power_off().await?;
                 info!("Device powered off");
             }
         }
         Commands::Red => {
-            if !device.is_on {
-                device.power_on().await?;
-            }
+            power_on_implicitly(&device, cli.no_power_on).await?;
             device.set_color(255, 0, 0).await?;
             info!("Color set to RED");
         }
         Commands::Green => {
-            if !device.is_on {
-                device.power_on().await?;
-            }
+            power_on_implicitly(&device, cli.no_power_on).await?;
             device.set_color(0, 255, 0).await?;
             info!("Color set to GREEN");
         }
         Commands::Blue => {
-            if !device.is_on {
-                device.power_on().await?;
-            }
+            power_on_implicitly(&device, cli.no_power_on).await?;
             device.set_color(0, 0, 255).await?;
             info!("Color set to BLUE");
         }
         Commands::White => {
-            if !device.is_on {
-                device.power_on().await?;
-            }
+            power_on_implicitly(&device, cli.no_power_on).await?;
             device.set_color(255, 255, 255).await?;
             info!("Color set to WHITE");
         }
-        Commands::Brightness { level } => {
-            // We need to ensure the device is on for brightness changes to be visible
-            if !device.is_on {
-                device.power_on().await?;
+        Commands::Brightness { level, up, down } => {
+            power_on_implicitly(&device, cli.no_power_on).await?;
+            let level = match (up, down) {
+                (Some(up), _) => BrightnessLevel::Relative(up as i16),
+                (None, Some(down)) => BrightnessLevel::Relative(-(down as i16)),
+                (None, None) => level,
+            };
+            let brightness = set_cached_brightness(&device, level).await?;
+            if !cli.json {
+                println!("Brightness: {}%", brightness);
             }
-            device.set_brightness(level).await?;
-            info!("Brightness set to {}", level);
+            outcome = outcome.field("brightness", brightness.to_string());
         }
         Commands::ColorTemp { kelvin } => {
-            if !device.is_on {
-                device.power_on().await?;
-            }
+            power_on_implicitly(&device, cli.no_power_on).await?;
             device.set_color_temp_kelvin(kelvin).await?;
             info!("Color temperature set to {}K", kelvin);
         }
-        Commands::Color { red, green, blue } => {
-            if !device.is_on {
-                device.power_on().await?;
-            }
+        Commands::Color {
+            hex,
+            red,
+            green,
+            blue,
+            hue,
+            sat,
+            val,
+        } => {
+            power_on_implicitly(&device, cli.no_power_on).await?;
+            let (red, green, blue) = color_input(hex, (red, green, blue), hue, sat, val);
             device.set_color(red, green, blue).await?;
             info!("Color set to RGB({}, {}, {})", red, green, blue);
         }
-        Commands::Effect { effect_type, speed } => {
-            if !device.is_on {
-                device.power_on().await?;
-            }
+        Commands::Effect {
+            speed,
+            shuffle: true,
+            dwell,
+            ..
+        } => {
+            power_on_implicitly(&device, cli.no_power_on).await?;
 
-            let effect_code = match effect_type {
-                EffectType::Rainbow => EFFECTS.crossfade_red_green_blue_yellow_cyan_magenta_white,
-                EffectType::Jump => EFFECTS.jump_red_green_blue,
-                EffectType::JumpAll => EFFECTS.jump_red_green_blue_yellow_cyan_magenta_white,
-                EffectType::CrossfadeRed => EFFECTS.crossfade_red,
-                EffectType::CrossfadeGreen => EFFECTS.crossfade_green,
-                EffectType::CrossfadeBlue => EFFECTS.crossfade_blue,
-                EffectType::CrossfadeRgb => EFFECTS.crossfade_red_green_blue,
-                EffectType::Blink => EFFECTS.blink_red_green_blue_yellow_cyan_magenta_white,
-                EffectType::BlinkRed => EFFECTS.blink_red,
-                EffectType::BlinkGreen => EFFECTS.blink_green,
-                EffectType::BlinkBlue => EFFECTS.blink_blue,
-            };
+            let device_type = device.device_type();
+            let effects: Vec<Effect> = effects::all()
+                .iter()
+                .filter(|info| info.is_supported_on(device_type))
+                .map(|info| info.effect)
+                .collect();
+
+            // Stop the shuffle on Ctrl+C
+            let cancel = CancelHandle::new();
+            let ctrl_c_cancel = cancel.clone();
+            tokio::spawn(async move {
+                if tokio::signal::ctrl_c().await.is_ok() {
+                    info!("Received Ctrl+C, stopping effect shuffle");
+                    ctrl_c_cancel.cancel();
+                }
+            });
+
+            info!("Starting effect shuffle. Press Ctrl+C to exit.");
+            device
+                .run_effect_shuffle(effects, Duration::from_secs(dwell), speed, cancel)
+                .await?;
+        }
+        Commands::Effect {
+            effect_type, speed, ..
+        } => {
+            power_on_implicitly(&device, cli.no_power_on).await?;
 
-            device.set_effect(effect_code).await?;
+            // Sending the effect again restarts its animation, so only the
+            // speed changes when it already runs
+            if device.effect() == Some(effect_type) {
+                debug!(
+                    "{} is already running, only changing its speed",
+                    effect_type
+                );
+            } else {
+                device.set_effect(effect_type).await?;
+            }
             device.set_effect_speed(speed).await?;
             info!("Effect set to {} with speed {}", effect_type, speed);
         }
-        Commands::ScheduleOn { hour, minute, days } => {
-            if !device.is_on {
-                device.power_on().await?;
-            }
+        Commands::Speed { speed } => {
+            device.set_effect_speed(speed).await?;
+            info!("Effect speed set to {}", speed);
+        }
+        Commands::Crossfade {
+            from,
+            to,
+            period,
+            easing,
+            breathe,
+            duration,
+        } => {
+            power_on_implicitly(&device, cli.no_power_on).await?;
+            let options = CrossfadeOptions {
+                easing: easing.into(),
+                breathe,
+                duration,
+            };
 
-            let days_value = parse_days(&days);
+            info!("Starting crossfade. Press Ctrl+C to exit.");
+            tokio::select! {
+                result = device.run_crossfade(from, to, period, options) => result?,
+                _ = tokio::signal::ctrl_c() => info!("Received Ctrl+C, stopping crossfade"),
+            }
+        }
+        Commands::Fade {
+            to_color,
+            to_brightness,
+            duration,
+            steps,
+            force,
+        } => {
+            let delay = Duration::from_millis(device.command_delay);
+            let steps = fade_steps(duration, steps, delay, force)?;
+            let target = match (to_color, to_brightness) {
+                (Some(color), _) => FadeTarget::Color(color),
+                (None, Some(level)) => FadeTarget::Brightness(level),
+                (None, None) => unreachable!("clap requires --to-color or --to-brightness"),
+            };
+            power_on_implicitly(&device, cli.no_power_on).await?;
 
-            device
-                .set_schedule_on(days_value, hour, minute, true)
-                .await?;
             info!(
-                "Schedule set to turn on at {:02}:{:02} on {}",
-                hour, minute, days
+                "Fading to {:?} over {:?} in {} steps. Press Ctrl+C to stop.",
+                target, duration, steps
             );
+            tokio::select! {
+                result = device.fade(target, duration, steps) => result?,
+                _ = tokio::signal::ctrl_c() => info!("Received Ctrl+C, stopping the fade"),
+            }
+            if let FadeTarget::Brightness(_) = target {
+                remember_brightness(&device);
+            }
+            outcome = outcome.field("steps", steps.to_string());
         }
-        Commands::ScheduleOff { hour, minute, days } => {
-            if !device.is_on {
-                device.power_on().await?;
+        Commands::Cycle {
+            colors,
+            interval,
+            fade,
+            count,
+            on_exit,
+        } => {
+            power_on_implicitly(&device, cli.no_power_on).await?;
+
+            info!(
+                "Cycling through {} colors. Press Ctrl+C to exit.",
+                colors.len()
+            );
+            tokio::select! {
+                result = run_cycle(&device, &colors, interval, fade, count) => result?,
+                _ = tokio::signal::ctrl_c() => {
+                    info!("Received Ctrl+C, stopping the cycle");
+                    match on_exit {
+                        CycleExit::First => {
+                            let (red, green, blue) = colors[0];
+                            device.set_color(red, green, blue).await?;
+                        }
+                        CycleExit::Off => device.power_off().await?,
+                    }
+                }
             }
+        }
+        Commands::Random {
+            transition,
+            duration,
+            seed,
+        } => {
+            power_on_implicitly(&device, cli.no_power_on).await?;
 
-            let days_value = parse_days(&days);
+            info!("Starting random colors. Press Ctrl+C to exit.");
+            let duration = duration.unwrap_or(Duration::MAX);
+            tokio::select! {
+                result = device.run_random(transition, duration, seed) => result?,
+                _ = tokio::signal::ctrl_c() => info!("Received Ctrl+C, stopping random colors"),
+            }
+        }
+        Commands::Fire { seconds, kelvin } => {
+            power_on_implicitly(&device, cli.no_power_on).await?;
 
+            info!("Starting fire. Press Ctrl+C to exit.");
             device
-                .set_schedule_off(days_value, hour, minute, true)
+                .run_fire(Duration::from_secs(seconds), kelvin, cancel_on_ctrl_c())
                 .await?;
+        }
+        Commands::ProbeEffects {
+            start,
+            end,
+            dwell_ms,
+            output,
+        } => {
+            power_on_implicitly(&device, cli.no_power_on).await?;
+            run_effect_probe(&mut device, start, end, dwell_ms, &output).await?;
+        }
+        Commands::ScheduleOn {
+            hour, minute, days, ..
+        } => {
+            device.set_schedule_on(days, hour, minute, true).await?;
+            let schedule = schedule_at(days, hour, minute);
+            remember_schedules(&device, Some(Some(schedule)), None);
+            info!(
+                "Schedule set to turn on {} ({})",
+                schedule,
+                describe_next(&schedule)
+            );
+        }
+        Commands::ScheduleOff {
+            hour, minute, days, ..
+        } => {
+            device.set_schedule_off(days, hour, minute, true).await?;
+            let schedule = schedule_at(days, hour, minute);
+            remember_schedules(&device, None, Some(Some(schedule)));
+            info!(
+                "Schedule set to turn off {} ({})",
+                schedule,
+                describe_next(&schedule)
+            );
+        }
+        Commands::Schedule { days, on, off } => {
+            device.set_daily_schedule(days, on, off).await?;
+            let (on, off) = (
+                schedule_at(days, on.0, on.1),
+                schedule_at(days, off.0, off.1),
+            );
+            remember_schedules(&device, Some(Some(on)), Some(Some(off)));
+            info!(
+                "Schedule set to turn on {} ({}) and off at {}",
+                on,
+                describe_next(&on),
+                off.time.format("%H:%M")
+            );
+        }
+        Commands::ScheduleClear { on, off, .. } => {
+            // Neither flag (or --all) clears both
+            let (on, off) = if on || off { (on, off) } else { (true, true) };
+            if on {
+                device.clear_schedule_on().await?;
+                info!("On schedule cleared");
+            }
+            if off {
+                device.clear_schedule_off().await?;
+                info!("Off schedule cleared");
+            }
+            remember_schedules(&device, on.then_some(None), off.then_some(None));
+        }
+        Commands::Timer {
+            off_in,
+            on_in,
+            stay_connected,
+        } => {
+            let (delay, power_on) = match (off_in, on_in) {
+                (_, Some(delay)) => (delay, true),
+                (Some(delay), None) => (delay, false),
+                (None, None) => unreachable!("clap requires --off-in or --on-in"),
+            };
+            outcome = run_timer(&device, delay, power_on, stay_connected).await?;
+        }
+        Commands::At { time, action } => {
+            let (cancel, timer) = device.at_time(time, action)?;
+            forward_ctrl_c(&cancel);
             info!(
-                "Schedule set to turn off at {:02}:{:02} on {}",
-                hour, minute, days
+                "Applying {:?} at {}. Press Ctrl+C to cancel.",
+                action,
+                time.format("%Y-%m-%d %H:%M:%S")
             );
+            let applied = timer.await?;
+            outcome = outcome.field("applied", applied.to_string());
         }
+        #[cfg(feature = "audio")]
         Commands::Audio {
             mode,
             range,
@@ -363,98 +1354,1781 @@ async fn main() -> Result<()> {
             update_ms,
             test,
             device: audio_device,
+            audio_host,
+            fast,
         } => {
-            if !device.is_on {
-                device.power_on().await?;
-            }
+            // Create audio monitor
+            let audio_monitor =
+                match AudioMonitor::new_with_host(audio_device, audio_host.as_deref()) {
+                    Ok(monitor) => monitor,
+                    Err(e) => {
+                        error!("Failed to initialize audio monitoring: {}", e);
+                        return Err(e.into());
+                    }
+                };
+
+            power_on_implicitly(&device, cli.no_power_on).await?;
+            device.fast_mode = fast;
+            audio_monitor.set_config(AudioVisualization {
+                power_on: !cli.no_power_on,
+                ..audio_monitor.get_config()
+            });
 
             run_audio_visualization(
                 &mut device,
-                mode,
-                range,
-                sensitivity,
-                update_ms,
+                audio_monitor,
+                mode.unwrap_or(AudioModeType::FrequencyColor),
+                range.unwrap_or(AudioRangeType::Full),
+                sensitivity.unwrap_or(70),
+                update_ms.unwrap_or(50),
                 test,
-                audio_device,
             )
             .await?;
         }
     }
 
-    info!("Command completed successfully");
-    Ok(())
+    remember_state(&device);
+    if let Some(seconds) = cli.repeat {
+        repeat_look(&device, Duration::from_secs(seconds), repeat_power).await;
+    }
+    outcome.device = Some(device.address());
+    outcome.state = Some(device.snapshot());
+    Ok(outcome)
 }
 
-/// Parse days string to bitmask
-#[instrument]
-fn parse_days(days: &str) -> u8 {
-    debug!("Parsing days string: {}", days);
-    let result = match days.to_lowercase().as_str() {
-        "mon" | "monday" => WEEK_DAYS.monday,
-        "tue" | "tuesday" => WEEK_DAYS.tuesday,
-        "wed" | "wednesday" => WEEK_DAYS.wednesday,
-        "thu" | "thursday" => WEEK_DAYS.thursday,
-        "fri" | "friday" => WEEK_DAYS.friday,
-        "sat" | "saturday" => WEEK_DAYS.saturday,
-        "sun" | "sunday" => WEEK_DAYS.sunday,
-        "all" => WEEK_DAYS.all,
-        "weekdays" => WEEK_DAYS.week_days,
-        "weekend" => WEEK_DAYS.weekend_days,
-        _ => {
-            debug!("Parsing composite days string");
-            let mut combined = 0;
-            for day in days.split(',') {
-                let day_value = parse_days(day);
-                debug!("  Day '{}' = {:#04x}", day, day_value);
-                combined |= day_value;
+/// Whether `command` leaves a steady look that `--repeat` can re-apply
+fn sets_look(command: &Commands) -> bool {
+    matches!(
+        command,
+        Commands::On
+            | Commands::Off
+            | Commands::Red
+            | Commands::Green
+            | Commands::Blue
+            | Commands::White
+            | Commands::Color { .. }
+            | Commands::Brightness { .. }
+            | Commands::ColorTemp { .. }
+            | Commands::Effect { shuffle: false, .. }
+            | Commands::Scene {
+                action: SceneAction::Apply { .. }
             }
-            combined
-        }
-    };
-
-    trace!("Days '{}' parsed to bitmask: {:#04x}", days, result);
-    result
+    )
 }
 
-/// Sleep for specified number of seconds
-#[instrument]
-async fn sleep(seconds: u64) {
-    trace!("Sleeping for {}s", seconds);
-    tokio::time::sleep(Duration::from_secs(seconds)).await;
-    trace!("Sleep completed");
+/// Re-apply the device's current look every `interval` until Ctrl+C, for
+/// `--repeat`
+///
+/// The whole look is sent each time, power included, since the cached state
+/// can't tell what the strip forgot. Without `power` (`--no-power-on` with a
+/// command other than `on` or `off`) the power is left alone, as the cached
+/// power state then only reflects what was last sent. A failed attempt is logged and retried on the next tick; the
+/// device reconnects on its own when it can.
+async fn repeat_look(device: &BleLedDevice, interval: Duration, power: bool) {
+    let look = device.snapshot();
+    let cancel = cancel_on_ctrl_c();
+    info!(
+        "Re-applying every {}s, press Ctrl+C to stop",
+        interval.as_secs()
+    );
+    let mut ticker = tokio::time::interval(interval);
+    // The first tick completes immediately, and the look was just applied
+    ticker.tick().await;
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => return,
+            _ = ticker.tick() => {}
+        }
+        let result = async {
+            if !power {
+                return device.apply_scene_without_power(&look).await;
+            }
+            if look.is_on {
+                device.power_on().await?;
+            }
+            device.apply_scene(&look).await
+        }
+        .await;
+        match result {
+            Ok(()) => debug!("Re-applied {}", describe_scene(&look)),
+            Err(e) => warn!("Could not re-apply the look: {}", e),
+        }
+    }
 }
 
-/// Run audio visualization on the LED strip
+/// Fill in flags that were not given from the config file
+fn apply_config(cli: &mut Cli, command: &mut Commands, config: config::Config) {
+    // A device picked on the command line replaces the configured one entirely
+    if cli.addresses.is_empty() && cli.name.is_none() && !cli.all_devices {
+        cli.addresses.extend(config.address);
+        cli.name = config.name;
+    }
+    cli.timeout = cli.timeout.or(config.scan_timeout);
+    cli.connect_retries = cli.connect_retries.or(config.connect_retries);
+    cli.max_brightness = cli.max_brightness.or(config.max_brightness);
+    cli.gamma = cli.gamma.or(config.gamma);
+    cli.scenes = cli.scenes.take().or(config.scenes);
+    cli.no_power_on = cli.no_power_on || config.power_on == Some(false);
+
+    // Only the audio command has defaults of its own
+    #[cfg(not(feature = "audio"))]
+    let _ = command;
+    #[cfg(feature = "audio")]
+    if let Commands::Audio {
+        mode,
+        range,
+        sensitivity,
+        update_ms,
+        device,
+        audio_host,
+        fast,
+        ..
+    } = command
+    {
+        let audio = config.audio;
+        *mode = mode.or(audio.mode);
+        *range = range.or(audio.range);
+        *sensitivity = sensitivity.or(audio.sensitivity);
+        *update_ms = update_ms.or(audio.update_ms);
+        if device.is_none() {
+            *device = audio.device;
+        }
+        if audio_host.is_none() {
+            *audio_host = audio.host;
+        }
+        *fast = *fast || audio.fast.unwrap_or(false);
+    }
+}
+
+/// Connect to every device in `addresses` and run `command` on them as a group
+///
+/// Devices are connected one after another, as scanning concurrently on a
+/// single adapter is unreliable, then the command goes to all of them at
+/// once. A device that can't be connected to or fails the command doesn't
+/// stop the others; each one is reported, and any failure makes the exit
+/// code non-zero.
+async fn run_on_group(
+    cli: &Cli,
+    command: Commands,
+    addresses: &[String],
+    bluetooth: &Bluetooth,
+) -> Result<Outcome> {
+    let mut devices = Vec::with_capacity(addresses.len());
+    let mut connected = Vec::with_capacity(addresses.len());
+    let mut failures: Vec<(usize, Error)> = Vec::new();
+    for (index, address) in addresses.iter().enumerate() {
+        let connected_device = BleLedDevice::builder()
+            .address(address)
+            .bluetooth(bluetooth.clone())
+            .connect()
+            .await;
+        match connected_device {
+            Ok(mut device) => {
+                if let Some(max) = cli.max_brightness {
+                    device.max_brightness = max;
+                }
+                if let Some(gamma) = cli.gamma {
+                    device.brightness_curve = BrightnessCurve::Gamma(gamma);
+                }
+                devices.push(device);
+                connected.push(index);
+            }
+            Err(e) => {
+                error!("Failed to connect to {}: {}", address, e);
+                failures.push((index, e));
+            }
+        }
+    }
+
+    if !devices.is_empty() {
+        let mut group = DeviceGroup::new(devices);
+        if let Err(e) = run_group_command(&mut group, command, !cli.no_power_on).await {
+            // Only per-device failures are reported per device
+            match e.downcast::<Error>() {
+                Ok(Error::GroupError(errors)) => failures.extend(
+                    errors
+                        .into_iter()
+                        .map(|(index, error)| (connected[index], error)),
+                ),
+                Ok(error) => return Err(error.into()),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    let mut results = Vec::with_capacity(addresses.len());
+    for (index, address) in addresses.iter().enumerate() {
+        let failure = failures.iter().find(|(i, _)| *i == index).map(|(_, e)| e);
+        match failure {
+            None => {
+                if !cli.json {
+                    println!("{}: ok", address);
+                }
+                results.push(format!(
+                    "{{\"address\":{},\"ok\":true}}",
+                    Value::from(address.as_str())
+                ));
+            }
+            Some(e) => {
+                if !cli.json {
+                    println!("{}: failed: {}", address, e);
+                }
+                results.push(format!(
+                    "{{\"address\":{},\"ok\":false,\"error\":{{\"kind\":\"{}\",\"message\":{}}}}}",
+                    Value::from(address.as_str()),
+                    e.kind(),
+                    Value::from(e.to_string())
+                ));
+            }
+        }
+    }
+
+    let mut outcome = Outcome::default().field("devices", format!("[{}]", results.join(",")));
+    if !failures.is_empty() {
+        outcome.exit_code = Error::GroupError(failures).exit_code();
+    }
+    Ok(outcome)
+}
+
+/// Run a command against a group of devices
+///
+/// Like for a single device, commands that change the light power the
+/// devices on first unless `implicit_power_on` is false (`--no-power-on`).
+#[instrument(skip(group, command))]
+async fn run_group_command(
+    group: &mut DeviceGroup,
+    command: Commands,
+    implicit_power_on: bool,
+) -> Result<()> {
+    // Each device powers on and runs the command in one operation, so a
+    // device failing either step doesn't stop the rest of the group
+    match command {
+        Commands::On => group.power_on().await?,
+        Commands::Off => group.power_off().await?,
+        Commands::Red => {
+            group
+                .for_each(|device| async move {
+                    power_on_if(device, implicit_power_on).await?;
+                    device.set_color(255, 0, 0).await
+                })
+                .await?
+        }
+        Commands::Green => {
+            group
+                .for_each(|device| async move {
+                    power_on_if(device, implicit_power_on).await?;
+                    device.set_color(0, 255, 0).await
+                })
+                .await?
+        }
+        Commands::Blue => {
+            group
+                .for_each(|device| async move {
+                    power_on_if(device, implicit_power_on).await?;
+                    device.set_color(0, 0, 255).await
+                })
+                .await?
+        }
+        Commands::White => {
+            group
+                .for_each(|device| async move {
+                    power_on_if(device, implicit_power_on).await?;
+                    device.set_color(255, 255, 255).await
+                })
+                .await?
+        }
+        Commands::Color {
+            hex,
+            red,
+            green,
+            blue,
+            hue,
+            sat,
+            val,
+        } => {
+            let (red, green, blue) = color_input(hex, (red, green, blue), hue, sat, val);
+            group
+                .for_each(|device| async move {
+                    power_on_if(device, implicit_power_on).await?;
+                    device.set_color(red, green, blue).await
+                })
+                .await?
+        }
+        Commands::Brightness {
+            level: BrightnessLevel::Absolute(level),
+            up: None,
+            down: None,
+        } => {
+            group
+                .for_each(|device| async move {
+                    power_on_if(device, implicit_power_on).await?;
+                    device.set_brightness(level).await
+                })
+                .await?
+        }
+        Commands::ColorTemp { kelvin } => {
+            group
+                .for_each(|device| async move {
+                    power_on_if(device, implicit_power_on).await?;
+                    device.set_color_temp_kelvin(kelvin).await
+                })
+                .await?
+        }
+        Commands::Effect {
+            effect_type,
+            speed,
+            shuffle: false,
+            ..
+        } => {
+            group
+                .for_each(|device| async move {
+                    power_on_if(device, implicit_power_on).await?;
+                    device.set_effect(effect_type).await?;
+                    device.set_effect_speed(speed).await
+                })
+                .await?
+        }
+        Commands::Speed { speed } => group.set_effect_speed(speed).await?,
+        _ => {
+            return Err(Error::InvalidCommand(
+                "This command is not supported for a group of devices".to_string(),
+            )
+            .into());
+        }
+    }
+
+    info!("Command applied to {} devices", group.len());
+    Ok(())
+}
+
+/// Power `device` on first when `power_on` is set, for group commands
+async fn power_on_if(device: &BleLedDevice, power_on: bool) -> elk_led_controller::Result<()> {
+    if power_on {
+        device.power_on().await?;
+    }
+    Ok(())
+}
+
+/// Print the frames `command` would send, without any Bluetooth I/O
+///
+/// Mirrors what the command does on a freshly connected device, including
+/// powering it on first (unless `--no-power-on`). Commands that only make sense with a live device
+/// (e.g. `audio` or `repl`) are rejected.
+fn run_dry(cli: &Cli, command: Commands) -> Result<Outcome> {
+    let mut dry = dry_run::DryRun::new(cli.max_brightness, cli.gamma, !cli.no_power_on);
+    match command {
+        // Nothing to send; show what doesn't need the device
+        Commands::Effects => return Ok(print_effects(None, cli.json)),
+        Commands::ScheduleShow => {
+            return show_schedules(cli.addresses.first().map(String::as_str), cli.json)
+        }
+        Commands::Scene {
+            action: SceneAction::List,
+        } => return list_scenes(&scene_store(cli)?, cli.json),
+        Commands::On => dry.ensure_on(),
+        // Like the real command, this only sends a frame if the device is known to be on
+        Commands::Off => {}
+        Commands::Red => {
+            dry.implicit_on();
+            dry.color(255, 0, 0);
+        }
+        Commands::Green => {
+            dry.implicit_on();
+            dry.color(0, 255, 0);
+        }
+        Commands::Blue => {
+            dry.implicit_on();
+            dry.color(0, 0, 255);
+        }
+        Commands::White => {
+            dry.implicit_on();
+            dry.color(255, 255, 255);
+        }
+        Commands::Color {
+            hex,
+            red,
+            green,
+            blue,
+            hue,
+            sat,
+            val,
+        } => {
+            dry.implicit_on();
+            let (red, green, blue) = color_input(hex, (red, green, blue), hue, sat, val);
+            dry.color(red, green, blue);
+        }
+        Commands::Brightness { level, up, down } => {
+            dry.implicit_on();
+            let level = match (up, down) {
+                (Some(up), _) => BrightnessLevel::Relative(up as i16),
+                (None, Some(down)) => BrightnessLevel::Relative(-(down as i16)),
+                (None, None) => level,
+            };
+            let level = match level {
+                BrightnessLevel::Absolute(level) => level,
+                // Resolve the change like the real command, without updating the cache
+                BrightnessLevel::Relative(change) => {
+                    let current = cli
+                        .addresses
+                        .first()
+                        .and_then(|address| cache::DeviceCache::load(address).ok())
+                        .and_then(|cache| cache.get("brightness")?.parse::<u8>().ok())
+                        .unwrap_or(100);
+                    (current as i16 + change).clamp(0, 100) as u8
+                }
+            };
+            dry.brightness(level);
+        }
+        Commands::ColorTemp { kelvin } => {
+            dry.implicit_on();
+            dry.color_temp(kelvin);
+        }
+        Commands::Effect {
+            effect_type,
+            speed,
+            shuffle: false,
+            ..
+        } => {
+            dry.implicit_on();
+            dry.effect(effect_type, Some(speed))?;
+        }
+        Commands::Speed { speed } => {
+            dry.effect_speed(speed);
+        }
+        Commands::ScheduleOn {
+            hour, minute, days, ..
+        } => {
+            dry.schedule(false, days, hour, minute, true);
+        }
+        Commands::ScheduleOff {
+            hour, minute, days, ..
+        } => {
+            dry.schedule(true, days, hour, minute, true);
+        }
+        Commands::Schedule { days, on, off } => {
+            if on == off {
+                return Err(Error::SameScheduleTimes {
+                    hours: on.0,
+                    minutes: on.1,
+                }
+                .into());
+            }
+            dry.schedule(false, days, on.0, on.1, true);
+            dry.schedule(true, days, off.0, off.1, true);
+        }
+        Commands::ScheduleClear { on, off, .. } => {
+            let (on, off) = if on || off { (on, off) } else { (true, true) };
+            if on {
+                dry.schedule(false, Days::NONE, 0, 0, false);
+            }
+            if off {
+                dry.schedule(true, Days::NONE, 0, 0, false);
+            }
+        }
+        Commands::Run { script, .. } => {
+            for step in parse_script(&script)? {
+                if step == LineCommand::Quit {
+                    break;
+                }
+                dry.line_command(&step)?;
+            }
+        }
+        Commands::RunScript { file, .. } => {
+            for (number, step) in read_script(&file)? {
+                if step == LineCommand::Quit {
+                    break;
+                }
+                dry.line_command(&step)
+                    .wrap_err_with(|| format!("line {}", number))?;
+            }
+        }
+        Commands::Raw {
+            frame,
+            unchecked,
+            id,
+            sub_id,
+            args,
+        } => dry.raw(raw_frame(frame.as_deref(), unchecked, id, sub_id, &args)?),
+        command => {
+            return Err(Error::InvalidCommand(format!(
+                "'{}' can't be shown with --dry-run",
+                command.name()
+            ))
+            .into())
+        }
+    }
+
+    if !cli.json {
+        dry.print();
+    }
+    Ok(Outcome::default().field("frames", dry.to_json()))
+}
+
+/// Look up the type of the targeted device with a short scan, without connecting
+///
+/// Returns `None` (after a warning) when the device can't be found, so the
+/// catalog is still printed.
+async fn find_device_type(
+    address: Option<&str>,
+    name: Option<&str>,
+    timeout: Duration,
+) -> Option<DeviceType> {
+    let devices = match BleLedDevice::scan(timeout).await {
+        Ok(devices) => devices,
+        Err(e) => {
+            warn!("Could not scan for the device, listing all effects: {}", e);
+            return None;
+        }
+    };
+    // --address wins over --name, like when connecting
+    let found = devices.into_iter().find(|d| match address {
+        Some(address) => d.address.eq_ignore_ascii_case(address) || d.id == address,
+        None => d.name.as_deref() == name,
+    });
+    match found.and_then(|d| d.device_type) {
+        Some(device_type) => Some(device_type),
+        None => {
+            warn!("Device not found, listing effects without checking support");
+            None
+        }
+    }
+}
+
+/// Print the effect catalog as a table, marking support when the device type is known
+fn print_effects(device_type: Option<DeviceType>, json: bool) -> Outcome {
+    let supported = |info: &effects::EffectInfo| {
+        device_type.map(|device_type| info.is_supported_on(device_type))
+    };
+    if !json {
+        if let Some(device_type) = device_type {
+            println!("Support on {:?}:", device_type);
+            println!(
+                "{:<22} {:<6} {:<10} DESCRIPTION",
+                "NAME", "CODE", "SUPPORTED"
+            );
+        } else {
+            println!("{:<22} {:<6} DESCRIPTION", "NAME", "CODE");
+        }
+        for info in effects::all() {
+            match supported(info) {
+                Some(supported) => println!(
+                    "{:<22} {:#04x}   {:<10} {}",
+                    info.name,
+                    info.code,
+                    if supported { "yes" } else { "no" },
+                    info.description
+                ),
+                None => println!(
+                    "{:<22} {:#04x}   {}",
+                    info.name, info.code, info.description
+                ),
+            }
+        }
+    }
+
+    let entries: Vec<String> = effects::all()
+        .iter()
+        .map(|info| {
+            let supported = supported(info).map_or_else(|| "null".to_string(), |s| s.to_string());
+            format!(
+                "{{\"name\":\"{}\",\"code\":{},\"description\":{},\"supported\":{}}}",
+                info.name,
+                info.code,
+                Value::from(info.description),
+                supported
+            )
+        })
+        .collect();
+    Outcome::default()
+        .field(
+            "device_type",
+            json_string(device_type.map(DeviceType::name)),
+        )
+        .field("effects", format!("[{}]", entries.join(",")))
+}
+
+/// Parse a hex color argument
+fn parse_color(value: &str) -> std::result::Result<Color, String> {
+    elk_led_controller::parse_color(value).map_err(|e| e.to_string())
+}
+
+/// A brightness argument: a level, or a change to the current level
+#[derive(Debug, Clone, Copy)]
+enum BrightnessLevel {
+    /// Set this level (0-100)
+    Absolute(u8),
+    /// Change the current level by this much, clamped to 0-100
+    Relative(i16),
+}
+
+/// The color picked by the `color` arguments, which clap keeps from clashing
+///
+/// `--hue` (with `--sat` and `--val`) and the positional color win over the
+/// channel flags, whose defaults make white.
+fn color_input(hex: Option<Color>, rgb: Color, hue: Option<u16>, sat: u8, val: u8) -> Color {
+    match (hex, hue) {
+        (_, Some(hue)) => hsv_to_rgb(hue as f32, sat as f32 / 100.0, val as f32 / 100.0),
+        (Some(color), None) => color,
+        (None, None) => rgb,
+    }
+}
+
+/// Parse a brightness level (`40`) or change (`+10`, `-10`)
+fn parse_brightness(value: &str) -> std::result::Result<BrightnessLevel, String> {
+    let value = value.trim();
+    let invalid = || format!("invalid brightness '{}', expected 0-100, +N or -N", value);
+    if value.starts_with(['+', '-']) {
+        let change: i16 = value.parse().map_err(|_| invalid())?;
+        if !(-100..=100).contains(&change) {
+            return Err(invalid());
+        }
+        return Ok(BrightnessLevel::Relative(change));
+    }
+    match value.parse::<u8>() {
+        Ok(level) if level <= 100 => Ok(BrightnessLevel::Absolute(level)),
+        _ => Err(invalid()),
+    }
+}
+
+/// Parse a positive brightness gamma
+fn parse_gamma(value: &str) -> std::result::Result<f32, String> {
+    let gamma = value
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid gamma '{}', expected a positive number", value))?;
+    config::check_gamma(gamma)
+}
+
+/// Power the device on before a command that changes its light
+///
+/// The rule for all commands: those that change what the strip shows
+/// (colors, brightness, effects and animations) power it on first, unless
+/// `--no-power-on` is given. Commands that configure it (schedules, timers,
+/// raw frames), only adjust what already runs (`speed`) or spell the power
+/// out themselves (`on`, `off`, `run`, `repl`, scenes, `demo`) don't.
+async fn power_on_implicitly(device: &BleLedDevice, no_power_on: bool) -> Result<()> {
+    if !no_power_on && !device.is_on() {
+        device.power_on().await?;
+    }
+    Ok(())
+}
+
+/// Set the brightness, resolving a relative change against the device cache
+///
+/// The device can't report its brightness, so a change starts from the last
+/// level this tool set (or the library's default if there is none). The
+/// cache stays locked until the new level is written, so rapid invocations
+/// from a hotkey add up instead of racing. Returns the new level.
+async fn set_cached_brightness(device: &BleLedDevice, level: BrightnessLevel) -> Result<u8> {
+    let address = device.address();
+    let _lock = cache::DeviceCache::lock(&address)?;
+    let mut cache = cache::DeviceCache::load(&address)?;
+
+    let brightness = match level {
+        BrightnessLevel::Absolute(level) => level,
+        BrightnessLevel::Relative(change) => {
+            let current = match cache.get("brightness").and_then(|b| b.parse::<u8>().ok()) {
+                Some(current) => current,
+                None => {
+                    warn!(
+                        "No brightness cached for {}, assuming {}%",
+                        address,
+                        device.brightness()
+                    );
+                    device.brightness()
+                }
+            };
+            (current as i16 + change).clamp(0, 100) as u8
+        }
+    };
+
+    device.set_brightness(brightness).await?;
+    // set_brightness applies --max-brightness, so cache what was sent
+    let brightness = device.brightness();
+    cache.set("brightness", Some(brightness.to_string()));
+    cache.save()?;
+    Ok(brightness)
+}
+
+/// Record the schedules just written to `device` in the device cache
+///
+/// `None` leaves a schedule as cached, `Some(None)` marks it cleared. A cache
+/// that can't be written only costs `schedule-show` its answer, so failures
+/// are logged rather than failing the command.
+fn remember_schedules(
+    device: &BleLedDevice,
+    on: Option<Option<Schedule>>,
+    off: Option<Option<Schedule>>,
+) {
+    let result = cache::DeviceCache::load(&device.address()).and_then(|mut cache| {
+        if let Some(on) = on {
+            cache.set_schedule("schedule_on", on);
+        }
+        if let Some(off) = off {
+            cache.set_schedule("schedule_off", off);
+        }
+        cache.save()
+    });
+    if let Err(e) = result {
+        warn!("Could not update the device cache: {}", e);
+    }
+}
+
+/// Seed the device's state with the one this tool last left it in
+///
+/// The device can't report its state, so otherwise every run would start
+/// from the library's defaults. Power stays as connected, so `on` still
+/// sends its frame when the strip was switched off by other means.
+fn restore_cached_state(device: &BleLedDevice) {
+    let cached = cache::DeviceCache::load(&device.address())
+        .ok()
+        .and_then(|cache| DeviceState::from_json(cache.get("state")?).ok());
+    if let Some(state) = cached {
+        debug!("Restored cached state {}", state.to_json());
+        device.restore_state(DeviceState {
+            is_on: device.is_on(),
+            ..state
+        });
+    }
+}
+
+/// Record the device's brightness in the device cache, so a relative change
+/// such as `brightness +10` starts from it
+///
+/// Like [`remember_schedules`], failures are only logged.
+fn remember_brightness(device: &BleLedDevice) {
+    let address = device.address();
+    let result = cache::DeviceCache::lock(&address).and_then(|_lock| {
+        let mut cache = cache::DeviceCache::load(&address)?;
+        cache.set("brightness", Some(device.brightness().to_string()));
+        cache.save()
+    });
+    if let Err(e) = result {
+        warn!("Could not update the device cache: {}", e);
+    }
+}
+
+/// Number of writes for a fade: `steps`, or one per `command_delay`
+///
+/// Writing faster than the device's command delay drops or delays updates,
+/// so more `steps` than that allows are refused unless `force` is set.
+fn fade_steps(
+    duration: Duration,
+    steps: Option<u32>,
+    command_delay: Duration,
+    force: bool,
+) -> Result<u32> {
+    let max_steps = (duration.as_millis() / command_delay.max(Duration::from_millis(1)).as_millis())
+        .clamp(1, u32::MAX as u128) as u32;
+    match steps {
+        None => Ok(max_steps),
+        Some(steps) if steps > max_steps && !force => Err(Error::InvalidCommand(format!(
+            "{} steps over {:?} is a write every {:?}, faster than the device's {:?} \
+             command delay; use at most {} steps, a longer --duration or --force",
+            steps,
+            duration,
+            duration / steps,
+            command_delay,
+            max_steps
+        ))
+        .into()),
+        Some(steps) => Ok(steps),
+    }
+}
+
+/// Record the device's state in the device cache for the next run
+///
+/// Like [`remember_schedules`], failures are only logged.
+fn remember_state(device: &BleLedDevice) {
+    let address = device.address();
+    let result = cache::DeviceCache::lock(&address).and_then(|_lock| {
+        let mut cache = cache::DeviceCache::load(&address)?;
+        cache.set("state", Some(device.snapshot().to_json()));
+        cache.save()
+    });
+    if let Err(e) = result {
+        warn!("Could not update the device cache: {}", e);
+    }
+}
+
+/// Scene file from `--scenes`, the config file, or the default location
+fn scene_store(cli: &Cli) -> Result<SceneStore> {
+    let path = cli
+        .scenes
+        .clone()
+        .or_else(config::default_scenes_path)
+        .ok_or_else(|| color_eyre::eyre::eyre!("No home directory, pass --scenes"))?;
+    SceneStore::load(&path)
+        .wrap_err_with(|| format!("Could not read scenes from {}", path.display()))
+}
+
+/// Error for a scene name that isn't in `scenes`, listing the ones that are
+fn no_such_scene(scenes: &SceneStore, name: &str) -> color_eyre::Report {
+    let names: Vec<&str> = scenes.iter().map(|(name, _)| name).collect();
+    if names.is_empty() {
+        Error::InvalidCommand(format!(
+            "No scene named '{}', {} has no scenes yet",
+            name,
+            scenes.path().display()
+        ))
+        .into()
+    } else {
+        Error::InvalidCommand(format!(
+            "No scene named '{}', expected one of: {}",
+            name,
+            names.join(", ")
+        ))
+        .into()
+    }
+}
+
+/// One-line summary of a scene, e.g. `#ff8800, 80%` or `effect rainbow at speed 60, 100%`
+fn describe_scene(scene: &DeviceState) -> String {
+    let look = match (scene.effect, scene.color_temp_kelvin) {
+        (Some(effect), _) => match scene.effect_speed {
+            Some(speed) => format!("effect {} at speed {}", effect, speed),
+            None => format!("effect {}", effect),
+        },
+        (None, Some(kelvin)) => format!("white {}K", kelvin),
+        (None, None) => {
+            let (r, g, b) = scene.rgb_color;
+            format!("#{:02x}{:02x}{:02x}", r, g, b)
+        }
+    };
+    if scene.is_on {
+        format!("{}, {}%", look, scene.brightness)
+    } else {
+        "off".to_string()
+    }
+}
+
+/// Print the saved scenes with a summary of each
+fn list_scenes(scenes: &SceneStore, json: bool) -> Result<Outcome> {
+    if !json {
+        if scenes.iter().next().is_none() {
+            println!("No scenes saved in {}", scenes.path().display());
+        }
+        for (name, scene) in scenes.iter() {
+            println!("{:<16} {}", name, describe_scene(scene));
+        }
+    }
+
+    let entries: Vec<String> = scenes
+        .iter()
+        .map(|(name, scene)| {
+            format!(
+                "{{\"name\":{},\"summary\":{},\"state\":{}}}",
+                json_string(Some(name)),
+                json_string(Some(&describe_scene(scene))),
+                scene.to_json()
+            )
+        })
+        .collect();
+    Ok(Outcome::default().field("scenes", format!("[{}]", entries.join(","))))
+}
+
+/// Print the schedules this tool last wrote, for one device or all of them
+fn show_schedules(address: Option<&str>, json: bool) -> Result<Outcome> {
+    let caches = match address {
+        Some(address) => vec![cache::DeviceCache::load(address)?],
+        None => cache::DeviceCache::all()?,
+    };
+
+    let describe = |schedule: Option<Schedule>| match schedule {
+        Some(schedule) => format!(
+            "{} at {} ({})",
+            days_to_string(schedule.days.bits()),
+            schedule.time.format("%H:%M"),
+            describe_next(&schedule)
+        ),
+        None => "not set".to_string(),
+    };
+    if !json {
+        println!("Schedules as last written by this tool (the device can't report them):");
+        if caches.is_empty() {
+            println!("  none recorded yet");
+        }
+        for cache in &caches {
+            println!("{}", cache.address());
+            println!("  on:  {}", describe(cache.schedule("schedule_on")));
+            println!("  off: {}", describe(cache.schedule("schedule_off")));
+        }
+    }
+
+    let schedule_json = |schedule: Option<Schedule>| {
+        json_string(schedule.map(|schedule| schedule.to_string()).as_deref())
+    };
+    let devices: Vec<String> = caches
+        .iter()
+        .map(|cache| {
+            format!(
+                "{{\"device\":{},\"on\":{},\"off\":{}}}",
+                Value::from(cache.address()),
+                schedule_json(cache.schedule("schedule_on")),
+                schedule_json(cache.schedule("schedule_off"))
+            )
+        })
+        .collect();
+    Ok(Outcome::default()
+        .field("schedules", format!("[{}]", devices.join(",")))
+        .field("verified", "false".to_string()))
+}
+
+/// Parse a timer delay such as `30m` or `1h15m`, rejecting zero
+fn parse_delay(value: &str) -> std::result::Result<Duration, String> {
+    match parse_duration(value) {
+        Ok(delay) if delay.is_zero() => Err("the delay must be longer than zero".to_string()),
+        Ok(delay) => Ok(delay),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// A cancel handle that fires on Ctrl+C
+fn cancel_on_ctrl_c() -> CancelHandle {
+    let cancel = CancelHandle::new();
+    forward_ctrl_c(&cancel);
+    cancel
+}
+
+/// Cancels `cancel` on Ctrl+C
+fn forward_ctrl_c(cancel: &CancelHandle) {
+    let ctrl_c_cancel = cancel.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            info!("Received Ctrl+C, cancelling");
+            ctrl_c_cancel.cancel();
+        }
+    });
+}
+
+/// Loop through `colors`, showing each for `interval`
+///
+/// With `fade` the start of each interval fades in from the previous color.
+/// Stops after `count` rounds through the list, if given.
+async fn run_cycle(
+    device: &BleLedDevice,
+    colors: &[Color],
+    interval: Duration,
+    fade: Option<Duration>,
+    count: Option<u32>,
+) -> Result<()> {
+    let mut round = 0;
+    while count.is_none_or(|count| round < count) {
+        for &color in colors {
+            let started = tokio::time::Instant::now();
+            debug!("Cycle switching to RGB{:?}", color);
+            match fade {
+                Some(fade) => device.fade_to_color(color, fade).await?,
+                None => device.set_color(color.0, color.1, color.2).await?,
+            }
+            tokio::time::sleep_until(started + interval).await;
+        }
+        round += 1;
+    }
+    Ok(())
+}
+
+/// Turn the device off (or on) after `delay`
+///
+/// With `stay_connected` the command is sent from here when the delay is
+/// over. Otherwise the device's schedule is programmed for that minute and
+/// cleared once it fired, as the schedule would repeat a week later. Either
+/// way the process waits, and Ctrl+C cancels the timer.
+async fn run_timer(
+    device: &BleLedDevice,
+    delay: Duration,
+    power_on: bool,
+    stay_connected: bool,
+) -> Result<Outcome> {
+    use chrono::{Datelike, Local, Timelike};
+
+    let verb = if power_on { "on" } else { "off" };
+
+    if stay_connected {
+        let at = Local::now() + chrono::Duration::from_std(delay)?;
+        let action = if power_on {
+            LineCommand::PowerOn
+        } else {
+            LineCommand::PowerOff
+        };
+        let (cancel, timer) = device.at_time(at, action)?;
+        forward_ctrl_c(&cancel);
+        info!(
+            "Turning {} at {}. Press Ctrl+C to cancel.",
+            verb,
+            at.format("%Y-%m-%d %H:%M:%S")
+        );
+        let applied = timer.await?;
+        return Ok(Outcome::default()
+            .field("at", json_string(Some(&at.to_rfc3339())))
+            .field("applied", applied.to_string()));
+    }
+
+    let cancel = cancel_on_ctrl_c();
+    let at = if power_on {
+        // The schedule has minute resolution, so round up like sleep_timer does
+        let at = Local::now() + chrono::Duration::from_std(delay)?;
+        let at = match at.with_second(0).and_then(|t| t.with_nanosecond(0)) {
+            Some(minute) if minute == at => minute,
+            Some(minute) => minute + chrono::Duration::minutes(1),
+            None => at,
+        };
+        device
+            .set_schedule_on(
+                Days::from_weekday(at.weekday()),
+                at.hour() as u8,
+                at.minute() as u8,
+                true,
+            )
+            .await?;
+        at
+    } else {
+        device.sleep_timer(delay).await?.off_at()
+    };
+    info!(
+        "Turning {} at {}. Press Ctrl+C to cancel the timer.",
+        verb,
+        at.format("%Y-%m-%d %H:%M")
+    );
+
+    // Wait past the scheduled minute so the one-off entry can be cleared
+    let wait = (at - Local::now()).to_std().unwrap_or_default() + Duration::from_secs(60);
+    let fired = tokio::select! {
+        _ = tokio::time::sleep(wait) => true,
+        _ = cancel.cancelled() => false,
+    };
+    if power_on {
+        device
+            .set_schedule_on(
+                Days::from_weekday(at.weekday()),
+                at.hour() as u8,
+                at.minute() as u8,
+                false,
+            )
+            .await?;
+    } else {
+        device.cancel_sleep_timer().await?;
+    }
+    Ok(Outcome::default()
+        .field("at", json_string(Some(&at.to_rfc3339())))
+        .field("applied", fired.to_string()))
+}
+
+/// Parse a local time such as `2024-01-01T20:00` or `20:00` (the next time it is 20:00)
+fn parse_local_time(value: &str) -> std::result::Result<chrono::DateTime<chrono::Local>, String> {
+    use chrono::{Local, NaiveDateTime, NaiveTime, TimeZone};
+
+    let value = value.trim();
+    let naive = [
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%d %H:%M:%S",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok());
+    let naive = match naive {
+        Some(naive) => naive,
+        None => {
+            let time = match NaiveTime::parse_from_str(value, "%H:%M:%S") {
+                Ok(time) => time,
+                // Without a date the time of day parser has the clearer error
+                Err(_) if !value.contains('-') => {
+                    let (hour, minute) = parse_time_of_day(value)?;
+                    NaiveTime::from_hms_opt(hour as u32, minute as u32, 0)
+                        .expect("parse_time_of_day checks the range")
+                }
+                Err(_) => {
+                    return Err(format!(
+                        "invalid time '{}', expected e.g. 2024-01-01T20:00 or 20:00",
+                        value
+                    ))
+                }
+            };
+            let now = Local::now().naive_local();
+            let today = now.date().and_time(time);
+            if today > now {
+                today
+            } else {
+                today + chrono::Duration::days(1)
+            }
+        }
+    };
+
+    let when = Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .ok_or_else(|| format!("{} does not exist in the local time zone", naive))?;
+    if when <= Local::now() {
+        return Err(format!("{} is in the past", naive));
+    }
+    Ok(when)
+}
+
+/// Parse a time of day into hour and minute
+///
+/// Takes `H:MM` or `HH:MM` on a 24-hour clock, or a 12-hour clock with an
+/// am/pm suffix such as `8:30pm`, `8:30 PM` or `8pm`.
+fn parse_time_of_day(value: &str) -> std::result::Result<(u8, u8), String> {
+    let invalid = || {
+        format!(
+            "invalid time '{}', expected e.g. 07:30, 7:30 or 7:30pm",
+            value
+        )
+    };
+
+    let lower = value.trim().to_ascii_lowercase();
+    let (clock, pm) = match (lower.strip_suffix("am"), lower.strip_suffix("pm")) {
+        (Some(clock), _) => (clock.trim_end(), Some(false)),
+        (_, Some(clock)) => (clock.trim_end(), Some(true)),
+        _ => (lower.as_str(), None),
+    };
+    let (hour, minute) = match clock.split_once(':') {
+        Some((hour, minute)) => (hour, minute),
+        // Only "8pm" may leave out the minutes
+        None if pm.is_some() => (clock, "00"),
+        None => return Err(invalid()),
+    };
+    let digits = |s: &str, len: std::ops::RangeInclusive<usize>| {
+        len.contains(&s.len()) && s.bytes().all(|b| b.is_ascii_digit())
+    };
+    if !digits(hour, 1..=2) || !digits(minute, 2..=2) {
+        return Err(invalid());
+    }
+    let (hour, minute): (u8, u8) = (
+        hour.parse().map_err(|_| invalid())?,
+        minute.parse().map_err(|_| invalid())?,
+    );
+
+    if minute > 59 {
+        return Err(format!("invalid time '{}': minute must be 00-59", value));
+    }
+    let hour = match pm {
+        None if hour > 23 => {
+            return Err(format!("invalid time '{}': hour must be 0-23", value));
+        }
+        None => hour,
+        Some(_) if !(1..=12).contains(&hour) => {
+            return Err(format!(
+                "invalid time '{}': hour must be 1-12 with am/pm",
+                value
+            ));
+        }
+        // 12am is midnight and 12pm is noon
+        Some(pm) => hour % 12 + if pm { 12 } else { 0 },
+    };
+    Ok((hour, minute))
+}
+
+/// Build a schedule from CLI values, which clap has already range-checked
+fn schedule_at(days: Days, hour: u8, minute: u8) -> Schedule {
+    let time = chrono::NaiveTime::from_hms_opt(hour as u32, minute as u32, 0)
+        .expect("hour and minute are validated by clap");
+    Schedule::new(days, time)
+}
+
+/// Describe when a schedule fires next, e.g. `next: Tue 08:30 (in 9h 12m)`
+fn describe_next(schedule: &Schedule) -> String {
+    let now = chrono::Local::now();
+    let Some(next) = schedule.next_occurrence(now) else {
+        return "never fires".to_string();
+    };
+
+    // Round up so a schedule in 30 seconds reads as 1m, not 0m
+    let minutes = ((next - now).num_seconds() + 59) / 60;
+    let until = match (minutes / (24 * 60), minutes / 60 % 24, minutes % 60) {
+        (0, 0, m) => format!("{}m", m),
+        (0, h, m) => format!("{}h {}m", h, m),
+        (d, h, _) => format!("{}d {}h", d, h),
+    };
+    format!("next: {} (in {})", next.format("%a %H:%M"), until)
+}
+
+/// The frame for `raw`: `frame` parsed as hex, or built from `id`
+///
+/// Hex frames must be 9 bytes long unless `unchecked`; frames that don't
+/// start with 0x7e or end with 0xef only get a warning.
+fn raw_frame(
+    frame: Option<&str>,
+    unchecked: bool,
+    id: Option<u8>,
+    sub_id: u8,
+    args: &[u8],
+) -> Result<Vec<u8>> {
+    let frame = match (frame, id) {
+        (_, Some(id)) => {
+            if args.len() > 3 {
+                return Err(Error::InvalidCommand(format!(
+                    "--args takes at most three values, got {}",
+                    args.len()
+                ))
+                .into());
+            }
+            let arg = |i: usize| args.get(i).copied().unwrap_or(0);
+            protocol::generic_frame(id, sub_id, arg(0), arg(1), arg(2)).to_vec()
+        }
+        (Some(frame), None) => protocol::parse_hex_frame(frame)?,
+        (None, None) => unreachable!("clap requires a frame or --id"),
+    };
+
+    if !unchecked && frame.len() != protocol::COMMAND_LEN {
+        return Err(Error::InvalidFrame(format!(
+            "Frame has {} bytes, commands have {}; pass --unchecked to send it anyway",
+            frame.len(),
+            protocol::COMMAND_LEN
+        ))
+        .into());
+    }
+    if frame.first() != Some(&protocol::FRAME_HEADER)
+        || frame.last() != Some(&protocol::FRAME_TERMINATOR)
+    {
+        warn!(
+            "Frame doesn't start with {:#04x} and end with {:#04x}, the device will likely ignore it",
+            protocol::FRAME_HEADER,
+            protocol::FRAME_TERMINATOR
+        );
+    }
+    Ok(frame)
+}
+
+/// Parse an effect code (or other byte) given in decimal or 0x-prefixed hex
+fn parse_code(value: &str) -> std::result::Result<u8, String> {
+    let parsed = match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    parsed.map_err(|e| format!("invalid code '{}': {}", value, e))
+}
+
+/// Interactively probe effect codes and write the notes to a JSON file
 #[instrument(skip(device))]
+async fn run_effect_probe(
+    device: &mut BleLedDevice,
+    start: u8,
+    end: u8,
+    dwell_ms: u64,
+    output: &std::path::Path,
+) -> Result<()> {
+    info!(
+        "Probing effect codes {:#04x}-{:#04x}, {}ms each",
+        start, end, dwell_ms
+    );
+    eprintln!("Describe what the strip does for each code, or press Enter (or s) to skip.");
+
+    let notes = device
+        .probe_effects(start..=end, Duration::from_millis(dwell_ms), |code| {
+            eprint!("{:#04x}> ", code);
+            let _ = std::io::stderr().flush();
+
+            // Let the runtime hand this worker's other tasks to another
+            // thread while the prompt waits for the terminal
+            let mut line = String::new();
+            let read = tokio::task::block_in_place(|| std::io::stdin().read_line(&mut line));
+            if read.is_err() {
+                return None;
+            }
+            let note = line.trim();
+            (!note.is_empty() && note != "s").then(|| note.to_string())
+        })
+        .await?;
+
+    let device_type = device.get_device_type_name();
+    let entries: Vec<String> = notes
+        .iter()
+        .map(|(code, note)| {
+            format!(
+                "    {{ \"code\": {}, \"hex\": \"{:#04x}\", \"note\": {} }}",
+                code,
+                code,
+                Value::from(note.as_str())
+            )
+        })
+        .collect();
+    let json = format!(
+        "{{\n  \"device_type\": \"{}\",\n  \"effects\": [\n{}\n  ]\n}}\n",
+        device_type,
+        entries.join(",\n")
+    );
+    std::fs::write(output, json)?;
+
+    info!("Wrote {} notes to {}", notes.len(), output.display());
+    Ok(())
+}
+
+/// Lines of stdin, read without blocking the runtime
+fn stdin_lines() -> Lines<BufReader<tokio::io::Stdin>> {
+    BufReader::new(tokio::io::stdin()).lines()
+}
+
+/// Read line commands from stdin and run them until `quit` or end of input
+///
+/// Errors are printed and the session goes on.
+async fn run_repl(device: &BleLedDevice, json: bool) -> Result<()> {
+    // With --json every command answers with one JSON line and there is no prompt
+    if !json {
+        println!(
+            "Connected to {}. Type 'help' for commands.",
+            device.get_device_type_name()
+        );
+    }
+    let mut lines = stdin_lines();
+    loop {
+        if !json {
+            print!("elk> ");
+            std::io::stdout().flush()?;
+        }
+
+        let Some(line) = lines.next_line().await? else {
+            if !json {
+                println!();
+            }
+            return Ok(());
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let command = match line.parse::<LineCommand>() {
+            Ok(LineCommand::Quit) => return Ok(()),
+            Ok(command) => command,
+            Err(e) => {
+                let e = color_eyre::Report::from(e);
+                if json {
+                    println!("{}", error_json(&e));
+                } else {
+                    eprintln!("error: {}", e);
+                }
+                continue;
+            }
+        };
+        let result = match command {
+            // Readable status instead of the JSON elkd prints
+            LineCommand::Status => run_status(device, json)
+                .await
+                .map(|outcome| (None, outcome)),
+            command => command.run(device).await.map_err(Into::into).map(|output| {
+                let outcome = Outcome {
+                    device: Some(device.address()),
+                    state: Some(device.snapshot()),
+                    ..Outcome::default()
+                };
+                (output, outcome)
+            }),
+        };
+        match result {
+            Ok((output, outcome)) if json => {
+                let outcome = match output {
+                    Some(output) if command == LineCommand::Help => {
+                        outcome.field("help", json_string(Some(&output)))
+                    }
+                    Some(output) if command == LineCommand::Metrics => {
+                        outcome.field("metrics", json_string(Some(&output)))
+                    }
+                    _ => outcome,
+                };
+                println!("{}", outcome.to_json(command.name()));
+            }
+            Ok((Some(output), _)) => println!("{}", output),
+            Ok((None, _)) => {}
+            Err(e) if json => println!("{}", error_json(&e)),
+            Err(e) => eprintln!("error: {}", e),
+        }
+    }
+}
+
+/// Show the device's identity and last known state
+///
+/// The protocol has no state query, so the state is what this process
+/// assumes after connecting and is marked as unverified. Prints it unless
+/// `json` is set; the outcome carries it either way.
+async fn run_status(device: &BleLedDevice, json: bool) -> Result<Outcome> {
+    let state = device.snapshot();
+    let rssi = device.rssi().await.unwrap_or_else(|e| {
+        debug!("Could not read RSSI: {}", e);
+        None
+    });
+    let firmware = device
+        .read_firmware_version()
+        .await
+        .map_err(|e| debug!("Could not read the firmware version: {}", e))
+        .ok();
+    let outcome = Outcome {
+        device: Some(device.address()),
+        state: Some(state),
+        ..Outcome::default()
+    }
+    .field(
+        "device_type",
+        json_string(Some(device.device_type().name())),
+    )
+    .field("name", json_string(device.name().as_deref()))
+    .field(
+        "rssi",
+        rssi.map_or_else(|| "null".to_string(), |r| r.to_string()),
+    )
+    .field("firmware", json_string(firmware.as_deref()))
+    .field("verified", "false".to_string())
+    .field("metrics", device.metrics().to_json());
+
+    if json {
+        return Ok(outcome);
+    }
+
+    let (r, g, b) = state.rgb_color;
+    println!("Device:      {}", device.get_device_type_name());
+    println!("Name:        {}", device.name().as_deref().unwrap_or("-"));
+    println!("Address:     {}", device.address());
+    println!("Firmware:    {}", firmware.as_deref().unwrap_or("unknown"));
+    println!(
+        "RSSI:        {}",
+        rssi.map_or_else(|| "-".to_string(), |r| format!("{} dBm", r))
+    );
+    let metrics = device.metrics();
+    println!(
+        "Commands:    {} sent, {} failed, {} retries",
+        metrics.commands_sent, metrics.commands_failed, metrics.retries
+    );
+    println!("State (unverified, the device does not report it):");
+    println!("  Power:       {}", if state.is_on { "on" } else { "off" });
+    println!("  Color:       #{:02x}{:02x}{:02x}", r, g, b);
+    println!("  Brightness:  {}%", state.brightness);
+    println!(
+        "  Effect:      {}",
+        state
+            .effect
+            .map_or_else(|| "-".to_string(), |e| e.to_string())
+    );
+    println!(
+        "  Color temp:  {}",
+        state
+            .color_temp_kelvin
+            .map_or_else(|| "-".to_string(), |k| format!("{}K", k))
+    );
+    Ok(outcome)
+}
+
+/// Print every frame the device pushes until `duration` passes or Ctrl+C
+///
+/// One line per frame, with what it does when it is a known command frame
+/// and its hex bytes; with `json`, one JSON object per frame on stdout
+/// before the final result. Unsubscribes before returning.
+async fn run_watch(
+    device: &BleLedDevice,
+    duration: Option<Duration>,
+    json: bool,
+) -> Result<Outcome> {
+    use futures::StreamExt;
+
+    let notifications = device.notifications().await?;
+    tokio::pin!(notifications);
+    let cancel = cancel_on_ctrl_c();
+    let deadline = tokio::time::sleep(duration.unwrap_or(Duration::MAX));
+    tokio::pin!(deadline);
+    info!("Watching for frames from the device. Press Ctrl+C to exit.");
+
+    let mut count = 0;
+    loop {
+        let frame = tokio::select! {
+            frame = notifications.next() => match frame {
+                Some(frame) => frame,
+                None => {
+                    warn!("The device stopped sending notifications");
+                    break;
+                }
+            },
+            _ = cancel.cancelled() => break,
+            _ = &mut deadline => break,
+        };
+        count += 1;
+
+        let time = chrono::Local::now().format("%H:%M:%S%.3f");
+        let hex = protocol::format_frame(&frame);
+        let event = protocol::frame_event(&frame).and_then(|event| describe_event(&event));
+        if json {
+            let event_fields = event.map_or_else(String::new, |(_, fields)| format!(",{}", fields));
+            println!(
+                "{{\"time\":\"{}\",\"frame\":\"{}\",\"description\":\"{}\"{}}}",
+                time,
+                hex,
+                protocol::describe_frame(&frame),
+                event_fields
+            );
+        } else {
+            let text = event.map_or_else(|| "unknown frame".to_string(), |(text, _)| text);
+            println!("{} {:<24} {}", time, text, hex);
+        }
+    }
+
+    device.stop_notifications().await?;
+    info!("Stopped watching after {} frames", count);
+    Ok(Outcome::default().field("frames", count.to_string()))
+}
+
+/// Text and JSON fields for an event decoded from a pushed frame, e.g.
+/// `color 255,0,0` and `"event":"color","rgb":[255,0,0]`
+fn describe_event(event: &DeviceEvent) -> Option<(String, String)> {
+    let described = match *event {
+        DeviceEvent::PoweredOn => ("power on".to_string(), "\"event\":\"power_on\"".to_string()),
+        DeviceEvent::PoweredOff => (
+            "power off".to_string(),
+            "\"event\":\"power_off\"".to_string(),
+        ),
+        DeviceEvent::ColorSet((red, green, blue)) => (
+            format!("color {},{},{}", red, green, blue),
+            format!("\"event\":\"color\",\"rgb\":[{},{},{}]", red, green, blue),
+        ),
+        DeviceEvent::BrightnessSet(level) => (
+            format!("brightness {}", level),
+            format!("\"event\":\"brightness\",\"level\":{}", level),
+        ),
+        DeviceEvent::EffectSet(code) => (
+            format!("effect {:#04x}", code),
+            format!("\"event\":\"effect\",\"code\":{}", code),
+        ),
+        DeviceEvent::EffectSpeedSet(speed) => (
+            format!("effect speed {}", speed),
+            format!("\"event\":\"effect_speed\",\"speed\":{}", speed),
+        ),
+        _ => return None,
+    };
+    Some(described)
+}
+
+/// Scan for nearby devices and print them as a table unless `json` is set
+///
+/// Exits with code 3 when no compatible device was found, like a failed
+/// connection, so scripts can branch on it.
+async fn run_scan(duration: Duration, all: bool, json: bool) -> Result<Outcome> {
+    let devices: Vec<DiscoveredDevice> = BleLedDevice::scan(duration)
+        .await?
+        .into_iter()
+        .filter(|d| all || d.is_compatible())
+        .collect();
+    let found = devices.iter().any(DiscoveredDevice::is_compatible);
+
+    let entries: Vec<String> = devices
+        .iter()
+        .map(|d| {
+            format!(
+                "{{\"name\":{},\"address\":{},\"id\":{},\"device_type\":{},\"rssi\":{},\"connected\":{}}}",
+                json_string(d.name.as_deref()),
+                Value::from(d.address.as_str()),
+                Value::from(d.id.as_str()),
+                json_string(d.device_type.map(DeviceType::name)),
+                d.rssi.map_or_else(|| "null".to_string(), |r| r.to_string()),
+                d.connected,
+            )
+        })
+        .collect();
+    let mut outcome = Outcome::default().field("devices", format!("[{}]", entries.join(",")));
+    if !found {
+        outcome.exit_code = 3;
+    }
+
+    if json {
+        return Ok(outcome);
+    }
+
+    if devices.is_empty() {
+        println!("No devices found");
+        return Ok(outcome);
+    }
+    println!(
+        "{:<20} {:<36} {:<10} {:>5}  CONNECTED",
+        "NAME", "ADDRESS", "TYPE", "RSSI"
+    );
+    for d in &devices {
+        let device_type = d
+            .device_type
+            .map_or_else(|| "-".to_string(), |t| format!("{:?}", t));
+        let rssi = d.rssi.map_or_else(|| "-".to_string(), |r| r.to_string());
+        println!(
+            "{:<20} {:<36} {:<10} {:>5}  {}",
+            d.name.as_deref().unwrap_or("-"),
+            d.address,
+            device_type,
+            rssi,
+            if d.connected { "yes" } else { "no" }
+        );
+    }
+    Ok(outcome)
+}
+
+/// Run the steps of a script in order, stopping at the first failure
+/// unless `keep_going` is set
+///
+/// Steps come with their number, counted in `unit`s (`"Step"` or `"Line"`
+/// of a script file). Each step's result is logged and reported as the
+/// `steps` member of the JSON outcome, numbered under the lowercase unit.
+/// Any failed step makes the exit code 1.
+async fn run_script(
+    device: &BleLedDevice,
+    steps: &[(usize, LineCommand)],
+    unit: &str,
+    keep_going: bool,
+    json: bool,
+) -> Result<Outcome> {
+    let mut outcome = Outcome::default();
+    let mut results = Vec::with_capacity(steps.len());
+    let key = unit.to_lowercase();
+    for (number, step) in steps {
+        if *step == LineCommand::Quit {
+            info!("{} {}: quit, skipping the remaining steps", unit, number);
+            break;
+        }
+        match step.run(device).await {
+            Ok(output) => {
+                info!("{} {}: {} ok", unit, number, step.name());
+                // Keep stdout to the single JSON object with --json
+                match output {
+                    Some(output) if json => eprintln!("{}", output),
+                    Some(output) => println!("{}", output),
+                    None => {}
+                }
+                results.push(format!(
+                    "{{\"{}\":{},\"command\":\"{}\",\"ok\":true}}",
+                    key,
+                    number,
+                    step.name()
+                ));
+            }
+            Err(e) => {
+                error!("{} {}: {} failed: {}", unit, number, step.name(), e);
+                results.push(format!(
+                    "{{\"{}\":{},\"command\":\"{}\",\"ok\":false,\"error\":{{\"kind\":\"{}\",\"message\":{}}}}}",
+                    key,
+                    number,
+                    step.name(),
+                    e.kind(),
+                    Value::from(e.to_string())
+                ));
+                outcome.exit_code = 1;
+                if !keep_going {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(outcome.field("steps", format!("[{}]", results.join(","))))
+}
+
+/// Read and parse a script file for `run-script`
+fn read_script(path: &std::path::Path) -> Result<Vec<(usize, LineCommand)>> {
+    let text = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("Could not read {}", path.display()))?;
+    parse_script_lines(&text).wrap_err_with(|| format!("Invalid script {}", path.display()))
+}
+
+/// What a command did, printed as one JSON object with `--json`
+#[derive(Debug, Default)]
+struct Outcome {
+    /// Address of the device the command ran on
+    device: Option<String>,
+    /// Cached state of that device after the command
+    state: Option<DeviceState>,
+    /// Extra members as `(key, value)`, the values already formatted as JSON
+    fields: Vec<(&'static str, String)>,
+    /// Exit code for results scripts should branch on, 0 for success
+    exit_code: i32,
+}
+
+impl Outcome {
+    /// Adds a member whose value is already formatted as JSON
+    fn field(mut self, key: &'static str, value: String) -> Self {
+        self.fields.push((key, value));
+        self
+    }
+
+    /// Formats as `{"ok":true,"command":"...","device":"...","state":{...},...}`
+    ///
+    /// `ok` is false when the exit code is not 0, e.g. after a failed script step.
+    fn to_json(&self, command: &str) -> String {
+        let mut json = format!(
+            "{{\"ok\":{},\"command\":\"{}\"",
+            self.exit_code == 0,
+            command
+        );
+        if let Some(device) = &self.device {
+            json.push_str(&format!(",\"device\":{}", Value::from(device.as_str())));
+        }
+        if let Some(state) = &self.state {
+            json.push_str(&format!(",\"state\":{}", state.to_json()));
+        }
+        for (key, value) in &self.fields {
+            json.push_str(&format!(",\"{}\":{}", key, value));
+        }
+        json.push('}');
+        json
+    }
+}
+
+/// Formats a failure as `{"ok":false,"error":{"kind":"...","message":"..."}}`
+///
+/// The kind is the library's error variant, or `Other` for errors from
+/// elsewhere (e.g. reading a file).
+fn error_json(error: &color_eyre::Report) -> String {
+    let kind = error.downcast_ref::<Error>().map_or("Other", Error::kind);
+    format!(
+        "{{\"ok\":false,\"error\":{{\"kind\":\"{}\",\"message\":{}}}}}",
+        kind,
+        Value::from(error.to_string())
+    )
+}
+
+/// Format an optional string as a JSON string or `null`
+fn json_string(value: Option<&str>) -> String {
+    Value::from(value).to_string()
+}
+
+/// Sleep for specified number of seconds
+#[instrument]
+async fn sleep(seconds: u64) {
+    trace!("Sleeping for {}s", seconds);
+    tokio::time::sleep(Duration::from_secs(seconds)).await;
+    trace!("Sleep completed");
+}
+
+/// Run audio visualization on the LED strip
+#[cfg(feature = "audio")]
+#[instrument(skip(device, audio_monitor))]
 async fn run_audio_visualization(
     device: &mut BleLedDevice,
+    audio_monitor: AudioMonitor,
     mode: AudioModeType,
     range: AudioRangeType,
     sensitivity: u8,
     update_ms: u32,
     test: bool,
-    audio_device: Option<String>,
 ) -> Result<()> {
     info!("Initializing audio monitoring in {:?} mode", mode);
 
-    // Create audio monitor
-    let audio_monitor = match AudioMonitor::new_with_device(audio_device) {
-        Ok(monitor) => monitor,
-        Err(e) => {
-            error!("Failed to initialize audio monitoring: {}", e);
-            return Err(e.into());
-        }
-    };
-
     // Configure audio visualization
     let mut config = audio_monitor.get_config();
-    config.mode = mode.clone().into();
+    config.mode = mode.into();
     config.range = range.into();
     config.sensitivity = sensitivity as f32 / 100.0; // Convert 0-100 to 0.0-1.0
     config.update_interval_ms = update_ms;
 
     audio_monitor.set_config(config);
 
+    // Test mode - only display audio levels
+    if test {
+        info!("Starting audio level display. Press Ctrl+C to exit.");
+        let ctrl_c = tokio::signal::ctrl_c();
+        tokio::select! {
+            _ = display_audio_levels(&audio_monitor, update_ms) => {}
+            _ = ctrl_c => {
+                println!();
+                info!("Received Ctrl+C, stopping audio level display");
+            }
+        }
+        audio_monitor.stop();
+        return Ok(());
+    }
+
     // Normal mode - control LEDs with audio
     info!("Starting audio visualization. Press Ctrl+C to exit.");
 
@@ -480,83 +3154,176 @@ async fn run_audio_visualization(
     Ok(())
 }
 
+/// Continuously print ASCII meters for each frequency band
+#[cfg(feature = "audio")]
+async fn display_audio_levels(audio_monitor: &AudioMonitor, update_ms: u32) {
+    let mut stdout = std::io::stdout();
+    loop {
+        let analysis = audio_monitor.get_analysis();
+        print!(
+            "\rBass [{}] Mid [{}] High [{}] BPM {:5.1} ({:3.0}% sure)",
+            render_meter(analysis.energy[0], analysis.peak[0], 20),
+            render_meter(analysis.energy[1], analysis.peak[1], 20),
+            render_meter(analysis.energy[2], analysis.peak[2], 20),
+            analysis.bpm,
+            analysis.beat_confidence * 100.0
+        );
+        let _ = stdout.flush();
+        tokio::time::sleep(Duration::from_millis(update_ms as u64)).await;
+    }
+}
+
+/// Render a single meter bar, marking the peak position with `|`
+#[cfg(feature = "audio")]
+fn render_meter(level: f32, peak: f32, width: usize) -> String {
+    let filled = (level.clamp(0.0, 1.0) * width as f32).round() as usize;
+    let peak_pos = ((peak.clamp(0.0, 1.0) * width as f32).round() as usize).min(width - 1);
+    (0..width)
+        .map(|i| {
+            if peak > 0.0 && i == peak_pos {
+                '|'
+            } else if i < filled {
+                '#'
+            } else {
+                ' '
+            }
+        })
+        .collect()
+}
+
+/// The demo steps to run: `only` (or every step) without `skip`, in demo order
+fn demo_steps(only: &[DemoStep], skip: &[DemoStep]) -> Result<Vec<DemoStep>> {
+    let steps: Vec<DemoStep> = DemoStep::ALL
+        .into_iter()
+        .filter(|step| only.is_empty() || only.contains(step))
+        .filter(|step| !skip.contains(step))
+        .collect();
+    if steps.is_empty() {
+        return Err(Error::InvalidCommand("--skip leaves no demo steps to run".to_string()).into());
+    }
+    Ok(steps)
+}
+
 /// TODO: Convert this to test
 /// Run a demonstration of various LED strip features
 #[instrument(skip(device))]
-async fn run_demo(device: &mut BleLedDevice, duration: u64) -> Result<()> {
-    info!("Running LED strip demo with {}s intervals", duration);
+async fn run_demo(
+    device: &mut BleLedDevice,
+    duration: u64,
+    steps: &[DemoStep],
+    repeat: bool,
+) -> Result<()> {
+    info!(
+        "Running LED strip demo with {}s intervals: {:?}",
+        duration, steps
+    );
 
     // Power on the leds
     info!("Turning LEDs on");
     device.power_on().await?;
     sleep(duration).await;
 
-    // Set a static color
-    info!("Setting color to red");
-    device.set_color(255, 0, 0).await?; // Red
-    sleep(duration).await;
-
-    info!("Setting color to green");
-    device.set_color(0, 255, 0).await?; // Green
-    sleep(duration).await;
-
-    info!("Setting color to blue");
-    device.set_color(0, 0, 255).await?; // Blue
-    sleep(duration).await;
+    if repeat {
+        info!("Looping the demo. Press Ctrl+C to stop.");
+        tokio::select! {
+            result = async {
+                loop {
+                    if let Err(e) = run_demo_steps(device, duration, steps).await {
+                        break Err(e);
+                    }
+                }
+            } => result?,
+            _ = tokio::signal::ctrl_c() => info!("Received Ctrl+C, ending demo"),
+        }
+    } else {
+        run_demo_steps(device, duration, steps).await?;
+    }
 
-    // Set led brightness (0-100)
-    info!("Setting brightness to 50%");
-    device.set_brightness(50).await?;
-    sleep(duration).await;
+    // Go back to static white
+    info!("Back to static white");
+    device.set_color(255, 255, 255).await?;
+    sleep(1).await;
 
-    info!("Setting brightness to 100%");
-    device.set_brightness(100).await?;
-    sleep(duration).await;
+    // End demo by turning off the lights
+    info!("Turning LEDs off to end demo");
+    device.power_off().await?;
 
-    // Try color temperature
-    info!("Setting warm white (2700K)");
-    device.set_color_temp_kelvin(2700).await?;
-    sleep(duration).await;
+    info!("Demo completed!");
+    Ok(())
+}
 
-    info!("Setting cool white (6500K)");
-    device.set_color_temp_kelvin(6500).await?;
-    sleep(duration).await;
+/// Run one pass over the selected demo steps
+async fn run_demo_steps(
+    device: &mut BleLedDevice,
+    duration: u64,
+    steps: &[DemoStep],
+) -> Result<()> {
+    for step in steps {
+        match step {
+            DemoStep::Color => {
+                // Set a static color
+                info!("Setting color to red");
+                device.set_color(255, 0, 0).await?; // Red
+                sleep(duration).await;
 
-    // Set different effects
-    info!("Setting rainbow crossfade effect");
-    device
-        .set_effect(EFFECTS.crossfade_red_green_blue_yellow_cyan_magenta_white)
-        .await?;
-    sleep(duration).await;
+                info!("Setting color to green");
+                device.set_color(0, 255, 0).await?; // Green
+                sleep(duration).await;
 
-    info!("Setting RGB jump effect");
-    device.set_effect(EFFECTS.jump_red_green_blue).await?;
-    sleep(duration).await;
+                info!("Setting color to blue");
+                device.set_color(0, 0, 255).await?; // Blue
+                sleep(duration).await;
+            }
+            DemoStep::Brightness => {
+                // Set led brightness (0-100)
+                info!("Setting brightness to 50%");
+                device.set_brightness(50).await?;
+                sleep(duration).await;
 
-    info!("Setting RGB blink effect");
-    device
-        .set_effect(EFFECTS.blink_red_green_blue_yellow_cyan_magenta_white)
-        .await?;
-    sleep(duration).await;
+                info!("Setting brightness to 100%");
+                device.set_brightness(100).await?;
+                sleep(duration).await;
+            }
+            DemoStep::ColorTemp => {
+                // Try color temperature
+                info!("Setting warm white (2700K)");
+                device.set_color_temp_kelvin(2700).await?;
+                sleep(duration).await;
 
-    // Set effect speed
-    info!("Setting effect speed to slow (20)");
-    device.set_effect_speed(20).await?;
-    sleep(duration).await;
+                info!("Setting cool white (6500K)");
+                device.set_color_temp_kelvin(6500).await?;
+                sleep(duration).await;
+            }
+            DemoStep::Effects => {
+                // Set different effects
+                info!("Setting rainbow crossfade effect");
+                device.set_effect(Effect::CrossfadeAll).await?;
+                sleep(duration).await;
 
-    info!("Setting effect speed to fast (80)");
-    device.set_effect_speed(80).await?;
-    sleep(duration).await;
+                info!("Setting RGB jump effect");
+                device.set_effect(Effect::JumpRgb).await?;
+                sleep(duration).await;
 
-    // Go back to static white
-    info!("Back to static white");
-    device.set_color(255, 255, 255).await?;
-    sleep(1).await;
+                info!("Setting RGB blink effect");
+                device.set_effect(Effect::BlinkAll).await?;
+                sleep(duration).await;
+            }
+            DemoStep::Speed => {
+                // Speed changes are only visible while an effect runs
+                if device.effect().is_none() {
+                    device.set_effect(Effect::CrossfadeAll).await?;
+                }
 
-    // End demo by turning off the lights
-    info!("Turning LEDs off to end demo");
-    device.power_off().await?;
+                // Set effect speed
+                info!("Setting effect speed to slow (20)");
+                device.set_effect_speed(20).await?;
+                sleep(duration).await;
 
-    info!("Demo completed!");
+                info!("Setting effect speed to fast (80)");
+                device.set_effect_speed(80).await?;
+                sleep(duration).await;
+            }
+        }
+    }
     Ok(())
 }
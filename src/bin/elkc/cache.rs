@@ -0,0 +1,178 @@
+/*!
+ # Device cache
+
+ What this tool last wrote to each device, for settings the device cannot
+ report back (such as its schedules). One small text file per device with
+ `key value` lines, under `$XDG_CACHE_HOME/elk-led-controller` (falling back
+ to `~/.cache`, or `%LOCALAPPDATA%` on Windows).
+*/
+
+use chrono::NaiveTime;
+use color_eyre::eyre::{Result, WrapErr};
+use elk_led_controller::{Days, Schedule};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// Cached values of one device
+#[derive(Debug, Default)]
+pub struct DeviceCache {
+    /// File the cache is read from and saved to
+    path: PathBuf,
+    /// Device address, as reported by the library
+    address: String,
+    /// `key value` pairs, in file order
+    entries: Vec<(String, String)>,
+}
+
+/// Directory holding the per-device files
+fn cache_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .or_else(|| std::env::var_os("LOCALAPPDATA").map(PathBuf::from))?;
+    Some(base.join("elk-led-controller"))
+}
+
+impl DeviceCache {
+    /// File holding the cache of the device at `address`
+    fn path_for(address: &str) -> Result<PathBuf> {
+        let dir = cache_dir()
+            .ok_or_else(|| color_eyre::eyre::eyre!("No home directory for the device cache"))?;
+        // Addresses are MACs on Linux and Windows but UUIDs on macOS; keep
+        // only characters that are safe in a file name
+        let file: String = address
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect();
+        Ok(dir.join(format!("{}.txt", file)))
+    }
+
+    /// Takes an exclusive lock on the cache of the device at `address`
+    ///
+    /// Blocks until other processes holding it are done; the lock is
+    /// released when the returned file is dropped. Hold it across a
+    /// read-modify-write such as a relative brightness change, so rapid
+    /// invocations don't both start from the same old value.
+    pub fn lock(address: &str) -> Result<File> {
+        let path = Self::path_for(address)?.with_extension("lock");
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .wrap_err_with(|| format!("Could not create {}", dir.display()))?;
+        }
+        let file =
+            File::create(&path).wrap_err_with(|| format!("Could not open {}", path.display()))?;
+        file.lock()
+            .wrap_err_with(|| format!("Could not lock {}", path.display()))?;
+        Ok(file)
+    }
+
+    /// Loads the cache of the device at `address`, empty if there is none yet
+    pub fn load(address: &str) -> Result<Self> {
+        let path = Self::path_for(address)?;
+        if !path.exists() {
+            return Ok(Self {
+                path,
+                address: address.to_string(),
+                entries: Vec::new(),
+            });
+        }
+        let mut cache = Self::read(&path)?;
+        cache.address = address.to_string();
+        Ok(cache)
+    }
+
+    /// Loads the caches of every device this tool has written to
+    pub fn all() -> Result<Vec<Self>> {
+        let Some(dir) = cache_dir().filter(|dir| dir.is_dir()) else {
+            return Ok(Vec::new());
+        };
+        let mut caches = Vec::new();
+        for entry in
+            std::fs::read_dir(&dir).wrap_err_with(|| format!("Could not read {}", dir.display()))?
+        {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "txt") {
+                caches.push(Self::read(&path)?);
+            }
+        }
+        caches.sort_by(|a, b| a.address.cmp(&b.address));
+        Ok(caches)
+    }
+
+    /// Parses one cache file
+    fn read(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Could not read {}", path.display()))?;
+        let mut cache = Self {
+            path: path.to_path_buf(),
+            ..Self::default()
+        };
+        for line in text.lines() {
+            let (key, value) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+            match key {
+                "" => {}
+                "address" => cache.address = value.trim().to_string(),
+                key => cache
+                    .entries
+                    .push((key.to_string(), value.trim().to_string())),
+            }
+        }
+        Ok(cache)
+    }
+
+    /// Writes the cache back to its file
+    pub fn save(&self) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)
+                .wrap_err_with(|| format!("Could not create {}", dir.display()))?;
+        }
+        let mut text = format!("address {}\n", self.address);
+        for (key, value) in &self.entries {
+            text.push_str(&format!("{} {}\n", key, value));
+        }
+        std::fs::write(&self.path, text)
+            .wrap_err_with(|| format!("Could not write {}", self.path.display()))
+    }
+
+    /// Address of the device
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Value stored under `key`
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Stores `value` under `key`, or removes the key for `None`
+    pub fn set(&mut self, key: &str, value: Option<String>) {
+        self.entries.retain(|(k, _)| k != key);
+        if let Some(value) = value {
+            self.entries.push((key.to_string(), value));
+        }
+    }
+
+    /// Schedule stored under `key`, written as `<day bits> <HH:MM>`
+    pub fn schedule(&self, key: &str) -> Option<Schedule> {
+        let (days, time) = self.get(key)?.split_once(' ')?;
+        let days = Days::from_bits(u8::from_str_radix(days.trim_start_matches("0x"), 16).ok()?)?;
+        let time = NaiveTime::parse_from_str(time.trim(), "%H:%M").ok()?;
+        Some(Schedule::new(days, time))
+    }
+
+    /// Stores a schedule under `key`, or removes it for `None`
+    pub fn set_schedule(&mut self, key: &str, schedule: Option<Schedule>) {
+        let value = schedule.map(|schedule| {
+            format!(
+                "{:#04x} {}",
+                schedule.days.bits(),
+                schedule.time.format("%H:%M")
+            )
+        });
+        self.set(key, value);
+    }
+}
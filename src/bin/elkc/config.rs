@@ -0,0 +1,295 @@
+/*!
+ # Config file
+
+ Defaults for the command-line flags, read from a small TOML file. Explicit
+ flags win over the file, which wins over the built-in defaults.
+
+ Unknown keys and sections are rejected, so a typo doesn't silently fall back
+ to the default.
+*/
+
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use elk_led_controller::Error;
+use serde::{Deserialize, Deserializer};
+use std::fmt::Display;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "audio")]
+use super::{AudioModeType, AudioRangeType};
+#[cfg(feature = "audio")]
+use clap::ValueEnum;
+
+/// Commented template written by `config init`
+pub const TEMPLATE: &str = r#"# elk-led-controller CLI defaults
+# Flags given on the command line take precedence over these values.
+
+# Device to connect to, by address or advertised name
+# address = "BE:58:00:00:00:01"
+# name = "Desk Strip"
+
+# Seconds to scan for the device, and how many times to scan again
+# scan_timeout = 10
+# connect_retries = 0
+
+# Never set the brightness above this level (0-100)
+# max_brightness = 100
+
+# Brightness gamma, e.g. 2.2 so 50% looks half as bright (1.0 is linear)
+# gamma = 1.0
+
+# File the scene commands save to (default: scenes.txt next to this file)
+# scenes = "/home/me/scenes.txt"
+
+# Power the device on before commands that change its light (false is like --no-power-on)
+# power_on = true
+
+[audio]
+# mode = "frequency-color"
+# range = "full"
+# sensitivity = 70
+# update_ms = 50
+# device = "Speakers"
+# host = "alsa"
+# fast = false
+"#;
+
+/// Settings read from the config file, `None` where the file is silent
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Device address, like `--address`
+    pub address: Option<String>,
+    /// Advertised device name, like `--name`
+    pub name: Option<String>,
+    /// Scan timeout in seconds, like `--timeout`
+    pub scan_timeout: Option<u64>,
+    /// Like `--connect-retries`
+    #[serde(deserialize_with = "retries")]
+    pub connect_retries: Option<u32>,
+    /// Like `--max-brightness`
+    #[serde(deserialize_with = "percent")]
+    pub max_brightness: Option<u8>,
+    /// Like `--gamma`
+    #[serde(deserialize_with = "gamma")]
+    pub gamma: Option<f32>,
+    /// Like `--scenes`
+    pub scenes: Option<PathBuf>,
+    /// `false` is like `--no-power-on`
+    pub power_on: Option<bool>,
+    /// Defaults for the `audio` command
+    #[cfg(feature = "audio")]
+    pub audio: AudioConfig,
+    /// Without audio support the section is accepted but unused, so one
+    /// config file works for both builds
+    #[cfg(not(feature = "audio"))]
+    #[serde(rename = "audio")]
+    _audio: toml::Table,
+}
+
+/// Defaults for the `audio` command
+#[cfg(feature = "audio")]
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AudioConfig {
+    /// Like `audio --mode`
+    #[serde(deserialize_with = "value_enum")]
+    pub mode: Option<AudioModeType>,
+    /// Like `audio --range`
+    #[serde(deserialize_with = "value_enum")]
+    pub range: Option<AudioRangeType>,
+    /// Like `audio --sensitivity`
+    #[serde(deserialize_with = "percent")]
+    pub sensitivity: Option<u8>,
+    /// Like `audio --update-ms`
+    #[serde(deserialize_with = "update_ms")]
+    pub update_ms: Option<u32>,
+    /// Like `audio --device`
+    pub device: Option<String>,
+    /// Like `audio --audio-host`
+    pub host: Option<String>,
+    /// Like `audio --fast`
+    pub fast: Option<bool>,
+}
+
+/// Directory of the config file: `$XDG_CONFIG_HOME/elk-led-controller`,
+/// falling back to `~/.config` (or `%APPDATA%` on Windows)
+fn config_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))?;
+    Some(base.join("elk-led-controller"))
+}
+
+/// Default location of the config file, `config.toml` in [`config_dir`]
+pub fn default_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("config.toml"))
+}
+
+/// Default scene file, `scenes.txt` next to the config file
+pub fn default_scenes_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("scenes.txt"))
+}
+
+/// Reads and parses the config file at `path`
+pub fn load(path: &Path) -> Result<Config> {
+    let text = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("Could not read config file {}", path.display()))?;
+    toml::from_str(&text).map_err(|e| eyre!("Invalid config file {}: {}", path.display(), e))
+}
+
+/// Writes [`TEMPLATE`] to `path`, refusing to replace a file unless `force` is set
+pub fn init(path: &Path, force: bool) -> Result<()> {
+    if path.exists() && !force {
+        return Err(Error::InvalidCommand(format!(
+            "{} already exists, pass --force to overwrite it",
+            path.display()
+        ))
+        .into());
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .wrap_err_with(|| format!("Could not create {}", dir.display()))?;
+    }
+    std::fs::write(path, TEMPLATE).wrap_err_with(|| format!("Could not write {}", path.display()))
+}
+
+/// Checks a brightness gamma, which must be a positive number
+pub fn check_gamma(gamma: f32) -> std::result::Result<f32, String> {
+    if gamma > 0.0 && gamma.is_finite() {
+        Ok(gamma)
+    } else {
+        Err(format!(
+            "invalid gamma {}, expected a positive number",
+            gamma
+        ))
+    }
+}
+
+/// Reads an optional value and checks it is within `range`
+fn in_range<'de, D, T>(
+    deserializer: D,
+    range: RangeInclusive<T>,
+) -> std::result::Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + PartialOrd + Display,
+{
+    match Option::<T>::deserialize(deserializer)? {
+        Some(value) if !range.contains(&value) => Err(serde::de::Error::custom(format!(
+            "{} is out of range ({}..{})",
+            value,
+            range.start(),
+            range.end()
+        ))),
+        value => Ok(value),
+    }
+}
+
+/// A level from 0 to 100
+fn percent<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<u8>, D::Error> {
+    in_range(deserializer, 0..=100)
+}
+
+/// `connect_retries`, at most 100
+fn retries<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<u32>, D::Error> {
+    in_range(deserializer, 0..=100)
+}
+
+/// `update_ms`, from 1ms to 10s
+#[cfg(feature = "audio")]
+fn update_ms<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<u32>, D::Error> {
+    in_range(deserializer, 1..=10_000)
+}
+
+/// `gamma`, see [`check_gamma`]
+fn gamma<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Option<f32>, D::Error> {
+    Option::<f32>::deserialize(deserializer)?
+        .map(check_gamma)
+        .transpose()
+        .map_err(serde::de::Error::custom)
+}
+
+/// A string naming a value of a command-line enum, e.g. `mode = "frequency-color"`
+#[cfg(feature = "audio")]
+fn value_enum<'de, D, T>(deserializer: D) -> std::result::Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: ValueEnum,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|name| T::from_str(&name, true))
+        .transpose()
+        .map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> std::result::Result<Config, toml::de::Error> {
+        toml::from_str(text)
+    }
+
+    #[test]
+    fn template_parses_with_and_without_its_comments() {
+        let config = parse(TEMPLATE).unwrap();
+        assert!(config.address.is_none() && config.gamma.is_none());
+
+        // Uncomment the example settings, keeping the prose comments
+        let uncommented: String = TEMPLATE
+            .lines()
+            .map(|line| match line.strip_prefix("# ") {
+                Some(setting) if setting.contains(" = ") => setting,
+                _ => line,
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let config = parse(&uncommented).unwrap();
+        assert_eq!(config.name.as_deref(), Some("Desk Strip"));
+        assert_eq!(config.scan_timeout, Some(10));
+        assert_eq!(config.max_brightness, Some(100));
+        assert_eq!(config.gamma, Some(1.0));
+        assert_eq!(config.power_on, Some(true));
+        #[cfg(feature = "audio")]
+        {
+            assert!(matches!(
+                config.audio.mode,
+                Some(AudioModeType::FrequencyColor)
+            ));
+            assert_eq!(config.audio.update_ms, Some(50));
+        }
+    }
+
+    #[test]
+    fn rejects_a_gamma_that_is_not_positive() {
+        assert!(parse("gamma = 0").is_err());
+        assert!(parse("gamma = -2.2").is_err());
+        assert_eq!(parse("gamma = 2").unwrap().gamma, Some(2.0));
+        assert!(check_gamma(0.0).is_err());
+        assert!(check_gamma(f32::NAN).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_values_out_of_range() {
+        assert!(parse("adress = \"BE:58:00:00:00:01\"").is_err());
+        assert!(parse("[video]\nmode = \"pulse\"").is_err());
+        assert!(parse("max_brightness = 101").is_err());
+        assert!(parse("connect_retries = 101").is_err());
+        assert!(parse("name = 5").is_err());
+        assert!(parse("name = \"a\"\nname = \"b\"").is_err());
+        #[cfg(feature = "audio")]
+        {
+            assert!(parse("[audio]\nmode = \"disco\"").is_err());
+            assert!(parse("[audio]\nupdate_ms = 0").is_err());
+        }
+    }
+}
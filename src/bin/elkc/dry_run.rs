@@ -0,0 +1,238 @@
+/*!
+ # Dry run
+
+ Resolves commands into the exact frames they would send, without any
+ Bluetooth I/O, for `--dry-run`. Frames are built by the library's
+ `protocol` module with the same adjustments the device applies (brightness
+ ceiling and curve, effect speed inversion, leaving effect mode before a
+ static color), for an ELK-BLE device that has just been connected.
+*/
+
+use chrono::{Datelike, Timelike};
+use color_eyre::eyre::Result;
+use elk_led_controller::protocol::{self, CommandFrame, EXIT_EFFECT_FRAME};
+use elk_led_controller::{
+    device_day_of_week, BrightnessCurve, Days, DeviceConfig, DeviceType, Effect, Error, LineCommand,
+};
+use std::time::Duration;
+
+/// Device type whose frames are printed
+const DRY_RUN_DEVICE: DeviceType = DeviceType::ElkBle;
+
+/// One step of the resolved sequence
+#[derive(Debug)]
+enum Step {
+    /// A frame that would be written
+    Frame(Vec<u8>),
+    /// A pause between frames, from `sleep` in a `run` chain
+    Sleep(Duration),
+}
+
+/// Frames collected for the commands of one invocation
+#[derive(Debug)]
+pub struct DryRun {
+    /// Configuration of [`DRY_RUN_DEVICE`]
+    config: DeviceConfig,
+    /// Like `--gamma`
+    curve: BrightnessCurve,
+    /// Like `--max-brightness`
+    max_brightness: u8,
+    /// Unlike `--no-power-on`, power on before commands that change the light
+    implicit_power_on: bool,
+    /// Whether the device would be on
+    is_on: bool,
+    /// Whether an effect would be running
+    effect_active: bool,
+    /// Resolved steps, in order
+    steps: Vec<Step>,
+}
+
+impl DryRun {
+    /// Starts from the state of a freshly connected device: off, no effect
+    pub fn new(max_brightness: Option<u8>, gamma: Option<f32>, implicit_power_on: bool) -> Self {
+        Self {
+            config: DeviceConfig::for_device_type(DRY_RUN_DEVICE),
+            curve: gamma.map_or(BrightnessCurve::Linear, BrightnessCurve::Gamma),
+            max_brightness: max_brightness.unwrap_or(100),
+            implicit_power_on,
+            is_on: false,
+            effect_active: false,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Records a frame
+    fn send(&mut self, frame: CommandFrame) {
+        self.steps.push(Step::Frame(frame.to_vec()));
+    }
+
+    /// Like `send_raw`; the tracked state is left alone, as on the device
+    pub fn raw(&mut self, frame: Vec<u8>) {
+        self.steps.push(Step::Frame(frame));
+    }
+
+    /// Like `power_on`
+    pub fn power_on(&mut self) {
+        self.send(self.config.turn_on_cmd);
+        self.is_on = true;
+    }
+
+    /// Powers on unless already on
+    pub fn ensure_on(&mut self) {
+        if !self.is_on {
+            self.power_on();
+        }
+    }
+
+    /// Powers on before a command that changes the light, unless `--no-power-on`
+    pub fn implicit_on(&mut self) {
+        if self.implicit_power_on {
+            self.ensure_on();
+        }
+    }
+
+    /// Like `power_off`
+    pub fn power_off(&mut self) {
+        self.send(self.config.turn_off_cmd);
+        self.is_on = false;
+    }
+
+    /// Like `set_color`
+    pub fn color(&mut self, red: u8, green: u8, blue: u8) {
+        if self.effect_active {
+            self.send(EXIT_EFFECT_FRAME);
+            self.effect_active = false;
+        }
+        self.send(protocol::color_frame(red, green, blue));
+    }
+
+    /// Like `set_brightness`
+    pub fn brightness(&mut self, level: u8) {
+        let level = level.min(100).min(self.max_brightness);
+        self.send(protocol::brightness_frame(self.curve.apply(level)));
+    }
+
+    /// Like `set_color_temp_kelvin`
+    pub fn color_temp(&mut self, kelvin: u32) {
+        let (warm, cold) = self.config.color_temp_levels(kelvin);
+        if self.effect_active {
+            self.send(EXIT_EFFECT_FRAME);
+            self.effect_active = false;
+        }
+        self.send(protocol::color_temp_frame(warm, cold));
+    }
+
+    /// Like `set_effect` followed by `set_effect_speed`
+    pub fn effect(&mut self, effect: Effect, speed: Option<u8>) -> Result<()> {
+        if !self.config.supports_effect(effect) {
+            return Err(Error::UnsupportedEffect {
+                effect,
+                device_type: DRY_RUN_DEVICE,
+            }
+            .into());
+        }
+        self.send(protocol::effect_frame(effect.code()));
+        self.effect_active = true;
+        if let Some(speed) = speed {
+            self.effect_speed(speed);
+        }
+        Ok(())
+    }
+
+    /// Like `set_effect_speed`, which sends the speed even without an effect
+    pub fn effect_speed(&mut self, speed: u8) {
+        self.send(protocol::effect_speed_frame(
+            self.config.raw_effect_speed(speed),
+        ));
+    }
+
+    /// Like `set_schedule_on` (`off == false`) or `set_schedule_off`
+    ///
+    /// Hours, minutes and days are validated by the argument parser.
+    pub fn schedule(&mut self, off: bool, days: Days, hour: u8, minute: u8, enabled: bool) {
+        let value = if enabled {
+            days.bits() | 0x80
+        } else {
+            days.bits()
+        };
+        self.send(protocol::schedule_frame(off, hour, minute, value));
+    }
+
+    /// Resolves a command of a `run` chain
+    pub fn line_command(&mut self, command: &LineCommand) -> Result<()> {
+        match *command {
+            LineCommand::PowerOn => self.power_on(),
+            LineCommand::PowerOff => self.power_off(),
+            LineCommand::Color((r, g, b)) => self.color(r, g, b),
+            LineCommand::Brightness(level) => self.brightness(level),
+            LineCommand::Effect { effect, speed } => self.effect(effect, speed)?,
+            LineCommand::EffectSpeed(speed) => self.effect_speed(speed),
+            LineCommand::ColorTemp(kelvin) => {
+                let (min, max) = (self.config.min_color_temp_k, self.config.max_color_temp_k);
+                if !(min..=max).contains(&kelvin) {
+                    return Err(Error::ValueOutOfRange(kelvin, min, max).into());
+                }
+                self.color_temp(kelvin)
+            }
+            LineCommand::ScheduleOn { days, hour, minute } => {
+                self.schedule(false, days, hour, minute, true)
+            }
+            LineCommand::ScheduleOff { days, hour, minute } => {
+                self.schedule(true, days, hour, minute, true)
+            }
+            LineCommand::SyncTime => {
+                let now = chrono::Local::now();
+                self.send(protocol::time_frame(
+                    now.hour() as u8,
+                    now.minute() as u8,
+                    now.second() as u8,
+                    device_day_of_week(now.weekday()),
+                ));
+            }
+            LineCommand::Sleep(duration) => self.steps.push(Step::Sleep(duration)),
+            // Nothing is sent for these
+            LineCommand::Status
+            | LineCommand::Metrics
+            | LineCommand::ListEffects { .. }
+            | LineCommand::Help
+            | LineCommand::Quit => {}
+        }
+        Ok(())
+    }
+
+    /// Prints the frames as annotated hex, one per line
+    pub fn print(&self) {
+        println!(
+            "Dry run, frames for a freshly connected {:?} device:",
+            DRY_RUN_DEVICE
+        );
+        for step in &self.steps {
+            match step {
+                Step::Frame(frame) => println!(
+                    "{:<18} {}",
+                    format!("{}:", protocol::describe_frame(frame)),
+                    protocol::format_frame(frame)
+                ),
+                Step::Sleep(duration) => println!("{:<18} {:?}", "sleep:", duration),
+            }
+        }
+    }
+
+    /// The steps as a JSON array of `{"frame":"7e ...","description":"..."}`
+    /// and `{"sleep_ms":500}` objects
+    pub fn to_json(&self) -> String {
+        let steps: Vec<String> = self
+            .steps
+            .iter()
+            .map(|step| match step {
+                Step::Frame(frame) => format!(
+                    "{{\"frame\":\"{}\",\"description\":\"{}\"}}",
+                    protocol::format_frame(frame),
+                    protocol::describe_frame(frame)
+                ),
+                Step::Sleep(duration) => format!("{{\"sleep_ms\":{}}}", duration.as_millis()),
+            })
+            .collect();
+        format!("[{}]", steps.join(","))
+    }
+}
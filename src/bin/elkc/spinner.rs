@@ -0,0 +1,119 @@
+/*!
+ # Connection spinner
+
+ Shows what the connection is doing on a single self-updating stderr line,
+ driven by the progress events the library publishes while connecting:
+ `Scanning… found 3 peripherals`, `Connecting to ELK-BLEDOM (BE:58:…)`,
+ `Discovering services…`. The line is cleared when the spinner is dropped,
+ so an error printed next doesn't land behind it.
+*/
+
+use elk_led_controller::DeviceEvent;
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+
+/// Frames of the animation, one per tick
+const FRAMES: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// Time between redraws
+const TICK: Duration = Duration::from_millis(100);
+
+/// Whether a spinner line is on screen and has to be cleared before other output
+static SHOWN: AtomicBool = AtomicBool::new(false);
+
+/// A running spinner; dropping it stops the animation and clears its line
+pub struct Spinner {
+    task: JoinHandle<()>,
+}
+
+impl Spinner {
+    /// Starts following `events`, or returns `None` when stderr is not a terminal
+    pub fn start(mut events: broadcast::Receiver<DeviceEvent>) -> Option<Spinner> {
+        if !std::io::stderr().is_terminal() {
+            return None;
+        }
+        let task = tokio::spawn(async move {
+            let mut message = "Scanning…".to_string();
+            let mut frame = 0;
+            let mut tick = tokio::time::interval(TICK);
+            loop {
+                tokio::select! {
+                    event = events.recv() => match event {
+                        Ok(event) => {
+                            if let Some(text) = describe(&event) {
+                                message = text;
+                            }
+                        }
+                        Err(broadcast::error::RecvError::Lagged(_)) => {}
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    },
+                    _ = tick.tick() => {
+                        draw(FRAMES[frame % FRAMES.len()], &message);
+                        frame += 1;
+                    }
+                }
+            }
+        });
+        Some(Spinner { task })
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        self.task.abort();
+        clear_line();
+    }
+}
+
+/// The spinner text for a connection event, `None` for other events
+fn describe(event: &DeviceEvent) -> Option<String> {
+    match event {
+        DeviceEvent::Scanning { found: 1 } => Some("Scanning… found 1 peripheral".to_string()),
+        DeviceEvent::Scanning { found } => Some(format!("Scanning… found {} peripherals", found)),
+        DeviceEvent::Connecting {
+            name: Some(name),
+            address,
+        } => Some(format!("Connecting to {} ({})", name, address)),
+        DeviceEvent::Connecting {
+            name: None,
+            address,
+        } => Some(format!("Connecting to {}", address)),
+        DeviceEvent::DiscoveringServices => Some("Discovering services…".to_string()),
+        _ => None,
+    }
+}
+
+/// Redraw the spinner line
+fn draw(frame: char, message: &str) {
+    let mut stderr = std::io::stderr().lock();
+    let _ = write!(stderr, "\r\x1b[2K{} {}", frame, message);
+    let _ = stderr.flush();
+    SHOWN.store(true, Ordering::Relaxed);
+}
+
+/// Erase the spinner line, if one is shown
+fn clear_line() {
+    if SHOWN.swap(false, Ordering::Relaxed) {
+        let mut stderr = std::io::stderr().lock();
+        let _ = write!(stderr, "\r\x1b[2K");
+        let _ = stderr.flush();
+    }
+}
+
+/// Log output that first clears the spinner line, so log lines don't get
+/// appended to it; the spinner redraws below them on its next tick
+pub struct LogWriter<W>(pub W);
+
+impl<W: Write> Write for LogWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        clear_line();
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
@@ -1,83 +1,599 @@
+use clap::Parser;
 use elk_led_controller::*;
-use std::{env, io};
+use futures::future::BoxFuture;
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+};
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+use tokio::sync::{Mutex, Semaphore};
+use tracing::{info, warn};
+
+/// Environment variable with the default device address
+const ADDRESS_VAR: &str = "ELK_DEVICE_ADDRESS";
+
+/// Longest line a TCP or socket client may send, in bytes
+const MAX_LINE: usize = 4096;
+
+/// Keep a connection to an LED strip open and take one command per line,
+/// from stdin or from TCP or Unix socket clients
+#[derive(Parser)]
+#[command(name = "elkd")]
+struct Args {
+    /// Device MAC address or ID (default: $ELK_DEVICE_ADDRESS, then the first
+    /// compatible device)
+    address: Option<String>,
+
+    /// Read JSON requests and answer in JSON, one per line
+    #[arg(long)]
+    json: bool,
+
+    /// Accept TCP clients on this address instead of reading stdin, e.g.
+    /// 0.0.0.0:7e00 (a port with letters is read as hex, 7e00 = 32256)
+    #[arg(long, value_name = "ADDR", value_parser = parse_listen)]
+    listen: Option<SocketAddr>,
+
+    /// Accept clients on this Unix socket instead of reading stdin, e.g.
+    /// /run/elkd.sock; it is removed on shutdown
+    #[cfg(unix)]
+    #[arg(long, value_name = "PATH", conflicts_with = "listen")]
+    socket: Option<PathBuf>,
+
+    /// Permissions of the --socket file, in octal (e.g. 660)
+    #[cfg(unix)]
+    #[arg(long, value_name = "MODE", requires = "socket", value_parser = parse_mode)]
+    socket_mode: Option<u32>,
+
+    /// Most clients connected at once; more are turned away
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..))]
+    max_clients: u32,
+
+    /// Close a client that sends nothing for this many seconds
+    #[arg(long, value_name = "SECS", default_value_t = 300)]
+    idle_timeout: u64,
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Get a target id/mac address from command line arguments.
-    // If not provided, exit.
-    let usage = "Usage: elkd <id/mac address>";
-    let args: Vec<_> = env::args().collect();
-    if args.len() < 2 {
-        eprintln!("{usage}");
-        std::process::exit(1);
+    let args = Args::parse();
+
+    // Logs go to stderr; stdout carries the protocol
+    tracing_subscriber::fmt()
+        .compact()
+        .with_writer(io::stderr)
+        .init();
+
+    // Get a target id/mac address from the command line arguments, then from
+    // ELK_DEVICE_ADDRESS. If neither is set, connect to the first compatible device.
+    let address = args.address.clone().or_else(|| {
+        std::env::var(ADDRESS_VAR)
+            .ok()
+            .filter(|address| !address.is_empty())
+    });
+
+    // Initialize the device with the chosen address, or the first one found
+    let mut device = match address {
+        Some(address) => BleLedDevice::new_with_addr(&address).await?,
+        None => BleLedDevice::new_without_power().await?,
+    };
+    // Set a small delay for command processing. Streaming callers may also
+    // want `device.fast_mode = true` to skip write acknowledgements
+    device.command_delay = 0;
+
+    if let Some(listen) = args.listen {
+        let listener = TcpListener::bind(listen).await?;
+        info!("Listening on {}", listener.local_addr()?);
+        return serve(device, Listener::Tcp(listener), &args, ctrl_c()).await;
     }
-    if args[1] == "-h" || args[1] == "--help" {
-        eprintln!("{usage}");
-        std::process::exit(0);
+    #[cfg(unix)]
+    if let Some(path) = &args.socket {
+        let listener = bind_socket(path, args.socket_mode)?;
+        info!("Listening on {}", path.display());
+        let result = serve(device, Listener::Unix(listener), &args, ctrl_c()).await;
+        if let Err(e) = std::fs::remove_file(path) {
+            warn!("Could not remove {}: {}", path.display(), e);
+        }
+        return result;
     }
 
-    // Initialize the device with the provided address
-    let mut device = BleLedDevice::new_with_addr(&args[1]).await?;
-    device.command_delay = 0; // Set a small delay for command processing
-
     // Inform about successful initialization
-    println!("OK");
+    println!("{}", ready(args.json));
 
     // Mainloop: wait for user input, line by line
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
     loop {
-        // Read a command from stdin
-        let mut input: String = String::new();
-        io::stdin().read_line(&mut input).expect("!!");
-
-        // Read command and execute it
-        let mut cmd = input.trim().split(":");
-        match cmd.next() {
-            Some("power_on") => {
-                device.power_on().await?;
-                // Respond with OK message
-                println!("OK");
-            }
-            Some("power_off") => {
-                device.power_off().await?;
-                // Respond with OK message
-                println!("OK");
+        // Read a command from stdin, stopping when it is closed
+        let Some(input) = lines.next_line().await? else {
+            return Ok(());
+        };
+
+        // Execute the command and answer OK, its output or ERR <reason>
+        // (or the JSON equivalents)
+        match respond(&device, &input, args.json).await {
+            Some(response) => println!("{response}"),
+            None => return Ok(()),
+        }
+    }
+}
+
+/// Line announcing that the device is connected and commands are accepted
+fn ready(json: bool) -> &'static str {
+    if json {
+        "{\"ok\":true}"
+    } else {
+        "OK"
+    }
+}
+
+/// Runs one line of the text or JSON protocol; `None` ends the session
+async fn respond(device: &BleLedDevice, line: &str, json: bool) -> Option<String> {
+    if json {
+        console::respond_json(device, line).await
+    } else {
+        console::respond(device, line).await
+    }
+}
+
+/// Resolves on Ctrl+C
+async fn ctrl_c() {
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        warn!("Could not listen for Ctrl+C: {}", e);
+    }
+}
+
+/// What runs the clients' commands: the connected device, or a stand-in in tests
+trait Handler: Send + Sync + 'static {
+    /// Runs one parsed command, returning its output
+    fn run(&self, command: LineCommand) -> BoxFuture<'_, Result<Option<String>>>;
+
+    /// Lets go of the device once the server stops
+    fn close(&self) -> BoxFuture<'_, Result<()>>;
+}
+
+impl Handler for BleLedDevice {
+    fn run(&self, command: LineCommand) -> BoxFuture<'_, Result<Option<String>>> {
+        Box::pin(async move { command.run(self).await })
+    }
+
+    fn close(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(self.disconnect())
+    }
+}
+
+/// Parses `--listen`: an IP address and a port, decimal or (with letters) hex
+fn parse_listen(value: &str) -> std::result::Result<SocketAddr, String> {
+    if let Ok(addr) = value.parse() {
+        return Ok(addr);
+    }
+    let invalid = || format!("'{}' is not an address like 0.0.0.0:7e00", value);
+    let (ip, port) = value.rsplit_once(':').ok_or_else(invalid)?;
+    let ip: IpAddr = ip
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse()
+        .map_err(|_| invalid())?;
+    let port = u16::from_str_radix(port, 16).map_err(|_| invalid())?;
+    Ok(SocketAddr::new(ip, port))
+}
+
+/// Parses `--socket-mode`, an octal permission mode such as `660`
+#[cfg(unix)]
+fn parse_mode(value: &str) -> std::result::Result<u32, String> {
+    u32::from_str_radix(value, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o777)
+        .ok_or_else(|| format!("'{}' is not an octal mode like 660", value))
+}
+
+/// Creates the Unix socket at `path`, replacing a stale one
+///
+/// A socket file left behind by an elkd that didn't shut down cleanly is
+/// removed. One that still accepts connections, or a file that isn't a
+/// socket, is left alone and reported.
+///
+/// With a `mode`, the socket is created in a private directory and moved to
+/// `path` once its permissions are set, so no client can connect while it
+/// still has the default ones.
+#[cfg(unix)]
+fn bind_socket(path: &Path, mode: Option<u32>) -> Result<UnixListener> {
+    use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", path.display()),
+            )
+            .into());
+        }
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("{} is in use by another elkd", path.display()),
+            )
+            .into());
+        }
+        info!("Removing stale socket {}", path.display());
+        std::fs::remove_file(path)?;
+    }
+
+    let Some(mode) = mode else {
+        return Ok(UnixListener::bind(path)?);
+    };
+
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let private = path.with_file_name(format!(".{}.{}", name, std::process::id()));
+    std::fs::DirBuilder::new().mode(0o700).create(&private)?;
+    let staged = private.join(name.as_ref());
+    let bound = UnixListener::bind(&staged).and_then(|listener| {
+        std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(mode))?;
+        std::fs::rename(&staged, path)?;
+        Ok(listener)
+    });
+    // Only left behind if something above failed
+    let _ = std::fs::remove_file(&staged);
+    let _ = std::fs::remove_dir(&private);
+    Ok(bound?)
+}
+
+/// Where clients connect
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+/// The two halves of a client connection
+type Connection = (
+    Box<dyn AsyncRead + Send + Unpin>,
+    Box<dyn AsyncWrite + Send + Unpin>,
+);
+
+impl Listener {
+    /// Waits for the next client, returning its connection and a name for logs
+    async fn accept(&self, clients: &mut u64) -> io::Result<(Connection, String)> {
+        *clients += 1;
+        match self {
+            Listener::Tcp(listener) => {
+                let (stream, peer) = listener.accept().await?;
+                let (reader, writer) = stream.into_split();
+                Ok(((Box::new(reader), Box::new(writer)), peer.to_string()))
             }
-            Some("set_color") => {
-                let rgb: Vec<u8> = cmd
-                    .next()
-                    .expect("no color given")
-                    .split(",")
-                    .map(|s| s.trim().parse().expect("invalid color"))
-                    .collect();
-                if rgb.len() != 3 {
-                    eprintln!("ERR Invalid color format. Use R,G,B (e.g., 255,0,0 for red)");
-                    continue;
-                }
-                device.set_color(rgb[0], rgb[1], rgb[2]).await?;
-                // Respond with OK message
-                println!("OK");
+            // Unix clients have no address; number them instead
+            #[cfg(unix)]
+            Listener::Unix(listener) => {
+                let (stream, _) = listener.accept().await?;
+                let (reader, writer) = stream.into_split();
+                Ok((
+                    (Box::new(reader), Box::new(writer)),
+                    format!("#{}", clients),
+                ))
             }
-            Some("set_brightness") => {
-                let brightness: u8 = cmd
-                    .next()
-                    .expect("no brightness given")
-                    .trim()
-                    .parse()
-                    .expect("invalid brightness");
-                if brightness > 100 {
-                    eprintln!("ERR Brightness must be between 0 and 100");
+        }
+    }
+}
+
+/// Serves clients of `listener` until `shutdown` resolves, then disconnects
+/// the device
+///
+/// Each client gets its own session of the line protocol. Commands that
+/// use the device take turns, so the frames of two clients' commands never
+/// interleave; the others, such as `sleep`, run without waiting.
+async fn serve(
+    device: impl Handler,
+    listener: Listener,
+    args: &Args,
+    shutdown: impl Future<Output = ()>,
+) -> Result<()> {
+    let device = Arc::new(device);
+    let turn = Arc::new(Mutex::new(()));
+    let slots = Arc::new(Semaphore::new(args.max_clients as usize));
+    let idle_timeout = Duration::from_secs(args.idle_timeout);
+    tokio::pin!(shutdown);
+    let mut clients = 0;
+
+    loop {
+        let ((reader, mut writer), peer) = tokio::select! {
+            accepted = listener.accept(&mut clients) => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    warn!("Could not accept a client: {}", e);
                     continue;
                 }
-                device.set_brightness(brightness).await?;
-                // Respond with OK message
-                println!("OK");
-            }
-            Some(other) => {
-                eprintln!("ERR Unknown command: {other}");
+            },
+            _ = &mut shutdown => break,
+        };
+        let Ok(slot) = slots.clone().try_acquire_owned() else {
+            info!(
+                "Turned away {}: {} clients connected",
+                peer, args.max_clients
+            );
+            let refusal = if args.json {
+                "{\"ok\":false,\"error\":\"too many clients\"}\n"
+            } else {
+                "ERR too many clients\n"
+            };
+            let _ = writer.write_all(refusal.as_bytes()).await;
+            continue;
+        };
+
+        info!("Client {} connected", peer);
+        let device = device.clone();
+        let turn = turn.clone();
+        let json = args.json;
+        tokio::spawn(async move {
+            match serve_client(reader, writer, &*device, &turn, json, idle_timeout).await {
+                Ok(reason) => info!("Client {} disconnected ({})", peer, reason),
+                Err(e) => info!("Client {} disconnected: {}", peer, e),
             }
-            None => {
-                eprintln!("ERR No command given");
+            drop(slot);
+        });
+    }
+
+    info!("Shutting down");
+    // Waits for a command in progress to finish
+    let _turn = turn.lock().await;
+    device.close().await
+}
+
+/// Runs one client's session, returning why it ended
+///
+/// Commands that use the device wait for `turn`.
+async fn serve_client(
+    reader: impl AsyncRead + Unpin,
+    mut writer: impl AsyncWrite + Unpin,
+    device: &impl Handler,
+    turn: &Mutex<()>,
+    json: bool,
+    idle_timeout: Duration,
+) -> io::Result<&'static str> {
+    let mut reader = BufReader::new(reader);
+    writer
+        .write_all(format!("{}\n", ready(json)).as_bytes())
+        .await?;
+
+    loop {
+        let line = match tokio::time::timeout(idle_timeout, read_line(&mut reader)).await {
+            Ok(line) => match line? {
+                Some(line) => line,
+                None => return Ok("closed"),
+            },
+            Err(_) => return Ok("idle timeout"),
+        };
+        let run = |command: LineCommand| async move {
+            let _turn = if command.uses_device() {
+                Some(turn.lock().await)
+            } else {
+                None
+            };
+            device.run(command).await
+        };
+        let response = if json {
+            console::respond_json_with(&line, run).await
+        } else {
+            console::respond_with(&line, run).await
+        };
+        match response {
+            Some(response) => {
+                writer
+                    .write_all(format!("{}\n", response).as_bytes())
+                    .await?
             }
+            None => return Ok("quit"),
+        }
+    }
+}
+
+/// Reads one line without its line ending, or `None` at the end of input
+///
+/// A line longer than [`MAX_LINE`] is an error, so a client can't make elkd
+/// buffer without bound.
+async fn read_line(reader: &mut (impl AsyncBufRead + Unpin)) -> io::Result<Option<String>> {
+    let mut line = Vec::new();
+    let read = reader
+        .take(MAX_LINE as u64 + 1)
+        .read_until(b'\n', &mut line)
+        .await?;
+    if read == 0 {
+        return Ok(None);
+    }
+    if line.last() == Some(&b'\n') {
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+    } else if read > MAX_LINE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("line longer than {} bytes", MAX_LINE),
+        ));
+    }
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::UnixStream;
+    use tokio::sync::oneshot;
+
+    /// Stands in for the device, tracking the state its commands set like
+    /// [`BleLedDevice`] does
+    #[derive(Default)]
+    struct Strip {
+        state: std::sync::Mutex<DeviceState>,
+    }
+
+    impl Handler for Strip {
+        fn run(&self, command: LineCommand) -> BoxFuture<'_, Result<Option<String>>> {
+            Box::pin(async move {
+                match command {
+                    LineCommand::PowerOn => self.state.lock().unwrap().is_on = true,
+                    LineCommand::PowerOff => self.state.lock().unwrap().is_on = false,
+                    LineCommand::Color(color) => self.state.lock().unwrap().rgb_color = color,
+                    LineCommand::Brightness(level) => self.state.lock().unwrap().brightness = level,
+                    LineCommand::Sleep(duration) => tokio::time::sleep(duration).await,
+                    LineCommand::Status => {
+                        return Ok(Some(self.state.lock().unwrap().to_json()));
+                    }
+                    other => {
+                        return Err(Error::InvalidCommand(format!(
+                            "{} is not supported",
+                            other.name()
+                        )))
+                    }
+                }
+                Ok(None)
+            })
+        }
+
+        fn close(&self) -> BoxFuture<'_, Result<()>> {
+            Box::pin(async { Ok(()) })
         }
     }
+
+    #[tokio::test]
+    async fn serves_clients_on_a_unix_socket() {
+        let path = std::env::temp_dir().join(format!("elkd-test-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let args = Args::try_parse_from([
+            "elkd",
+            "--socket",
+            path.to_str().unwrap(),
+            "--socket-mode",
+            "600",
+            "--max-clients",
+            "1",
+        ])
+        .unwrap();
+
+        let listener = bind_socket(&path, args.socket_mode).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let (stop, stopped) = oneshot::channel::<()>();
+        let server = tokio::spawn(async move {
+            let shutdown = async {
+                let _ = stopped.await;
+            };
+            serve(Strip::default(), Listener::Unix(listener), &args, shutdown).await
+        });
+
+        let (reader, mut writer) = UnixStream::connect(&path).await.unwrap().into_split();
+        let mut lines = BufReader::new(reader).lines();
+        assert_eq!(lines.next_line().await.unwrap().as_deref(), Some("OK"));
+
+        writer.write_all(b"on\n").await.unwrap();
+        assert_eq!(lines.next_line().await.unwrap().as_deref(), Some("OK"));
+        writer.write_all(b"set_color:255,0,0\n").await.unwrap();
+        assert_eq!(lines.next_line().await.unwrap().as_deref(), Some("OK"));
+        writer.write_all(b"state\n").await.unwrap();
+        let reply = lines.next_line().await.unwrap().unwrap();
+        let state = DeviceState::from_json(&reply).unwrap();
+        assert!(state.is_on);
+        assert_eq!(state.rgb_color, (255, 0, 0));
+        assert_eq!(state.brightness, 100);
+        writer.write_all(b"dance\n").await.unwrap();
+        assert_eq!(
+            lines.next_line().await.unwrap().as_deref(),
+            Some("ERR Invalid command: unknown command 'dance', try 'help'")
+        );
+
+        // Only one client at a time
+        let second = UnixStream::connect(&path).await.unwrap();
+        let mut second = BufReader::new(second).lines();
+        assert_eq!(
+            second.next_line().await.unwrap().as_deref(),
+            Some("ERR too many clients")
+        );
+
+        writer.write_all(b"quit\n").await.unwrap();
+        assert_eq!(lines.next_line().await.unwrap(), None);
+
+        // A line over the limit ends the session instead of being buffered
+        let (reader, mut writer) = UnixStream::connect(&path).await.unwrap().into_split();
+        let mut lines = BufReader::new(reader).lines();
+        assert_eq!(lines.next_line().await.unwrap().as_deref(), Some("OK"));
+        writer.write_all(&[b'['; MAX_LINE + 1]).await.unwrap();
+        assert_eq!(lines.next_line().await.unwrap(), None);
+
+        stop.send(()).unwrap();
+        server.await.unwrap().unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_sleeping_client_does_not_hold_up_the_others() {
+        let path = std::env::temp_dir().join(format!("elkd-sleep-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let args = Args::try_parse_from(["elkd", "--socket", path.to_str().unwrap()]).unwrap();
+        let listener = bind_socket(&path, None).unwrap();
+
+        let (stop, stopped) = oneshot::channel::<()>();
+        let server = tokio::spawn(async move {
+            let shutdown = async {
+                let _ = stopped.await;
+            };
+            serve(Strip::default(), Listener::Unix(listener), &args, shutdown).await
+        });
+
+        let connect = || async {
+            let (reader, writer) = UnixStream::connect(&path).await.unwrap().into_split();
+            let mut lines = BufReader::new(reader).lines();
+            assert_eq!(lines.next_line().await.unwrap().as_deref(), Some("OK"));
+            (lines, writer)
+        };
+        let (_sleeper, mut sleeper_writer) = connect().await;
+        sleeper_writer.write_all(b"sleep 24h\n").await.unwrap();
+        // Let the server start the sleep before the other client's command
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let (mut lines, mut writer) = connect().await;
+
+        let answered = async {
+            writer.write_all(b"on\n").await.unwrap();
+            lines.next_line().await.unwrap()
+        };
+        let answer = tokio::time::timeout(Duration::from_secs(5), answered).await;
+        assert_eq!(answer.unwrap().as_deref(), Some("OK"));
+
+        // Shutting down doesn't wait for the sleep either
+        stop.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn replaces_a_stale_socket() {
+        let path = std::env::temp_dir().join(format!("elkd-stale-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        // A socket file nobody listens on, as left by a crashed elkd
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let _listener = bind_socket(&path, None).unwrap();
+            // A live one is left alone
+            assert!(bind_socket(&path, None).is_err());
+        });
+        std::fs::remove_file(&path).unwrap();
+    }
 }
@@ -0,0 +1,58 @@
+/*!
+ # Cancellation
+
+ Long-running helpers such as the effect shuffle take a [`CancelHandle`] so
+ they can be stopped from another task.
+*/
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+/// Shared flag for stopping a long-running operation
+///
+/// Clones share the same flag, so keep one clone and pass another to the
+/// operation.
+#[derive(Debug, Clone, Default)]
+pub struct CancelHandle {
+    /// Flag and wakeup shared between clones
+    inner: Arc<CancelState>,
+}
+
+/// State shared by all clones of a [`CancelHandle`]
+#[derive(Debug, Default)]
+struct CancelState {
+    /// Whether cancellation was requested
+    cancelled: AtomicBool,
+    /// Wakes tasks waiting in [`CancelHandle::cancelled`]
+    notify: Notify,
+}
+
+impl CancelHandle {
+    /// Creates a handle that has not been cancelled yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests cancellation of every operation holding a clone of this handle
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+        self.inner.notify.notify_waiters();
+    }
+
+    /// Whether cancellation was requested
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Waits until cancellation is requested
+    pub async fn cancelled(&self) {
+        loop {
+            let notified = self.inner.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
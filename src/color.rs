@@ -0,0 +1,155 @@
+/*!
+ # Color helpers
+
+ This module contains helpers for working with RGB colors, which are
+ represented as `(red, green, blue)` tuples throughout the library.
+*/
+
+use crate::{Error, Result};
+
+/// An RGB color as `(red, green, blue)`
+pub type Color = (u8, u8, u8);
+
+/// Parses a hex color such as `#00ffcc`, `00ffcc` or `#0fc`
+///
+/// # Arguments
+///
+/// * `value` - Hex color with an optional leading `#`
+pub fn parse_hex_color(value: &str) -> Result<Color> {
+    let hex = value.trim().trim_start_matches('#');
+    let invalid = || Error::InvalidColor(value.to_string());
+
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+
+    match hex.len() {
+        6 => {
+            let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+            Ok((channel(0)?, channel(2)?, channel(4)?))
+        }
+        3 => {
+            // Short form: each digit is repeated, so `f` becomes `ff`
+            let channel = |i: usize| {
+                u8::from_str_radix(&hex[i..i + 1], 16)
+                    .map(|v| v * 17)
+                    .map_err(|_| invalid())
+            };
+            Ok((channel(0)?, channel(1)?, channel(2)?))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Colors accepted by name in [`parse_color`]
+const NAMED_COLORS: &[(&str, Color)] = &[
+    ("red", (255, 0, 0)),
+    ("green", (0, 255, 0)),
+    ("blue", (0, 0, 255)),
+    ("white", (255, 255, 255)),
+    ("yellow", (255, 255, 0)),
+    ("cyan", (0, 255, 255)),
+    ("magenta", (255, 0, 255)),
+    ("orange", (255, 128, 0)),
+    ("purple", (128, 0, 255)),
+    ("pink", (255, 105, 180)),
+    ("warm", (255, 147, 41)),
+    ("off", (0, 0, 0)),
+];
+
+/// Looks up a color by name, ignoring case (`red`, `orange`, `warm`, ...)
+pub fn named_color(name: &str) -> Option<Color> {
+    let name = name.trim();
+    NAMED_COLORS
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(name))
+        .map(|&(_, color)| color)
+}
+
+/// Parses a hex color like [`parse_hex_color`], or a color name like [`named_color`]
+///
+/// # Arguments
+///
+/// * `value` - Hex color or color name
+pub fn parse_color(value: &str) -> Result<Color> {
+    named_color(value).map_or_else(|| parse_hex_color(value), Ok)
+}
+
+/// Linearly interpolates between two colors
+///
+/// # Arguments
+///
+/// * `from` - Color returned at `t = 0.0`
+/// * `to` - Color returned at `t = 1.0`
+/// * `t` - Position between the colors, clamped to 0.0-1.0
+pub fn lerp_color(from: Color, to: Color, t: f32) -> Color {
+    let t = t.clamp(0.0, 1.0);
+    let channel = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+    (
+        channel(from.0, to.0),
+        channel(from.1, to.1),
+        channel(from.2, to.2),
+    )
+}
+
+/// Converts HSV to RGB
+///
+/// # Arguments
+///
+/// * `hue` - Hue in degrees, wrapped into 0-360
+/// * `saturation` - Saturation (0.0-1.0)
+/// * `value` - Value (0.0-1.0)
+pub fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> Color {
+    let hue = hue.rem_euclid(360.0);
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+    let m = value - chroma;
+
+    let (r, g, b) = match hue as u32 / 60 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+
+    (
+        ((r + m) * 255.0).round() as u8,
+        ((g + m) * 255.0).round() as u8,
+        ((b + m) * 255.0).round() as u8,
+    )
+}
+
+/// Approximate RGB color of a black body at `kelvin`
+///
+/// Uses Tanner Helland's curve fit, which is good to a few percent between
+/// 1000 K and 40000 K; values outside that range are clamped.
+///
+/// # Arguments
+///
+/// * `kelvin` - Color temperature in kelvin
+pub fn kelvin_to_rgb(kelvin: u32) -> Color {
+    let temp = kelvin.clamp(1000, 40000) as f32 / 100.0;
+    let channel = |value: f32| value.clamp(0.0, 255.0).round() as u8;
+
+    let red = if temp <= 66.0 {
+        255.0
+    } else {
+        329.698_73 * (temp - 60.0).powf(-0.133_204_76)
+    };
+    let green = if temp <= 66.0 {
+        99.470_8 * temp.ln() - 161.119_57
+    } else {
+        288.122_16 * (temp - 60.0).powf(-0.075_514_85)
+    };
+    let blue = if temp >= 66.0 {
+        255.0
+    } else if temp <= 19.0 {
+        0.0
+    } else {
+        138.517_73 * (temp - 10.0).ln() - 305.044_8
+    };
+
+    (channel(red), channel(green), channel(blue))
+}
@@ -0,0 +1,936 @@
+/*!
+ # Line commands
+
+ One-line text commands shared by `elkd` and the interactive `repl` of the
+ command-line tool. Both spellings are accepted: `elkd`'s `set_color:255,0,0`
+ and the shorter `color ff0000`. [`respond`] handles one line of `elkd`'s
+ protocol, answering `OK`, the command's output or `ERR <reason>`;
+ [`respond_json`] does the same for its newline-delimited JSON mode
+ (`elkd --json`), where requests such as
+ `{"cmd":"set_color","r":255,"g":0,"b":0,"id":7}` map onto the same
+ commands.
+*/
+
+use crate::{BleLedDevice, Color, Days, Effect, Error, Result};
+use serde_json::Value;
+use std::future::Future;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Summary of the accepted commands, printed by `help`
+pub const HELP: &str = "\
+on                       power on (also power_on)
+off                      power off (also power_off)
+color <hex|name|R,G,B>   set a static color, e.g. color ff0000 (also set_color)
+brightness <0-100>       set the brightness (also set_brightness)
+effect <name> [speed]    start an effect, optionally at a speed 0-100 (also set_effect)
+speed <0-100>            set the speed of the running effect (also set_effect_speed)
+color_temp <kelvin>      set a white color temperature, e.g. color_temp 2700 (also set_color_temp)
+schedule_on <days> <hh:mm>   switch on at a time, e.g. schedule_on weekdays 07:30 (also set_schedule_on)
+schedule_off <days> <hh:mm>  switch off at a time (also set_schedule_off)
+sync_time                set the device clock to the local time
+list_effects [--json]    print the effect names, one per line or as a JSON array
+sleep <ms|duration>      wait, e.g. sleep 500 (milliseconds) or sleep 2s (for scripts)
+status                   print the last state sent to the device as JSON (also state)
+metrics                  print the command counters in the Prometheus text format
+help                     show this help
+quit                     end the session (also exit)";
+
+/// A parsed line command
+///
+/// ```
+/// use elk_led_controller::{Days, LineCommand};
+/// use std::time::Duration;
+///
+/// assert_eq!("set_effect_speed:40".parse::<LineCommand>()?, LineCommand::EffectSpeed(40));
+/// assert_eq!("color_temp 2700".parse::<LineCommand>()?, LineCommand::ColorTemp(2700));
+/// assert_eq!(
+///     "set_schedule_on:weekdays:07:30".parse::<LineCommand>()?,
+///     LineCommand::ScheduleOn { days: Days::WEEKDAYS, hour: 7, minute: 30 }
+/// );
+/// assert_eq!("state".parse::<LineCommand>()?, LineCommand::Status);
+/// assert_eq!("list_effects --json".parse::<LineCommand>()?, LineCommand::ListEffects { json: true });
+/// assert_eq!("sleep 500".parse::<LineCommand>()?, LineCommand::Sleep(Duration::from_millis(500)));
+/// assert_eq!("sleep 2s".parse::<LineCommand>()?, LineCommand::Sleep(Duration::from_secs(2)));
+/// assert!("set_schedule_off:mon:24:00".parse::<LineCommand>().is_err());
+/// assert!("set_effect_speed:101".parse::<LineCommand>().is_err());
+/// # Ok::<(), elk_led_controller::Error>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineCommand {
+    /// Power the device on
+    PowerOn,
+    /// Power the device off
+    PowerOff,
+    /// Set a static color
+    Color(Color),
+    /// Set the brightness (0-100)
+    Brightness(u8),
+    /// Start an effect, optionally setting its speed
+    Effect {
+        /// Effect to start
+        effect: Effect,
+        /// Speed to set once the effect runs (0-100)
+        speed: Option<u8>,
+    },
+    /// Set the speed of the running effect (0-100)
+    EffectSpeed(u8),
+    /// Set a white color temperature in kelvin
+    ColorTemp(u32),
+    /// Switch on at a time of day on the given days
+    ScheduleOn {
+        /// Days the schedule fires on
+        days: Days,
+        /// Hour (0-23)
+        hour: u8,
+        /// Minute (0-59)
+        minute: u8,
+    },
+    /// Switch off at a time of day on the given days
+    ScheduleOff {
+        /// Days the schedule fires on
+        days: Days,
+        /// Hour (0-23)
+        hour: u8,
+        /// Minute (0-59)
+        minute: u8,
+    },
+    /// Set the device clock to the local time
+    SyncTime,
+    /// Print the names accepted by `set_effect`
+    ListEffects {
+        /// Print a JSON array instead of one name per line
+        json: bool,
+    },
+    /// Wait before the next command, e.g. between steps of a script
+    Sleep(Duration),
+    /// Report the cached device state
+    Status,
+    /// Report the command counters in the Prometheus text format
+    Metrics,
+    /// Show the accepted commands
+    Help,
+    /// End the session
+    Quit,
+}
+
+impl LineCommand {
+    /// Canonical name of the command, as spelled by `elkd` (e.g. `set_color`)
+    pub fn name(&self) -> &'static str {
+        match self {
+            LineCommand::PowerOn => "power_on",
+            LineCommand::PowerOff => "power_off",
+            LineCommand::Color(_) => "set_color",
+            LineCommand::Brightness(_) => "set_brightness",
+            LineCommand::Effect { .. } => "set_effect",
+            LineCommand::EffectSpeed(_) => "set_effect_speed",
+            LineCommand::ColorTemp(_) => "set_color_temp",
+            LineCommand::ScheduleOn { .. } => "set_schedule_on",
+            LineCommand::ScheduleOff { .. } => "set_schedule_off",
+            LineCommand::SyncTime => "sync_time",
+            LineCommand::ListEffects { .. } => "list_effects",
+            LineCommand::Sleep(_) => "sleep",
+            LineCommand::Status => "status",
+            LineCommand::Metrics => "metrics",
+            LineCommand::Help => "help",
+            LineCommand::Quit => "quit",
+        }
+    }
+
+    /// Whether running the command sends anything to the device
+    ///
+    /// `sleep`, `status`, `metrics`, `list_effects`, `help` and `quit` don't,
+    /// so a server sharing one device between clients can run them without
+    /// waiting for its turn.
+    pub fn uses_device(&self) -> bool {
+        !matches!(
+            self,
+            LineCommand::Sleep(_)
+                | LineCommand::Status
+                | LineCommand::Metrics
+                | LineCommand::ListEffects { .. }
+                | LineCommand::Help
+                | LineCommand::Quit
+        )
+    }
+
+    /// Sends the command to `device`
+    ///
+    /// Returns the text to print for commands that produce output (`status`,
+    /// `metrics`, `list_effects` and `help`). [`LineCommand::Quit`] does nothing; the caller ends the
+    /// session.
+    pub async fn run(&self, device: &BleLedDevice) -> Result<Option<String>> {
+        match *self {
+            LineCommand::PowerOn => device.power_on().await?,
+            LineCommand::PowerOff => device.power_off().await?,
+            LineCommand::Color((r, g, b)) => device.set_color(r, g, b).await?,
+            LineCommand::Brightness(level) => device.set_brightness(level).await?,
+            LineCommand::Effect { effect, speed } => {
+                device.set_effect(effect).await?;
+                if let Some(speed) = speed {
+                    device.set_effect_speed(speed).await?;
+                }
+            }
+            LineCommand::EffectSpeed(speed) => device.set_effect_speed(speed).await?,
+            LineCommand::ColorTemp(kelvin) => device.try_set_color_temp_kelvin(kelvin).await?,
+            LineCommand::ScheduleOn { days, hour, minute } => {
+                device.set_schedule_on(days, hour, minute, true).await?
+            }
+            LineCommand::ScheduleOff { days, hour, minute } => {
+                device.set_schedule_off(days, hour, minute, true).await?
+            }
+            LineCommand::SyncTime => device.sync_time().await?,
+            LineCommand::Sleep(duration) => tokio::time::sleep(duration).await,
+            // The device doesn't report its state; this is what was last sent
+            LineCommand::Status => return Ok(Some(device.snapshot().to_json())),
+            LineCommand::Metrics => {
+                let text = device
+                    .metrics()
+                    .to_prometheus(&device.address(), device.is_connected().await);
+                return Ok(Some(text.trim_end().to_string()));
+            }
+            LineCommand::ListEffects { json } => return Ok(Some(effect_names(json))),
+            LineCommand::Help => return Ok(Some(HELP.to_string())),
+            LineCommand::Quit => {}
+        }
+        Ok(None)
+    }
+}
+
+impl FromStr for LineCommand {
+    type Err = Error;
+
+    fn from_str(line: &str) -> Result<Self> {
+        let line = line.trim();
+        let (name, args) = line
+            .split_once(|c: char| c == ':' || c.is_whitespace())
+            .unwrap_or((line, ""));
+        let args = args.trim();
+
+        let command = match name.to_lowercase().as_str() {
+            "on" | "power_on" => LineCommand::PowerOn,
+            "off" | "power_off" => LineCommand::PowerOff,
+            "color" | "set_color" => LineCommand::Color(parse_color(args)?),
+            "brightness" | "set_brightness" => {
+                LineCommand::Brightness(parse_percent(required(args, "a brightness")?)?)
+            }
+            "effect" | "set_effect" => {
+                let mut parts = required(args, "an effect")?
+                    .split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|part| !part.is_empty());
+                let effect = parts.next().unwrap_or_default().parse()?;
+                let speed = parts.next().map(parse_percent).transpose()?;
+                LineCommand::Effect { effect, speed }
+            }
+            "speed" | "set_effect_speed" => {
+                LineCommand::EffectSpeed(parse_percent(required(args, "a speed")?)?)
+            }
+            "color_temp" | "set_color_temp" => {
+                let kelvin = required(args, "a color temperature")?;
+                LineCommand::ColorTemp(
+                    kelvin.parse().map_err(|_| {
+                        Error::InvalidCommand(format!("'{}' is not a number", kelvin))
+                    })?,
+                )
+            }
+            "schedule_on" | "set_schedule_on" => {
+                let (days, hour, minute) = parse_schedule(args)?;
+                LineCommand::ScheduleOn { days, hour, minute }
+            }
+            "schedule_off" | "set_schedule_off" => {
+                let (days, hour, minute) = parse_schedule(args)?;
+                LineCommand::ScheduleOff { days, hour, minute }
+            }
+            "sync_time" => LineCommand::SyncTime,
+            "list_effects" => match args {
+                "" => LineCommand::ListEffects { json: false },
+                "--json" | "json" => LineCommand::ListEffects { json: true },
+                other => {
+                    return Err(Error::InvalidCommand(format!(
+                        "unexpected '{}', expected --json or nothing",
+                        other
+                    )))
+                }
+            },
+            "sleep" | "wait" => LineCommand::Sleep(parse_sleep(required(args, "a duration")?)?),
+            "status" | "state" => LineCommand::Status,
+            "metrics" => LineCommand::Metrics,
+            "help" | "?" => LineCommand::Help,
+            "quit" | "exit" => LineCommand::Quit,
+            "" => return Err(Error::InvalidCommand("no command given".to_string())),
+            other => {
+                return Err(Error::InvalidCommand(format!(
+                    "unknown command '{}', try 'help'",
+                    other
+                )))
+            }
+        };
+        Ok(command)
+    }
+}
+
+/// Handles one line of `elkd`'s protocol on `device`
+///
+/// Returns the response to print: `OK`, the output of `status` or `help`, or
+/// `ERR <reason>` when the line doesn't parse or the device rejects it.
+/// Returns `None` for `quit`, which ends the session.
+pub async fn respond(device: &BleLedDevice, line: &str) -> Option<String> {
+    respond_with(line, |command| async move { command.run(device).await }).await
+}
+
+/// Like [`respond`], with `run` carrying out the parsed command
+///
+/// For servers that do more around each command, such as waiting for their
+/// turn on a device shared between clients. `run` isn't called for `quit`
+/// or a line that doesn't parse.
+pub async fn respond_with<F, Fut>(line: &str, run: F) -> Option<String>
+where
+    F: FnOnce(LineCommand) -> Fut,
+    Fut: Future<Output = Result<Option<String>>>,
+{
+    let command: LineCommand = match line.parse() {
+        Ok(LineCommand::Quit) => return None,
+        Ok(command) => command,
+        Err(e) => return Some(format!("ERR {}", e)),
+    };
+    Some(match run(command).await {
+        Ok(Some(output)) => output,
+        Ok(None) => "OK".to_string(),
+        Err(e) => format!("ERR {}", e),
+    })
+}
+
+/// Handles one request of `elkd`'s JSON line protocol on `device`
+///
+/// The request is an object naming the command in `cmd`, with its arguments
+/// as fields and an optional `id` that is echoed back, so clients can send
+/// several requests before reading the responses. Unknown fields are
+/// ignored. The response is one line: `{"id":7,"ok":true}`, or
+/// `{"id":7,"ok":false,"error":"...","kind":"InvalidCommand"}` with the
+/// [`Error::kind`] of the failure. `state`, `metrics`, `list_effects` and
+/// `help` add their output as `state`, `metrics` (the Prometheus text),
+/// `effects` and `help`. Returns `None` for `quit`.
+///
+/// | `cmd` | Fields |
+/// |-------|--------|
+/// | `power_on`, `power_off`, `sync_time`, `state`, `metrics`, `list_effects`, `help`, `quit` | none |
+/// | `set_color` | `r`, `g`, `b` (0-255), or `color` (hex or name) |
+/// | `set_brightness` | `level` (0-100) |
+/// | `set_effect` | `effect` (name or hex code), optional `speed` (0-100) |
+/// | `set_effect_speed` | `speed` (0-100) |
+/// | `set_color_temp` | `kelvin` |
+/// | `set_schedule_on`, `set_schedule_off` | `days` (e.g. `"weekdays"`), `hour`, `minute` |
+/// | `sleep` | `duration` (e.g. `"500ms"`, or `"500"` for milliseconds) |
+///
+/// The short names of the text protocol (`color`, `on`, ...) are accepted too.
+pub async fn respond_json(device: &BleLedDevice, line: &str) -> Option<String> {
+    respond_json_with(line, |command| async move { command.run(device).await }).await
+}
+
+/// Like [`respond_json`], with `run` carrying out the parsed command, as in
+/// [`respond_with`]
+pub async fn respond_json_with<F, Fut>(line: &str, run: F) -> Option<String>
+where
+    F: FnOnce(LineCommand) -> Fut,
+    Fut: Future<Output = Result<Option<String>>>,
+{
+    let request = serde_json::from_str::<Value>(line).ok();
+    let id = request
+        .as_ref()
+        .and_then(|request| request.get("id"))
+        .map_or_else(|| "null".to_string(), Value::to_string);
+    let failure = |e: Error| {
+        format!(
+            "{{\"id\":{},\"ok\":false,\"error\":{},\"kind\":\"{}\"}}",
+            id,
+            Value::from(e.to_string()),
+            e.kind()
+        )
+    };
+
+    let command = match request.as_ref().map(json_command) {
+        Some(Ok(LineCommand::Quit)) => return None,
+        Some(Ok(command)) => command,
+        Some(Err(e)) => return Some(failure(e)),
+        None => {
+            return Some(failure(Error::InvalidCommand(
+                "request is not JSON".to_string(),
+            )))
+        }
+    };
+    let output = match run(command).await {
+        Ok(output) => output,
+        Err(e) => return Some(failure(e)),
+    };
+    let extra = match (command, output) {
+        (LineCommand::Status, Some(state)) => format!(",\"state\":{}", state),
+        (LineCommand::ListEffects { .. }, Some(effects)) => format!(",\"effects\":{}", effects),
+        (LineCommand::Metrics, Some(text)) => format!(",\"metrics\":{}", Value::from(text)),
+        (LineCommand::Help, Some(help)) => format!(",\"help\":{}", Value::from(help)),
+        _ => String::new(),
+    };
+    Some(format!("{{\"id\":{},\"ok\":true{}}}", id, extra))
+}
+
+/// The command described by a JSON request object
+fn json_command(request: &Value) -> Result<LineCommand> {
+    let Some(Value::String(cmd)) = request.get("cmd") else {
+        return Err(Error::InvalidCommand(
+            "request needs a \"cmd\" string".to_string(),
+        ));
+    };
+    let number = |key: &str, max: u32| -> Result<u32> {
+        match request.get(key).map(Value::as_u64) {
+            Some(Some(value)) if value > max as u64 => Err(Error::ValueOutOfRange(
+                value.min(u32::MAX as u64) as u32,
+                0,
+                max,
+            )),
+            Some(Some(value)) => Ok(value as u32),
+            Some(None) => Err(Error::InvalidCommand(format!(
+                "\"{}\" must be a whole number",
+                key
+            ))),
+            None => Err(Error::InvalidCommand(format!("expected \"{}\"", key))),
+        }
+    };
+    let text = |key: &str| -> Result<&str> {
+        match request.get(key) {
+            Some(Value::String(value)) => Ok(value),
+            Some(_) => Err(Error::InvalidCommand(format!(
+                "\"{}\" must be a string",
+                key
+            ))),
+            None => Err(Error::InvalidCommand(format!("expected \"{}\"", key))),
+        }
+    };
+    let schedule = || -> Result<(Days, u8, u8)> {
+        let days = text("days")?.parse()?;
+        Ok((days, number("hour", 23)? as u8, number("minute", 59)? as u8))
+    };
+
+    let command = match cmd.to_lowercase().as_str() {
+        "on" | "power_on" => LineCommand::PowerOn,
+        "off" | "power_off" => LineCommand::PowerOff,
+        "color" | "set_color" => match request.get("color") {
+            Some(_) => LineCommand::Color(parse_color(text("color")?)?),
+            None => LineCommand::Color((
+                number("r", 255)? as u8,
+                number("g", 255)? as u8,
+                number("b", 255)? as u8,
+            )),
+        },
+        "brightness" | "set_brightness" => LineCommand::Brightness(number("level", 100)? as u8),
+        "effect" | "set_effect" => LineCommand::Effect {
+            effect: text("effect")?.parse()?,
+            speed: match request.get("speed") {
+                Some(_) => Some(number("speed", 100)? as u8),
+                None => None,
+            },
+        },
+        "speed" | "set_effect_speed" => LineCommand::EffectSpeed(number("speed", 100)? as u8),
+        "color_temp" | "set_color_temp" => LineCommand::ColorTemp(number("kelvin", u32::MAX)?),
+        "schedule_on" | "set_schedule_on" => {
+            let (days, hour, minute) = schedule()?;
+            LineCommand::ScheduleOn { days, hour, minute }
+        }
+        "schedule_off" | "set_schedule_off" => {
+            let (days, hour, minute) = schedule()?;
+            LineCommand::ScheduleOff { days, hour, minute }
+        }
+        "sync_time" => LineCommand::SyncTime,
+        "list_effects" => LineCommand::ListEffects { json: true },
+        "sleep" | "wait" => LineCommand::Sleep(parse_sleep(text("duration")?)?),
+        "status" | "state" => LineCommand::Status,
+        "metrics" => LineCommand::Metrics,
+        "help" | "?" => LineCommand::Help,
+        "quit" | "exit" => LineCommand::Quit,
+        other => {
+            return Err(Error::InvalidCommand(format!(
+                "unknown command '{}'",
+                other
+            )))
+        }
+    };
+    Ok(command)
+}
+
+/// Parses a script of commands separated by `;` or new lines
+///
+/// Empty steps are skipped, so a trailing `;` is fine. Errors name the
+/// 1-based step that failed to parse.
+pub fn parse_script(script: &str) -> Result<Vec<LineCommand>> {
+    script
+        .split([';', '\n'])
+        .map(str::trim)
+        .filter(|step| !step.is_empty())
+        .enumerate()
+        .map(|(index, step)| {
+            step.parse().map_err(|e| {
+                Error::InvalidCommand(format!("step {} ('{}'): {}", index + 1, step, e))
+            })
+        })
+        .collect()
+}
+
+/// Parses a script file: one command per line, with `#` starting a comment line
+///
+/// Returns each command with its 1-based line number, skipping empty and
+/// comment lines. Errors name the line that failed to parse. A `#` later on a
+/// line is part of the command, as in `color #ff8800`.
+pub fn parse_script_lines(text: &str) -> Result<Vec<(usize, LineCommand)>> {
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| {
+            let command = line.parse().map_err(|e| {
+                Error::InvalidCommand(format!("line {} ('{}'): {}", number, line, e))
+            })?;
+            Ok((number, command))
+        })
+        .collect()
+}
+
+/// Parses a duration such as `6s`, `1500ms`, `2m` or `1h30m` (plain numbers are seconds)
+pub fn parse_duration(value: &str) -> Result<Duration> {
+    let value = value.trim();
+    let invalid = || Error::InvalidDuration(value.to_string());
+    if value.is_empty() {
+        return Err(invalid());
+    }
+
+    let mut seconds = 0.0;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let unit_start = rest
+            .find(|c: char| c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        let (number, tail) = rest.split_at(unit_start);
+        let unit_end = tail
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_end);
+
+        let number: f64 = number.trim().parse().map_err(|_| invalid())?;
+        seconds += match unit {
+            "ms" => number / 1000.0,
+            "" | "s" => number,
+            "m" => number * 60.0,
+            "h" => number * 3600.0,
+            _ => return Err(invalid()),
+        };
+        rest = tail;
+    }
+    Duration::try_from_secs_f64(seconds).map_err(|_| invalid())
+}
+
+/// Parses the argument of `sleep`, where a plain number is milliseconds
+fn parse_sleep(value: &str) -> Result<Duration> {
+    match value.trim().parse::<u64>() {
+        Ok(millis) => Ok(Duration::from_millis(millis)),
+        Err(_) => parse_duration(value),
+    }
+}
+
+/// Fails when a command that needs an argument got none
+fn required<'a>(args: &'a str, what: &str) -> Result<&'a str> {
+    if args.is_empty() {
+        Err(Error::InvalidCommand(format!("expected {}", what)))
+    } else {
+        Ok(args)
+    }
+}
+
+/// Parses a hex color (`ff0000`, `#f00`), a name (`orange`) or `R,G,B`
+fn parse_color(value: &str) -> Result<Color> {
+    let value = required(value, "a color")?;
+    if let Ok(color) = crate::parse_color(value) {
+        return Ok(color);
+    }
+
+    let channels: Vec<u8> = value
+        .split(',')
+        .map(|channel| channel.trim().parse())
+        .collect::<std::result::Result<_, _>>()
+        .map_err(|_| Error::InvalidColor(value.to_string()))?;
+    match channels[..] {
+        [r, g, b] => Ok((r, g, b)),
+        _ => Err(Error::InvalidColor(value.to_string())),
+    }
+}
+
+/// The catalog's effect names in catalog order, one per line or as a JSON array
+fn effect_names(json: bool) -> String {
+    let names = crate::effects::all().iter().map(|info| info.name);
+    if json {
+        let quoted: Vec<String> = names.map(|name| format!("\"{}\"", name)).collect();
+        format!("[{}]", quoted.join(","))
+    } else {
+        names.collect::<Vec<_>>().join("\n")
+    }
+}
+
+/// Parses `<days>:<hh>:<mm>` or `<days> <hh:mm>`, e.g. `weekdays:07:30`
+fn parse_schedule(value: &str) -> Result<(Days, u8, u8)> {
+    let value = required(value, "days and a time, e.g. weekdays:07:30")?;
+    let (days, time) = value
+        .split_once(|c: char| c == ':' || c.is_whitespace())
+        .ok_or_else(|| Error::InvalidCommand(format!("expected a time after '{}'", value)))?;
+    let time = time.trim();
+    let invalid = || Error::InvalidCommand(format!("'{}' is not a time (hh:mm)", time));
+    let (hour, minute) = time.split_once(':').ok_or_else(invalid)?;
+    let hour: u32 = hour.trim().parse().map_err(|_| invalid())?;
+    let minute: u32 = minute.trim().parse().map_err(|_| invalid())?;
+    if hour > 23 {
+        return Err(Error::ValueOutOfRange(hour, 0, 23));
+    }
+    if minute > 59 {
+        return Err(Error::ValueOutOfRange(minute, 0, 59));
+    }
+    Ok((days.parse()?, hour as u8, minute as u8))
+}
+
+/// Parses a value between 0 and 100
+fn parse_percent(value: &str) -> Result<u8> {
+    let parsed: u32 = value
+        .trim()
+        .parse()
+        .map_err(|_| Error::InvalidCommand(format!("'{}' is not a number", value)))?;
+    if parsed > 100 {
+        return Err(Error::ValueOutOfRange(parsed, 0, 100));
+    }
+    Ok(parsed as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parse(line: &str) -> Result<LineCommand> {
+        line.parse()
+    }
+
+    #[test]
+    fn parses_every_command_in_both_spellings() {
+        let cases = [
+            ("on", "power_on", LineCommand::PowerOn),
+            ("off", "power_off", LineCommand::PowerOff),
+            (
+                "color ff0000",
+                "set_color:255,0,0",
+                LineCommand::Color((255, 0, 0)),
+            ),
+            (
+                "brightness 40",
+                "set_brightness:40",
+                LineCommand::Brightness(40),
+            ),
+            (
+                "effect jump_rgb",
+                "set_effect:jump_rgb",
+                LineCommand::Effect {
+                    effect: Effect::JumpRgb,
+                    speed: None,
+                },
+            ),
+            (
+                "effect jump_rgb 60",
+                "set_effect:jump_rgb,60",
+                LineCommand::Effect {
+                    effect: Effect::JumpRgb,
+                    speed: Some(60),
+                },
+            ),
+            (
+                "speed 100",
+                "set_effect_speed:100",
+                LineCommand::EffectSpeed(100),
+            ),
+            (
+                "color_temp 2700",
+                "set_color_temp:2700",
+                LineCommand::ColorTemp(2700),
+            ),
+            (
+                "schedule_on weekdays 07:30",
+                "set_schedule_on:weekdays:07:30",
+                LineCommand::ScheduleOn {
+                    days: Days::WEEKDAYS,
+                    hour: 7,
+                    minute: 30,
+                },
+            ),
+            (
+                "schedule_off mon 23:59",
+                "set_schedule_off:mon:23:59",
+                LineCommand::ScheduleOff {
+                    days: Days::MONDAY,
+                    hour: 23,
+                    minute: 59,
+                },
+            ),
+            ("sync_time", "SYNC_TIME", LineCommand::SyncTime),
+            (
+                "list_effects",
+                "list_effects",
+                LineCommand::ListEffects { json: false },
+            ),
+            (
+                "list_effects --json",
+                "list_effects:json",
+                LineCommand::ListEffects { json: true },
+            ),
+            (
+                "sleep 2s",
+                "wait:2000",
+                LineCommand::Sleep(Duration::from_secs(2)),
+            ),
+            ("status", "state", LineCommand::Status),
+            ("metrics", " metrics ", LineCommand::Metrics),
+            ("help", "?", LineCommand::Help),
+            ("quit", "exit", LineCommand::Quit),
+        ];
+        for (short, long, expected) in cases {
+            assert_eq!(parse(short).unwrap(), expected, "{}", short);
+            assert_eq!(parse(long).unwrap(), expected, "{}", long);
+        }
+    }
+
+    #[test]
+    fn parses_command_arguments() {
+        assert_eq!(
+            parse("color #f80").unwrap(),
+            LineCommand::Color((255, 136, 0))
+        );
+        assert_eq!(
+            parse("color orange").unwrap(),
+            LineCommand::Color((255, 128, 0))
+        );
+        assert_eq!(
+            parse("color 1, 2, 3").unwrap(),
+            LineCommand::Color((1, 2, 3))
+        );
+        assert_eq!(
+            parse("effect 0x9d").unwrap(),
+            LineCommand::Effect {
+                effect: Effect::from(0x9d),
+                speed: None
+            }
+        );
+        assert_eq!(
+            parse("sleep 500").unwrap(),
+            LineCommand::Sleep(Duration::from_millis(500))
+        );
+        assert_eq!(
+            parse("schedule_on mon-fri,sun 00:00").unwrap(),
+            LineCommand::ScheduleOn {
+                days: Days::WEEKDAYS | Days::SUNDAY,
+                hour: 0,
+                minute: 0
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        for line in [
+            "brightness",
+            "brightness abc",
+            "set_effect_speed:",
+            "color_temp warm",
+            "color_temp",
+            "list_effects --yaml",
+            "sleep",
+            "set_schedule_on:weekdays",
+            "set_schedule_on:weekdays:0730",
+            "schedule_on weekdays 7",
+        ] {
+            assert!(
+                matches!(parse(line), Err(Error::InvalidCommand(_))),
+                "{}: {:?}",
+                line,
+                parse(line)
+            );
+        }
+
+        assert!(matches!(
+            parse("brightness 101"),
+            Err(Error::ValueOutOfRange(101, 0, 100))
+        ));
+        assert!(matches!(
+            parse("effect jump_rgb 150"),
+            Err(Error::ValueOutOfRange(150, 0, 100))
+        ));
+        assert!(matches!(
+            parse("set_schedule_off:mon:24:00"),
+            Err(Error::ValueOutOfRange(24, 0, 23))
+        ));
+        assert!(matches!(
+            parse("set_schedule_off:mon:23:60"),
+            Err(Error::ValueOutOfRange(60, 0, 59))
+        ));
+        assert!(matches!(
+            parse("schedule_on funday 07:30"),
+            Err(Error::InvalidDays(_))
+        ));
+        assert!(matches!(
+            parse("sleep soon"),
+            Err(Error::InvalidDuration(_))
+        ));
+        assert!(matches!(parse("color"), Err(Error::InvalidCommand(_))));
+        assert!(matches!(parse("color zz"), Err(Error::InvalidColor(_))));
+        assert!(matches!(parse("color 1,2"), Err(Error::InvalidColor(_))));
+        assert!(matches!(
+            parse("color 256,0,0"),
+            Err(Error::InvalidColor(_))
+        ));
+        assert!(matches!(
+            parse("effect disco"),
+            Err(Error::UnknownEffect { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_and_empty_commands() {
+        for line in ["dance", "set_colour:255,0,0", "power 1"] {
+            match parse(line) {
+                Err(Error::InvalidCommand(message)) => {
+                    assert!(message.starts_with("unknown command"), "{}", message)
+                }
+                other => panic!("{}: {:?}", line, other),
+            }
+        }
+        assert!(matches!(parse(""), Err(Error::InvalidCommand(_))));
+        assert!(matches!(parse("   "), Err(Error::InvalidCommand(_))));
+    }
+
+    #[test]
+    fn parses_schedules() {
+        assert_eq!(
+            parse_schedule("weekend:09:05").unwrap(),
+            (Days::WEEKEND, 9, 5)
+        );
+        assert_eq!(parse_schedule("all 23:59").unwrap(), (Days::ALL, 23, 59));
+        assert_eq!(parse_schedule("tue: 7:00").unwrap(), (Days::TUESDAY, 7, 0));
+        assert!(parse_schedule("").is_err());
+        assert!(parse_schedule("mon").is_err());
+        assert!(parse_schedule("mon:aa:00").is_err());
+        assert!(parse_schedule("mon:-1:00").is_err());
+    }
+
+    #[test]
+    fn maps_json_requests_to_commands() {
+        let cases = [
+            (json!({"cmd": "power_on", "id": 1}), LineCommand::PowerOn),
+            (json!({"cmd": "off"}), LineCommand::PowerOff),
+            (
+                json!({"cmd": "set_color", "r": 255, "g": 0, "b": 8}),
+                LineCommand::Color((255, 0, 8)),
+            ),
+            (
+                json!({"cmd": "color", "color": "#00ff00"}),
+                LineCommand::Color((0, 255, 0)),
+            ),
+            (
+                json!({"cmd": "set_brightness", "level": 40}),
+                LineCommand::Brightness(40),
+            ),
+            (
+                json!({"cmd": "set_effect", "effect": "jump_rgb", "speed": 60}),
+                LineCommand::Effect {
+                    effect: Effect::JumpRgb,
+                    speed: Some(60),
+                },
+            ),
+            (
+                json!({"cmd": "set_effect", "effect": "0x87"}),
+                LineCommand::Effect {
+                    effect: Effect::JumpRgb,
+                    speed: None,
+                },
+            ),
+            (
+                json!({"cmd": "set_effect_speed", "speed": 100}),
+                LineCommand::EffectSpeed(100),
+            ),
+            (
+                json!({"cmd": "set_color_temp", "kelvin": 4000}),
+                LineCommand::ColorTemp(4000),
+            ),
+            (
+                json!({"cmd": "set_schedule_on", "days": "weekdays", "hour": 7, "minute": 30}),
+                LineCommand::ScheduleOn {
+                    days: Days::WEEKDAYS,
+                    hour: 7,
+                    minute: 30,
+                },
+            ),
+            (
+                json!({"cmd": "set_schedule_off", "days": "sun", "hour": 0, "minute": 0}),
+                LineCommand::ScheduleOff {
+                    days: Days::SUNDAY,
+                    hour: 0,
+                    minute: 0,
+                },
+            ),
+            (json!({"cmd": "sync_time"}), LineCommand::SyncTime),
+            (
+                json!({"cmd": "list_effects"}),
+                LineCommand::ListEffects { json: true },
+            ),
+            (
+                json!({"cmd": "sleep", "duration": "500"}),
+                LineCommand::Sleep(Duration::from_millis(500)),
+            ),
+            (json!({"cmd": "state"}), LineCommand::Status),
+            (json!({"cmd": "metrics"}), LineCommand::Metrics),
+            (json!({"cmd": "help"}), LineCommand::Help),
+            (json!({"cmd": "quit"}), LineCommand::Quit),
+            // Unknown fields are ignored
+            (json!({"cmd": "on", "extra": [1, 2]}), LineCommand::PowerOn),
+        ];
+        for (request, expected) in cases {
+            assert_eq!(json_command(&request).unwrap(), expected, "{}", request);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_json_requests() {
+        for request in [
+            json!({}),
+            json!([]),
+            json!({"cmd": 7}),
+            json!({"cmd": "dance"}),
+            json!({"cmd": "set_color", "r": 255, "g": 0}),
+            json!({"cmd": "set_brightness", "level": 1.5}),
+            json!({"cmd": "set_brightness", "level": -1}),
+            json!({"cmd": "set_brightness", "level": "40"}),
+            json!({"cmd": "set_effect", "effect": 135}),
+            json!({"cmd": "sleep", "duration": 500}),
+        ] {
+            assert!(
+                matches!(json_command(&request), Err(Error::InvalidCommand(_))),
+                "{}",
+                request
+            );
+        }
+
+        let too_bright = json!({"cmd": "set_brightness", "level": 101});
+        assert!(matches!(
+            json_command(&too_bright),
+            Err(Error::ValueOutOfRange(101, 0, 100))
+        ));
+        let late = json!({"cmd": "set_schedule_on", "days": "mon", "hour": 24, "minute": 0});
+        assert!(matches!(
+            json_command(&late),
+            Err(Error::ValueOutOfRange(24, 0, 23))
+        ));
+        let huge = json!({"cmd": "set_color", "r": 1u64 << 40, "g": 0, "b": 0});
+        assert!(matches!(
+            json_command(&huge),
+            Err(Error::ValueOutOfRange(u32::MAX, 0, 255))
+        ));
+    }
+}
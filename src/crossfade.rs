@@ -0,0 +1,290 @@
+/*!
+ # Software crossfades
+
+ The firmware can only crossfade between its preset color sets. This module
+ drives a crossfade between any two colors, or through random colors, from
+ the host by streaming color commands to the device. One-way fades of the
+ color or the brightness work the same way.
+*/
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::f32::consts::PI;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, instrument};
+
+use crate::color::{hsv_to_rgb, lerp_color};
+use crate::{BleLedDevice, Color, Error, Result};
+
+/// Shape of the transition between the two colors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Easing {
+    /// Constant rate of change, reversing sharply at each color
+    Linear,
+    /// Sine wave, slowing down as it approaches each color
+    #[default]
+    Sine,
+}
+
+impl Easing {
+    /// Mix factor (0.0 = first color, 1.0 = second color) at `phase` of a period
+    fn mix(self, phase: f32) -> f32 {
+        match self {
+            Easing::Linear => 1.0 - (2.0 * phase - 1.0).abs(),
+            Easing::Sine => (1.0 - (2.0 * PI * phase).cos()) / 2.0,
+        }
+    }
+}
+
+/// Options for [`BleLedDevice::run_crossfade`]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CrossfadeOptions {
+    /// Shape of the transition
+    pub easing: Easing,
+    /// Dim towards the middle of each transition, brightest at the pure colors
+    pub breathe: bool,
+    /// Stop after this long; `None` runs until the future is dropped
+    pub duration: Option<Duration>,
+}
+
+/// Where a [`BleLedDevice::fade`] ends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FadeTarget {
+    /// Fade the color, keeping the brightness
+    Color(Color),
+    /// Fade the brightness (0-100), keeping the color
+    Brightness(u8),
+}
+
+/// Fraction of the starting brightness kept at the dimmest point when breathing
+const BREATHE_MIN_LEVEL: f32 = 0.3;
+
+/// Smallest hue step between two random colors, in degrees, so every
+/// transition is visible
+const RANDOM_MIN_HUE_STEP: f32 = 60.0;
+
+impl BleLedDevice {
+    /// Fades from the current color to `to` over `duration`
+    ///
+    /// The fade eases in and out, paced by `command_delay`, and always ends
+    /// on exactly `to`. A zero duration sets `to` at once.
+    ///
+    /// # Arguments
+    ///
+    /// * `to` - Target color as (red, green, blue)
+    /// * `duration` - How long the fade takes
+    #[instrument(skip(self))]
+    pub async fn fade_to_color(&self, to: Color, duration: Duration) -> Result<()> {
+        self.fade_until(to, duration, None).await
+    }
+
+    /// Fades from the current color or brightness to `target` in `steps` writes
+    ///
+    /// The writes are spread evenly over `duration`, easing in and out, and
+    /// the last one sets exactly `target`; a step whose value doesn't change
+    /// is skipped. Unlike [`BleLedDevice::fade_to_color`], the pace isn't
+    /// bounded by `command_delay`, so keep `duration / steps` above it.
+    /// Dropping the future leaves the device at the last value written.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ValueOutOfRange` for zero `steps` or a brightness above 100.
+    #[instrument(skip(self))]
+    pub async fn fade(&self, target: FadeTarget, duration: Duration, steps: u32) -> Result<()> {
+        if steps == 0 {
+            return Err(Error::ValueOutOfRange(0, 1, u32::MAX));
+        }
+        if let FadeTarget::Brightness(level @ 101..) = target {
+            return Err(Error::ValueOutOfRange(level as u32, 0, 100));
+        }
+
+        let (from_color, from_brightness) = (self.rgb_color(), self.brightness());
+        let start = tokio::time::Instant::now();
+        let mut last = None;
+        for step in 1..=steps {
+            tokio::time::sleep_until(start + duration.mul_f64(step as f64 / steps as f64)).await;
+            // The first half of a sine period eases from 0.0 to 1.0
+            let mix = Easing::Sine.mix(step as f32 / steps as f32 / 2.0);
+            let value = match target {
+                FadeTarget::Color(to) => FadeTarget::Color(lerp_color(from_color, to, mix)),
+                FadeTarget::Brightness(to) => FadeTarget::Brightness(
+                    (from_brightness as f32 + (to as f32 - from_brightness as f32) * mix).round()
+                        as u8,
+                ),
+            };
+            // The last step lands on the target despite rounding
+            let value = if step == steps { target } else { value };
+            if last == Some(value) {
+                continue;
+            }
+            match value {
+                FadeTarget::Color((red, green, blue)) => self.set_color(red, green, blue).await?,
+                FadeTarget::Brightness(level) => self.set_brightness(level).await?,
+            }
+            last = Some(value);
+        }
+        debug!("Fade to {:?} done after {:?}", target, start.elapsed());
+        Ok(())
+    }
+
+    /// Fades like [`BleLedDevice::fade_to_color`], stopping early at `stop_at`
+    async fn fade_until(
+        &self,
+        to: Color,
+        duration: Duration,
+        stop_at: Option<Instant>,
+    ) -> Result<()> {
+        let from = self.rgb_color();
+        let start = Instant::now();
+        let mut last_color = None;
+
+        while start.elapsed() < duration {
+            if stop_at.is_some_and(|stop_at| Instant::now() >= stop_at) {
+                return Ok(());
+            }
+            let progress = start.elapsed().as_secs_f32() / duration.as_secs_f32();
+            // The first half of a sine period eases from 0.0 to 1.0
+            let color = lerp_color(from, to, Easing::Sine.mix(progress / 2.0));
+            if last_color != Some(color) {
+                self.set_color(color.0, color.1, color.2).await?;
+                last_color = Some(color);
+            } else {
+                tokio::time::sleep(Duration::from_millis(self.command_delay)).await;
+            }
+        }
+
+        if last_color != Some(to) {
+            self.set_color(to.0, to.1, to.2).await?;
+        }
+        Ok(())
+    }
+
+    /// Crossfades back and forth between two colors for `options.duration`,
+    /// or until the future is dropped
+    ///
+    /// One full period goes from `from` to `to` and back. Updates are paced by
+    /// `command_delay`, and a write is skipped when the color has not changed.
+    /// Cancel by dropping the future, e.g. from `tokio::select!`.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - First color as (red, green, blue)
+    /// * `to` - Second color as (red, green, blue)
+    /// * `period` - Time for a full cycle from `from` to `to` and back
+    /// * `options` - Easing, brightness breathing and how long to run
+    #[instrument(skip(self))]
+    pub async fn run_crossfade(
+        &self,
+        from: (u8, u8, u8),
+        to: (u8, u8, u8),
+        period: Duration,
+        options: CrossfadeOptions,
+    ) -> Result<()> {
+        if period.is_zero() {
+            return Err(Error::General(
+                "Crossfade period must be longer than zero".to_string(),
+            ));
+        }
+
+        info!(
+            "Crossfading between RGB{:?} and RGB{:?} every {:?}",
+            from, to, period
+        );
+
+        let base_brightness = self.brightness();
+        let start = Instant::now();
+        let mut last_color = None;
+        let mut last_brightness = None;
+
+        while options
+            .duration
+            .is_none_or(|duration| start.elapsed() < duration)
+        {
+            let phase = (start.elapsed().as_secs_f32() / period.as_secs_f32()).fract();
+            let mix = options.easing.mix(phase);
+            let mut sent = false;
+
+            let color = lerp_color(from, to, mix);
+            if last_color != Some(color) {
+                debug!("Crossfade at phase {:.2}, RGB{:?}", phase, color);
+                self.set_color(color.0, color.1, color.2).await?;
+                last_color = Some(color);
+                sent = true;
+            }
+
+            if options.breathe {
+                // Brightest at either color, dimmest halfway between them
+                let distance = (2.0 * mix - 1.0).abs();
+                let level = BREATHE_MIN_LEVEL + (1.0 - BREATHE_MIN_LEVEL) * distance;
+                let brightness = (base_brightness as f32 * level).round() as u8;
+                if last_brightness != Some(brightness) {
+                    self.set_brightness(brightness).await?;
+                    last_brightness = Some(brightness);
+                    sent = true;
+                }
+            }
+
+            // The setters already wait `command_delay`; only pace idle iterations here
+            if !sent {
+                tokio::time::sleep(Duration::from_millis(self.command_delay)).await;
+            }
+        }
+
+        info!("Crossfade finished");
+        Ok(())
+    }
+
+    /// Fades through random colors for `duration`, or until the future is dropped
+    ///
+    /// Each color is a fully saturated random hue at least 60 degrees away
+    /// from the previous one, reached over `transition` with a sine easing
+    /// from the current color. Updates are paced by `command_delay`.
+    ///
+    /// With a `seed` the sequence of colors is the same on every run (for a
+    /// given version of this library), which makes it reproducible in tests.
+    ///
+    /// # Arguments
+    ///
+    /// * `transition` - Time to fade from one color to the next
+    /// * `duration` - How long to keep fading
+    /// * `seed` - Seed for the random colors, `None` for a different sequence each run
+    #[instrument(skip(self))]
+    pub async fn run_random(
+        &self,
+        transition: Duration,
+        duration: Duration,
+        seed: Option<u64>,
+    ) -> Result<()> {
+        if transition.is_zero() {
+            return Err(Error::General(
+                "Random color transition must be longer than zero".to_string(),
+            ));
+        }
+
+        info!(
+            "Fading through random colors every {:?} for {:?}",
+            transition, duration
+        );
+
+        let mut rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let start = Instant::now();
+        // No deadline when it's too far away to represent
+        let stop_at = start.checked_add(duration);
+        let mut hue: f32 = rng.gen_range(0.0..360.0);
+
+        while start.elapsed() < duration {
+            let to = hsv_to_rgb(hue, 1.0, 1.0);
+            debug!("Fading to RGB{:?}", to);
+            self.fade_until(to, transition, stop_at).await?;
+
+            hue = (hue + rng.gen_range(RANDOM_MIN_HUE_STEP..=360.0 - RANDOM_MIN_HUE_STEP))
+                .rem_euclid(360.0);
+        }
+
+        info!("Random colors finished");
+        Ok(())
+    }
+}
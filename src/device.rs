@@ -1,39 +1,149 @@
 use btleplug::api::{
-    Central, Characteristic, Manager as _, Peripheral as _, ScanFilter, WriteType,
+    Central, Characteristic, Manager as _, Peripheral as _, PeripheralProperties, ScanFilter,
+    WriteType,
 };
 use btleplug::platform::{Adapter, Manager, Peripheral};
-use chrono::{self, Datelike, Timelike};
+use chrono::{self, Datelike, Offset, Timelike};
+use futures::{Stream, StreamExt};
+use parking_lot::RwLock;
+use serde_json::Value;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::{Mutex, Semaphore};
+use tokio::sync::{broadcast, Mutex, Semaphore};
 use tokio::time;
 use tracing::{debug, error, info, instrument, trace, warn};
 use uuid::Uuid;
 
 // Import our custom error type
-use crate::{Error, Result};
+use crate::effects::{codes_contain, supported_effect_codes};
+use crate::events::DeviceEvent;
+use crate::metrics::{DeviceMetrics, MetricCounters};
+use crate::persist::{self, StatePersister};
+use crate::protocol::{self, parse_frame, Frame, EXIT_EFFECT_FRAME};
+use crate::sleep_timer::SleepTimer;
+use crate::{CancelHandle, Error, Result};
 
 // Re-export schedule and effects modules
-pub use crate::effects::{Effects, EFFECTS};
-pub use crate::schedule::{Days, WEEK_DAYS};
-
-/// Gets the default Bluetooth adapter
-#[instrument(skip(manager))]
-async fn get_central(manager: &Manager) -> Result<Adapter> {
-    debug!("Getting default Bluetooth adapter");
-    let adapters = manager.adapters().await?;
-    if adapters.is_empty() {
-        error!("No Bluetooth adapters found");
-        return Err(Error::NoBluetoothAdapters);
-    }
-
-    let adapter = adapters.into_iter().next().unwrap();
-    debug!("Using Bluetooth adapter");
-    Ok(adapter)
+#[allow(deprecated)]
+pub use crate::effects::{Effect, EffectInfo, Effects, EFFECTS};
+#[allow(deprecated)]
+pub use crate::schedule::{Days, WeekDays, WEEK_DAYS};
+
+/// An opened Bluetooth adapter, shared by the scans and connections made through it
+///
+/// Setting up the Bluetooth stack takes a noticeable part of a connection, so
+/// open it once and hand clones to [`BleLedDeviceBuilder::bluetooth`] when
+/// scanning for or connecting to several devices. Constructors given none
+/// open their own.
+///
+/// ```no_run
+/// use elk_led_controller::{BleLedDevice, Bluetooth, DiscoveredDevice};
+/// use std::time::Duration;
+///
+/// # async fn example() -> elk_led_controller::Result<()> {
+/// let bluetooth = Bluetooth::open().await?;
+/// for found in bluetooth.scan(Duration::from_secs(5)).await? {
+///     if found.is_compatible() {
+///         let device = BleLedDevice::builder()
+///             .address(found.address)
+///             .bluetooth(bluetooth.clone())
+///             .connect()
+///             .await?;
+///         device.power_on().await?;
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Bluetooth {
+    /// The default adapter
+    adapter: Adapter,
+}
+
+impl Bluetooth {
+    /// Opens the default Bluetooth adapter
+    #[instrument]
+    pub async fn open() -> Result<Bluetooth> {
+        debug!("Getting default Bluetooth adapter");
+        let manager = Manager::new().await?;
+        let adapters = manager.adapters().await?;
+        if adapters.is_empty() {
+            error!("No Bluetooth adapters found");
+            return Err(Error::NoBluetoothAdapters);
+        }
+
+        let adapter = adapters.into_iter().next().unwrap();
+        debug!("Using Bluetooth adapter");
+        Ok(Bluetooth { adapter })
+    }
+
+    /// Scans for `duration` and lists every peripheral seen, compatible or not
+    ///
+    /// See [`BleLedDevice::scan`].
+    #[instrument(skip(self))]
+    pub async fn scan(&self, duration: Duration) -> Result<Vec<DiscoveredDevice>> {
+        let central = &self.adapter;
+
+        info!(
+            "Scanning for BLE devices for {} seconds...",
+            duration.as_secs()
+        );
+        central.start_scan(ScanFilter::default()).await?;
+        time::sleep(duration).await;
+
+        let mut devices = Vec::new();
+        for p in central.peripherals().await? {
+            let Ok(Some(props)) = p.properties().await else {
+                continue;
+            };
+            devices.push(DiscoveredDevice {
+                device_type: detect_device_type(&props),
+                name: props.local_name,
+                address: p.address().to_string(),
+                id: p.id().to_string(),
+                rssi: props.rssi,
+                connected: p.is_connected().await.unwrap_or(false),
+            });
+        }
+        central.stop_scan().await?;
+
+        devices.sort_by_key(|d| (!d.is_compatible(), std::cmp::Reverse(d.rssi)));
+        debug!("Scan found {} peripherals", devices.len());
+        Ok(devices)
+    }
+}
+
+/// Publishes a connection progress event on `events`, if any
+fn report(events: Option<&broadcast::Sender<DeviceEvent>>, event: DeviceEvent) {
+    if let Some(events) = events {
+        // Sending only fails when nobody is subscribed, which is fine
+        let _ = events.send(event);
+    }
+}
+
+/// Connects `peripheral` again and rediscovers its services, returning how long it took
+///
+/// The peripheral keeps its id, so this skips the scan a constructor needs.
+async fn reconnect_peripheral(peripheral: &Peripheral) -> Result<Duration> {
+    let started = time::Instant::now();
+    if !peripheral.is_connected().await? {
+        peripheral.connect().await?;
+    }
+    peripheral.discover_services().await?;
+    let elapsed = started.elapsed();
+    info!("Reconnected to {} in {:?}", peripheral.address(), elapsed);
+    Ok(elapsed)
 }
 
 /// Supported device types for LED control
+///
+/// With the `serde` feature these serialize as snake_case strings (`"elk_ble"`, `"led_ble"`, ...).
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum DeviceType {
     /// ELK-BLE device type
     ElkBle,
@@ -49,6 +159,77 @@ pub enum DeviceType {
     Unknown,
 }
 
+impl DeviceType {
+    /// The snake_case name used in JSON output, the same as serde's (`"elk_ble"`, ...)
+    pub fn name(self) -> &'static str {
+        match self {
+            DeviceType::ElkBle => "elk_ble",
+            DeviceType::LedBle => "led_ble",
+            DeviceType::Melk => "melk",
+            DeviceType::ElkBulb => "elk_bulb",
+            DeviceType::ElkLampl => "elk_lampl",
+            DeviceType::Unknown => "unknown",
+        }
+    }
+}
+
+/// What `set_effect_speed` does while no effect is active
+///
+/// The library only knows about effects it set itself, so after connecting to
+/// a device that already runs an effect, [`SpeedWithoutEffect::Send`] is the
+/// only way to change its speed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpeedWithoutEffect {
+    /// Send the speed command anyway and log a warning; some firmwares
+    /// briefly flash the strip
+    #[default]
+    Send,
+    /// Do nothing and return `Ok(())`
+    Skip,
+    /// Do nothing and return [`Error::NoActiveEffect`]
+    Error,
+}
+
+/// Mapping from requested brightness to the level sent to the device
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BrightnessCurve {
+    /// Send the requested level unchanged
+    #[default]
+    Linear,
+    /// Raise the requested level (as a 0.0-1.0 fraction) to this power
+    Gamma(f32),
+    /// Treat the requested level as CIE 1931 lightness (L*), so 50% looks half as bright
+    Cie1931,
+}
+
+impl BrightnessCurve {
+    /// Maps a requested brightness (0-100) to the device's 0-100 scale
+    ///
+    /// Non-zero requests never map to 0, so dimming does not switch the strip off.
+    pub fn apply(self, value: u8) -> u8 {
+        let value = value.min(100);
+        if value == 0 {
+            return 0;
+        }
+
+        let fraction = value as f32 / 100.0;
+        let mapped = match self {
+            BrightnessCurve::Linear => return value,
+            BrightnessCurve::Gamma(gamma) => fraction.powf(gamma),
+            BrightnessCurve::Cie1931 => {
+                let lightness = fraction * 100.0;
+                if lightness <= 8.0 {
+                    lightness / 903.3
+                } else {
+                    ((lightness + 16.0) / 116.0).powi(3)
+                }
+            }
+        };
+
+        ((mapped * 100.0).round() as u8).clamp(1, 100)
+    }
+}
+
 /// Configuration for different device types
 #[derive(Debug, Clone)]
 pub struct DeviceConfig {
@@ -66,6 +247,47 @@ pub struct DeviceConfig {
     pub max_color_temp_k: u32,
     /// Command processing time in milliseconds
     pub command_delay: u64,
+    /// Whether the firmware treats a higher speed byte as slower
+    pub invert_effect_speed: bool,
+    /// Effect codes the firmware is known to implement
+    pub supported_effects: &'static [RangeInclusive<u8>],
+}
+
+impl DeviceConfig {
+    /// Configuration used for devices of the given type
+    pub fn for_device_type(device_type: DeviceType) -> Self {
+        BleLedDevice::get_device_config(device_type)
+    }
+
+    /// Whether the effect is in [`DeviceConfig::supported_effects`]
+    pub fn supports_effect(&self, effect: Effect) -> bool {
+        codes_contain(self.supported_effects, effect.code())
+    }
+
+    /// Warm and cold levels (0-100 each) showing `kelvin`, clamped to the
+    /// supported range
+    ///
+    /// [`DeviceConfig::min_color_temp_k`] is fully warm, `(100, 0)`, and
+    /// [`DeviceConfig::max_color_temp_k`] fully cold, `(0, 100)`.
+    pub fn color_temp_levels(&self, kelvin: u32) -> (u8, u8) {
+        let (min_k, max_k) = (self.min_color_temp_k, self.max_color_temp_k);
+        let cold = protocol::color_temp_percent(kelvin.clamp(min_k, max_k), min_k, max_k);
+        (100 - cold, cold)
+    }
+
+    /// Speed byte sent for an effect speed (0-100), honoring
+    /// [`DeviceConfig::invert_effect_speed`]
+    ///
+    /// The mapping is its own inverse, so it also turns a raw byte back into
+    /// the speed it shows.
+    pub fn raw_effect_speed(&self, speed: u8) -> u8 {
+        let speed = speed.min(100);
+        if self.invert_effect_speed {
+            100 - speed
+        } else {
+            speed
+        }
+    }
 }
 
 /// Command queue to manage Bluetooth commands with rate limiting
@@ -87,16 +309,21 @@ impl CommandQueue {
         }
     }
 
+    /// Runs `future` once no other command is running and the minimum
+    /// delay has passed
+    ///
+    /// Cancellation safe: if the returned future is dropped, the permit and
+    /// lock are released, and once `future` has been started it still counts
+    /// as the last command, as its write may have reached the device.
     async fn execute<T, F>(&self, future: F) -> T
     where
-        F: std::future::Future<Output = T> + Send + 'static,
-        T: Send + 'static,
+        F: std::future::Future<Output = T>,
     {
         // Acquire permit to ensure only one command executes at a time
         let _permit = self.semaphore.acquire().await.unwrap();
 
         // Check if we need to wait before executing
-        let mut last_cmd = self.last_command.lock().await;
+        let last_cmd = self.last_command.lock().await;
         let elapsed = last_cmd.elapsed();
         if elapsed < self.min_delay {
             let wait_time = self.min_delay - elapsed;
@@ -104,26 +331,534 @@ impl CommandQueue {
             tokio::time::sleep(wait_time).await;
         }
 
-        // Execute the command
-        let result = future.await;
+        // Update the last command time when done, even if dropped midway
+        let _mark = MarkSent(last_cmd);
+        future.await
+    }
+}
+
+/// Records the time a command finished (or was abandoned) when dropped
+struct MarkSent<'a>(tokio::sync::MutexGuard<'a, std::time::Instant>);
 
-        // Update last command time
-        *last_cmd = std::time::Instant::now();
+impl Drop for MarkSent<'_> {
+    fn drop(&mut self) {
+        *self.0 = std::time::Instant::now();
+    }
+}
 
-        result
+/// Last known state of a device, as tracked by the library
+///
+/// The device does not report its state, so this reflects the commands sent
+/// through this [`BleLedDevice`]. With the `serde` feature it serializes
+/// with the same field names as [`DeviceState::to_json`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceState {
+    /// Current power state
+    pub is_on: bool,
+    /// Current RGB color (red, green, blue)
+    pub rgb_color: (u8, u8, u8),
+    /// Current brightness (0-100)
+    pub brightness: u8,
+    /// Current effect mode if active
+    pub effect: Option<Effect>,
+    /// Current effect speed if an effect is active
+    pub effect_speed: Option<u8>,
+    /// Current color temperature in Kelvin if using white mode
+    pub color_temp_kelvin: Option<u32>,
+}
+
+impl Default for DeviceState {
+    fn default() -> Self {
+        Self {
+            is_on: false,
+            rgb_color: (255, 255, 255),
+            brightness: 100,
+            effect: None,
+            effect_speed: None,
+            color_temp_kelvin: Some(5000),
+        }
+    }
+}
+
+impl DeviceState {
+    /// Formats the state as a single-line JSON object
+    ///
+    /// The effect is given by name (e.g. `"crossfade_red"`) and absent values
+    /// are `null`:
+    /// `{"is_on":true,"rgb_color":[255,0,0],"brightness":80,"effect":null,"effect_speed":null,"color_temp_kelvin":null}`
+    pub fn to_json(&self) -> String {
+        fn or_null<T: std::fmt::Display>(value: Option<T>) -> String {
+            value.map_or_else(|| "null".to_string(), |v| v.to_string())
+        }
+
+        let (r, g, b) = self.rgb_color;
+        format!(
+            "{{\"is_on\":{},\"rgb_color\":[{},{},{}],\"brightness\":{},\"effect\":{},\"effect_speed\":{},\"color_temp_kelvin\":{}}}",
+            self.is_on,
+            r,
+            g,
+            b,
+            self.brightness,
+            // Effect names are plain snake_case, so they need no escaping
+            or_null(self.effect.map(|effect| format!("\"{}\"", effect))),
+            or_null(self.effect_speed),
+            or_null(self.color_temp_kelvin),
+        )
+    }
+
+    /// Parses a state written by [`DeviceState::to_json`]
+    ///
+    /// Missing keys keep their default value and unknown keys are ignored.
+    pub fn from_json(json: &str) -> Result<DeviceState> {
+        /// The value as an integer of type `T`, if it is a whole number that fits
+        fn integer<T: TryFrom<u64>>(value: &Value) -> Option<T> {
+            value.as_u64().and_then(|value| T::try_from(value).ok())
+        }
+
+        let invalid = || Error::InvalidState(json.trim().to_string());
+        let Ok(Value::Object(fields)) = serde_json::from_str(json) else {
+            return Err(invalid());
+        };
+
+        let mut state = DeviceState::default();
+        for (key, value) in &fields {
+            match (key.as_str(), value) {
+                ("is_on", Value::Bool(on)) => state.is_on = *on,
+                ("rgb_color", Value::Array(channels)) => {
+                    let channels: Vec<u8> = channels
+                        .iter()
+                        .map(integer)
+                        .collect::<Option<_>>()
+                        .ok_or_else(invalid)?;
+                    match channels[..] {
+                        [r, g, b] => state.rgb_color = (r, g, b),
+                        _ => return Err(invalid()),
+                    }
+                }
+                ("brightness", value) => state.brightness = integer(value).ok_or_else(invalid)?,
+                ("effect", Value::Null) => state.effect = None,
+                ("effect", Value::String(name)) => state.effect = Some(name.parse()?),
+                ("effect_speed", Value::Null) => state.effect_speed = None,
+                ("effect_speed", value) => {
+                    state.effect_speed = Some(integer(value).ok_or_else(invalid)?)
+                }
+                ("color_temp_kelvin", Value::Null) => state.color_temp_kelvin = None,
+                ("color_temp_kelvin", value) => {
+                    state.color_temp_kelvin = Some(integer(value).ok_or_else(invalid)?)
+                }
+                ("is_on" | "rgb_color" | "effect", _) => return Err(invalid()),
+                _ => {}
+            }
+        }
+        Ok(state)
+    }
+}
+
+/// Builder for connecting to an LED strip with custom settings
+///
+/// ```rust,no_run
+/// # use elk_led_controller::*;
+/// # async fn example() -> Result<()> {
+/// let device = BleLedDevice::builder()
+///     .address("BE:58:00:00:00:01")
+///     .min_brightness(10)
+///     .power_on(true)
+///     .connect()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct BleLedDeviceBuilder {
+    /// MAC address or ID of the device, or `None` for the first compatible one
+    address: Option<String>,
+    /// Advertised name of the device, used when no address is set
+    name: Option<String>,
+    /// Whether to power the device on after connecting
+    power_on: bool,
+    /// Brightness floor applied by `set_brightness`
+    min_brightness: u8,
+    /// Brightness ceiling applied by `set_brightness`, `None` for 100
+    max_brightness: Option<u8>,
+    /// Curve applied by `set_brightness`
+    brightness_curve: BrightnessCurve,
+    /// Behavior of `set_effect_speed` without an active effect
+    speed_without_effect: SpeedWithoutEffect,
+    /// Write commands without waiting for an acknowledgement
+    fast_mode: bool,
+    /// How long each discovery scan lasts, `None` for [`DEFAULT_SCAN_TIMEOUT`]
+    scan_timeout: Option<Duration>,
+    /// How many more scans to run when no device is found
+    connect_retries: u32,
+    /// Channel for device events
+    events: Option<broadcast::Sender<DeviceEvent>>,
+    /// File the cached state is loaded from and saved to
+    state_file: Option<PathBuf>,
+    /// Configuration replacing the one picked for the detected device type
+    device_config: Option<DeviceConfig>,
+    /// Adapter to scan and connect with, `None` to open one on connect
+    bluetooth: Option<Bluetooth>,
+}
+
+impl BleLedDeviceBuilder {
+    /// Connect to the device with this MAC address or ID
+    pub fn address(mut self, address: impl Into<String>) -> Self {
+        self.address = Some(address.into());
+        self
+    }
+
+    /// Connect to the device advertising this name (case-insensitive)
+    ///
+    /// An address set with [`BleLedDeviceBuilder::address`] takes precedence.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// How long to scan for the device (default: [`DEFAULT_SCAN_TIMEOUT`])
+    pub fn scan_timeout(mut self, timeout: Duration) -> Self {
+        self.scan_timeout = Some(timeout);
+        self
+    }
+
+    /// Scan again this many times when no matching device is found (default: 0)
+    pub fn connect_retries(mut self, retries: u32) -> Self {
+        self.connect_retries = retries;
+        self
+    }
+
+    /// Power the device on once connected (default: false)
+    pub fn power_on(mut self, power_on: bool) -> Self {
+        self.power_on = power_on;
+        self
+    }
+
+    /// Never let `set_brightness` go below this level (0-100, default: 0)
+    ///
+    /// A floor of 0 keeps the strip able to go fully dark.
+    pub fn min_brightness(mut self, value: u8) -> Self {
+        self.min_brightness = value.min(100);
+        self
+    }
+
+    /// Never let `set_brightness` go above this level (0-100, default: 100)
+    pub fn max_brightness(mut self, value: u8) -> Self {
+        self.max_brightness = Some(value.min(100));
+        self
+    }
+
+    /// Map brightness through this curve (default: [`BrightnessCurve::Linear`])
+    pub fn brightness_curve(mut self, curve: BrightnessCurve) -> Self {
+        self.brightness_curve = curve;
+        self
+    }
+
+    /// What `set_effect_speed` does while no effect is active
+    /// (default: [`SpeedWithoutEffect::Send`])
+    pub fn speed_without_effect(mut self, behavior: SpeedWithoutEffect) -> Self {
+        self.speed_without_effect = behavior;
+        self
+    }
+
+    /// Write commands without waiting for the device to acknowledge them (default: false)
+    ///
+    /// See [`BleLedDevice::fast_mode`].
+    pub fn fast_mode(mut self, fast_mode: bool) -> Self {
+        self.fast_mode = fast_mode;
+        self
+    }
+
+    /// Publish a [`DeviceEvent`] on this channel after every applied command
+    ///
+    /// The channel also receives the connection's progress
+    /// ([`DeviceEvent::Scanning`], [`DeviceEvent::Connecting`] and
+    /// [`DeviceEvent::DiscoveringServices`]) while [`BleLedDeviceBuilder::connect`] runs.
+    pub fn events(mut self, sender: broadcast::Sender<DeviceEvent>) -> Self {
+        self.events = Some(sender);
+        self
+    }
+
+    /// Load the cached state from this file on connect and save it after each command
+    ///
+    /// Lets the state survive restarts, so the first command after a reboot
+    /// starts from what the strip last showed. Writes are debounced by
+    /// [`PERSIST_DEBOUNCE`](crate::persist::PERSIST_DEBOUNCE). A missing file
+    /// starts from the default state; an unreadable one is logged and ignored.
+    pub fn state_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.state_file = Some(path.into());
+        self
     }
+
+    /// Use this configuration instead of the one picked for the detected device type
+    ///
+    /// An escape hatch for clones that report one type but behave like
+    /// another, e.g. a `MELK` strip that takes writes on `0000ffe1`. Start
+    /// from [`DeviceConfig::for_device_type`] and change what differs; the
+    /// device type is still detected and reported as before.
+    pub fn device_config(mut self, config: DeviceConfig) -> Self {
+        self.device_config = Some(config);
+        self
+    }
+
+    /// Scan and connect with this already opened adapter
+    ///
+    /// Saves setting up the Bluetooth stack again when connecting to several
+    /// devices, or to one found by [`Bluetooth::scan`].
+    pub fn bluetooth(mut self, bluetooth: Bluetooth) -> Self {
+        self.bluetooth = Some(bluetooth);
+        self
+    }
+
+    /// Scans for and connects to the device
+    #[instrument(skip(self))]
+    pub async fn connect(self) -> Result<BleLedDevice> {
+        let scan_timeout = self.scan_timeout.unwrap_or(DEFAULT_SCAN_TIMEOUT);
+        let bluetooth = match &self.bluetooth {
+            Some(bluetooth) => bluetooth.clone(),
+            None => Bluetooth::open().await?,
+        };
+        let mut attempt = 0;
+        let mut device = loop {
+            let result = match (&self.address, &self.name) {
+                (Some(address), _) => {
+                    BleLedDevice::new_matching(
+                        &bluetooth.adapter,
+                        DeviceFilter::Address(address),
+                        scan_timeout,
+                        self.device_config.as_ref(),
+                        self.events.as_ref(),
+                    )
+                    .await
+                }
+                (None, Some(name)) => {
+                    BleLedDevice::new_matching(
+                        &bluetooth.adapter,
+                        DeviceFilter::Name(name),
+                        scan_timeout,
+                        self.device_config.as_ref(),
+                        self.events.as_ref(),
+                    )
+                    .await
+                }
+                (None, None) => {
+                    BleLedDevice::new_first_compatible(
+                        &bluetooth.adapter,
+                        scan_timeout,
+                        self.device_config.as_ref(),
+                        self.events.as_ref(),
+                    )
+                    .await
+                }
+            };
+
+            match result {
+                Err(e @ (Error::NoCompatibleDevice | Error::DeviceNotFound(_)))
+                    if attempt < self.connect_retries =>
+                {
+                    attempt += 1;
+                    warn!(
+                        "{}, scanning again (retry {}/{})",
+                        e, attempt, self.connect_retries
+                    );
+                }
+                result => break result?,
+            }
+        };
+        device.min_brightness = self.min_brightness;
+        device.max_brightness = self.max_brightness.unwrap_or(100);
+        device.brightness_curve = self.brightness_curve;
+        device.speed_without_effect = self.speed_without_effect;
+        device.fast_mode = self.fast_mode;
+        device.events = self.events;
+
+        if let Some(path) = self.state_file {
+            match persist::load_state(&path) {
+                Ok(Some(state)) => {
+                    info!("Restored device state from {}", path.display());
+                    *device.state.write() = state;
+                }
+                Ok(None) => debug!("No saved state at {}", path.display()),
+                Err(e) => warn!("Ignoring saved state at {}: {}", path.display(), e),
+            }
+            device.persister = Some(StatePersister::spawn(path, device.snapshot()));
+        }
+
+        if self.power_on {
+            info!("Powering on device");
+            device.power_on().await?;
+        }
+
+        Ok(device)
+    }
+}
+
+/// How long discovery scans for a device by default
+pub const DEFAULT_SCAN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Advertised name prefixes of known device types
+const NAME_PREFIXES: [(&str, DeviceType); 5] = [
+    ("ELK-BLE", DeviceType::ElkBle),
+    ("LEDBLE", DeviceType::LedBle),
+    ("MELK", DeviceType::Melk),
+    ("ELK-BULB", DeviceType::ElkBulb),
+    ("ELK-LAMPL", DeviceType::ElkLampl),
+];
+
+/// Advertised UUIDs of clones that speak the default protocol
+///
+/// Clones usually advertise the `fff0` service, some advertise the `fff3`
+/// write characteristic used by [`DeviceType::Unknown`] directly.
+const DEFAULT_PROTOCOL_UUIDS: [Uuid; 2] = [
+    Uuid::from_u128(0x0000fff0_0000_1000_8000_00805f9b34fb),
+    Uuid::from_u128(0x0000fff3_0000_1000_8000_00805f9b34fb),
+];
+
+/// Firmware and software revision strings of the standard Device Information service
+const REVISION_UUIDS: [Uuid; 2] = [
+    Uuid::from_u128(0x00002a26_0000_1000_8000_00805f9b34fb),
+    Uuid::from_u128(0x00002a28_0000_1000_8000_00805f9b34fb),
+];
+
+/// Identifies a compatible device from its advertisement
+///
+/// Known name prefixes map to their device type. Devices with other names
+/// that advertise the default protocol's service are treated as
+/// [`DeviceType::Unknown`], which uses the default configuration.
+fn detect_device_type(props: &PeripheralProperties) -> Option<DeviceType> {
+    if let Some(name) = &props.local_name {
+        debug!("Found device: {}", name);
+        if let Some((_, device_type)) = NAME_PREFIXES
+            .iter()
+            .find(|(prefix, _)| name.starts_with(prefix))
+        {
+            return Some(*device_type);
+        }
+    }
+
+    if props
+        .services
+        .iter()
+        .any(|uuid| DEFAULT_PROTOCOL_UUIDS.contains(uuid))
+    {
+        debug!(
+            "Device {:?} advertises a known service, treating it as an unknown clone",
+            props.local_name
+        );
+        return Some(DeviceType::Unknown);
+    }
+
+    None
+}
+
+/// Criterion for picking a device during discovery
+#[derive(Debug, Clone, Copy)]
+enum DeviceFilter<'a> {
+    /// MAC address or platform ID, case-insensitive
+    Address(&'a str),
+    /// Advertised local name, case-insensitive
+    Name(&'a str),
+}
+
+impl DeviceFilter<'_> {
+    /// Whether a discovered peripheral with advertised `name` matches
+    fn matches(&self, peripheral: &Peripheral, name: &str) -> bool {
+        match *self {
+            DeviceFilter::Address(addr) => {
+                peripheral.address().to_string().eq_ignore_ascii_case(addr)
+                    || peripheral.id().to_string().eq_ignore_ascii_case(addr)
+            }
+            DeviceFilter::Name(wanted) => name.eq_ignore_ascii_case(wanted),
+        }
+    }
+}
+
+/// Formats as `address BE:58:...` or `name 'Desk'`, for messages
+impl std::fmt::Display for DeviceFilter<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeviceFilter::Address(addr) => write!(f, "address {}", addr),
+            DeviceFilter::Name(name) => write!(f, "name '{}'", name),
+        }
+    }
+}
+
+/// A peripheral seen by [`BleLedDevice::scan`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DiscoveredDevice {
+    /// Advertised local name, if any
+    pub name: Option<String>,
+    /// Bluetooth address
+    pub address: String,
+    /// Platform-specific id, accepted by `new_with_addr` like the address
+    pub id: String,
+    /// Detected device type, `None` for incompatible peripherals
+    pub device_type: Option<DeviceType>,
+    /// Signal strength of the last advertisement in dBm
+    pub rssi: Option<i16>,
+    /// Whether the peripheral is already connected to this host
+    pub connected: bool,
+}
+
+impl DiscoveredDevice {
+    /// Whether the peripheral can be controlled by this library
+    pub fn is_compatible(&self) -> bool {
+        self.device_type.is_some()
+    }
+}
+
+/// Validates a schedule and builds its day byte, the top bit marking it enabled
+fn schedule_value(days: Days, hours: u8, minutes: u8, enabled: bool) -> Result<u8> {
+    if hours > 23 {
+        return Err(Error::ValueOutOfRange(hours as u32, 0, 23));
+    }
+    if minutes > 59 {
+        return Err(Error::ValueOutOfRange(minutes as u32, 0, 59));
+    }
+    if enabled && days.is_empty() {
+        return Err(Error::EmptyScheduleDays);
+    }
+
+    Ok(if enabled {
+        days.bits() | 0x80
+    } else {
+        days.bits()
+    })
+}
+
+/// Converts a raw day bitmask, rejecting bits outside [`Days::ALL`] (0x7f)
+/// that would otherwise spill into the enabled flag
+fn days_from_bits(bits: u8) -> Result<Days> {
+    Days::from_bits(bits)
+        .ok_or_else(|| Error::ValueOutOfRange(bits as u32, 0, Days::ALL.bits() as u32))
+}
+
+/// Builds the frame setting the device clock (0x83) to a wall-clock time
+fn time_frame(local: chrono::NaiveDateTime) -> [u8; 9] {
+    protocol::time_frame(
+        local.hour() as u8,
+        local.minute() as u8,
+        local.second() as u8,
+        crate::schedule::device_day_of_week(local.weekday()),
+    )
 }
 
 /// Main struct for controlling an LED strip via Bluetooth LE
+///
+/// Commands take `&self`, so a device can be shared between tasks (e.g. in an
+/// `Arc`). The cached state lives behind a single lock: a setter updates it in
+/// one write after its command succeeded, and [`BleLedDevice::snapshot`] reads
+/// all fields under one read lock, so a snapshot never mixes two updates.
+/// Commands from different tasks are sent in the order they reach the queue.
 pub struct BleLedDevice {
     /// The connected Bluetooth peripheral
     peripheral: Peripheral,
+    /// Advertised local name captured during discovery
+    local_name: Option<String>,
     /// Characteristic used for sending commands
     write_characteristic: Characteristic,
     /// Optional characteristic for reading device state
-    /// This is currently stored for future implementation of device status reading,
-    /// but not yet used in the current version.
-    #[allow(dead_code)]
     read_characteristic: Option<Characteristic>,
     /// Type of the connected device
     device_type: DeviceType,
@@ -131,28 +866,47 @@ pub struct BleLedDevice {
     config: DeviceConfig,
     /// Command queue for rate limiting
     command_queue: Arc<CommandQueue>,
-    /// Current power state
-    pub is_on: bool,
-    /// Current RGB color (red, green, blue)
-    pub rgb_color: (u8, u8, u8),
-    /// Current brightness (0-100)
-    pub brightness: u8,
-    /// Current effect mode if active
-    pub effect: Option<u8>,
-    /// Current effect speed if an effect is active
-    pub effect_speed: Option<u8>,
-    /// Current color temperature in Kelvin if using white mode
-    pub color_temp_kelvin: Option<u32>,
+    /// Last known state, updated after each successful command
+    state: RwLock<DeviceState>,
     /// Delay configuration for command processing (in milliseconds)
     pub command_delay: u64,
+    /// Lowest brightness `set_brightness` will send (0-100, 0 disables the floor)
+    pub min_brightness: u8,
+    /// Highest brightness `set_brightness` will send (0-100, 100 disables the ceiling)
+    ///
+    /// The floor wins if the two overlap.
+    pub max_brightness: u8,
+    /// Curve mapping requested brightness to the device's scale
+    pub brightness_curve: BrightnessCurve,
+    /// What `set_effect_speed` does while no effect is active
+    pub speed_without_effect: SpeedWithoutEffect,
+    /// Write commands without response, even when the characteristic supports acknowledged writes
+    ///
+    /// Waiting for the acknowledgement takes a large share of a 50ms audio
+    /// update. Without it a dropped write goes unnoticed, so only failures to
+    /// hand the write to the adapter are retried.
+    pub fast_mode: bool,
+    /// Channel events are published on after each applied command
+    events: Option<broadcast::Sender<DeviceEvent>>,
+    /// Writer saving the state to the builder's state file
+    persister: Option<StatePersister>,
+    /// Counters of the writes made by `send_command`
+    metrics: MetricCounters,
+    /// Pending sleep timer programmed into the off schedule
+    pub(crate) sleep_timer: parking_lot::Mutex<Option<SleepTimer>>,
 }
 
 impl BleLedDevice {
+    /// Creates a builder for configuring the connection before connecting
+    pub fn builder() -> BleLedDeviceBuilder {
+        BleLedDeviceBuilder::default()
+    }
+
     /// Creates a new instance by scanning for and connecting to a compatible LED strip
     /// and automatically powers it on
     #[instrument]
     pub async fn new() -> Result<BleLedDevice> {
-        let mut device = Self::new_without_power().await?;
+        let device = Self::new_without_power().await?;
 
         // Power on by default
         info!("Powering on device");
@@ -170,24 +924,42 @@ impl BleLedDevice {
     /// without automatically powering it on
     #[instrument]
     pub async fn new_without_power() -> Result<BleLedDevice> {
+        let bluetooth = Bluetooth::open().await?;
+        Self::new_first_compatible(&bluetooth.adapter, DEFAULT_SCAN_TIMEOUT, None, None).await
+    }
+
+    /// Scans up to `scan_timeout` for the first compatible device and connects without powering it on
+    ///
+    /// `custom_config` replaces the configuration picked for the device type.
+    /// Progress is reported on `events`.
+    async fn new_first_compatible(
+        central: &Adapter,
+        scan_timeout: Duration,
+        custom_config: Option<&DeviceConfig>,
+        events: Option<&broadcast::Sender<DeviceEvent>>,
+    ) -> Result<BleLedDevice> {
         info!("Initializing BLE LED controller");
-        let manager = Manager::new().await?;
-        let central = get_central(&manager).await?;
 
         info!("Scanning for compatible BLE devices...");
         central.start_scan(ScanFilter::default()).await?;
 
-        // Maximum time to wait for device discovery (10 seconds)
-        let max_discovery_time = Duration::from_secs(10);
+        // Maximum time to wait for device discovery
+        let max_discovery_time = scan_timeout;
         let start_time = std::time::Instant::now();
         let mut found_device = false;
-        let mut device: Option<(Peripheral, DeviceType)> = None;
+        let mut device: Option<(Peripheral, DeviceType, String)> = None;
 
         // Poll for devices until we find a compatible one or timeout
         while start_time.elapsed() < max_discovery_time && !found_device {
             // Poll for new devices
             let peripherals = central.peripherals().await?;
             debug!("Found {} BLE peripherals so far", peripherals.len());
+            report(
+                events,
+                DeviceEvent::Scanning {
+                    found: peripherals.len(),
+                },
+            );
 
             if !peripherals.is_empty() {
                 info!(
@@ -198,31 +970,15 @@ impl BleLedDevice {
                 // Check each peripheral for compatibility
                 for p in peripherals {
                     if let Ok(Some(props)) = p.properties().await {
-                        if let Some(name) = props.local_name {
-                            debug!("Found device: {}", name);
-                            let device_type = if name.starts_with("ELK-BLE") {
-                                DeviceType::ElkBle
-                            } else if name.starts_with("LEDBLE") {
-                                DeviceType::LedBle
-                            } else if name.starts_with("MELK") {
-                                DeviceType::Melk
-                            } else if name.starts_with("ELK-BULB") {
-                                DeviceType::ElkBulb
-                            } else if name.starts_with("ELK-LAMPL") {
-                                DeviceType::ElkLampl
-                            } else {
-                                DeviceType::Unknown
-                            };
-
-                            if device_type != DeviceType::Unknown {
-                                info!(
-                                    "Found compatible device: {} (type: {:?})",
-                                    name, device_type
-                                );
-                                device = Some((p, device_type));
-                                found_device = true;
-                                break;
-                            }
+                        if let Some(device_type) = detect_device_type(&props) {
+                            let name = props.local_name.unwrap_or_default();
+                            info!(
+                                "Found compatible device: {} (type: {:?})",
+                                name, device_type
+                            );
+                            device = Some((p, device_type, name));
+                            found_device = true;
+                            break;
                         }
                     }
                 }
@@ -251,20 +1007,36 @@ impl BleLedDevice {
             return Err(Error::NoCompatibleDevice);
         }
 
-        if let Some((peripheral, device_type)) = device {
+        if let Some((peripheral, device_type, name)) = device {
             // Connection and fetching of characteristics
             info!("Connecting to device...");
+            report(
+                events,
+                DeviceEvent::Connecting {
+                    name: (!name.is_empty()).then(|| name.clone()),
+                    address: peripheral.address().to_string(),
+                },
+            );
             if !peripheral.is_connected().await? {
                 peripheral.connect().await?;
             }
 
             central.stop_scan().await?;
             debug!("Discovering services...");
+            report(events, DeviceEvent::DiscoveringServices);
             peripheral.discover_services().await?;
 
-            // Get configuration for this device type
-            let config = Self::get_device_config(device_type);
-            debug!("Using config for device type: {:?}", device_type);
+            // Get configuration for this device type, unless the caller brought one
+            let config = match custom_config {
+                Some(config) => {
+                    debug!("Using custom config for device type: {:?}", device_type);
+                    config.clone()
+                }
+                None => {
+                    debug!("Using config for device type: {:?}", device_type);
+                    Self::get_device_config(device_type)
+                }
+            };
 
             // Create command queue with device-specific delay
             let command_queue = Arc::new(CommandQueue::new(config.command_delay));
@@ -292,18 +1064,23 @@ impl BleLedDevice {
 
             let device = BleLedDevice {
                 peripheral,
+                local_name: (!name.is_empty()).then_some(name),
                 write_characteristic: write_char,
                 read_characteristic: read_char,
                 device_type,
                 config,
                 command_queue,
-                is_on: false,
-                rgb_color: (255, 255, 255),
-                brightness: 100,
-                effect: None,
-                effect_speed: None,
-                color_temp_kelvin: Some(5000),
+                state: RwLock::new(DeviceState::default()),
                 command_delay: 200,
+                min_brightness: 0,
+                max_brightness: 100,
+                speed_without_effect: SpeedWithoutEffect::default(),
+                fast_mode: false,
+                brightness_curve: BrightnessCurve::Linear,
+                events: None,
+                persister: None,
+                metrics: MetricCounters::default(),
+                sleep_timer: parking_lot::Mutex::new(None),
             };
 
             // Sync time for devices that support it
@@ -326,28 +1103,81 @@ impl BleLedDevice {
         }
     }
 
+    /// Scans for `duration` and lists every peripheral seen, compatible or not
+    ///
+    /// Compatible devices come first, each group ordered by signal strength.
+    /// Nothing is connected to, so this is safe to run next to another client.
+    /// This opens the adapter for the one scan; use [`Bluetooth::scan`] to
+    /// reuse it for the connections that follow.
+    #[instrument]
+    pub async fn scan(duration: Duration) -> Result<Vec<DiscoveredDevice>> {
+        Bluetooth::open().await?.scan(duration).await
+    }
+
     /// Creates a new instance by scanning for and connecting to a LED strip with a specific MAC address or ID
     /// without automatically powering it on
     #[instrument]
     pub async fn new_with_addr(addr: &str) -> Result<BleLedDevice> {
+        let bluetooth = Bluetooth::open().await?;
+        Self::new_matching(
+            &bluetooth.adapter,
+            DeviceFilter::Address(addr),
+            DEFAULT_SCAN_TIMEOUT,
+            None,
+            None,
+        )
+        .await
+    }
+
+    /// Creates a new instance by scanning for and connecting to a LED strip with a specific
+    /// advertised name (case-insensitive) without automatically powering it on
+    #[instrument]
+    pub async fn new_with_name(name: &str) -> Result<BleLedDevice> {
+        let bluetooth = Bluetooth::open().await?;
+        Self::new_matching(
+            &bluetooth.adapter,
+            DeviceFilter::Name(name),
+            DEFAULT_SCAN_TIMEOUT,
+            None,
+            None,
+        )
+        .await
+    }
+
+    /// Scans up to `scan_timeout` for the first device matching `filter` and connects without
+    /// powering it on
+    ///
+    /// `custom_config` replaces the configuration picked for the device type.
+    /// Progress is reported on `events`.
+    async fn new_matching(
+        central: &Adapter,
+        filter: DeviceFilter<'_>,
+        scan_timeout: Duration,
+        custom_config: Option<&DeviceConfig>,
+        events: Option<&broadcast::Sender<DeviceEvent>>,
+    ) -> Result<BleLedDevice> {
         info!("Initializing BLE LED controller");
-        let manager = Manager::new().await?;
-        let central = get_central(&manager).await?;
 
         info!("Scanning for compatible BLE devices...");
         central.start_scan(ScanFilter::default()).await?;
 
-        // Maximum time to wait for device discovery (10 seconds)
-        let max_discovery_time = Duration::from_secs(10);
+        // Maximum time to wait for device discovery
+        let max_discovery_time = scan_timeout;
         let start_time = std::time::Instant::now();
         let mut found_device = false;
-        let mut device: Option<(Peripheral, DeviceType)> = None;
+        let mut device: Option<(Peripheral, DeviceType, String)> = None;
 
         // Poll for devices until we find a compatible one or timeout
         while start_time.elapsed() < max_discovery_time && !found_device {
             // Poll for new devices
             let peripherals = central.peripherals().await?;
             debug!("Found {} BLE peripherals so far", peripherals.len());
+            report(
+                events,
+                DeviceEvent::Scanning {
+                    found: peripherals.len(),
+                },
+            );
 
             if !peripherals.is_empty() {
                 info!(
@@ -358,38 +1188,23 @@ impl BleLedDevice {
                 // Check each peripheral
                 for p in peripherals {
                     if let Ok(Some(props)) = p.properties().await {
-                        if let Some(name) = props.local_name {
-                            debug!("Found device: {} {}", p.id().to_string().to_lowercase(), name);
-                            // Skip if the address does not match
-                            if p.address().to_string().to_lowercase() != addr.to_lowercase()
-                                && p.id().to_string().to_lowercase() != addr.to_lowercase()
-                            {
+                        if let Some(name) = props.local_name.clone() {
+                            debug!(
+                                "Found device: {} {}",
+                                p.id().to_string().to_lowercase(),
+                                name
+                            );
+                            // Skip if the address or name does not match
+                            if !filter.matches(&p, &name) {
                                 continue;
                             }
 
-                            debug!("Found device: {}", name);
-                            let device_type = if name.starts_with("ELK-BLE") {
-                                DeviceType::ElkBle
-                            } else if name.starts_with("LEDBLE") {
-                                DeviceType::LedBle
-                            } else if name.starts_with("MELK") {
-                                DeviceType::Melk
-                            } else if name.starts_with("ELK-BULB") {
-                                DeviceType::ElkBulb
-                            } else if name.starts_with("ELK-LAMPL") {
-                                DeviceType::ElkLampl
-                            } else {
+                            let device_type = detect_device_type(&props).unwrap_or_else(|| {
+                                error!("Device with {} is not compatible: {}", filter, name);
                                 DeviceType::Unknown
-                            };
-
-                            if device_type == DeviceType::Unknown {
-                                error!(
-                                    "Device with a given address {} is not compatible: {}",
-                                    addr, name,
-                                );
-                            }
+                            });
 
-                            device = Some((p, device_type));
+                            device = Some((p, device_type, name));
                             found_device = true;
                             break;
                         }
@@ -414,26 +1229,43 @@ impl BleLedDevice {
         if !found_device {
             central.stop_scan().await?;
             error!(
-                "No compatible LED device found within {} seconds",
+                "No device with {} found within {} seconds",
+                filter,
                 max_discovery_time.as_secs()
             );
-            return Err(Error::NoCompatibleDevice);
+            return Err(Error::DeviceNotFound(filter.to_string()));
         }
 
-        if let Some((peripheral, device_type)) = device {
+        if let Some((peripheral, device_type, name)) = device {
             // Connection and fetching of characteristics
             info!("Connecting to device...");
+            report(
+                events,
+                DeviceEvent::Connecting {
+                    name: (!name.is_empty()).then(|| name.clone()),
+                    address: peripheral.address().to_string(),
+                },
+            );
             if !peripheral.is_connected().await? {
                 peripheral.connect().await?;
             }
 
             central.stop_scan().await?;
             debug!("Discovering services...");
+            report(events, DeviceEvent::DiscoveringServices);
             peripheral.discover_services().await?;
 
-            // Get configuration for this device type
-            let config = Self::get_device_config(device_type);
-            debug!("Using config for device type: {:?}", device_type);
+            // Get configuration for this device type, unless the caller brought one
+            let config = match custom_config {
+                Some(config) => {
+                    debug!("Using custom config for device type: {:?}", device_type);
+                    config.clone()
+                }
+                None => {
+                    debug!("Using config for device type: {:?}", device_type);
+                    Self::get_device_config(device_type)
+                }
+            };
 
             // Create command queue with device-specific delay
             let command_queue = Arc::new(CommandQueue::new(config.command_delay));
@@ -461,18 +1293,23 @@ impl BleLedDevice {
 
             let device = BleLedDevice {
                 peripheral,
+                local_name: (!name.is_empty()).then_some(name),
                 write_characteristic: write_char,
                 read_characteristic: read_char,
                 device_type,
                 config,
                 command_queue,
-                is_on: false,
-                rgb_color: (255, 255, 255),
-                brightness: 100,
-                effect: None,
-                effect_speed: None,
-                color_temp_kelvin: Some(5000),
+                state: RwLock::new(DeviceState::default()),
                 command_delay: 200,
+                min_brightness: 0,
+                max_brightness: 100,
+                speed_without_effect: SpeedWithoutEffect::default(),
+                fast_mode: false,
+                brightness_curve: BrightnessCurve::Linear,
+                events: None,
+                persister: None,
+                metrics: MetricCounters::default(),
+                sleep_timer: parking_lot::Mutex::new(None),
             };
 
             // Sync time for devices that support it
@@ -506,6 +1343,8 @@ impl BleLedDevice {
                 min_color_temp_k: 2700,
                 max_color_temp_k: 6500,
                 command_delay: 15, // 15 seems to be the lowest value supported
+                invert_effect_speed: true, // 100 is the slowest animation on ELK-BLE
+                supported_effects: supported_effect_codes(device_type),
             },
             DeviceType::LedBle => DeviceConfig {
                 write_uuid: Uuid::parse_str("0000ffe1-0000-1000-8000-00805f9b34fb").unwrap(),
@@ -515,6 +1354,8 @@ impl BleLedDevice {
                 min_color_temp_k: 2700,
                 max_color_temp_k: 6500,
                 command_delay: 15,
+                invert_effect_speed: false,
+                supported_effects: supported_effect_codes(device_type),
             },
             DeviceType::Melk => DeviceConfig {
                 write_uuid: Uuid::parse_str("0000fff3-0000-1000-8000-00805f9b34fb").unwrap(),
@@ -524,6 +1365,8 @@ impl BleLedDevice {
                 min_color_temp_k: 2700,
                 max_color_temp_k: 6500,
                 command_delay: 15,
+                invert_effect_speed: false,
+                supported_effects: supported_effect_codes(device_type),
             },
             DeviceType::ElkBulb | DeviceType::ElkLampl | DeviceType::Unknown => DeviceConfig {
                 write_uuid: Uuid::parse_str("0000fff3-0000-1000-8000-00805f9b34fb").unwrap(),
@@ -533,6 +1376,8 @@ impl BleLedDevice {
                 min_color_temp_k: 2700,
                 max_color_temp_k: 6500,
                 command_delay: 15,
+                invert_effect_speed: false,
+                supported_effects: supported_effect_codes(device_type),
             },
         }
     }
@@ -549,41 +1394,221 @@ impl BleLedDevice {
         }
     }
 
-    /// Synchronizes the device's internal clock with the system time
-    #[instrument(skip(self))]
-    async fn sync_time(&self) -> Result<()> {
-        let system_time = chrono::Local::now();
-        debug!(
-            "Syncing device time to {}:{}:{} day:{}",
-            system_time.hour(),
-            system_time.minute(),
-            system_time.second(),
-            system_time.weekday().number_from_monday()
-        );
-
-        self.send_command(&[
-            0x7e,
-            0x00,
-            0x83,
-            system_time.hour() as u8,
-            system_time.minute() as u8,
-            system_time.second() as u8,
-            system_time.weekday().number_from_monday() as u8,
-            0x00,
-            0xef,
-        ])
-        .await?;
+    /// Get the Bluetooth address of the connected peripheral
+    pub fn address(&self) -> String {
+        self.peripheral.address().to_string()
+    }
 
-        debug!("Time synchronization complete");
-        Ok(())
+    /// Whether the peripheral is still connected, `false` if that can't be told
+    pub async fn is_connected(&self) -> bool {
+        self.peripheral.is_connected().await.unwrap_or(false)
     }
 
-    /// Sets a custom time on the device
+    /// Connects again after the link dropped, returning how long it took
     ///
-    /// # Arguments
+    /// This goes straight to the known peripheral on the already opened
+    /// adapter instead of scanning, and keeps the configuration, cached state
+    /// and characteristics. Writes reconnect on their own when they find the
+    /// device disconnected; call this to recover before the next command.
+    pub async fn reconnect(&self) -> Result<Duration> {
+        reconnect_peripheral(&self.peripheral).await
+    }
+
+    /// Closes the connection, e.g. before a daemon exits
     ///
-    /// * `hour` - Hour (0-23)
-    /// * `minute` - Minute (0-59)
+    /// Does nothing when already disconnected. A later command reconnects on
+    /// its own.
+    pub async fn disconnect(&self) -> Result<()> {
+        if self.peripheral.is_connected().await? {
+            self.peripheral.disconnect().await?;
+            info!("Disconnected from {}", self.address());
+        }
+        Ok(())
+    }
+
+    /// Get the platform-specific id of the connected peripheral
+    pub fn id(&self) -> String {
+        self.peripheral.id().to_string()
+    }
+
+    /// Publish events on `sender` from now on, or stop publishing with `None`
+    pub fn set_event_sender(&mut self, sender: Option<broadcast::Sender<DeviceEvent>>) {
+        self.events = sender;
+    }
+
+    /// Subscribe to the device's events, if an event channel is set
+    pub fn subscribe(&self) -> Option<broadcast::Receiver<DeviceEvent>> {
+        self.events.as_ref().map(broadcast::Sender::subscribe)
+    }
+
+    /// Publishes an event if an event channel is set
+    ///
+    /// Called after every state change, so it also queues the new state for
+    /// the state file.
+    fn emit(&self, event: DeviceEvent) {
+        if let Some(persister) = &self.persister {
+            if !matches!(event, DeviceEvent::Error(_)) {
+                persister.update(self.snapshot());
+            }
+        }
+        if let Some(events) = &self.events {
+            // Sending only fails when nobody is subscribed, which is fine
+            let _ = events.send(event);
+        }
+    }
+
+    /// Get a consistent copy of the whole cached state
+    pub fn snapshot(&self) -> DeviceState {
+        *self.state.read()
+    }
+
+    /// Replaces the cached state without sending anything
+    ///
+    /// For callers keeping the state themselves between connections, like
+    /// [`BleLedDeviceBuilder::state_file`] does on connect.
+    pub fn restore_state(&self, state: DeviceState) {
+        *self.state.write() = state;
+    }
+
+    /// Whether the strip is on
+    pub fn is_on(&self) -> bool {
+        self.state.read().is_on
+    }
+
+    /// Current RGB color (red, green, blue)
+    pub fn rgb_color(&self) -> (u8, u8, u8) {
+        self.state.read().rgb_color
+    }
+
+    /// Current brightness (0-100)
+    pub fn brightness(&self) -> u8 {
+        self.state.read().brightness
+    }
+
+    /// Current effect mode if active
+    pub fn effect(&self) -> Option<Effect> {
+        self.state.read().effect
+    }
+
+    /// Current effect speed if an effect is active
+    pub fn effect_speed(&self) -> Option<u8> {
+        self.state.read().effect_speed
+    }
+
+    /// Current color temperature in Kelvin if using white mode
+    pub fn color_temp_kelvin(&self) -> Option<u32> {
+        self.state.read().color_temp_kelvin
+    }
+
+    /// Get the type of the connected device
+    pub fn device_type(&self) -> DeviceType {
+        self.device_type
+    }
+
+    /// Counts of the commands sent, failed and retried since connecting
+    ///
+    /// A rising share of retries or failures over a long session points at
+    /// a degrading connection.
+    pub fn metrics(&self) -> DeviceMetrics {
+        self.metrics.snapshot()
+    }
+
+    /// Whether the firmware of this device implements `effect`
+    pub fn supports_effect(&self, effect: Effect) -> bool {
+        self.config.supports_effect(effect)
+    }
+
+    /// Get the advertised local name of the connected peripheral
+    pub fn name(&self) -> Option<String> {
+        self.local_name.clone()
+    }
+
+    /// Signal strength of the peripheral in dBm, if the platform reports it
+    pub async fn rssi(&self) -> Result<Option<i16>> {
+        Ok(self
+            .peripheral
+            .properties()
+            .await?
+            .and_then(|props| props.rssi))
+    }
+
+    /// Synchronizes the device's internal clock with the system time
+    ///
+    /// Runs automatically when connecting; see [`BleLedDevice::run_time_resync`]
+    /// for long-running processes.
+    #[instrument(skip(self))]
+    pub async fn sync_time(&self) -> Result<()> {
+        self.sync_time_with(chrono::Local::now()).await
+    }
+
+    /// Sets the device's clock to the wall-clock time of `datetime`
+    ///
+    /// The device has no notion of time zones, so its schedules follow the
+    /// local time of whatever zone `datetime` is in.
+    #[instrument(skip(self, datetime))]
+    pub async fn sync_time_with<Tz: chrono::TimeZone>(
+        &self,
+        datetime: chrono::DateTime<Tz>,
+    ) -> Result<()> {
+        let local = datetime.naive_local();
+        debug!(
+            "Syncing device time to {}:{}:{} day:{}",
+            local.hour(),
+            local.minute(),
+            local.second(),
+            crate::schedule::device_day_of_week(local.weekday())
+        );
+
+        self.send_command(&time_frame(local)).await?;
+
+        debug!("Time synchronization complete");
+        Ok(())
+    }
+
+    /// Resyncs the device clock every `interval`, and as soon as the local
+    /// UTC offset changes (DST), until `cancel` is triggered
+    ///
+    /// Without this, a device connected for months keeps its clock an hour
+    /// off after a DST change, and its schedules with it.
+    ///
+    /// A failed sync, e.g. while the device is out of range, is logged and
+    /// tried again at the next check; only cancelling ends the loop.
+    #[instrument(skip(self, cancel))]
+    pub async fn run_time_resync(&self, interval: Duration, cancel: CancelHandle) -> Result<()> {
+        // How often to look for a changed UTC offset
+        const OFFSET_CHECK: Duration = Duration::from_secs(60);
+
+        let mut offset = chrono::Local::now().offset().fix();
+        let mut last_sync = time::Instant::now();
+        while !cancel.is_cancelled() {
+            tokio::select! {
+                _ = time::sleep(OFFSET_CHECK.min(interval)) => {}
+                _ = cancel.cancelled() => break,
+            }
+
+            let now = chrono::Local::now();
+            let offset_changed = now.offset().fix() != offset;
+            if offset_changed || last_sync.elapsed() >= interval {
+                if offset_changed {
+                    info!("UTC offset changed, resyncing device time");
+                }
+                if let Err(e) = self.sync_time_with(now).await {
+                    warn!("Failed to resync device time, retrying: {}", e);
+                    continue;
+                }
+                offset = now.offset().fix();
+                last_sync = time::Instant::now();
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets a custom time on the device
+    ///
+    /// # Arguments
+    ///
+    /// * `hour` - Hour (0-23)
+    /// * `minute` - Minute (0-59)
     /// * `second` - Second (0-59)
     /// * `day_of_week` - Day of week (1-7, where 1 is Monday)
     #[instrument(skip(self))]
@@ -604,18 +1629,8 @@ impl BleLedDevice {
             hour, minute, second, day_of_week
         );
 
-        self.send_command(&[
-            0x7e,
-            0x00,
-            0x83,
-            hour,
-            minute,
-            second,
-            day_of_week,
-            0x00,
-            0xef,
-        ])
-        .await?;
+        self.send_command(&protocol::time_frame(hour, minute, second, day_of_week))
+            .await?;
 
         debug!("Custom time set successfully");
         Ok(())
@@ -623,10 +1638,11 @@ impl BleLedDevice {
 
     /// Turns the LED strip on
     #[instrument(skip(self))]
-    pub async fn power_on(&mut self) -> Result<()> {
+    pub async fn power_on(&self) -> Result<()> {
         debug!("Turning LED strip on");
         self.send_command(&self.config.turn_on_cmd).await?;
-        self.is_on = true;
+        self.state.write().is_on = true;
+        self.emit(DeviceEvent::PoweredOn);
 
         // Add a small delay to ensure the command has been processed
         time::sleep(Duration::from_millis(self.command_delay)).await;
@@ -636,10 +1652,11 @@ impl BleLedDevice {
 
     /// Turns the LED strip off
     #[instrument(skip(self))]
-    pub async fn power_off(&mut self) -> Result<()> {
+    pub async fn power_off(&self) -> Result<()> {
         debug!("Turning LED strip off");
         self.send_command(&self.config.turn_off_cmd).await?;
-        self.is_on = false;
+        self.state.write().is_on = false;
+        self.emit(DeviceEvent::PoweredOff);
 
         // Add a small delay to ensure the command has been processed
         time::sleep(Duration::from_millis(self.command_delay)).await;
@@ -655,45 +1672,36 @@ impl BleLedDevice {
     /// * `green_value` - Green component (0-255)
     /// * `blue_value` - Blue component (0-255)
     #[instrument(skip(self))]
-    pub async fn set_color(
-        &mut self,
-        red_value: u8,
-        green_value: u8,
-        blue_value: u8,
-    ) -> Result<()> {
+    pub async fn set_color(&self, red_value: u8, green_value: u8, blue_value: u8) -> Result<()> {
         debug!(
             "Setting color to RGB({}, {}, {})",
             red_value, green_value, blue_value
         );
 
         // First, ensure we're in RGB mode (not an effect)
-        if self.effect.is_some() {
+        if self.state.read().effect.is_some() {
             debug!("Disabling active effect before setting color");
             // Send a pre-command to disable effects mode
-            self.send_command(&[0x7e, 0x00, 0x05, 0x01, 0x00, 0x00, 0x00, 0x00, 0xef])
-                .await?;
+            self.send_command(&EXIT_EFFECT_FRAME).await?;
+            // The effect is off now, even if the next command fails
+            self.state.write().effect = None;
             // Add a small delay after disabling effect
             time::sleep(Duration::from_millis(self.command_delay)).await;
         }
 
         // Now set the RGB color
         trace!("Sending RGB color command");
-        self.send_command(&[
-            0x7e,
-            0x00,
-            0x05,
-            0x03,
-            red_value,
-            green_value,
-            blue_value,
-            0x00,
-            0xef,
-        ])
-        .await?;
+        self.send_command(&protocol::color_frame(red_value, green_value, blue_value))
+            .await?;
 
         // Update the state
-        self.rgb_color = (red_value, green_value, blue_value);
-        self.effect = None; // Setting a static color disables any active effect
+        {
+            let mut state = self.state.write();
+            state.rgb_color = (red_value, green_value, blue_value);
+            state.effect = None; // Setting a static color disables any active effect
+            state.color_temp_kelvin = None;
+        }
+        self.emit(DeviceEvent::ColorSet((red_value, green_value, blue_value)));
 
         // Add a small delay to ensure the command has been processed
         time::sleep(Duration::from_millis(self.command_delay)).await;
@@ -706,34 +1714,50 @@ impl BleLedDevice {
 
     /// Sets the brightness level
     ///
+    /// Values above [`BleLedDevice::max_brightness`] are lowered to it and
+    /// values below [`BleLedDevice::min_brightness`] are raised to it, then the
+    /// result is mapped through [`BleLedDevice::brightness_curve`]. The cached
+    /// `brightness` keeps the limited level, before the curve.
+    ///
     /// # Arguments
     ///
     /// * `value` - Brightness level (0-100)
     #[instrument(skip(self))]
-    pub async fn set_brightness(&mut self, value: u8) -> Result<()> {
-        let limited_value = value.min(100);
+    pub async fn set_brightness(&self, value: u8) -> Result<()> {
+        let mut limited_value = value.min(100);
         if value > 100 {
             warn!(
                 "Brightness value {} out of range (0-100), limiting to 100",
                 value
             );
         }
+        if limited_value > self.max_brightness {
+            trace!(
+                "Brightness {} above the configured ceiling, lowering to {}",
+                limited_value,
+                self.max_brightness
+            );
+            limited_value = self.max_brightness;
+        }
+        if limited_value < self.min_brightness {
+            trace!(
+                "Brightness {} below the configured floor, raising to {}",
+                limited_value,
+                self.min_brightness
+            );
+            limited_value = self.min_brightness.min(100);
+        }
+
+        let device_value = self.brightness_curve.apply(limited_value);
+        debug!(
+            "Setting brightness to {}% (device level {})",
+            limited_value, device_value
+        );
+        self.send_command(&protocol::brightness_frame(device_value))
+            .await?;
 
-        debug!("Setting brightness to {}%", limited_value);
-        self.send_command(&[
-            0x7e,
-            0x00,
-            0x01,
-            limited_value,
-            0x00,
-            0x00,
-            0x00,
-            0x00,
-            0xef,
-        ])
-        .await?;
-
-        self.brightness = limited_value;
+        self.state.write().brightness = limited_value;
+        self.emit(DeviceEvent::BrightnessSet(limited_value));
 
         info!("Brightness set to {}%", limited_value);
         Ok(())
@@ -743,16 +1767,32 @@ impl BleLedDevice {
     ///
     /// # Arguments
     ///
-    /// * `value` - Effect code (use the EFFECTS constant)
-    #[instrument(skip(self))]
-    pub async fn set_effect(&mut self, value: u8) -> Result<()> {
-        debug!("Setting effect mode to code: {:#04x}", value);
+    /// * `value` - Effect to run (an [`Effect`], or a raw effect code as `u8`)
+    ///
+    /// Returns [`Error::UnsupportedEffect`] if the connected firmware is not
+    /// known to implement the effect; see [`BleLedDevice::set_effect_unchecked`]
+    /// to send it anyway.
+    pub async fn set_effect(&self, value: impl Into<Effect>) -> Result<()> {
+        let effect = value.into();
+        self.check_effect_supported(effect)?;
+        self.set_effect_unchecked(effect).await
+    }
+
+    /// Sets a light effect mode without checking device support
+    ///
+    /// Meant for experimenting with undocumented codes. Unsupported effects can
+    /// leave some strips frozen until they are power cycled.
+    #[instrument(skip(self, value))]
+    pub async fn set_effect_unchecked(&self, value: impl Into<Effect>) -> Result<()> {
+        let effect = value.into();
+        debug!("Setting effect mode to {} ({:#04x})", effect, effect.code());
 
         // Send the effect command with retries
-        self.send_command(&[0x7e, 0x00, 0x03, value, 0x03, 0x00, 0x00, 0x00, 0xef])
+        self.send_command(&protocol::effect_frame(effect.code()))
             .await?;
 
-        self.effect = Some(value);
+        self.state.write().effect = Some(effect);
+        self.emit(DeviceEvent::EffectSet(effect.code()));
 
         // Add a small delay to ensure the command has been processed
         time::sleep(Duration::from_millis(self.command_delay)).await;
@@ -760,13 +1800,80 @@ impl BleLedDevice {
         Ok(())
     }
 
+    /// Starts `value` if no effect runs, or goes back to the static light if one does
+    ///
+    /// Starting an effect keeps the last static color (or white color
+    /// temperature) in the cached state, and that is what comes back, so an
+    /// effect button flips between the two without losing the color. Returns
+    /// whether an effect is running now.
+    pub async fn toggle_effect(&self, value: impl Into<Effect>) -> Result<bool> {
+        let state = self.snapshot();
+        if state.effect.is_none() {
+            self.set_effect(value).await?;
+            return Ok(true);
+        }
+
+        match state.color_temp_kelvin {
+            Some(kelvin) => self.set_color_temp_kelvin(kelvin).await?,
+            None => {
+                let (red, green, blue) = state.rgb_color;
+                self.set_color(red, green, blue).await?;
+            }
+        }
+        Ok(false)
+    }
+
+    /// Steps through effect codes to find out what undocumented ones do
+    ///
+    /// Each code in `range` is sent unchecked and left running for `dwell`,
+    /// then `observer` is called with the code. It returns a description of
+    /// what the strip did, or `None` to skip the code.
+    ///
+    /// # Arguments
+    ///
+    /// * `range` - Effect codes to try
+    /// * `dwell` - How long each code runs before the observer is asked
+    /// * `observer` - Callback collecting a note for each code
+    #[instrument(skip(self, observer))]
+    pub async fn probe_effects(
+        &self,
+        range: RangeInclusive<u8>,
+        dwell: Duration,
+        mut observer: impl FnMut(u8) -> Option<String>,
+    ) -> Result<Vec<(u8, String)>> {
+        let mut notes = Vec::new();
+        for code in range {
+            debug!("Probing effect code {:#04x}", code);
+            self.set_effect_unchecked(Effect::from(code)).await?;
+            time::sleep(dwell).await;
+
+            if let Some(note) = observer(code) {
+                notes.push((code, note));
+            }
+        }
+
+        info!("Probed effects, collected {} notes", notes.len());
+        Ok(notes)
+    }
+
     /// Sets the speed of the current effect
     ///
+    /// Higher values always mean a faster animation. Firmwares that interpret
+    /// the speed byte the other way around (see
+    /// [`DeviceConfig::invert_effect_speed`]) get `100 - value` on the wire.
+    ///
     /// # Arguments
     ///
-    /// * `value` - Effect speed (0-100)
+    /// * `value` - Effect speed (0-100, 0 = slowest, 100 = fastest)
+    ///
+    /// Without an active effect the outcome depends on
+    /// [`BleLedDevice::speed_without_effect`].
     #[instrument(skip(self))]
-    pub async fn set_effect_speed(&mut self, value: u8) -> Result<()> {
+    pub async fn set_effect_speed(&self, value: u8) -> Result<()> {
+        if !self.speed_allowed(self.state.read().effect.is_some())? {
+            return Ok(());
+        }
+
         let limited_value = value.min(100);
         if value > 100 {
             warn!(
@@ -775,26 +1882,10 @@ impl BleLedDevice {
             );
         }
 
-        if self.effect.is_none() {
-            warn!("Setting effect speed without an active effect. This may not have any effect.");
-        }
-
-        debug!("Setting effect speed to {}", limited_value);
-        // Send the effect speed command with retries
-        self.send_command(&[
-            0x7e,
-            0x00,
-            0x02,
-            limited_value,
-            0x00,
-            0x00,
-            0x00,
-            0x00,
-            0xef,
-        ])
-        .await?;
-
-        self.effect_speed = Some(limited_value);
+        self.send_effect_speed(self.config.raw_effect_speed(limited_value))
+            .await?;
+        self.state.write().effect_speed = Some(limited_value);
+        self.emit(DeviceEvent::EffectSpeedSet(limited_value));
 
         // Add a small delay to ensure the command has been processed
         time::sleep(Duration::from_millis(self.command_delay)).await;
@@ -802,13 +1893,91 @@ impl BleLedDevice {
         Ok(())
     }
 
+    /// Sets the effect speed byte exactly as the firmware receives it
+    ///
+    /// Unlike [`set_effect_speed`](Self::set_effect_speed) no normalization is
+    /// applied, so the meaning of the value depends on the device firmware.
+    /// The cached `effect_speed` is updated with the speed the byte maps to,
+    /// see [`DeviceConfig::raw_effect_speed`].
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - Raw effect speed byte (0-100)
+    ///
+    /// Without an active effect the outcome depends on
+    /// [`BleLedDevice::speed_without_effect`].
+    #[instrument(skip(self))]
+    pub async fn set_effect_speed_raw(&self, value: u8) -> Result<()> {
+        if !self.speed_allowed(self.state.read().effect.is_some())? {
+            return Ok(());
+        }
+        self.send_effect_speed(value).await?;
+        let speed = self.config.raw_effect_speed(value);
+        self.state.write().effect_speed = Some(speed);
+        self.emit(DeviceEvent::EffectSpeedSet(speed));
+
+        // Add a small delay to ensure the command has been processed
+        time::sleep(Duration::from_millis(self.command_delay)).await;
+        Ok(())
+    }
+
+    /// Whether a speed command should be sent, given whether an effect is active
+    fn speed_allowed(&self, effect_active: bool) -> Result<bool> {
+        if effect_active {
+            return Ok(true);
+        }
+
+        match self.speed_without_effect {
+            SpeedWithoutEffect::Send => {
+                warn!(
+                    "Setting effect speed without an active effect. This may not have any effect."
+                );
+                Ok(true)
+            }
+            SpeedWithoutEffect::Skip => {
+                debug!("No active effect, skipping effect speed");
+                Ok(false)
+            }
+            SpeedWithoutEffect::Error => Err(Error::NoActiveEffect),
+        }
+    }
+
+    /// Sends the effect speed byte, without any checks or delay
+    async fn send_effect_speed(&self, value: u8) -> Result<()> {
+        let limited_value = value.min(100);
+
+        debug!("Setting raw effect speed to {}", limited_value);
+        // Send the effect speed command with retries
+        self.send_command(&protocol::effect_speed_frame(limited_value))
+            .await
+    }
+
+    /// Sets the color temperature in Kelvin for white light, or fails if it is out of range
+    ///
+    /// Unlike [`BleLedDevice::set_color_temp_kelvin`], a value outside the
+    /// device's `min_color_temp_k..=max_color_temp_k` is
+    /// [`Error::ValueOutOfRange`] and nothing is sent, so a caller computing
+    /// the wrong temperature finds out.
+    #[instrument(skip(self))]
+    pub async fn try_set_color_temp_kelvin(&self, value: u32) -> Result<()> {
+        let (min, max) = (self.config.min_color_temp_k, self.config.max_color_temp_k);
+        if !(min..=max).contains(&value) {
+            return Err(Error::ValueOutOfRange(value, min, max));
+        }
+        self.set_color_temp_kelvin(value).await
+    }
+
     /// Sets the color temperature in Kelvin for white light
     ///
+    /// A value outside the device's `min_color_temp_k..=max_color_temp_k` is
+    /// clamped to the nearest end with a warning; use
+    /// [`BleLedDevice::try_set_color_temp_kelvin`] to get an error instead.
+    ///
     /// # Arguments
     ///
     /// * `value` - Color temperature in Kelvin (typically 2700-6500)
     #[instrument(skip(self))]
-    pub async fn set_color_temp_kelvin(&mut self, value: u32) -> Result<()> {
+    pub async fn set_color_temp_kelvin(&self, value: u32) -> Result<()> {
         // Ensure value is within range
         let temp = value
             .max(self.config.min_color_temp_k)
@@ -823,21 +1992,16 @@ impl BleLedDevice {
 
         debug!("Setting color temperature to {}K", temp);
 
-        // Calculate color temp percent (0-100) from kelvin value
-        let color_temp_percent = ((temp - self.config.min_color_temp_k) * 100
-            / (self.config.max_color_temp_k - self.config.min_color_temp_k))
-            as u8;
-
-        // Set warm/cold values
-        let warm = color_temp_percent;
-        let cold = 100 - color_temp_percent;
+        // Warmer light is more of the warm channel
+        let (warm, cold) = self.config.color_temp_levels(temp);
 
         // First, ensure we're in white mode (not an effect)
-        if self.effect.is_some() {
+        if self.state.read().effect.is_some() {
             debug!("Disabling active effect before setting color temperature");
             // Send a pre-command to disable effects mode
-            self.send_command(&[0x7e, 0x00, 0x05, 0x01, 0x00, 0x00, 0x00, 0x00, 0xef])
-                .await?;
+            self.send_command(&EXIT_EFFECT_FRAME).await?;
+            // The effect is off now, even if the next command fails
+            self.state.write().effect = None;
             // Add a small delay after disabling effect
             time::sleep(Duration::from_millis(self.command_delay)).await;
         }
@@ -848,11 +2012,15 @@ impl BleLedDevice {
             warm,
             cold
         );
-        self.send_command(&[0x7e, 0x00, 0x05, 0x02, warm, cold, 0x00, 0x00, 0xef])
+        self.send_command(&protocol::color_temp_frame(warm, cold))
             .await?;
 
-        self.color_temp_kelvin = Some(temp);
-        self.effect = None; // Setting color temp disables any active effect
+        {
+            let mut state = self.state.write();
+            state.color_temp_kelvin = Some(temp);
+            state.effect = None; // Setting color temp disables any active effect
+        }
+        self.emit(DeviceEvent::ColorTempSet(temp));
 
         // Add a small delay to ensure the command has been processed
         time::sleep(Duration::from_millis(self.command_delay)).await;
@@ -864,28 +2032,31 @@ impl BleLedDevice {
     ///
     /// # Arguments
     ///
-    /// * `days` - Bitmask of days (use the WEEK_DAYS constants)
+    /// * `days` - Days the schedule applies to, e.g. `Days::MONDAY | Days::THURSDAY`
     /// * `hours` - Hour to turn on (0-23)
     /// * `minutes` - Minute to turn on (0-59)
     /// * `enabled` - Whether to enable or disable this schedule
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ValueOutOfRange` for an hour above 23 or a minute above 59,
+    /// and `Error::EmptyScheduleDays` when enabling a schedule without any day.
     #[instrument(skip(self))]
     pub async fn set_schedule_on(
         &self,
-        days: u8,
+        days: Days,
         hours: u8,
         minutes: u8,
         enabled: bool,
     ) -> Result<()> {
-        let hours = hours.min(23);
-        let minutes = minutes.min(59);
-        let value = if enabled { days + 0x80 } else { days };
+        let value = schedule_value(days, hours, minutes, enabled)?;
 
         debug!(
-            "Setting schedule to turn on at {}:{:02} on days: {:#04x}, enabled: {}",
+            "Setting schedule to turn on at {}:{:02} on days: {}, enabled: {}",
             hours, minutes, days, enabled
         );
 
-        self.send_command(&[0x7e, 0x00, 0x82, hours, minutes, 0x00, 0x00, value, 0xef])
+        self.send_command(&protocol::schedule_frame(false, hours, minutes, value))
             .await?;
 
         // Add a small delay to ensure the command has been processed
@@ -898,28 +2069,31 @@ impl BleLedDevice {
     ///
     /// # Arguments
     ///
-    /// * `days` - Bitmask of days (use the WEEK_DAYS constants)
+    /// * `days` - Days the schedule applies to, e.g. `Days::MONDAY | Days::THURSDAY`
     /// * `hours` - Hour to turn off (0-23)
     /// * `minutes` - Minute to turn off (0-59)
     /// * `enabled` - Whether to enable or disable this schedule
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ValueOutOfRange` for an hour above 23 or a minute above 59,
+    /// and `Error::EmptyScheduleDays` when enabling a schedule without any day.
     #[instrument(skip(self))]
     pub async fn set_schedule_off(
         &self,
-        days: u8,
+        days: Days,
         hours: u8,
         minutes: u8,
         enabled: bool,
     ) -> Result<()> {
-        let hours = hours.min(23);
-        let minutes = minutes.min(59);
-        let value = if enabled { days + 0x80 } else { days };
+        let value = schedule_value(days, hours, minutes, enabled)?;
 
         debug!(
-            "Setting schedule to turn off at {}:{:02} on days: {:#04x}, enabled: {}",
+            "Setting schedule to turn off at {}:{:02} on days: {}, enabled: {}",
             hours, minutes, days, enabled
         );
 
-        self.send_command(&[0x7e, 0x00, 0x82, hours, minutes, 0x00, 0x01, value, 0xef])
+        self.send_command(&protocol::schedule_frame(true, hours, minutes, value))
             .await?;
 
         // Add a small delay to ensure the command has been processed
@@ -928,6 +2102,80 @@ impl BleLedDevice {
         Ok(())
     }
 
+    /// Programs the on and off schedules together, e.g. for a daily routine
+    ///
+    /// `on` and `off` are `(hours, minutes)`. Both are validated before
+    /// anything is sent, so a bad value leaves the device's schedules alone.
+    /// Returns once the device has acknowledged both (unless in fast mode).
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ValueOutOfRange` for an hour above 23 or a minute above 59,
+    /// `Error::EmptyScheduleDays` without any day, and `Error::SameScheduleTimes`
+    /// when `on` and `off` are the same time.
+    #[instrument(skip(self))]
+    pub async fn set_daily_schedule(&self, days: Days, on: (u8, u8), off: (u8, u8)) -> Result<()> {
+        schedule_value(days, on.0, on.1, true)?;
+        schedule_value(days, off.0, off.1, true)?;
+        if on == off {
+            return Err(Error::SameScheduleTimes {
+                hours: on.0,
+                minutes: on.1,
+            });
+        }
+
+        self.set_schedule_on(days, on.0, on.1, true).await?;
+        self.set_schedule_off(days, off.0, off.1, true).await
+    }
+
+    /// Disables the device's on schedule
+    ///
+    /// Equivalent to `set_schedule_on(Days::NONE, 0, 0, false)`.
+    #[instrument(skip(self))]
+    pub async fn clear_schedule_on(&self) -> Result<()> {
+        self.set_schedule_on(Days::NONE, 0, 0, false).await
+    }
+
+    /// Disables the device's off schedule
+    ///
+    /// Equivalent to `set_schedule_off(Days::NONE, 0, 0, false)`.
+    #[instrument(skip(self))]
+    pub async fn clear_schedule_off(&self) -> Result<()> {
+        self.set_schedule_off(Days::NONE, 0, 0, false).await
+    }
+
+    /// Sets a schedule to turn on the device from a raw day bitmask
+    ///
+    /// Fails with [`Error::ValueOutOfRange`] if `days` has bits above 0x7f
+    /// set; the top bit is the enabled flag and is set from `enabled`.
+    #[deprecated(note = "use `set_schedule_on` with `Days` instead")]
+    pub async fn set_schedule_on_bits(
+        &self,
+        days: u8,
+        hours: u8,
+        minutes: u8,
+        enabled: bool,
+    ) -> Result<()> {
+        self.set_schedule_on(days_from_bits(days)?, hours, minutes, enabled)
+            .await
+    }
+
+    /// Sets a schedule to turn off the device from a raw day bitmask
+    ///
+    /// Fails with [`Error::ValueOutOfRange`] if `days` has bits above 0x7f
+    /// set; the top bit is the enabled flag and is set from `enabled`.
+    #[deprecated(note = "use `set_schedule_off` with `Days` instead")]
+    pub async fn set_schedule_off_bits(
+        &self,
+        days: u8,
+        hours: u8,
+        minutes: u8,
+        enabled: bool,
+    ) -> Result<()> {
+        self.set_schedule_off(days_from_bits(days)?, hours, minutes, enabled)
+            .await
+    }
+
     /// Sends a generic command to the device with retries
     ///
     /// # Arguments
@@ -951,38 +2199,172 @@ impl BleLedDevice {
             id, sub_id, arg1, arg2, arg3
         );
 
-        self.send_command(&[0x7e, 0x00, id, sub_id, arg1, arg2, arg3, 0x00, 0xef])
+        self.send_command(&protocol::generic_frame(id, sub_id, arg1, arg2, arg3))
             .await?;
         debug!("Generic command sent successfully");
         Ok(())
     }
 
+    /// Fails with [`Error::UnsupportedEffect`] if the firmware lacks the effect
+    fn check_effect_supported(&self, effect: Effect) -> Result<()> {
+        if self.config.supports_effect(effect) {
+            Ok(())
+        } else {
+            warn!(
+                "Effect {} is not supported on {}",
+                effect,
+                self.get_device_type_name()
+            );
+            Err(Error::UnsupportedEffect {
+                effect,
+                device_type: self.device_type,
+            })
+        }
+    }
+
+    /// Starts a batch of commands that are sent back-to-back on commit
+    pub fn batch(&self) -> CommandBatch<'_> {
+        CommandBatch {
+            device: self,
+            steps: Vec::new(),
+        }
+    }
+
+    /// The read characteristic, which not every device exposes
+    fn read_characteristic(&self) -> Result<&Characteristic> {
+        self.read_characteristic
+            .as_ref()
+            .ok_or_else(|| Error::CharacteristicNotFound(self.config.read_uuid.to_string()))
+    }
+
+    /// Reads and validates a frame from the read characteristic
+    #[instrument(skip(self))]
+    pub async fn read_frame(&self) -> Result<Frame> {
+        let read_characteristic = self.read_characteristic()?;
+
+        let bytes = self.peripheral.read(read_characteristic).await?;
+        trace!("Read {} bytes: {:02x?}", bytes.len(), bytes);
+
+        let frame = parse_frame(&bytes)?;
+        debug!("Received frame with command {:#04x}", frame.command);
+        Ok(frame)
+    }
+
+    /// Subscribes to the read characteristic and streams what the device pushes
+    ///
+    /// Each item is the raw value of one notification, unvalidated, e.g. a
+    /// frame sent after the IR remote or the phone app changed the strip;
+    /// [`protocol::frame_event`] decodes the known ones. Call
+    /// [`BleLedDevice::stop_notifications`] when done.
+    #[instrument(skip(self))]
+    pub async fn notifications(&self) -> Result<impl Stream<Item = Vec<u8>> + Send + 'static> {
+        let read_characteristic = self.read_characteristic()?;
+        let notifications = self.peripheral.notifications().await?;
+        self.peripheral.subscribe(read_characteristic).await?;
+        debug!("Subscribed to {}", read_characteristic.uuid);
+
+        let uuid = read_characteristic.uuid;
+        Ok(notifications.filter_map(move |notification| {
+            futures::future::ready((notification.uuid == uuid).then_some(notification.value))
+        }))
+    }
+
+    /// Unsubscribes from the read characteristic after [`BleLedDevice::notifications`]
+    #[instrument(skip(self))]
+    pub async fn stop_notifications(&self) -> Result<()> {
+        let read_characteristic = self.read_characteristic()?;
+        self.peripheral.unsubscribe(read_characteristic).await?;
+        debug!("Unsubscribed from {}", read_characteristic.uuid);
+        Ok(())
+    }
+
+    /// Reads the firmware version reported by the device
+    ///
+    /// The LED protocol has no version query, so this reads the Firmware
+    /// Revision String of the standard Device Information service, or the
+    /// Software Revision String when only that one is present.
+    ///
+    /// Returns [`Error::CharacteristicNotFound`] for devices that expose
+    /// neither, which includes most cheap controllers.
+    #[instrument(skip(self))]
+    pub async fn read_firmware_version(&self) -> Result<String> {
+        let characteristics = self.peripheral.characteristics();
+        let characteristic = REVISION_UUIDS
+            .iter()
+            .find_map(|uuid| characteristics.iter().find(|c| c.uuid == *uuid))
+            .ok_or_else(|| Error::CharacteristicNotFound(REVISION_UUIDS[0].to_string()))?;
+
+        let bytes = self.peripheral.read(characteristic).await?;
+        let version = String::from_utf8_lossy(&bytes)
+            .trim_end_matches('\0')
+            .trim()
+            .to_string();
+        debug!("Firmware version: {}", version);
+        Ok(version)
+    }
+
+    /// Sends `frame` as-is, without checking its length or framing bytes
+    ///
+    /// For exploring the protocol. The cached state is not updated, and a
+    /// malformed or unknown frame can leave the device in a state that only
+    /// power cycling fixes. Goes through the same queue, pacing and retries
+    /// as every other command.
+    #[instrument(skip(self, frame), fields(frame = %protocol::format_frame(frame)))]
+    pub async fn send_raw(&self, frame: &[u8]) -> Result<()> {
+        self.send_command(frame).await?;
+        debug!("Raw frame sent successfully");
+        Ok(())
+    }
+
+    /// Whether writes wait for the device to acknowledge them
+    ///
+    /// True when the characteristic supports acknowledged writes and
+    /// [`fast_mode`](BleLedDevice::fast_mode) is off.
+    pub fn acknowledged_writes(&self) -> bool {
+        self.write_type() == WriteType::WithResponse
+    }
+
+    /// Write type for commands: with response when supported, unless fast
+    /// mode trades the acknowledgement for latency
+    fn write_type(&self) -> WriteType {
+        if !self.fast_mode
+            && self
+                .write_characteristic
+                .properties
+                .contains(btleplug::api::CharPropFlags::WRITE)
+        {
+            WriteType::WithResponse
+        } else {
+            WriteType::WithoutResponse
+        }
+    }
+
     /// Helper function to ensure commands are sent reliably with rate limiting
+    ///
+    /// Callers update the cached state only after this returns `Ok`, and
+    /// without awaiting in between, so dropping a command method's future
+    /// (e.g. a losing `tokio::select!` branch) never records a write that
+    /// failed. Each frame goes out as a single GATT write, so it is never
+    /// half-sent; a dropped call may still have reached the device, in which
+    /// case the cached state lags behind until the next command.
     #[instrument(skip(self, command), fields(cmd_length = command.len()))]
     async fn send_command(&self, command: &[u8]) -> Result<()> {
-        // Create a clone of the command for the async block
-        let cmd = command.to_vec();
-        let peripheral = self.peripheral.clone();
-        let write_characteristic = self.write_characteristic.clone();
+        // The queued future is awaited in place, so it can borrow the
+        // peripheral, characteristic and command instead of cloning them
+        let peripheral = &self.peripheral;
+        let write_characteristic = &self.write_characteristic;
+        let write_type = self.write_type();
+        let metrics = &self.metrics;
 
         // Use the command queue to handle rate limiting
-        self.command_queue
+        let result = self
+            .command_queue
             .execute(async move {
                 // TODO: Fix this as delay is not working
                 // BLE can be unreliable, so we implement retries
                 let max_retries = 3;
                 let mut attempt = 0;
 
-                // Determine write type - prefer WriteWithResponse when supported
-                let write_type = if write_characteristic
-                    .properties
-                    .contains(btleplug::api::CharPropFlags::WRITE)
-                {
-                    WriteType::WithResponse
-                } else {
-                    WriteType::WithoutResponse
-                };
-
                 while attempt < max_retries {
                     trace!(
                         "Sending BLE command (attempt {}/{})",
@@ -991,11 +2373,12 @@ impl BleLedDevice {
                     );
 
                     match peripheral
-                        .write(&write_characteristic, &cmd, write_type)
+                        .write(write_characteristic, command, write_type)
                         .await
                     {
                         Ok(_) => {
                             trace!("Command sent successfully");
+                            metrics.record_sent();
                             return Ok(());
                         }
                         Err(e) => {
@@ -1006,12 +2389,21 @@ impl BleLedDevice {
                             );
 
                             if attempt < max_retries {
+                                metrics.record_retry();
+                                // A dropped link won't come back by waiting
+                                if !peripheral.is_connected().await.unwrap_or(true) {
+                                    match reconnect_peripheral(peripheral).await {
+                                        Ok(_) => continue,
+                                        Err(e) => warn!("Reconnecting failed: {}", e),
+                                    }
+                                }
                                 // Wait a bit before retrying
                                 trace!("Waiting before retry...");
                                 tokio::time::sleep(std::time::Duration::from_millis(300)).await;
                             } else {
                                 // Log the last error
                                 error!("Command failed permanently: {}", e);
+                                metrics.record_failed();
                                 return Err(Error::BleError(e.to_string()));
                             }
                         }
@@ -1022,6 +2414,412 @@ impl BleLedDevice {
                 error!("Command failed after {} attempts", max_retries);
                 Err(Error::CommandTimeout(max_retries))
             })
-            .await
+            .await;
+
+        if let Err(e) = &result {
+            self.emit(DeviceEvent::Error(e.to_string()));
+        }
+        result
+    }
+}
+
+/// A single queued operation in a [`CommandBatch`]
+#[derive(Debug, Clone, Copy)]
+enum BatchStep {
+    PowerOn,
+    PowerOff,
+    Color(u8, u8, u8),
+    Brightness(u8),
+    Effect(Effect),
+    EffectSpeed(u8),
+}
+
+impl BatchStep {
+    /// Name of the step used in error reports
+    fn name(&self) -> &'static str {
+        match self {
+            BatchStep::PowerOn => "power_on",
+            BatchStep::PowerOff => "power_off",
+            BatchStep::Color(..) => "color",
+            BatchStep::Brightness(_) => "brightness",
+            BatchStep::Effect(_) => "effect",
+            BatchStep::EffectSpeed(_) => "effect_speed",
+        }
+    }
+
+    /// Sets the fields of `state` this step changes, leaving the others alone
+    fn apply(self, state: &mut DeviceState) {
+        match self {
+            BatchStep::PowerOn => state.is_on = true,
+            BatchStep::PowerOff => state.is_on = false,
+            BatchStep::Color(r, g, b) => {
+                state.rgb_color = (r, g, b);
+                state.effect = None;
+                state.color_temp_kelvin = None;
+            }
+            BatchStep::Brightness(value) => state.brightness = value,
+            BatchStep::Effect(effect) => state.effect = Some(effect),
+            BatchStep::EffectSpeed(value) => state.effect_speed = Some(value),
+        }
+    }
+}
+
+/// Builder that queues several setters and sends them back-to-back
+///
+/// Created with [`BleLedDevice::batch`]. Each step updates the cached device
+/// state once its command has been sent, and only in the fields it sets, so
+/// changes made meanwhile through other setters are kept.
+///
+/// ```rust,no_run
+/// # use elk_led_controller::*;
+/// # async fn example(device: &mut BleLedDevice) -> Result<()> {
+/// device
+///     .batch()
+///     .color(255, 120, 0)
+///     .brightness(50)
+///     .commit()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct CommandBatch<'a> {
+    /// Device the batch will be sent to
+    device: &'a BleLedDevice,
+    /// Queued operations, in order
+    steps: Vec<BatchStep>,
+}
+
+impl<'a> CommandBatch<'a> {
+    /// Queue turning the LED strip on
+    pub fn power_on(mut self) -> Self {
+        self.steps.push(BatchStep::PowerOn);
+        self
+    }
+
+    /// Queue turning the LED strip off
+    pub fn power_off(mut self) -> Self {
+        self.steps.push(BatchStep::PowerOff);
+        self
+    }
+
+    /// Queue setting the RGB color
+    pub fn color(mut self, red_value: u8, green_value: u8, blue_value: u8) -> Self {
+        self.steps
+            .push(BatchStep::Color(red_value, green_value, blue_value));
+        self
+    }
+
+    /// Queue setting the brightness level (0-100)
+    pub fn brightness(mut self, value: u8) -> Self {
+        self.steps.push(BatchStep::Brightness(value.min(100)));
+        self
+    }
+
+    /// Queue setting a light effect mode
+    pub fn effect(mut self, value: impl Into<Effect>) -> Self {
+        self.steps.push(BatchStep::Effect(value.into()));
+        self
+    }
+
+    /// Queue setting the effect speed (0-100, higher is faster)
+    pub fn effect_speed(mut self, value: u8) -> Self {
+        self.steps.push(BatchStep::EffectSpeed(value.min(100)));
+        self
+    }
+
+    /// Sends all queued commands back-to-back and updates the cached state
+    ///
+    /// If a command fails, [`Error::BatchStepFailed`] reports which step it
+    /// was; the cached state keeps the steps sent before it.
+    #[instrument(skip(self), fields(steps = self.steps.len()))]
+    pub async fn commit(self) -> Result<()> {
+        let device = self.device;
+
+        // Reject unsupported effects and speeds without an effect before anything is sent
+        let mut effect_active = device.state.read().effect.is_some();
+        for step in &self.steps {
+            match step {
+                BatchStep::Effect(effect) => {
+                    device.check_effect_supported(*effect)?;
+                    effect_active = true;
+                }
+                BatchStep::Color(..) => effect_active = false,
+                BatchStep::EffectSpeed(_)
+                    if !effect_active
+                        && device.speed_without_effect == SpeedWithoutEffect::Error =>
+                {
+                    return Err(Error::NoActiveEffect);
+                }
+                _ => {}
+            }
+        }
+
+        for (index, step) in self.steps.iter().enumerate() {
+            // Brightness is stored as sent, within the device's limits
+            let step = match *step {
+                BatchStep::Brightness(value) => BatchStep::Brightness(
+                    value
+                        .min(device.max_brightness)
+                        .max(device.min_brightness.min(100)),
+                ),
+                step => step,
+            };
+            let mut frames: Vec<[u8; 9]> = Vec::with_capacity(2);
+            let event = match step {
+                BatchStep::PowerOn => {
+                    frames.push(device.config.turn_on_cmd);
+                    DeviceEvent::PoweredOn
+                }
+                BatchStep::PowerOff => {
+                    frames.push(device.config.turn_off_cmd);
+                    DeviceEvent::PoweredOff
+                }
+                BatchStep::Color(r, g, b) => {
+                    if device.state.read().effect.is_some() {
+                        // Leave effect mode before setting a static color
+                        frames.push(EXIT_EFFECT_FRAME);
+                    }
+                    frames.push(protocol::color_frame(r, g, b));
+                    DeviceEvent::ColorSet((r, g, b))
+                }
+                BatchStep::Brightness(value) => {
+                    let device_value = device.brightness_curve.apply(value);
+                    frames.push(protocol::brightness_frame(device_value));
+                    DeviceEvent::BrightnessSet(value)
+                }
+                BatchStep::Effect(value) => {
+                    frames.push(protocol::effect_frame(value.code()));
+                    DeviceEvent::EffectSet(value.code())
+                }
+                BatchStep::EffectSpeed(_)
+                    if device.state.read().effect.is_none()
+                        && device.speed_without_effect == SpeedWithoutEffect::Skip =>
+                {
+                    continue
+                }
+                BatchStep::EffectSpeed(value) => {
+                    frames.push(protocol::effect_speed_frame(
+                        device.config.raw_effect_speed(value),
+                    ));
+                    DeviceEvent::EffectSpeedSet(value)
+                }
+            };
+
+            for frame in &frames {
+                if let Err(e) = device.send_command(frame).await {
+                    error!("Batch step {} ({}) failed: {}", index, step.name(), e);
+                    return Err(Error::BatchStepFailed {
+                        step: index,
+                        name: step.name(),
+                        source: Box::new(e),
+                    });
+                }
+            }
+
+            step.apply(&mut device.state.write());
+            device.emit(event);
+        }
+
+        info!("Batch of {} commands applied", self.steps.len());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_updates_keep_each_others_fields() {
+        let state = Arc::new(RwLock::new(DeviceState::default()));
+        let mut tasks = Vec::new();
+
+        // Each writer owns one field, as concurrent setters would
+        for writer in 0..3 {
+            let state = state.clone();
+            tasks.push(tokio::spawn(async move {
+                for n in 0..=100 {
+                    let step = match writer {
+                        0 => BatchStep::Color(n, n, n),
+                        1 => BatchStep::Brightness(n),
+                        _ => BatchStep::EffectSpeed(n),
+                    };
+                    step.apply(&mut state.write());
+                    tokio::task::yield_now().await;
+                }
+            }));
+        }
+
+        // A snapshot never shows a color halfway through an update
+        for _ in 0..3 {
+            let state = state.clone();
+            tasks.push(tokio::spawn(async move {
+                for _ in 0..1000 {
+                    let (r, g, b) = state.read().rgb_color;
+                    assert!(r == g && g == b, "torn color {:?}", (r, g, b));
+                    tokio::task::yield_now().await;
+                }
+            }));
+        }
+
+        for task in tasks {
+            task.await.unwrap();
+        }
+        let state = *state.read();
+        assert_eq!(state.rgb_color, (100, 100, 100));
+        assert_eq!(state.brightness, 100);
+        assert_eq!(state.effect_speed, Some(100));
+    }
+
+    #[test]
+    fn time_frame_for_a_known_datetime() {
+        // Sunday 22 June 2025, 21:07:05
+        let sunday = chrono::NaiveDate::from_ymd_opt(2025, 6, 22)
+            .unwrap()
+            .and_hms_opt(21, 7, 5)
+            .unwrap();
+        assert_eq!(
+            time_frame(sunday),
+            [0x7e, 0x00, 0x83, 21, 7, 5, 7, 0x00, 0xef]
+        );
+
+        // The wall-clock time of the zone is sent: 23:30 UTC on that Sunday
+        // is already 01:30 on Monday in UTC+2
+        let utc = sunday.date().and_hms_opt(23, 30, 0).unwrap().and_utc();
+        let east = chrono::FixedOffset::east_opt(2 * 3600).unwrap();
+        assert_eq!(
+            time_frame(utc.with_timezone(&east).naive_local()),
+            [0x7e, 0x00, 0x83, 1, 30, 0, 1, 0x00, 0xef]
+        );
+    }
+
+    #[test]
+    fn state_json_round_trips() {
+        let state = DeviceState {
+            is_on: true,
+            rgb_color: (255, 136, 0),
+            brightness: 35,
+            effect: Some(Effect::JumpRgb),
+            effect_speed: Some(60),
+            color_temp_kelvin: None,
+        };
+        assert_eq!(DeviceState::from_json(&state.to_json()).unwrap(), state);
+
+        // Whitespace, key order and unknown keys don't matter
+        let json = r#" { "brightness": 10 , "extra": [1, {"a": null}], "is_on": false } "#;
+        let parsed = DeviceState::from_json(json).unwrap();
+        assert_eq!(parsed.brightness, 10);
+        assert_eq!(parsed.rgb_color, DeviceState::default().rgb_color);
+    }
+
+    #[test]
+    fn state_json_rejects_malformed_values() {
+        for json in [
+            "",
+            "[]",
+            r#"{"is_on":1}"#,
+            r#"{"rgb_color":[255,0]}"#,
+            r#"{"rgb_color":[256,0,0]}"#,
+            r#"{"brightness":1.5}"#,
+            r#"{"effect_speed":"fast"}"#,
+            r#"{"effect":"disco"}"#,
+            r#"{"brightness":10"#,
+        ] {
+            assert!(DeviceState::from_json(json).is_err(), "{} parsed", json);
+        }
+    }
+
+    #[test]
+    fn raw_effect_speed_maps_both_ways() {
+        let elk = DeviceConfig::for_device_type(DeviceType::ElkBle);
+        assert_eq!(elk.raw_effect_speed(0), 100);
+        assert_eq!(elk.raw_effect_speed(30), 70);
+        assert_eq!(elk.raw_effect_speed(elk.raw_effect_speed(30)), 30);
+        assert_eq!(elk.raw_effect_speed(150), 0);
+
+        let melk = DeviceConfig::for_device_type(DeviceType::Melk);
+        assert_eq!(melk.raw_effect_speed(30), 30);
+        assert_eq!(melk.raw_effect_speed(150), 100);
+    }
+
+    #[test]
+    fn color_temp_endpoints_are_fully_warm_and_fully_cold() {
+        let config = DeviceConfig::for_device_type(DeviceType::ElkBle);
+        assert_eq!(config.color_temp_levels(2700), (100, 0));
+        assert_eq!(config.color_temp_levels(6500), (0, 100));
+        assert_eq!(config.color_temp_levels(4600), (50, 50));
+        // Out of range values are clamped
+        assert_eq!(config.color_temp_levels(1000), (100, 0));
+        assert_eq!(config.color_temp_levels(10000), (0, 100));
+    }
+
+    #[test]
+    fn schedule_times_accept_23_59() {
+        assert_eq!(schedule_value(Days::MONDAY, 23, 59, true).unwrap(), 0x81);
+        assert_eq!(schedule_value(Days::MONDAY, 0, 0, true).unwrap(), 0x81);
+    }
+
+    #[test]
+    fn schedule_times_reject_24_and_60() {
+        assert!(matches!(
+            schedule_value(Days::MONDAY, 24, 0, true),
+            Err(Error::ValueOutOfRange(24, 0, 23))
+        ));
+        assert!(matches!(
+            schedule_value(Days::MONDAY, 0, 60, true),
+            Err(Error::ValueOutOfRange(60, 0, 59))
+        ));
+        // Not clamped even for a disabled schedule
+        assert!(schedule_value(Days::MONDAY, 27, 75, false).is_err());
+    }
+
+    #[test]
+    fn day_bits_stop_at_0x7f() {
+        assert_eq!(days_from_bits(0x7f).unwrap(), Days::ALL);
+        assert_eq!(days_from_bits(0x00).unwrap(), Days::NONE);
+        for bits in [0x80, 0x81, 0xff] {
+            assert!(matches!(
+                days_from_bits(bits),
+                Err(Error::ValueOutOfRange(value, 0, 0x7f)) if value == bits as u32
+            ));
+        }
+    }
+
+    #[test]
+    fn enabled_flag_does_not_carry_into_the_days() {
+        assert_eq!(schedule_value(Days::ALL, 8, 0, true).unwrap(), 0xff);
+        assert_eq!(schedule_value(Days::ALL, 8, 0, false).unwrap(), 0x7f);
+        assert_eq!(schedule_value(Days::SUNDAY, 8, 0, true).unwrap(), 0xc0);
+    }
+
+    #[test]
+    fn enabled_schedules_need_a_day() {
+        assert!(matches!(
+            schedule_value(Days::NONE, 8, 30, true),
+            Err(Error::EmptyScheduleDays)
+        ));
+        assert_eq!(schedule_value(Days::NONE, 8, 30, false).unwrap(), 0x00);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trips_device_types() {
+        let types = [
+            (DeviceType::ElkBle, "elk_ble"),
+            (DeviceType::LedBle, "led_ble"),
+            (DeviceType::Melk, "melk"),
+            (DeviceType::ElkBulb, "elk_bulb"),
+            (DeviceType::ElkLampl, "elk_lampl"),
+            (DeviceType::Unknown, "unknown"),
+        ];
+        for (device_type, name) in types {
+            assert_eq!(device_type.name(), name);
+            let json = serde_json::to_string(&device_type).unwrap();
+            assert_eq!(json, format!("\"{}\"", name));
+            assert_eq!(
+                serde_json::from_str::<DeviceType>(&json).unwrap(),
+                device_type
+            );
+        }
     }
 }
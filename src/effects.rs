@@ -2,10 +2,17 @@
  # Effect modes for LED strips
 
  This module defines various effect modes available for the LED strips.
- It includes constants for different effects like jump, crossfade, and blink.
+ It includes the [`Effect`] enum for effects like jump, crossfade, and blink.
 */
 
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use crate::{DeviceType, Error, Result};
+
 /// Represents available effect modes for LED strips
+#[deprecated(note = "use the `Effect` enum instead")]
 #[derive(Debug, Clone, Copy)]
 pub struct Effects {
     /// Red, green, blue jump effect
@@ -55,6 +62,8 @@ pub struct Effects {
 }
 
 /// Predefined effects with their command values
+#[deprecated(note = "use the `Effect` enum instead")]
+#[allow(deprecated)]
 pub const EFFECTS: Effects = Effects {
     jump_red_green_blue: 0x87,
     jump_red_green_blue_yellow_cyan_magenta_white: 0x88,
@@ -79,3 +88,491 @@ pub const EFFECTS: Effects = Effects {
     blink_white: 0x9c,
     blink_red_green_blue_yellow_cyan_magenta_white: 0x95,
 };
+
+/// A light effect mode supported by the LED strip firmware
+///
+/// Names used by `Display` and `FromStr` are snake_case (`crossfade_red`);
+/// `FromStr` also accepts dashes instead of underscores, the short aliases
+/// `rainbow`, `jump` and `blink`, and hex codes such as `0x87`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Effect {
+    /// Red, green, blue jump effect
+    JumpRgb,
+    /// All colors jump effect
+    JumpAll,
+    /// Red crossfade effect
+    CrossfadeRed,
+    /// Green crossfade effect
+    CrossfadeGreen,
+    /// Blue crossfade effect
+    CrossfadeBlue,
+    /// Yellow crossfade effect
+    CrossfadeYellow,
+    /// Cyan crossfade effect
+    CrossfadeCyan,
+    /// Magenta crossfade effect
+    CrossfadeMagenta,
+    /// White crossfade effect
+    CrossfadeWhite,
+    /// Red and green crossfade effect
+    CrossfadeRedGreen,
+    /// Red and blue crossfade effect
+    CrossfadeRedBlue,
+    /// Green and blue crossfade effect
+    CrossfadeGreenBlue,
+    /// Red, green, blue crossfade effect
+    CrossfadeRgb,
+    /// All colors crossfade effect (rainbow)
+    CrossfadeAll,
+    /// Red blink effect
+    BlinkRed,
+    /// Green blink effect
+    BlinkGreen,
+    /// Blue blink effect
+    BlinkBlue,
+    /// Yellow blink effect
+    BlinkYellow,
+    /// Cyan blink effect
+    BlinkCyan,
+    /// Magenta blink effect
+    BlinkMagenta,
+    /// White blink effect
+    BlinkWhite,
+    /// All colors blink effect
+    BlinkAll,
+    /// Any other effect code, sent as-is
+    Raw(u8),
+}
+
+impl Effect {
+    /// All known effects, in command code order
+    pub const ALL: [Effect; 22] = [
+        Effect::JumpRgb,
+        Effect::JumpAll,
+        Effect::CrossfadeRgb,
+        Effect::CrossfadeAll,
+        Effect::CrossfadeRed,
+        Effect::CrossfadeGreen,
+        Effect::CrossfadeBlue,
+        Effect::CrossfadeYellow,
+        Effect::CrossfadeCyan,
+        Effect::CrossfadeMagenta,
+        Effect::CrossfadeWhite,
+        Effect::CrossfadeRedGreen,
+        Effect::CrossfadeRedBlue,
+        Effect::CrossfadeGreenBlue,
+        Effect::BlinkAll,
+        Effect::BlinkRed,
+        Effect::BlinkGreen,
+        Effect::BlinkBlue,
+        Effect::BlinkYellow,
+        Effect::BlinkCyan,
+        Effect::BlinkMagenta,
+        Effect::BlinkWhite,
+    ];
+
+    /// Get the command code sent to the device
+    pub const fn code(self) -> u8 {
+        match self {
+            Effect::JumpRgb => 0x87,
+            Effect::JumpAll => 0x88,
+            Effect::CrossfadeRgb => 0x89,
+            Effect::CrossfadeAll => 0x8a,
+            Effect::CrossfadeRed => 0x8b,
+            Effect::CrossfadeGreen => 0x8c,
+            Effect::CrossfadeBlue => 0x8d,
+            Effect::CrossfadeYellow => 0x8e,
+            Effect::CrossfadeCyan => 0x8f,
+            Effect::CrossfadeMagenta => 0x90,
+            Effect::CrossfadeWhite => 0x91,
+            Effect::CrossfadeRedGreen => 0x92,
+            Effect::CrossfadeRedBlue => 0x93,
+            Effect::CrossfadeGreenBlue => 0x94,
+            Effect::BlinkAll => 0x95,
+            Effect::BlinkRed => 0x96,
+            Effect::BlinkGreen => 0x97,
+            Effect::BlinkBlue => 0x98,
+            Effect::BlinkYellow => 0x99,
+            Effect::BlinkCyan => 0x9a,
+            Effect::BlinkMagenta => 0x9b,
+            Effect::BlinkWhite => 0x9c,
+            Effect::Raw(code) => code,
+        }
+    }
+
+    /// All crossfade effects, the smooth ones suited to ambient lighting
+    pub fn all_crossfades() -> Vec<Effect> {
+        Self::ALL
+            .into_iter()
+            .filter(|effect| (0x89..=0x94).contains(&effect.code()))
+            .collect()
+    }
+
+    /// Get the known effect for a command code, if any
+    pub fn from_code(code: u8) -> Option<Effect> {
+        Self::ALL.into_iter().find(|effect| effect.code() == code)
+    }
+
+    /// Get the snake_case name of a known effect
+    pub const fn name(self) -> Option<&'static str> {
+        let name = match self {
+            Effect::JumpRgb => "jump_rgb",
+            Effect::JumpAll => "jump_all",
+            Effect::CrossfadeRgb => "crossfade_rgb",
+            Effect::CrossfadeAll => "crossfade_all",
+            Effect::CrossfadeRed => "crossfade_red",
+            Effect::CrossfadeGreen => "crossfade_green",
+            Effect::CrossfadeBlue => "crossfade_blue",
+            Effect::CrossfadeYellow => "crossfade_yellow",
+            Effect::CrossfadeCyan => "crossfade_cyan",
+            Effect::CrossfadeMagenta => "crossfade_magenta",
+            Effect::CrossfadeWhite => "crossfade_white",
+            Effect::CrossfadeRedGreen => "crossfade_red_green",
+            Effect::CrossfadeRedBlue => "crossfade_red_blue",
+            Effect::CrossfadeGreenBlue => "crossfade_green_blue",
+            Effect::BlinkAll => "blink_all",
+            Effect::BlinkRed => "blink_red",
+            Effect::BlinkGreen => "blink_green",
+            Effect::BlinkBlue => "blink_blue",
+            Effect::BlinkYellow => "blink_yellow",
+            Effect::BlinkCyan => "blink_cyan",
+            Effect::BlinkMagenta => "blink_magenta",
+            Effect::BlinkWhite => "blink_white",
+            Effect::Raw(_) => return None,
+        };
+        Some(name)
+    }
+}
+
+impl From<u8> for Effect {
+    fn from(code: u8) -> Self {
+        Effect::from_code(code).unwrap_or(Effect::Raw(code))
+    }
+}
+
+impl From<Effect> for u8 {
+    fn from(effect: Effect) -> Self {
+        effect.code()
+    }
+}
+
+impl fmt::Display for Effect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "{:#04x}", self.code()),
+        }
+    }
+}
+
+impl FromStr for Effect {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some(effect) = by_name(s) {
+            return Ok(effect);
+        }
+
+        // Raw hex codes such as 0x87
+        let trimmed = s.trim().to_lowercase();
+        if let Some(hex) = trimmed.strip_prefix("0x") {
+            if let Ok(code) = u8::from_str_radix(hex, 16) {
+                return Ok(Effect::from(code));
+            }
+        }
+
+        Err(Error::UnknownEffect {
+            name: s.to_string(),
+            suggestions: suggestions(s).into_iter().map(String::from).collect(),
+        })
+    }
+}
+
+/// Serialized as its `FromStr` name (e.g. `"crossfade_red"`), or a hex code such as `"0x9d"`
+#[cfg(feature = "serde")]
+impl serde::Serialize for Effect {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Accepts everything `FromStr` does, including aliases and hex codes
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Effect {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        let name = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+        name.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Catalog entry describing a known effect
+#[derive(Debug, Clone, Copy)]
+pub struct EffectInfo {
+    /// The effect itself
+    pub effect: Effect,
+    /// Command code sent to the device
+    pub code: u8,
+    /// snake_case name accepted by `FromStr`
+    pub name: &'static str,
+    /// Human readable description
+    pub description: &'static str,
+    /// Device types known to support the effect
+    pub supported_on: &'static [DeviceType],
+}
+
+impl EffectInfo {
+    /// Whether the effect is known to work on the given device type, i.e.
+    /// whether it is listed in [`EffectInfo::supported_on`]
+    pub fn is_supported_on(&self, device_type: DeviceType) -> bool {
+        self.supported_on.contains(&device_type)
+    }
+}
+
+/// Effect codes implemented by every known firmware
+///
+/// MELK and LEDBLE firmwares only implement the jump effects, the
+/// all-color crossfades and the all-color blink; other codes can leave
+/// the strip frozen until it is power cycled.
+pub const BASIC_EFFECT_CODES: &[RangeInclusive<u8>] = &[0x87..=0x8a, 0x95..=0x95];
+
+/// The full 0x87-0x9c effect range implemented by the ELK firmwares
+pub const FULL_EFFECT_CODES: &[RangeInclusive<u8>] = &[0x87..=0x9c];
+
+/// Get the effect codes known to work on a device type
+///
+/// Unknown devices are assumed to implement the full range.
+pub const fn supported_effect_codes(device_type: DeviceType) -> &'static [RangeInclusive<u8>] {
+    match device_type {
+        DeviceType::LedBle | DeviceType::Melk => BASIC_EFFECT_CODES,
+        DeviceType::ElkBle | DeviceType::ElkBulb | DeviceType::ElkLampl | DeviceType::Unknown => {
+            FULL_EFFECT_CODES
+        }
+    }
+}
+
+/// Whether `code` falls into any of `ranges`
+pub const fn codes_contain(ranges: &[RangeInclusive<u8>], code: u8) -> bool {
+    let mut i = 0;
+    while i < ranges.len() {
+        if code >= *ranges[i].start() && code <= *ranges[i].end() {
+            return true;
+        }
+        i += 1;
+    }
+    false
+}
+
+/// Device types implementing [`BASIC_EFFECT_CODES`]
+const ALL_DEVICE_TYPES: &[DeviceType] = &[
+    DeviceType::ElkBle,
+    DeviceType::LedBle,
+    DeviceType::Melk,
+    DeviceType::ElkBulb,
+    DeviceType::ElkLampl,
+    DeviceType::Unknown,
+];
+
+/// Device types implementing [`FULL_EFFECT_CODES`], including unknown ones
+/// as in [`supported_effect_codes`]
+const ELK_DEVICE_TYPES: &[DeviceType] = &[
+    DeviceType::ElkBle,
+    DeviceType::ElkBulb,
+    DeviceType::ElkLampl,
+    DeviceType::Unknown,
+];
+
+/// Build a catalog entry for a known effect
+const fn info(effect: Effect, description: &'static str) -> EffectInfo {
+    let name = match effect.name() {
+        Some(name) => name,
+        None => panic!("raw effects have no catalog entry"),
+    };
+    EffectInfo {
+        effect,
+        code: effect.code(),
+        name,
+        description,
+        supported_on: if codes_contain(BASIC_EFFECT_CODES, effect.code()) {
+            ALL_DEVICE_TYPES
+        } else {
+            ELK_DEVICE_TYPES
+        },
+    }
+}
+
+/// Catalog of all known effects, in command code order
+static CATALOG: [EffectInfo; 22] = [
+    info(Effect::JumpRgb, "Jump between red, green and blue"),
+    info(
+        Effect::JumpAll,
+        "Jump through red, green, blue, yellow, cyan, magenta and white",
+    ),
+    info(
+        Effect::CrossfadeRgb,
+        "Crossfade through red, green and blue",
+    ),
+    info(
+        Effect::CrossfadeAll,
+        "Crossfade through all colors (rainbow)",
+    ),
+    info(Effect::CrossfadeRed, "Fade red in and out"),
+    info(Effect::CrossfadeGreen, "Fade green in and out"),
+    info(Effect::CrossfadeBlue, "Fade blue in and out"),
+    info(Effect::CrossfadeYellow, "Fade yellow in and out"),
+    info(Effect::CrossfadeCyan, "Fade cyan in and out"),
+    info(Effect::CrossfadeMagenta, "Fade magenta in and out"),
+    info(Effect::CrossfadeWhite, "Fade white in and out"),
+    info(Effect::CrossfadeRedGreen, "Crossfade between red and green"),
+    info(Effect::CrossfadeRedBlue, "Crossfade between red and blue"),
+    info(
+        Effect::CrossfadeGreenBlue,
+        "Crossfade between green and blue",
+    ),
+    info(
+        Effect::BlinkAll,
+        "Blink through red, green, blue, yellow, cyan, magenta and white",
+    ),
+    info(Effect::BlinkRed, "Blink red"),
+    info(Effect::BlinkGreen, "Blink green"),
+    info(Effect::BlinkBlue, "Blink blue"),
+    info(Effect::BlinkYellow, "Blink yellow"),
+    info(Effect::BlinkCyan, "Blink cyan"),
+    info(Effect::BlinkMagenta, "Blink magenta"),
+    info(Effect::BlinkWhite, "Blink white"),
+];
+
+/// Short aliases accepted in addition to the catalog names
+const ALIASES: [(&str, Effect); 3] = [
+    ("rainbow", Effect::CrossfadeAll),
+    ("jump", Effect::JumpRgb),
+    ("blink", Effect::BlinkAll),
+];
+
+/// Get the catalog of all known effects
+pub fn all() -> &'static [EffectInfo] {
+    &CATALOG
+}
+
+/// Get the catalog entry for an effect, if it is a known one
+pub fn info_for(effect: Effect) -> Option<&'static EffectInfo> {
+    CATALOG.iter().find(|info| info.effect == effect)
+}
+
+/// Strip case and separators so `Crossfade-Red` matches `crossfade_red`
+fn normalize(name: &str) -> String {
+    name.trim()
+        .chars()
+        .filter(|c| !matches!(c, '_' | '-' | ' '))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Look up an effect by name, ignoring case and `-`/`_`/space separators
+pub fn by_name(name: &str) -> Option<Effect> {
+    let wanted = normalize(name);
+    ALIASES
+        .iter()
+        .map(|(alias, effect)| (*alias, *effect))
+        .chain(CATALOG.iter().map(|info| (info.name, info.effect)))
+        .find(|(candidate, _)| normalize(candidate) == wanted)
+        .map(|(_, effect)| effect)
+}
+
+/// Suggest known effect names close to an unknown one
+pub fn suggestions(name: &str) -> Vec<&'static str> {
+    let wanted = normalize(name);
+    let mut scored: Vec<(usize, &'static str)> = CATALOG
+        .iter()
+        .map(|info| info.name)
+        .chain(ALIASES.iter().map(|(alias, _)| *alias))
+        .filter_map(|candidate| {
+            let normalized = normalize(candidate);
+            let distance = edit_distance(&wanted, &normalized);
+            let close = distance <= 3 || (!wanted.is_empty() && normalized.contains(&wanted));
+            close.then_some((distance, candidate))
+        })
+        .collect();
+    scored.sort();
+    scored.into_iter().take(3).map(|(_, name)| name).collect()
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catalog_support_matches_the_device_code_ranges() {
+        let types = [
+            DeviceType::ElkBle,
+            DeviceType::LedBle,
+            DeviceType::Melk,
+            DeviceType::ElkBulb,
+            DeviceType::ElkLampl,
+            DeviceType::Unknown,
+        ];
+        for info in all() {
+            for device_type in types {
+                assert_eq!(
+                    info.is_supported_on(device_type),
+                    codes_contain(supported_effect_codes(device_type), info.code),
+                    "{} on {:?}",
+                    info.name,
+                    device_type
+                );
+            }
+        }
+        let fade = info_for(Effect::CrossfadeRed).unwrap();
+        assert!(fade.is_supported_on(DeviceType::ElkBle));
+        assert!(!fade.is_supported_on(DeviceType::Melk));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trips_every_effect_by_name() {
+        for effect in Effect::ALL {
+            let json = serde_json::to_string(&effect).unwrap();
+            assert_eq!(json, format!("\"{}\"", effect.name().unwrap()));
+            assert_eq!(serde_json::from_str::<Effect>(&json).unwrap(), effect);
+        }
+        assert_eq!(
+            serde_json::to_string(&Effect::CrossfadeRed).unwrap(),
+            "\"crossfade_red\""
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_uses_hex_for_unnamed_codes() {
+        let raw = Effect::from(0x9d);
+        let json = serde_json::to_string(&raw).unwrap();
+        assert_eq!(json, "\"0x9d\"");
+        assert_eq!(serde_json::from_str::<Effect>(&json).unwrap(), raw);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_rejects_unknown_names() {
+        assert!(serde_json::from_str::<Effect>("\"crossfade_purple\"").is_err());
+    }
+}
@@ -0,0 +1,49 @@
+/*!
+ # Device events
+
+ A [`BleLedDevice`](crate::BleLedDevice) can publish a [`DeviceEvent`] on a
+ broadcast channel after every applied command, so metrics and live UIs can
+ follow its state without polling. A channel given to the builder also
+ receives the progress of the connection, e.g. to drive a spinner.
+*/
+
+use crate::color::Color;
+
+/// Number of events a subscriber can lag behind before missing some
+pub const DEFAULT_EVENT_CAPACITY: usize = 64;
+
+/// Something that happened on a device
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum DeviceEvent {
+    /// The strip was turned on
+    PoweredOn,
+    /// The strip was turned off
+    PoweredOff,
+    /// A static color was set
+    ColorSet(Color),
+    /// The brightness was set (0-100, as requested)
+    BrightnessSet(u8),
+    /// An effect was started, with its command code
+    EffectSet(u8),
+    /// The effect speed was set (0-100, higher is faster)
+    EffectSpeedSet(u8),
+    /// A color temperature in Kelvin was set
+    ColorTempSet(u32),
+    /// A command failed to reach the device
+    Error(String),
+    /// Connecting: the scan has seen this many peripherals so far
+    Scanning {
+        /// Peripherals seen, compatible or not
+        found: usize,
+    },
+    /// Connecting: a device was picked and is being connected to
+    Connecting {
+        /// Advertised name, if any
+        name: Option<String>,
+        /// Bluetooth address
+        address: String,
+    },
+    /// Connecting: reading the services of the connected device
+    DiscoveringServices,
+}
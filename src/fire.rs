@@ -0,0 +1,139 @@
+/*!
+ # Fire flicker
+
+ A flickering flame look the firmware has no effect for. Brightness and
+ color temperature wander around a warm base with smooth random noise,
+ streamed from the host as color and brightness commands.
+*/
+
+use rand::Rng;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, instrument};
+
+use crate::color::kelvin_to_rgb;
+use crate::{BleLedDevice, CancelHandle, Error, Result};
+
+/// Time between the starts of flicker updates
+const FIRE_STEP: Duration = Duration::from_millis(80);
+
+/// Lowest base color temperature accepted, in kelvin
+const FIRE_MIN_KELVIN: u32 = 1000;
+
+/// Highest base color temperature accepted, in kelvin
+const FIRE_MAX_KELVIN: u32 = 4000;
+
+/// How far the flame cools towards red at its dimmest, in kelvin
+const FIRE_KELVIN_SWING: f32 = 700.0;
+
+/// Fraction of the starting brightness kept at the dimmest flicker
+const FIRE_MIN_LEVEL: f32 = 0.45;
+
+/// One dimension of smooth value noise
+///
+/// Random values on integer lattice points, blended with a smoothstep in
+/// between: cheap, and continuous like Perlin noise, which is all a flame
+/// needs.
+#[derive(Debug)]
+struct Noise {
+    /// Lattice values in 0.0-1.0, repeating
+    lattice: Vec<f32>,
+}
+
+impl Noise {
+    /// Noise with `len` random lattice points before it repeats
+    fn new(rng: &mut impl Rng, len: usize) -> Self {
+        Self {
+            lattice: (0..len).map(|_| rng.gen()).collect(),
+        }
+    }
+
+    /// Noise value in 0.0-1.0 at `x`
+    fn at(&self, x: f32) -> f32 {
+        let i = x.floor();
+        let t = x - i;
+        let t = t * t * (3.0 - 2.0 * t);
+        let a = self.lattice[i as usize % self.lattice.len()];
+        let b = self.lattice[(i as usize + 1) % self.lattice.len()];
+        a + (b - a) * t
+    }
+
+    /// Slow swells with faster flicker on top, in 0.0-1.0
+    fn flame(&self, seconds: f32) -> f32 {
+        (0.6 * self.at(seconds * 1.5) + 0.4 * self.at(seconds * 7.0 + 101.0)).clamp(0.0, 1.0)
+    }
+}
+
+impl BleLedDevice {
+    /// Flickers like a candle or fire for `duration`, or until `cancel` is triggered
+    ///
+    /// Brightness dips and the color cools towards red around `base_kelvin`,
+    /// driven by smooth noise so the flame swells rather than strobes.
+    /// Updates start every 80 ms, or less often when `command_delay` makes
+    /// the writes take longer, and a write is skipped when nothing changed. Afterwards the strip is left at
+    /// the base color and the brightness it had before.
+    ///
+    /// # Arguments
+    ///
+    /// * `duration` - How long to flicker
+    /// * `base_kelvin` - Color temperature of the flame (1000-4000 K), e.g. 1900 for a candle
+    /// * `cancel` - Handle that stops the flicker early
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ValueOutOfRange` for a base temperature outside 1000-4000 K.
+    #[instrument(skip(self, cancel))]
+    pub async fn run_fire(
+        &self,
+        duration: Duration,
+        base_kelvin: u32,
+        cancel: CancelHandle,
+    ) -> Result<()> {
+        if !(FIRE_MIN_KELVIN..=FIRE_MAX_KELVIN).contains(&base_kelvin) {
+            return Err(Error::ValueOutOfRange(
+                base_kelvin,
+                FIRE_MIN_KELVIN,
+                FIRE_MAX_KELVIN,
+            ));
+        }
+
+        info!("Flickering around {}K for {:?}", base_kelvin, duration);
+
+        let noise = Noise::new(&mut rand::thread_rng(), 256);
+        let base_brightness = self.brightness();
+        let start = Instant::now();
+        let mut last_color = None;
+        let mut last_brightness = None;
+
+        while !cancel.is_cancelled() && start.elapsed() < duration {
+            let tick = tokio::time::Instant::now();
+            let flame = noise.flame(start.elapsed().as_secs_f32());
+
+            // Dimmer flames burn redder
+            let kelvin = base_kelvin as f32 - FIRE_KELVIN_SWING * (1.0 - flame);
+            let color = kelvin_to_rgb(kelvin.max(FIRE_MIN_KELVIN as f32) as u32);
+            let level = FIRE_MIN_LEVEL + (1.0 - FIRE_MIN_LEVEL) * flame;
+            let brightness = (base_brightness as f32 * level).round() as u8;
+
+            if last_color != Some(color) {
+                debug!("Fire at {:.0}K, RGB{:?}", kelvin, color);
+                self.set_color(color.0, color.1, color.2).await?;
+                last_color = Some(color);
+            }
+            if last_brightness != Some(brightness) {
+                self.set_brightness(brightness).await?;
+                last_brightness = Some(brightness);
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep_until(tick + FIRE_STEP) => {}
+                _ = cancel.cancelled() => break,
+            }
+        }
+
+        let (red, green, blue) = kelvin_to_rgb(base_kelvin);
+        self.set_color(red, green, blue).await?;
+        self.set_brightness(base_brightness).await?;
+        info!("Fire stopped");
+        Ok(())
+    }
+}
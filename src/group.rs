@@ -0,0 +1,141 @@
+/*!
+ # Device groups
+
+ This module allows controlling several LED strips together. Commands are
+ sent to every device concurrently and a failing device does not abort the
+ command for the rest of the group.
+*/
+
+use futures::future::join_all;
+use std::future::Future;
+use tracing::{info, instrument, warn};
+
+use crate::{BleLedDevice, Effect, Error, Result};
+
+/// A group of LED devices that are controlled together
+pub struct DeviceGroup {
+    /// Devices in the group, in the order they were added
+    devices: Vec<BleLedDevice>,
+}
+
+impl DeviceGroup {
+    /// Creates a group from already connected devices
+    pub fn new(devices: Vec<BleLedDevice>) -> Self {
+        Self { devices }
+    }
+
+    /// Connects to every device in `addresses` (MAC address or ID) and groups them
+    ///
+    /// Devices are connected one after another, as scanning concurrently on a
+    /// single adapter is unreliable.
+    #[instrument]
+    pub async fn connect(addresses: &[String]) -> Result<Self> {
+        let mut devices = Vec::with_capacity(addresses.len());
+        for addr in addresses {
+            devices.push(BleLedDevice::new_with_addr(addr).await?);
+        }
+        info!("Connected to a group of {} devices", devices.len());
+        Ok(Self::new(devices))
+    }
+
+    /// Get the devices in the group
+    pub fn devices(&self) -> &[BleLedDevice] {
+        &self.devices
+    }
+
+    /// Get mutable access to the devices in the group
+    pub fn devices_mut(&mut self) -> &mut [BleLedDevice] {
+        &mut self.devices
+    }
+
+    /// Number of devices in the group
+    pub fn len(&self) -> usize {
+        self.devices.len()
+    }
+
+    /// Whether the group has no devices
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    /// Turns every LED strip on
+    pub async fn power_on(&mut self) -> Result<()> {
+        self.for_each(|device| device.power_on()).await
+    }
+
+    /// Turns every LED strip off
+    pub async fn power_off(&mut self) -> Result<()> {
+        self.for_each(|device| device.power_off()).await
+    }
+
+    /// Sets the RGB color of every LED strip
+    pub async fn set_color(
+        &mut self,
+        red_value: u8,
+        green_value: u8,
+        blue_value: u8,
+    ) -> Result<()> {
+        self.for_each(|device| device.set_color(red_value, green_value, blue_value))
+            .await
+    }
+
+    /// Sets the brightness level (0-100) of every LED strip
+    pub async fn set_brightness(&mut self, value: u8) -> Result<()> {
+        self.for_each(|device| device.set_brightness(value)).await
+    }
+
+    /// Sets a light effect mode on every LED strip
+    pub async fn set_effect(&mut self, value: impl Into<Effect>) -> Result<()> {
+        let effect = value.into();
+        self.for_each(|device| device.set_effect(effect)).await
+    }
+
+    /// Sets the effect speed (0-100) on every LED strip
+    pub async fn set_effect_speed(&mut self, value: u8) -> Result<()> {
+        self.for_each(|device| device.set_effect_speed(value)).await
+    }
+
+    /// Sets the color temperature in Kelvin on every LED strip
+    pub async fn set_color_temp_kelvin(&mut self, value: u32) -> Result<()> {
+        self.for_each(|device| device.set_color_temp_kelvin(value))
+            .await
+    }
+
+    /// Runs an operation on every device concurrently, collecting per-device errors
+    ///
+    /// Use it for multi-step commands, so a device failing an early step
+    /// doesn't keep the others from finishing:
+    ///
+    /// ```no_run
+    /// # async fn demo(group: &mut elk_led_controller::group::DeviceGroup) -> elk_led_controller::Result<()> {
+    /// group
+    ///     .for_each(|device| async move {
+    ///         device.power_on().await?;
+    ///         device.set_color(255, 0, 0).await
+    ///     })
+    ///     .await
+    /// # }
+    /// ```
+    pub async fn for_each<'a, F, Fut>(&'a mut self, operation: F) -> Result<()>
+    where
+        F: FnMut(&'a mut BleLedDevice) -> Fut,
+        Fut: Future<Output = Result<()>> + 'a,
+    {
+        let results = join_all(self.devices.iter_mut().map(operation)).await;
+
+        let errors: Vec<(usize, Error)> = results
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, result)| result.err().map(|e| (index, e)))
+            .collect();
+
+        if errors.is_empty() {
+            return Ok(());
+        }
+
+        for (index, error) in &errors {
+            warn!("Device {} in group failed: {}", index, error);
+        }
+        Err(Error::GroupError(errors))
+    }
+}
@@ -12,24 +12,25 @@
  * Brightness adjustment
  * Effect modes (fade, jump, blink)
  * Effect speed control
- * Scheduling
- * Audio monitoring and visualization
+ * Software crossfades between any two colors, or through random ones
+ * Shuffling through effects at random
+ * A flickering fire effect driven from the host
+ * Scheduling, on the device and host-side for any action
+ * Controlling groups of devices together
+ * Audio monitoring and visualization (`audio` feature, on by default)
 
  ## Example
 
- ```rust
+ ```rust,no_run
  use elk_led_controller::*;
 
  #[tokio::main]
- async fn main() -> Result<(), Error> {
+ async fn main() -> Result<()> {
      // Initialize tracing for logs
      tracing_subscriber::fmt::init();
 
-     // Initialize error handling
-     color_eyre::install()?;
-
      // Initialize and connect to the device
-     let mut device = BleLedDevice::new_without_power().await?;
+     let device = BleLedDevice::new_without_power().await?;
 
      // Basic operations
      device.power_on().await?;
@@ -54,6 +55,14 @@ pub enum Error {
     #[error("No compatible LED device found")]
     NoCompatibleDevice,
 
+    /// An effect speed was set while no effect is active
+    #[error("No effect is active, so there is no effect speed to set")]
+    NoActiveEffect,
+
+    /// No device matched the requested address or name
+    #[error("No device with {0} found")]
+    DeviceNotFound(String),
+
     /// Failed to find required BLE characteristic
     #[error("Could not find required BLE characteristic: {0}")]
     CharacteristicNotFound(String),
@@ -66,10 +75,84 @@ pub enum Error {
     #[error("Command timed out after {0} retries")]
     CommandTimeout(u8),
 
+    /// Malformed frame received from the device
+    #[error("Invalid frame: {0}")]
+    InvalidFrame(String),
+
+    /// Unknown effect name, with close matches from the effect catalog
+    #[error("Unknown effect '{name}'{}", did_you_mean(suggestions))]
+    UnknownEffect {
+        /// The name that was looked up
+        name: String,
+        /// Known effect names close to `name`
+        suggestions: Vec<String>,
+    },
+
+    /// Effect code not implemented by the connected device's firmware
+    #[error("Effect {effect} is not supported on {device_type:?} devices")]
+    UnsupportedEffect {
+        /// The rejected effect
+        effect: Effect,
+        /// Type of the connected device
+        device_type: DeviceType,
+    },
+
+    /// Color string that could not be parsed
+    #[error("Invalid color '{0}', expected hex as #rrggbb, rrggbb, #rgb or rgb (e.g. #ff69b4), or a name such as orange")]
+    InvalidColor(String),
+
+    /// Text command that could not be parsed
+    #[error("Invalid command: {0}")]
+    InvalidCommand(String),
+
+    /// Duration string that could not be parsed
+    #[error("Invalid duration '{0}', expected e.g. 90s, 1500ms, 2m or 1h30m")]
+    InvalidDuration(String),
+
+    /// Saved device state that could not be parsed
+    #[error("Invalid saved state: {0}")]
+    InvalidState(String),
+
+    /// Scene name that can't be stored
+    #[error("Invalid scene name '{0}', expected a single word")]
+    InvalidSceneName(String),
+
+    /// Day list that could not be parsed
+    #[error("Invalid day '{0}', expected a day (mon), a range (mon-fri), all, weekdays, weekend or none")]
+    InvalidDays(String),
+
     /// Value out of range
     #[error("Value {0} out of range ({1}..{2})")]
     ValueOutOfRange(u32, u32, u32),
 
+    /// An enabled schedule has no day selected
+    #[error("An enabled schedule needs at least one day")]
+    EmptyScheduleDays,
+
+    /// The on and off schedules of a daily schedule are at the same time
+    #[error("The on and off times are both {hours}:{minutes:02}")]
+    SameScheduleTimes {
+        /// Hour of both schedules
+        hours: u8,
+        /// Minute of both schedules
+        minutes: u8,
+    },
+
+    /// One or more devices in a group failed, with their index in the group
+    #[error("{} device(s) in the group failed", .0.len())]
+    GroupError(Vec<(usize, Error)>),
+
+    /// A step of a command batch failed
+    #[error("Batch step {step} ({name}) failed: {source}")]
+    BatchStepFailed {
+        /// Index of the failed step in the batch
+        step: usize,
+        /// Name of the failed operation
+        name: &'static str,
+        /// The underlying error
+        source: Box<Error>,
+    },
+
     /// General error
     #[error("Error: {0}")]
     General(String),
@@ -78,7 +161,11 @@ pub enum Error {
     #[error(transparent)]
     BtlePlugError(#[from] btleplug::Error),
 
-    /// Audio capture error
+    /// File system error, e.g. while saving the device state
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// Audio capture error (these audio variants only occur with the `audio` feature)
     #[error("Audio capture error: {0}")]
     AudioCaptureError(String),
 
@@ -95,15 +182,266 @@ pub enum Error {
     Other(#[from] Box<dyn std::error::Error + Send + Sync>),
 }
 
+impl Error {
+    /// Name of the error variant, e.g. `"NoCompatibleDevice"`
+    ///
+    /// Stable across releases, so scripts can branch on it instead of the message.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::NoBluetoothAdapters => "NoBluetoothAdapters",
+            Error::NoCompatibleDevice => "NoCompatibleDevice",
+            Error::NoActiveEffect => "NoActiveEffect",
+            Error::DeviceNotFound(_) => "DeviceNotFound",
+            Error::CharacteristicNotFound(_) => "CharacteristicNotFound",
+            Error::BleError(_) => "BleError",
+            Error::CommandTimeout(_) => "CommandTimeout",
+            Error::InvalidFrame(_) => "InvalidFrame",
+            Error::UnknownEffect { .. } => "UnknownEffect",
+            Error::UnsupportedEffect { .. } => "UnsupportedEffect",
+            Error::InvalidColor(_) => "InvalidColor",
+            Error::InvalidCommand(_) => "InvalidCommand",
+            Error::InvalidDuration(_) => "InvalidDuration",
+            Error::InvalidState(_) => "InvalidState",
+            Error::InvalidSceneName(_) => "InvalidSceneName",
+            Error::InvalidDays(_) => "InvalidDays",
+            Error::ValueOutOfRange(..) => "ValueOutOfRange",
+            Error::EmptyScheduleDays => "EmptyScheduleDays",
+            Error::SameScheduleTimes { .. } => "SameScheduleTimes",
+            Error::GroupError(_) => "GroupError",
+            Error::BatchStepFailed { .. } => "BatchStepFailed",
+            Error::General(_) => "General",
+            Error::BtlePlugError(_) => "BtlePlugError",
+            Error::Io(_) => "Io",
+            Error::AudioCaptureError(_) => "AudioCaptureError",
+            Error::StreamBuildError(_) => "StreamBuildError",
+            Error::StreamPlayError(_) => "StreamPlayError",
+            Error::Other(_) => "Other",
+        }
+    }
+
+    /// Process exit code for the category of the error, as used by the CLI
+    ///
+    /// | Code | Category |
+    /// |------|----------|
+    /// | 1 | anything else, e.g. I/O errors |
+    /// | 2 | no Bluetooth adapter |
+    /// | 3 | no compatible or matching device |
+    /// | 4 | Bluetooth communication failed or the connection was lost |
+    /// | 5 | invalid argument or value |
+    /// | 6 | audio capture failed |
+    ///
+    /// Batch failures take the code of the failed step, and group failures
+    /// the code their devices agree on (1 if they differ).
+    ///
+    /// ```
+    /// use elk_led_controller::Error;
+    ///
+    /// assert_eq!(Error::NoBluetoothAdapters.exit_code(), 2);
+    /// assert_eq!(Error::DeviceNotFound("address AA".into()).exit_code(), 3);
+    /// assert_eq!(Error::CommandTimeout(3).exit_code(), 4);
+    /// assert_eq!(Error::ValueOutOfRange(120, 0, 100).exit_code(), 5);
+    /// assert_eq!(Error::AudioCaptureError("no input".into()).exit_code(), 6);
+    /// assert_eq!(Error::General("oops".into()).exit_code(), 1);
+    /// ```
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::NoBluetoothAdapters => 2,
+            Error::NoCompatibleDevice | Error::DeviceNotFound(_) => 3,
+            Error::CharacteristicNotFound(_)
+            | Error::BleError(_)
+            | Error::CommandTimeout(_)
+            | Error::BtlePlugError(_) => 4,
+            Error::NoActiveEffect
+            | Error::InvalidFrame(_)
+            | Error::UnknownEffect { .. }
+            | Error::UnsupportedEffect { .. }
+            | Error::InvalidColor(_)
+            | Error::InvalidCommand(_)
+            | Error::InvalidDuration(_)
+            | Error::InvalidSceneName(_)
+            | Error::InvalidDays(_)
+            | Error::ValueOutOfRange(..)
+            | Error::EmptyScheduleDays
+            | Error::SameScheduleTimes { .. } => 5,
+            Error::AudioCaptureError(_)
+            | Error::StreamBuildError(_)
+            | Error::StreamPlayError(_) => 6,
+            Error::BatchStepFailed { source, .. } => source.exit_code(),
+            Error::GroupError(failures) => {
+                let mut codes = failures.iter().map(|(_, error)| error.exit_code());
+                let first = codes.next().unwrap_or(1);
+                if codes.all(|code| code == first) {
+                    first
+                } else {
+                    1
+                }
+            }
+            Error::InvalidState(_) | Error::General(_) | Error::Io(_) | Error::Other(_) => 1,
+        }
+    }
+}
+
+/// Format a "did you mean" hint for error messages
+fn did_you_mean(suggestions: &[String]) -> String {
+    if suggestions.is_empty() {
+        String::new()
+    } else {
+        format!(" (did you mean: {}?)", suggestions.join(", "))
+    }
+}
+
 // Import needed for Result type extension
 pub type Result<T> = std::result::Result<T, Error>;
 
 // Re-export modules
+pub mod at_time;
+#[cfg(feature = "audio")]
 pub mod audio;
+pub mod cancel;
+pub mod color;
+pub mod console;
+pub mod crossfade;
 pub mod device;
 pub mod effects;
+pub mod events;
+pub mod fire;
+pub mod group;
+pub mod metrics;
+pub mod persist;
+pub mod protocol;
+pub mod scene;
 pub mod schedule;
+pub mod scheduler;
+pub mod shuffle;
+pub mod sleep_timer;
+pub mod solar;
 
 // Re-export key types
-pub use audio::{AudioMonitor, AudioVisualization, FrequencyRange, VisualizationMode};
-pub use device::{BleLedDevice, Days, DeviceConfig, DeviceType, Effects, EFFECTS, WEEK_DAYS};
+#[cfg(feature = "audio")]
+pub use audio::{
+    AudioAnalysis, AudioMonitor, AudioVisualization, FrequencyRange, Normalization,
+    VisualizationMode, BPM_CONFIDENCE_THRESHOLD,
+};
+pub use cancel::CancelHandle;
+pub use color::{
+    hsv_to_rgb, kelvin_to_rgb, lerp_color, named_color, parse_color, parse_hex_color, Color,
+};
+pub use console::LineCommand;
+pub use crossfade::{CrossfadeOptions, Easing, FadeTarget};
+#[allow(deprecated)]
+pub use device::{
+    BleLedDevice, BleLedDeviceBuilder, Bluetooth, BrightnessCurve, CommandBatch, Days,
+    DeviceConfig, DeviceState, DeviceType, DiscoveredDevice, Effect, EffectInfo, Effects,
+    SpeedWithoutEffect, WeekDays, DEFAULT_SCAN_TIMEOUT, EFFECTS, WEEK_DAYS,
+};
+pub use events::{DeviceEvent, DEFAULT_EVENT_CAPACITY};
+pub use group::DeviceGroup;
+pub use metrics::DeviceMetrics;
+pub use protocol::{parse_frame, parse_hex_frame, Frame};
+pub use scene::SceneStore;
+pub use schedule::{days_to_string, device_day_of_week, Schedule};
+pub use scheduler::{
+    EntryId, EntryInfo, LocalScheduler, PowerStrategy, ScheduleTime, SchedulerOptions,
+};
+pub use sleep_timer::SleepTimer;
+pub use solar::Location;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_code_per_error_category() {
+        let cases: Vec<(Error, i32)> = vec![
+            (Error::NoBluetoothAdapters, 2),
+            (Error::NoCompatibleDevice, 3),
+            (Error::DeviceNotFound("address BE:58:00:00:00:01".into()), 3),
+            (Error::CharacteristicNotFound("fff3".into()), 4),
+            (Error::BleError("write failed".into()), 4),
+            (Error::CommandTimeout(3), 4),
+            (Error::BtlePlugError(btleplug::Error::NotConnected), 4),
+            (Error::NoActiveEffect, 5),
+            (Error::InvalidFrame("too short".into()), 5),
+            (
+                Error::UnknownEffect {
+                    name: "disco".into(),
+                    suggestions: vec![],
+                },
+                5,
+            ),
+            (
+                Error::UnsupportedEffect {
+                    effect: Effect::JumpRgb,
+                    device_type: DeviceType::ElkBulb,
+                },
+                5,
+            ),
+            (Error::InvalidColor("zz".into()), 5),
+            (Error::InvalidCommand("dance".into()), 5),
+            (Error::InvalidDuration("soon".into()), 5),
+            (Error::InvalidSceneName("two words".into()), 5),
+            (Error::InvalidDays("funday".into()), 5),
+            (Error::ValueOutOfRange(120, 0, 100), 5),
+            (Error::EmptyScheduleDays, 5),
+            (
+                Error::SameScheduleTimes {
+                    hours: 7,
+                    minutes: 30,
+                },
+                5,
+            ),
+            (Error::AudioCaptureError("no input".into()), 6),
+            (Error::StreamBuildError("bad config".into()), 6),
+            (Error::StreamPlayError("device gone".into()), 6),
+            (Error::InvalidState("{".into()), 1),
+            (Error::General("oops".into()), 1),
+            (Error::Io(std::io::Error::other("disk full")), 1),
+            (Error::Other("elsewhere".into()), 1),
+        ];
+        for (error, code) in cases {
+            assert_eq!(error.exit_code(), code, "{}", error.kind());
+        }
+    }
+
+    #[test]
+    fn batch_failures_take_the_code_of_the_failed_step() {
+        let failed = |source: Error| Error::BatchStepFailed {
+            step: 2,
+            name: "set_brightness",
+            source: Box::new(source),
+        };
+        assert_eq!(failed(Error::ValueOutOfRange(120, 0, 100)).exit_code(), 5);
+        assert_eq!(failed(Error::CommandTimeout(3)).exit_code(), 4);
+        assert_eq!(failed(Error::General("oops".into())).exit_code(), 1);
+    }
+
+    #[test]
+    fn group_failures_take_the_code_their_devices_agree_on() {
+        let agreeing = Error::GroupError(vec![
+            (0, Error::CommandTimeout(3)),
+            (2, Error::BleError("write failed".into())),
+        ]);
+        assert_eq!(agreeing.exit_code(), 4);
+
+        let differing = Error::GroupError(vec![
+            (0, Error::CommandTimeout(3)),
+            (1, Error::DeviceNotFound("address BE:58:00:00:00:02".into())),
+        ]);
+        assert_eq!(differing.exit_code(), 1);
+
+        let single = Error::GroupError(vec![(1, Error::NoCompatibleDevice)]);
+        assert_eq!(single.exit_code(), 3);
+        assert_eq!(Error::GroupError(vec![]).exit_code(), 1);
+
+        // A failed batch inside a group counts with its step's code
+        let nested = Error::GroupError(vec![(
+            0,
+            Error::BatchStepFailed {
+                step: 0,
+                name: "power_on",
+                source: Box::new(Error::CommandTimeout(3)),
+            },
+        )]);
+        assert_eq!(nested.exit_code(), 4);
+    }
+}
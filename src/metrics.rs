@@ -0,0 +1,125 @@
+/*!
+ # Command metrics
+
+ Counters of the BLE writes made by a [`BleLedDevice`](crate::BleLedDevice),
+ for telling from a long-running process whether the connection is getting
+ worse. Read them with
+ [`BleLedDevice::metrics`](crate::BleLedDevice::metrics), as JSON or in the
+ Prometheus text format for a monitoring stack to scrape.
+*/
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Snapshot of a device's command counters since it connected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DeviceMetrics {
+    /// Commands that reached the device, possibly after retries
+    pub commands_sent: u64,
+    /// Commands that failed on every attempt
+    pub commands_failed: u64,
+    /// Writes repeated after a failed attempt
+    pub retries: u64,
+}
+
+impl DeviceMetrics {
+    /// Formats the counters as a single-line JSON object:
+    /// `{"commands_sent":120,"commands_failed":1,"retries":4}`
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"commands_sent\":{},\"commands_failed\":{},\"retries\":{}}}",
+            self.commands_sent, self.commands_failed, self.retries
+        )
+    }
+
+    /// Formats the counters in the Prometheus text exposition format
+    ///
+    /// Emits `elk_commands_total`, `elk_command_failures_total`,
+    /// `elk_command_retries_total` and the `elk_connected` gauge (1 or 0),
+    /// labelled with the device address, ready to be served from a
+    /// `/metrics` endpoint:
+    ///
+    /// ```
+    /// use elk_led_controller::DeviceMetrics;
+    ///
+    /// let metrics = DeviceMetrics { commands_sent: 120, commands_failed: 1, retries: 4 };
+    /// let text = metrics.to_prometheus("BE:58:00:00:00:01", true);
+    /// assert!(text.contains("elk_commands_total{device=\"BE:58:00:00:00:01\"} 120\n"));
+    /// assert!(text.contains("elk_connected{device=\"BE:58:00:00:00:01\"} 1\n"));
+    /// ```
+    pub fn to_prometheus(&self, address: &str, connected: bool) -> String {
+        let label = address
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n");
+        let metrics = [
+            (
+                "elk_commands_total",
+                "counter",
+                "Commands that reached the device, possibly after retries",
+                self.commands_sent,
+            ),
+            (
+                "elk_command_failures_total",
+                "counter",
+                "Commands that failed on every attempt",
+                self.commands_failed,
+            ),
+            (
+                "elk_command_retries_total",
+                "counter",
+                "Writes repeated after a failed attempt",
+                self.retries,
+            ),
+            (
+                "elk_connected",
+                "gauge",
+                "Whether the device is connected (1) or not (0)",
+                connected as u64,
+            ),
+        ];
+        let mut text = String::new();
+        for (name, kind, help, value) in metrics {
+            text.push_str(&format!(
+                "# HELP {name} {help}\n# TYPE {name} {kind}\n{name}{{device=\"{label}\"}} {value}\n"
+            ));
+        }
+        text
+    }
+}
+
+/// Counters updated by `send_command`, shared by all its callers
+#[derive(Debug, Default)]
+pub(crate) struct MetricCounters {
+    /// See [`DeviceMetrics::commands_sent`]
+    sent: AtomicU64,
+    /// See [`DeviceMetrics::commands_failed`]
+    failed: AtomicU64,
+    /// See [`DeviceMetrics::retries`]
+    retries: AtomicU64,
+}
+
+impl MetricCounters {
+    /// Counts a command that reached the device
+    pub(crate) fn record_sent(&self) {
+        self.sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a command that failed on every attempt
+    pub(crate) fn record_failed(&self) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a write that is about to be repeated
+    pub(crate) fn record_retry(&self) {
+        self.retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Current values of the counters
+    pub(crate) fn snapshot(&self) -> DeviceMetrics {
+        DeviceMetrics {
+            commands_sent: self.sent.load(Ordering::Relaxed),
+            commands_failed: self.failed.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,167 @@
+/*!
+ # State persistence
+
+ The device cannot report its state, so after a restart the cached
+ [`DeviceState`] would start from defaults while the strip still shows what
+ it last had. With [`BleLedDeviceBuilder::state_file`](crate::BleLedDeviceBuilder::state_file)
+ the state is saved as JSON after each command and loaded again on connect.
+*/
+
+use crate::{DeviceState, Result};
+use parking_lot::Mutex;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{debug, warn};
+
+/// How long the state must stay unchanged before it is written
+///
+/// Audio visualization changes the state many times a second; only the
+/// last state of a burst reaches the disk.
+pub const PERSIST_DEBOUNCE: Duration = Duration::from_secs(1);
+
+/// Loads a state saved by a previous run
+///
+/// A missing file is not an error and gives `None`.
+pub fn load_state(path: &Path) -> Result<Option<DeviceState>> {
+    match std::fs::read_to_string(path) {
+        Ok(json) => DeviceState::from_json(&json).map(Some),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Writes `state` to `path` through a temporary file, so a crash mid-write
+/// never leaves a truncated state behind
+pub fn save_state(path: &Path, state: &DeviceState) -> Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    std::fs::write(&tmp, state.to_json())?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Background writer saving the latest state after [`PERSIST_DEBOUNCE`]
+///
+/// A state still waiting out the debounce when the persister is dropped is
+/// written right away, so the last command before exiting isn't lost.
+#[derive(Debug)]
+pub(crate) struct StatePersister {
+    /// Latest state, picked up by the writer task
+    state: watch::Sender<DeviceState>,
+    /// Where the state is written
+    path: PathBuf,
+    /// Whether the latest state hasn't been written yet
+    pending: Arc<AtomicBool>,
+    /// Held while writing, so the task and a flush on drop don't interleave
+    writing: Arc<Mutex<()>>,
+}
+
+impl StatePersister {
+    /// Starts the writer task; it ends once the persister is dropped
+    pub(crate) fn spawn(path: PathBuf, initial: DeviceState) -> Self {
+        let (state, mut rx) = watch::channel(initial);
+        let pending = Arc::new(AtomicBool::new(false));
+        let writing = Arc::new(Mutex::new(()));
+        tokio::spawn({
+            let path = path.clone();
+            let pending = pending.clone();
+            let writing = writing.clone();
+            async move {
+                while rx.changed().await.is_ok() {
+                    tokio::time::sleep(PERSIST_DEBOUNCE).await;
+                    let _writing = writing.lock();
+                    // Already flushed on drop
+                    if !pending.swap(false, Ordering::SeqCst) {
+                        continue;
+                    }
+                    let latest = *rx.borrow_and_update();
+                    write(&path, &latest);
+                }
+            }
+        });
+        Self {
+            state,
+            path,
+            pending,
+            writing,
+        }
+    }
+
+    /// Queues `state` to be written, if it differs from the last one queued
+    pub(crate) fn update(&self, state: DeviceState) {
+        self.state.send_if_modified(|current| {
+            let changed = *current != state;
+            if changed {
+                self.pending.store(true, Ordering::SeqCst);
+            }
+            *current = state;
+            changed
+        });
+    }
+}
+
+impl Drop for StatePersister {
+    fn drop(&mut self) {
+        let _writing = self.writing.lock();
+        if self.pending.swap(false, Ordering::SeqCst) {
+            write(&self.path, &self.state.borrow());
+        }
+    }
+}
+
+/// Saves `state`, logging rather than failing
+fn write(path: &Path, state: &DeviceState) {
+    match save_state(path, state) {
+        Ok(()) => debug!("Saved device state to {}", path.display()),
+        Err(e) => warn!("Could not save device state to {}: {}", path.display(), e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn pending_state_is_written_on_drop() {
+        let path = std::env::temp_dir().join(format!("elk-persist-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let persister = StatePersister::spawn(path.clone(), DeviceState::default());
+        let state = DeviceState {
+            is_on: true,
+            brightness: 42,
+            ..Default::default()
+        };
+        persister.update(state);
+        // Dropped well within the debounce
+        drop(persister);
+
+        assert_eq!(load_state(&path).unwrap(), Some(state));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn bursts_are_written_once_debounced() {
+        let path = std::env::temp_dir().join(format!("elk-debounce-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let persister = StatePersister::spawn(path.clone(), DeviceState::default());
+        for brightness in 1..=10 {
+            persister.update(DeviceState {
+                brightness,
+                ..Default::default()
+            });
+        }
+        assert_eq!(load_state(&path).unwrap(), None);
+
+        tokio::time::sleep(PERSIST_DEBOUNCE * 2).await;
+        let saved = load_state(&path).unwrap().unwrap();
+        assert_eq!(saved.brightness, 10);
+
+        drop(persister);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
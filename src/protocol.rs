@@ -0,0 +1,281 @@
+/*!
+ # Wire protocol helpers
+
+ This module contains helpers for working with the raw frames exchanged
+ with the LED controller. Every frame starts with a `0x7e` header byte and
+ ends with a `0xef` terminator.
+
+ The frame builders are pure functions taking the bytes as the firmware
+ receives them, so commands can be encoded without a connected device (e.g.
+ to print them); [`BleLedDevice`](crate::BleLedDevice) sends frames built
+ here.
+*/
+
+use crate::{DeviceEvent, Error, Result};
+
+/// Header byte that starts every frame
+pub const FRAME_HEADER: u8 = 0x7e;
+
+/// Terminator byte that ends every frame
+pub const FRAME_TERMINATOR: u8 = 0xef;
+
+/// Minimum frame length: header, length byte, command and terminator
+const MIN_FRAME_LEN: usize = 4;
+
+/// A validated frame received from the device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// Command byte (third byte of the frame)
+    pub command: u8,
+    /// Bytes between the command and the terminator
+    pub payload: Vec<u8>,
+}
+
+/// Parses and validates a raw frame
+///
+/// # Arguments
+///
+/// * `bytes` - Raw bytes as read from the device
+pub fn parse_frame(bytes: &[u8]) -> Result<Frame> {
+    if bytes.len() < MIN_FRAME_LEN {
+        return Err(Error::InvalidFrame(format!(
+            "frame too short: expected at least {} bytes, got {}",
+            MIN_FRAME_LEN,
+            bytes.len()
+        )));
+    }
+
+    if bytes[0] != FRAME_HEADER {
+        return Err(Error::InvalidFrame(format!(
+            "bad header byte {:#04x}, expected {:#04x}",
+            bytes[0], FRAME_HEADER
+        )));
+    }
+
+    let last = bytes[bytes.len() - 1];
+    if last != FRAME_TERMINATOR {
+        return Err(Error::InvalidFrame(format!(
+            "bad terminator byte {:#04x}, expected {:#04x}",
+            last, FRAME_TERMINATOR
+        )));
+    }
+
+    Ok(Frame {
+        command: bytes[2],
+        payload: bytes[3..bytes.len() - 1].to_vec(),
+    })
+}
+
+/// Length of every command frame sent to the device
+pub const COMMAND_LEN: usize = 9;
+
+/// A command frame as written to the device
+pub type CommandFrame = [u8; COMMAND_LEN];
+
+/// Frame that leaves effect mode, sent before a static color or white
+pub const EXIT_EFFECT_FRAME: CommandFrame = [0x7e, 0x00, 0x05, 0x01, 0x00, 0x00, 0x00, 0x00, 0xef];
+
+/// Frame setting a static RGB color
+pub const fn color_frame(red: u8, green: u8, blue: u8) -> CommandFrame {
+    [0x7e, 0x00, 0x05, 0x03, red, green, blue, 0x00, 0xef]
+}
+
+/// Frame setting the brightness byte as the firmware receives it (0-100)
+pub const fn brightness_frame(value: u8) -> CommandFrame {
+    [0x7e, 0x00, 0x01, value, 0x00, 0x00, 0x00, 0x00, 0xef]
+}
+
+/// Frame starting the effect with the given code
+pub const fn effect_frame(code: u8) -> CommandFrame {
+    [0x7e, 0x00, 0x03, code, 0x03, 0x00, 0x00, 0x00, 0xef]
+}
+
+/// Frame setting the effect speed byte as the firmware receives it (0-100)
+pub const fn effect_speed_frame(value: u8) -> CommandFrame {
+    [0x7e, 0x00, 0x02, value, 0x00, 0x00, 0x00, 0x00, 0xef]
+}
+
+/// Frame setting white light from its warm and cold levels (0-100 each)
+pub const fn color_temp_frame(warm: u8, cold: u8) -> CommandFrame {
+    [0x7e, 0x00, 0x05, 0x02, warm, cold, 0x00, 0x00, 0xef]
+}
+
+/// Position of `kelvin` in `min_k..=max_k` as a percentage
+///
+/// Rounds to the nearest step, so the ends of the range map exactly to 0
+/// and 100. `kelvin` is expected to be within the range already.
+pub const fn color_temp_percent(kelvin: u32, min_k: u32, max_k: u32) -> u8 {
+    let span = max_k - min_k;
+    match ((kelvin - min_k) * 100 + span / 2).checked_div(span) {
+        Some(percent) => percent as u8,
+        None => 100,
+    }
+}
+
+/// Frame programming the on (`off == false`) or off schedule
+///
+/// `days` is the day byte, with the top bit marking the schedule enabled.
+pub const fn schedule_frame(off: bool, hours: u8, minutes: u8, days: u8) -> CommandFrame {
+    [
+        0x7e, 0x00, 0x82, hours, minutes, 0x00, off as u8, days, 0xef,
+    ]
+}
+
+/// Frame setting the device clock; `day_of_week` is 1 for Monday to 7, see
+/// [`device_day_of_week`](crate::device_day_of_week)
+pub const fn time_frame(hour: u8, minute: u8, second: u8, day_of_week: u8) -> CommandFrame {
+    [
+        0x7e,
+        0x00,
+        0x83,
+        hour,
+        minute,
+        second,
+        day_of_week,
+        0x00,
+        0xef,
+    ]
+}
+
+/// Frame for an arbitrary command and sub command with three arguments
+pub const fn generic_frame(id: u8, sub_id: u8, arg1: u8, arg2: u8, arg3: u8) -> CommandFrame {
+    [0x7e, 0x00, id, sub_id, arg1, arg2, arg3, 0x00, 0xef]
+}
+
+/// Names what a command frame does, e.g. `color` or `brightness`
+///
+/// Returns `unknown` for frames the library doesn't send itself.
+pub fn describe_frame(frame: &[u8]) -> &'static str {
+    match frame {
+        [FRAME_HEADER, _, 0x04, 0x00, ..] => "power off",
+        [FRAME_HEADER, _, 0x04, ..] => "power on",
+        [FRAME_HEADER, _, 0x05, 0x01, ..] => "exit effect",
+        [FRAME_HEADER, _, 0x05, 0x02, ..] => "color temperature",
+        [FRAME_HEADER, _, 0x05, 0x03, ..] => "color",
+        [FRAME_HEADER, _, 0x01, ..] => "brightness",
+        [FRAME_HEADER, _, 0x03, ..] => "effect",
+        [FRAME_HEADER, _, 0x02, ..] => "effect speed",
+        [FRAME_HEADER, _, 0x82, _, _, _, 0x00, ..] => "schedule on",
+        [FRAME_HEADER, _, 0x82, ..] => "schedule off",
+        [FRAME_HEADER, _, 0x83, ..] => "time",
+        _ => "unknown",
+    }
+}
+
+/// Decodes a command frame into the change it makes, e.g. a frame the phone
+/// app or the IR remote sent that the device pushed as a notification
+///
+/// Values are the bytes on the wire: the brightness after any curve and the
+/// effect speed before any inversion. Returns `None` for other frames, which
+/// are best shown as hex.
+pub fn frame_event(frame: &[u8]) -> Option<DeviceEvent> {
+    let event = match *frame {
+        [FRAME_HEADER, _, 0x04, 0x00, .., FRAME_TERMINATOR] => DeviceEvent::PoweredOff,
+        [FRAME_HEADER, _, 0x04, _, .., FRAME_TERMINATOR] => DeviceEvent::PoweredOn,
+        [FRAME_HEADER, _, 0x05, 0x03, red, green, blue, .., FRAME_TERMINATOR] => {
+            DeviceEvent::ColorSet((red, green, blue))
+        }
+        [FRAME_HEADER, _, 0x01, level, .., FRAME_TERMINATOR] => DeviceEvent::BrightnessSet(level),
+        [FRAME_HEADER, _, 0x03, code, .., FRAME_TERMINATOR] => DeviceEvent::EffectSet(code),
+        [FRAME_HEADER, _, 0x02, speed, .., FRAME_TERMINATOR] => DeviceEvent::EffectSpeedSet(speed),
+        _ => return None,
+    };
+    Some(event)
+}
+
+/// Formats a frame as space-separated hex bytes, e.g. `7e 00 05 03 ff 00 00 00 ef`
+pub fn format_frame(frame: &[u8]) -> String {
+    frame
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Parses hex bytes such as `7e00050300ff0000ef`, `7e 00 05 03 ...` or
+/// `0x7e,0x00,0x05,...`
+///
+/// Bytes may be separated by spaces or commas and prefixed with `0x`; a
+/// token of more than two digits is read as several bytes.
+pub fn parse_hex_frame(text: &str) -> Result<Vec<u8>> {
+    let invalid = |reason: &str| Error::InvalidFrame(format!("'{}': {}", text.trim(), reason));
+
+    let mut bytes = Vec::new();
+    for token in text
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|token| !token.is_empty())
+    {
+        let digits = token
+            .strip_prefix("0x")
+            .or_else(|| token.strip_prefix("0X"))
+            .unwrap_or(token);
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid("expected hex bytes"));
+        }
+        if digits.len() <= 2 {
+            bytes.push(u8::from_str_radix(digits, 16).map_err(|_| invalid("expected hex bytes"))?);
+        } else if digits.len() % 2 == 0 {
+            for pair in digits.as_bytes().chunks(2) {
+                let pair = std::str::from_utf8(pair).map_err(|_| invalid("expected hex bytes"))?;
+                bytes
+                    .push(u8::from_str_radix(pair, 16).map_err(|_| invalid("expected hex bytes"))?);
+            }
+        } else {
+            return Err(invalid("odd number of hex digits"));
+        }
+    }
+
+    if bytes.is_empty() {
+        return Err(invalid("no bytes given"));
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_frame_reads_command_and_payload() {
+        let frame = parse_frame(&color_frame(0xff, 0x80, 0x00)).unwrap();
+        assert_eq!(frame.command, 0x05);
+        assert_eq!(frame.payload, [0x03, 0xff, 0x80, 0x00, 0x00]);
+
+        // The shortest frame has an empty payload
+        let frame = parse_frame(&[0x7e, 0x00, 0x01, 0xef]).unwrap();
+        assert_eq!(frame.command, 0x01);
+        assert!(frame.payload.is_empty());
+    }
+
+    #[test]
+    fn parse_frame_rejects_truncated_frames() {
+        let full = color_frame(0xff, 0x80, 0x00);
+        for len in 0..MIN_FRAME_LEN {
+            assert!(
+                matches!(parse_frame(&full[..len]), Err(Error::InvalidFrame(_))),
+                "{} bytes",
+                len
+            );
+        }
+        // Cut before the terminator
+        let err = parse_frame(&full[..COMMAND_LEN - 1]).unwrap_err();
+        assert!(err.to_string().contains("bad terminator"), "{}", err);
+    }
+
+    #[test]
+    fn parse_frame_rejects_corrupted_frames() {
+        let mut bad_header = color_frame(1, 2, 3);
+        bad_header[0] = 0x7f;
+        let err = parse_frame(&bad_header).unwrap_err();
+        assert!(err.to_string().contains("bad header byte 0x7f"), "{}", err);
+
+        let mut bad_terminator = color_frame(1, 2, 3);
+        bad_terminator[COMMAND_LEN - 1] = 0xee;
+        let err = parse_frame(&bad_terminator).unwrap_err();
+        assert!(
+            err.to_string().contains("bad terminator byte 0xee"),
+            "{}",
+            err
+        );
+    }
+}
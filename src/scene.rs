@@ -0,0 +1,174 @@
+/*!
+ # Scenes
+
+ Named snapshots of a device's look (power, color or white temperature,
+ effect, brightness) that can be applied again later. A [`SceneStore`] keeps
+ them in a small text file, one scene per line as the name followed by the
+ [`DeviceState::to_json`] of the scene.
+*/
+
+use std::path::{Path, PathBuf};
+use tracing::{info, instrument};
+
+use crate::{BleLedDevice, DeviceState, Error, Result};
+
+/// Named scenes read from and saved to one file
+#[derive(Debug, Clone, Default)]
+pub struct SceneStore {
+    /// File the scenes are read from and saved to
+    path: PathBuf,
+    /// Scenes in file order
+    scenes: Vec<(String, DeviceState)>,
+}
+
+impl SceneStore {
+    /// Loads the scenes at `path`; a missing file gives an empty store
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut scenes = Vec::new();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, json) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| Error::InvalidState(line.to_string()))?;
+            scenes.push((name.to_string(), DeviceState::from_json(json)?));
+        }
+        Ok(Self { path, scenes })
+    }
+
+    /// Writes the scenes back to the file, through a temporary file
+    pub fn save(&self) -> Result<()> {
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut text = String::new();
+        for (name, state) in &self.scenes {
+            text.push_str(&format!("{} {}\n", name, state.to_json()));
+        }
+        let mut tmp = self.path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        std::fs::write(&tmp, text)?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    /// File the store reads and writes
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The scene called `name`
+    pub fn get(&self, name: &str) -> Option<&DeviceState> {
+        self.scenes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, state)| state)
+    }
+
+    /// Adds a scene, replacing one with the same name
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidSceneName` for an empty name or one containing
+    /// whitespace.
+    pub fn insert(&mut self, name: &str, state: DeviceState) -> Result<()> {
+        if name.is_empty() || name.contains(char::is_whitespace) || name.starts_with('#') {
+            return Err(Error::InvalidSceneName(name.to_string()));
+        }
+        match self.scenes.iter_mut().find(|(n, _)| n == name) {
+            Some((_, existing)) => *existing = state,
+            None => self.scenes.push((name.to_string(), state)),
+        }
+        Ok(())
+    }
+
+    /// Removes the scene called `name`, returning whether there was one
+    pub fn remove(&mut self, name: &str) -> bool {
+        let before = self.scenes.len();
+        self.scenes.retain(|(n, _)| n != name);
+        self.scenes.len() != before
+    }
+
+    /// Scenes with their names, in file order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &DeviceState)> {
+        self.scenes
+            .iter()
+            .map(|(name, state)| (name.as_str(), state))
+    }
+}
+
+impl BleLedDevice {
+    /// Applies a scene saved from [`BleLedDevice::snapshot`]
+    ///
+    /// Sets the effect (with its speed), else the white temperature, else
+    /// the color, then the brightness. A scene that is off only powers the
+    /// device off.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::UnsupportedEffect` before sending anything when the
+    /// scene's effect isn't implemented by this device, so a scene is never
+    /// half-applied for that reason.
+    #[instrument(skip(self))]
+    pub async fn apply_scene(&self, scene: &DeviceState) -> Result<()> {
+        self.check_scene_effect(scene)?;
+        if !scene.is_on {
+            return self.power_off().await;
+        }
+        if !self.is_on() {
+            self.power_on().await?;
+        }
+        self.apply_scene_without_power(scene).await
+    }
+
+    /// Applies the look of a scene like [`BleLedDevice::apply_scene`], but
+    /// leaves the power as it is
+    ///
+    /// For callers that don't manage the power, e.g. `elkc --no-power-on`,
+    /// where the scene's power state only reflects what was last sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::UnsupportedEffect` before sending anything, like
+    /// [`BleLedDevice::apply_scene`].
+    #[instrument(skip(self))]
+    pub async fn apply_scene_without_power(&self, scene: &DeviceState) -> Result<()> {
+        self.check_scene_effect(scene)?;
+        match (scene.effect, scene.color_temp_kelvin) {
+            (Some(effect), _) => {
+                self.set_effect(effect).await?;
+                if let Some(speed) = scene.effect_speed {
+                    self.set_effect_speed(speed).await?;
+                }
+            }
+            (None, Some(kelvin)) => self.set_color_temp_kelvin(kelvin).await?,
+            (None, None) => {
+                let (red, green, blue) = scene.rgb_color;
+                self.set_color(red, green, blue).await?;
+            }
+        }
+        self.set_brightness(scene.brightness).await?;
+
+        info!("Scene applied");
+        Ok(())
+    }
+
+    /// Fails when the scene's effect isn't implemented by this device
+    fn check_scene_effect(&self, scene: &DeviceState) -> Result<()> {
+        match scene.effect {
+            Some(effect) if !self.supports_effect(effect) => Err(Error::UnsupportedEffect {
+                effect,
+                device_type: self.device_type(),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
@@ -1,13 +1,384 @@
-/*! 
+/*!
  # Scheduling functionality for LED strips
- 
+
  This module provides scheduling capabilities for the LED strips,
  allowing them to be turned on or off at specific days and times.
 */
 
+use chrono::{DateTime, Local, NaiveTime};
+use std::fmt;
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, Not, Sub};
+use std::str::FromStr;
+
+use crate::scheduler::{next_occurrence, ScheduleTime};
+use crate::{Error, Result};
+
+/// Set of days of the week for scheduling
+///
+/// Combine days with `|`, e.g. `Days::MONDAY | Days::THURSDAY`. On the wire
+/// each day is one bit of [`Days::bits`], Monday being the lowest.
+///
+/// The clock and the schedules number days the same way, starting on Monday:
+///
+/// | Day | chrono | Clock byte ([`device_day_of_week`]) | Schedule bit |
+/// |-----|--------|-------------------------------------|--------------|
+/// | Monday | `Weekday::Mon` | 1 | `0x01` |
+/// | Tuesday | `Weekday::Tue` | 2 | `0x02` |
+/// | ... | | | |
+/// | Sunday | `Weekday::Sun` | 7 | `0x40` |
+///
+/// Use [`Days::from_weekday`] and [`device_day_of_week`] rather than
+/// converting by hand, so a schedule for "today" fires today.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Days(u8);
+
+/// The day-of-week byte of the clock frame for `weekday`: 1 for Monday to 7
+/// for Sunday, matching the bit order of [`Days`]
+///
+/// ```
+/// use elk_led_controller::device_day_of_week;
+/// use chrono::Weekday;
+///
+/// assert_eq!(device_day_of_week(Weekday::Mon), 1);
+/// assert_eq!(device_day_of_week(Weekday::Sun), 7);
+/// ```
+pub fn device_day_of_week(weekday: chrono::Weekday) -> u8 {
+    weekday.number_from_monday() as u8
+}
+
+/// Short names and days, in week order
+const DAY_NAMES: [(&str, Days); 7] = [
+    ("Mon", Days::MONDAY),
+    ("Tue", Days::TUESDAY),
+    ("Wed", Days::WEDNESDAY),
+    ("Thu", Days::THURSDAY),
+    ("Fri", Days::FRIDAY),
+    ("Sat", Days::SATURDAY),
+    ("Sun", Days::SUNDAY),
+];
+
+impl Days {
+    /// Monday (0x01)
+    pub const MONDAY: Days = Days(0x01);
+    /// Tuesday (0x02)
+    pub const TUESDAY: Days = Days(0x02);
+    /// Wednesday (0x04)
+    pub const WEDNESDAY: Days = Days(0x04);
+    /// Thursday (0x08)
+    pub const THURSDAY: Days = Days(0x08);
+    /// Friday (0x10)
+    pub const FRIDAY: Days = Days(0x10);
+    /// Saturday (0x20)
+    pub const SATURDAY: Days = Days(0x20);
+    /// Sunday (0x40)
+    pub const SUNDAY: Days = Days(0x40);
+    /// No days (0x00)
+    pub const NONE: Days = Days(0x00);
+    /// All days (0x7F)
+    pub const ALL: Days = Days(0x7f);
+    /// Monday to Friday (0x1F)
+    pub const WEEKDAYS: Days = Days(0x1f);
+    /// Saturday and Sunday (0x60)
+    pub const WEEKEND: Days = Days(0x60);
+
+    /// Get the bitmask sent to the device
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Create from a bitmask, or `None` if bits outside [`Days::ALL`] are set
+    pub const fn from_bits(bits: u8) -> Option<Days> {
+        if bits & !Self::ALL.0 == 0 {
+            Some(Days(bits))
+        } else {
+            None
+        }
+    }
+
+    /// Create from a bitmask, dropping bits outside [`Days::ALL`]
+    pub const fn from_bits_truncate(bits: u8) -> Days {
+        Days(bits & Self::ALL.0)
+    }
+
+    /// Whether no day is set
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether every day in `other` is also set here
+    pub const fn contains(self, other: Days) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Days set in either
+    pub const fn union(self, other: Days) -> Days {
+        Days(self.0 | other.0)
+    }
+
+    /// Days set in both
+    pub const fn intersection(self, other: Days) -> Days {
+        Days(self.0 & other.0)
+    }
+
+    /// Days set here but not in `other`
+    pub const fn difference(self, other: Days) -> Days {
+        Days(self.0 & !other.0)
+    }
+
+    /// Add the days in `other`
+    pub fn insert(&mut self, other: Days) {
+        self.0 |= other.0;
+    }
+
+    /// Remove the days in `other`
+    pub fn remove(&mut self, other: Days) {
+        self.0 &= !other.0;
+    }
+
+    /// The single day for a chrono weekday
+    pub fn from_weekday(weekday: chrono::Weekday) -> Days {
+        Days(1 << weekday.num_days_from_monday())
+    }
+
+    /// The single day for the clock's day-of-week byte (1 for Monday to 7
+    /// for Sunday), or `None` outside that range
+    ///
+    /// ```
+    /// use elk_led_controller::{device_day_of_week, Days};
+    /// use chrono::Weekday;
+    ///
+    /// assert_eq!(Days::from_device_day(7), Some(Days::SUNDAY));
+    /// assert_eq!(Days::from_device_day(0), None);
+    /// for weekday in [Weekday::Mon, Weekday::Wed, Weekday::Sat, Weekday::Sun] {
+    ///     assert_eq!(Days::from_device_day(device_day_of_week(weekday)), Some(Days::from_weekday(weekday)));
+    /// }
+    /// ```
+    pub const fn from_device_day(day_of_week: u8) -> Option<Days> {
+        match day_of_week {
+            1..=7 => Some(Days(1 << (day_of_week - 1))),
+            _ => None,
+        }
+    }
+
+    /// Iterate over the individual days that are set, Monday first
+    pub fn iter(self) -> impl Iterator<Item = Days> {
+        DAY_NAMES
+            .into_iter()
+            .map(|(_, day)| day)
+            .filter(move |day| self.contains(*day))
+    }
+}
+
+impl BitOr for Days {
+    type Output = Days;
+
+    fn bitor(self, rhs: Days) -> Days {
+        self.union(rhs)
+    }
+}
+
+impl BitOrAssign for Days {
+    fn bitor_assign(&mut self, rhs: Days) {
+        self.insert(rhs);
+    }
+}
+
+impl BitAnd for Days {
+    type Output = Days;
+
+    fn bitand(self, rhs: Days) -> Days {
+        self.intersection(rhs)
+    }
+}
+
+impl BitAndAssign for Days {
+    fn bitand_assign(&mut self, rhs: Days) {
+        *self = self.intersection(rhs);
+    }
+}
+
+impl Sub for Days {
+    type Output = Days;
+
+    fn sub(self, rhs: Days) -> Days {
+        self.difference(rhs)
+    }
+}
+
+impl Not for Days {
+    type Output = Days;
+
+    fn not(self) -> Days {
+        Days::ALL.difference(self)
+    }
+}
+
+impl From<Days> for u8 {
+    fn from(days: Days) -> Self {
+        days.bits()
+    }
+}
+
+/// Formats as short day names, e.g. `Mon,Tue,Fri`, or `None` when empty
+impl fmt::Display for Days {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "None");
+        }
+
+        let names: Vec<&str> = DAY_NAMES
+            .iter()
+            .filter(|(_, day)| self.contains(*day))
+            .map(|(name, _)| *name)
+            .collect();
+        write!(f, "{}", names.join(","))
+    }
+}
+
+/// Describes a day mask for people, e.g. `Mon, Wed, Fri`
+///
+/// The common sets read as `Every day`, `Weekdays` and `Weekend`, and an
+/// empty mask as `No days`. Bits outside [`Days::ALL`], such as the enabled
+/// flag (0x80) of a schedule frame, are ignored.
+///
+/// ```
+/// use elk_led_controller::days_to_string;
+///
+/// assert_eq!(days_to_string(0x15), "Mon, Wed, Fri");
+/// assert_eq!(days_to_string(0x7f), "Every day");
+/// assert_eq!(days_to_string(0x1f), "Weekdays");
+/// assert_eq!(days_to_string(0x60), "Weekend");
+/// assert_eq!(days_to_string(0x00), "No days");
+/// assert_eq!(days_to_string(0x80 | 0x41), "Mon, Sun");
+/// ```
+pub fn days_to_string(mask: u8) -> String {
+    match Days::from_bits_truncate(mask) {
+        Days::NONE => "No days".to_string(),
+        Days::ALL => "Every day".to_string(),
+        Days::WEEKDAYS => "Weekdays".to_string(),
+        Days::WEEKEND => "Weekend".to_string(),
+        days => DAY_NAMES
+            .iter()
+            .filter(|(_, day)| days.contains(*day))
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(", "),
+    }
+}
+
+/// Parses a single day name such as `mon` or `Monday`
+fn parse_day(name: &str) -> Option<Days> {
+    let day = match name.trim().to_lowercase().as_str() {
+        "mon" | "monday" => Days::MONDAY,
+        "tue" | "tuesday" => Days::TUESDAY,
+        "wed" | "wednesday" => Days::WEDNESDAY,
+        "thu" | "thursday" => Days::THURSDAY,
+        "fri" | "friday" => Days::FRIDAY,
+        "sat" | "saturday" => Days::SATURDAY,
+        "sun" | "sunday" => Days::SUNDAY,
+        _ => return None,
+    };
+    Some(day)
+}
+
+/// Every day from `first` to `last`, wrapping past Sunday (`fri-mon`)
+fn day_range(first: Days, last: Days) -> Days {
+    let index = |day: Days| day.bits().trailing_zeros();
+    let (start, end) = (index(first), index(last));
+
+    let mut days = Days::NONE;
+    let mut i = start;
+    loop {
+        days |= Days(1 << i);
+        if i == end {
+            return days;
+        }
+        i = (i + 1) % 7;
+    }
+}
+
+/// Parses comma-separated day names and ranges, ignoring case
+///
+/// Accepts short (`mon`) and full (`monday`) names, ranges such as `mon-fri`
+/// (which may wrap around the weekend, e.g. `fri-mon`) and the aliases
+/// `all`, `weekdays`, `weekend` and `none`. Repeated days are allowed, so
+/// `mon-wed,tue` is the same as `mon-wed`.
+impl FromStr for Days {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut days = Days::NONE;
+        for part in s.split(',') {
+            let token = part.trim();
+            let invalid = || Error::InvalidDays(token.to_string());
+
+            let day = match token.to_lowercase().as_str() {
+                "all" => Days::ALL,
+                "weekdays" => Days::WEEKDAYS,
+                "weekend" => Days::WEEKEND,
+                "none" => Days::NONE,
+                _ => match token.split_once('-') {
+                    Some((first, last)) => day_range(
+                        parse_day(first).ok_or_else(invalid)?,
+                        parse_day(last).ok_or_else(invalid)?,
+                    ),
+                    None => parse_day(token).ok_or_else(invalid)?,
+                },
+            };
+            days |= day;
+        }
+        Ok(days)
+    }
+}
+
+/// A weekly schedule: a local time of day on a set of days
+///
+/// This is what the device's on/off schedules store; displays as e.g.
+/// `weekdays at 08:30`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Schedule {
+    /// Days the schedule fires on
+    pub days: Days,
+    /// Local time of day it fires at
+    pub time: NaiveTime,
+}
+
+impl Schedule {
+    /// Creates a schedule firing at `time` on `days`
+    pub const fn new(days: Days, time: NaiveTime) -> Self {
+        Self { days, time }
+    }
+
+    /// Next time the schedule fires strictly after `after`
+    ///
+    /// Wraps around to the following week, so a schedule for today's weekday
+    /// whose time has passed fires in seven days. Returns `None` when no day is
+    /// set. Times skipped by a DST change fire right after the gap, and times
+    /// repeated by one fire on their first occurrence.
+    pub fn next_occurrence(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        next_occurrence(self.days, ScheduleTime::At(self.time), None, after)
+    }
+}
+
+/// Formats as e.g. `every day at 07:00`, `weekdays at 08:30` or
+/// `Mon,Thu at 19:15`, and `never` when no day is set
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let days = match self.days {
+            Days::NONE => return write!(f, "never"),
+            Days::ALL => "every day".to_string(),
+            Days::WEEKDAYS => "weekdays".to_string(),
+            Days::WEEKEND => "weekends".to_string(),
+            days => days.to_string(),
+        };
+        write!(f, "{} at {}", days, self.time.format("%H:%M"))
+    }
+}
+
 /// Represents days of the week for scheduling
+#[deprecated(note = "use the `Days` bitflags type instead")]
 #[derive(Debug, Clone, Copy)]
-pub struct Days {
+pub struct WeekDays {
     /// Monday (0x01)
     pub monday: u8,
     /// Tuesday (0x02)
@@ -33,16 +404,226 @@ pub struct Days {
 }
 
 /// Predefined day constants for scheduling
-pub const WEEK_DAYS: Days = Days {
-    monday: 0x01,
-    tuesday: 0x02,
-    wednesday: 0x04,
-    thursday: 0x08,
-    friday: 0x10,
-    saturday: 0x20,
-    sunday: 0x40,
-    all: 0x01 + 0x02 + 0x04 + 0x08 + 0x10 + 0x20 + 0x40,
-    week_days: 0x01 + 0x02 + 0x04 + 0x08 + 0x10,
-    weekend_days: 0x20 + 0x40,
-    none: 0x00,
-};
\ No newline at end of file
+#[deprecated(note = "use the `Days` constants instead")]
+#[allow(deprecated)]
+pub const WEEK_DAYS: WeekDays = WeekDays {
+    monday: Days::MONDAY.bits(),
+    tuesday: Days::TUESDAY.bits(),
+    wednesday: Days::WEDNESDAY.bits(),
+    thursday: Days::THURSDAY.bits(),
+    friday: Days::FRIDAY.bits(),
+    saturday: Days::SATURDAY.bits(),
+    sunday: Days::SUNDAY.bits(),
+    all: Days::ALL.bits(),
+    week_days: Days::WEEKDAYS.bits(),
+    weekend_days: Days::WEEKEND.bits(),
+    none: Days::NONE.bits(),
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Datelike, NaiveDate, TimeZone};
+
+    /// Local time on a day of June 2025; the 16th is a Monday
+    fn local(day: u32, hour: u32, minute: u32, second: u32) -> DateTime<Local> {
+        let naive = NaiveDate::from_ymd_opt(2025, 6, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, second)
+            .unwrap();
+        Local.from_local_datetime(&naive).unwrap()
+    }
+
+    fn schedule(days: Days, hour: u32, minute: u32) -> Schedule {
+        Schedule::new(days, NaiveTime::from_hms_opt(hour, minute, 0).unwrap())
+    }
+
+    #[test]
+    fn next_occurrence_at_the_scheduled_minute_is_next_week() {
+        let monday = schedule(Days::MONDAY, 8, 30);
+        // Exactly at the time it fires: strictly after, so a week later
+        assert_eq!(
+            monday.next_occurrence(local(16, 8, 30, 0)),
+            Some(local(23, 8, 30, 0))
+        );
+        // A second before, it is still today
+        assert_eq!(
+            monday.next_occurrence(local(16, 8, 29, 59)),
+            Some(local(16, 8, 30, 0))
+        );
+        assert_eq!(
+            schedule(Days::ALL, 8, 30).next_occurrence(local(16, 8, 30, 0)),
+            Some(local(17, 8, 30, 0))
+        );
+    }
+
+    #[test]
+    fn next_occurrence_around_midnight() {
+        let midnight = schedule(Days::TUESDAY, 0, 0);
+        // Monday 23:59 -> Tuesday 00:00, a minute later
+        assert_eq!(
+            midnight.next_occurrence(local(16, 23, 59, 0)),
+            Some(local(17, 0, 0, 0))
+        );
+        // Tuesday 00:00 itself -> next Tuesday
+        assert_eq!(
+            midnight.next_occurrence(local(17, 0, 0, 0)),
+            Some(local(24, 0, 0, 0))
+        );
+
+        let late = schedule(Days::MONDAY, 23, 59);
+        // Just after Monday ends, the next one is almost a week away
+        assert_eq!(
+            late.next_occurrence(local(17, 0, 0, 1)),
+            Some(local(23, 23, 59, 0))
+        );
+    }
+
+    #[test]
+    fn next_occurrence_wraps_around_the_week() {
+        // Saturday afternoon -> the weekday schedule fires on Monday
+        assert_eq!(
+            schedule(Days::WEEKDAYS, 7, 0).next_occurrence(local(21, 15, 0, 0)),
+            Some(local(23, 7, 0, 0))
+        );
+        assert_eq!(
+            schedule(Days::NONE, 7, 0).next_occurrence(local(21, 15, 0, 0)),
+            None
+        );
+    }
+
+    #[test]
+    fn schedules_display_their_days_and_time() {
+        assert_eq!(schedule(Days::ALL, 7, 0).to_string(), "every day at 07:00");
+        assert_eq!(
+            schedule(Days::WEEKDAYS, 8, 30).to_string(),
+            "weekdays at 08:30"
+        );
+        assert_eq!(schedule(Days::NONE, 8, 30).to_string(), "never");
+    }
+
+    #[test]
+    fn days_reject_typos() {
+        for typo in ["mondy", "mon,tuesdy", "mon-fir", "", "mon,,fri", "mon-"] {
+            assert!(
+                matches!(typo.parse::<Days>(), Err(Error::InvalidDays(_))),
+                "{:?}",
+                typo
+            );
+        }
+        match "mon,mondy".parse::<Days>() {
+            Err(Error::InvalidDays(token)) => assert_eq!(token, "mondy"),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn days_ignore_case() {
+        assert_eq!(
+            "MON, Wednesday,fRi".parse::<Days>().unwrap(),
+            Days::MONDAY | Days::WEDNESDAY | Days::FRIDAY
+        );
+        assert_eq!("WeekDays".parse::<Days>().unwrap(), Days::WEEKDAYS);
+        assert_eq!("Sat-SUN".parse::<Days>().unwrap(), Days::WEEKEND);
+    }
+
+    #[test]
+    fn days_allow_duplicates() {
+        assert_eq!("mon,mon,monday".parse::<Days>().unwrap(), Days::MONDAY);
+        assert_eq!(
+            "mon-wed,tue".parse::<Days>().unwrap(),
+            "mon-wed".parse::<Days>().unwrap()
+        );
+        assert_eq!("weekdays,weekend".parse::<Days>().unwrap(), Days::ALL);
+    }
+
+    #[test]
+    fn days_ranges_wrap_around_the_weekend() {
+        assert_eq!("mon-fri".parse::<Days>().unwrap(), Days::WEEKDAYS);
+        assert_eq!(
+            "fri-mon".parse::<Days>().unwrap(),
+            Days::FRIDAY | Days::WEEKEND | Days::MONDAY
+        );
+        assert_eq!(
+            "sun-tue".parse::<Days>().unwrap(),
+            Days::SUNDAY | Days::MONDAY | Days::TUESDAY
+        );
+        // A one-day range is just that day
+        assert_eq!("thu-thu".parse::<Days>().unwrap(), Days::THURSDAY);
+    }
+
+    #[test]
+    fn days_aliases() {
+        assert_eq!("all".parse::<Days>().unwrap(), Days::ALL);
+        assert_eq!("weekend".parse::<Days>().unwrap(), Days::WEEKEND);
+        assert_eq!("none".parse::<Days>().unwrap(), Days::NONE);
+    }
+
+    #[test]
+    fn days_to_string_names_the_common_sets() {
+        assert_eq!(days_to_string(0x00), "No days");
+        assert_eq!(days_to_string(0x7f), "Every day");
+        assert_eq!(days_to_string(0x1f), "Weekdays");
+        assert_eq!(days_to_string(0x60), "Weekend");
+    }
+
+    #[test]
+    fn days_to_string_lists_other_masks_in_week_order() {
+        assert_eq!(days_to_string(0x15), "Mon, Wed, Fri");
+        assert_eq!(days_to_string(0x41), "Mon, Sun");
+        assert_eq!(days_to_string(0x20), "Sat");
+        // One day short of a named set is listed day by day
+        assert_eq!(days_to_string(0x0f), "Mon, Tue, Wed, Thu");
+        assert_eq!(days_to_string(0x7e), "Tue, Wed, Thu, Fri, Sat, Sun");
+    }
+
+    #[test]
+    fn days_to_string_ignores_the_enabled_bit() {
+        assert_eq!(days_to_string(0x80), "No days");
+        assert_eq!(days_to_string(0xff), "Every day");
+        assert_eq!(days_to_string(0x80 | 0x1f), "Weekdays");
+        assert_eq!(days_to_string(0x80 | 0x41), "Mon, Sun");
+    }
+
+    #[test]
+    fn weekday_mapping_covers_the_whole_week() {
+        use chrono::Weekday;
+
+        let week = [
+            (Weekday::Mon, 1, Days::MONDAY),
+            (Weekday::Tue, 2, Days::TUESDAY),
+            (Weekday::Wed, 3, Days::WEDNESDAY),
+            (Weekday::Thu, 4, Days::THURSDAY),
+            (Weekday::Fri, 5, Days::FRIDAY),
+            (Weekday::Sat, 6, Days::SATURDAY),
+            (Weekday::Sun, 7, Days::SUNDAY),
+        ];
+        for (weekday, byte, day) in week {
+            assert_eq!(device_day_of_week(weekday), byte, "{}", weekday);
+            assert_eq!(Days::from_weekday(weekday), day, "{}", weekday);
+            assert_eq!(Days::from_device_day(byte), Some(day), "{}", weekday);
+        }
+        assert_eq!(Days::from_device_day(0), None);
+        assert_eq!(Days::from_device_day(8), None);
+    }
+
+    #[test]
+    fn clock_byte_and_schedule_bit_agree_on_today() {
+        // Monday the 16th to Sunday the 22nd
+        for day in 16..=22 {
+            let now = local(day, 6, 0, 0);
+            let clock_byte = device_day_of_week(now.weekday());
+            let today = Days::from_device_day(clock_byte).unwrap();
+            assert_eq!(today, Days::from_weekday(now.weekday()), "{}", now);
+
+            // A schedule for the day the clock reports fires later that day
+            assert_eq!(
+                schedule(today, 7, 30).next_occurrence(now),
+                Some(local(day, 7, 30, 0)),
+                "{}",
+                now
+            );
+        }
+        assert_eq!(device_day_of_week(local(22, 6, 0, 0).weekday()), 7);
+    }
+}
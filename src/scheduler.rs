@@ -0,0 +1,696 @@
+/*!
+ # Host-side scheduler
+
+ The firmware can only schedule powering on and off. This module runs
+ arbitrary actions against a shared device at local times on selected days,
+ e.g. "at 19:00 set warm white 30%". Times can also be relative to sunrise
+ or sunset, see [`ScheduleTime`].
+*/
+
+use chrono::{
+    DateTime, Datelike, Duration as ChronoDuration, Local, NaiveDate, NaiveDateTime, NaiveTime,
+    TimeZone, Timelike,
+};
+use futures::future::BoxFuture;
+use parking_lot::Mutex;
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tracing::{debug, error, info, instrument, warn};
+
+use crate::{BleLedDevice, CancelHandle, Days, Location, Result, SleepTimer};
+
+/// Action run by the scheduler against the shared device
+type Action<D> = Arc<dyn Fn(Arc<D>) -> BoxFuture<'static, Result<()>> + Send + Sync>;
+
+/// Run time and the named actions due at it
+type DueEntries<D> = (DateTime<Local>, Vec<(String, Action<D>)>);
+
+/// Source of the current local time
+type Clock = Arc<dyn Fn() -> DateTime<Local> + Send + Sync>;
+
+/// Local time at which [`PowerStrategy::Device`] reprograms the device schedule
+const REPROGRAM_TIME: NaiveTime = match NaiveTime::from_hms_opt(0, 1, 0) {
+    Some(time) => time,
+    None => panic!("invalid reprogram time"),
+};
+
+/// When on a given day an entry runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduleTime {
+    /// Fixed local time of day
+    At(NaiveTime),
+    /// Sunrise, shifted by `offset` (negative is before sunrise)
+    Sunrise {
+        /// Shift from sunrise
+        offset: ChronoDuration,
+    },
+    /// Sunset, shifted by `offset` (negative is before sunset)
+    Sunset {
+        /// Shift from sunset
+        offset: ChronoDuration,
+    },
+}
+
+impl ScheduleTime {
+    /// Sunrise without offset
+    pub const SUNRISE: ScheduleTime = ScheduleTime::Sunrise {
+        offset: ChronoDuration::zero(),
+    };
+    /// Sunset without offset
+    pub const SUNSET: ScheduleTime = ScheduleTime::Sunset {
+        offset: ChronoDuration::zero(),
+    };
+
+    /// Local wall-clock time on `date`
+    ///
+    /// Solar times need a `location` and return `None` without one, or when
+    /// the sun doesn't rise or set that day.
+    pub fn on_date(&self, date: NaiveDate, location: Option<Location>) -> Option<NaiveDateTime> {
+        let solar = |sunset: bool, offset: ChronoDuration| {
+            let (sunrise_utc, sunset_utc) = location?.sunrise_sunset(date)?;
+            let event = if sunset { sunset_utc } else { sunrise_utc };
+            Some(event.with_timezone(&Local).naive_local() + offset)
+        };
+
+        match *self {
+            ScheduleTime::At(time) => Some(date.and_time(time)),
+            ScheduleTime::Sunrise { offset } => solar(false, offset),
+            ScheduleTime::Sunset { offset } => solar(true, offset),
+        }
+    }
+
+    /// Whether the time depends on the position of the sun
+    pub fn is_solar(&self) -> bool {
+        !matches!(self, ScheduleTime::At(_))
+    }
+}
+
+impl From<NaiveTime> for ScheduleTime {
+    fn from(time: NaiveTime) -> Self {
+        ScheduleTime::At(time)
+    }
+}
+
+/// Formats as `19:00`, `sunset` or `sunrise-30m`
+impl fmt::Display for ScheduleTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, offset) = match *self {
+            ScheduleTime::At(time) => return write!(f, "{}", time.format("%H:%M")),
+            ScheduleTime::Sunrise { offset } => ("sunrise", offset),
+            ScheduleTime::Sunset { offset } => ("sunset", offset),
+        };
+
+        write!(f, "{}", name)?;
+        if !offset.is_zero() {
+            let sign = if offset < ChronoDuration::zero() {
+                '-'
+            } else {
+                '+'
+            };
+            write!(f, "{}{}m", sign, offset.num_minutes().abs())?;
+        }
+        Ok(())
+    }
+}
+
+/// How [`LocalScheduler::add_power`] switches the device on or off
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PowerStrategy {
+    /// The scheduler sends power on/off itself; the host must be running
+    #[default]
+    Host,
+    /// The scheduler reprograms the device's own schedule every day shortly
+    /// after midnight, so the device switches even if the host is asleep
+    Device,
+}
+
+/// Identifier of a scheduled entry, used to remove it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EntryId(u64);
+
+impl fmt::Display for EntryId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// Description of a scheduled entry, as returned by [`LocalScheduler::list`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntryInfo {
+    /// Identifier of the entry
+    pub id: EntryId,
+    /// Name given when the entry was added
+    pub name: String,
+    /// Days the entry runs on
+    pub days: Days,
+    /// Time of day the entry runs at
+    pub time: ScheduleTime,
+    /// Next time the entry will run, if it can run at all
+    pub next_run: Option<DateTime<Local>>,
+}
+
+/// A scheduled action
+struct Entry<D> {
+    /// Identifier of the entry
+    id: EntryId,
+    /// Human readable name used in logs
+    name: String,
+    /// Days the entry runs on
+    days: Days,
+    /// Time of day the entry runs at
+    time: ScheduleTime,
+    /// Whether the action also runs once when the scheduler starts
+    run_on_start: bool,
+    /// What to do when the entry fires
+    action: Action<D>,
+}
+
+/// Settings for [`LocalScheduler`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SchedulerOptions {
+    /// How many times a failing action is retried, e.g. while the device reconnects
+    pub retries: u32,
+    /// Wait between retries
+    pub retry_delay: Duration,
+    /// Where to compute sunrise and sunset for [`ScheduleTime::Sunrise`] and
+    /// [`ScheduleTime::Sunset`]; solar entries never run without it
+    pub location: Option<Location>,
+}
+
+impl Default for SchedulerOptions {
+    fn default() -> Self {
+        Self {
+            retries: 3,
+            retry_delay: Duration::from_secs(10),
+            location: None,
+        }
+    }
+}
+
+/// State shared by all clones of a [`LocalScheduler`]
+struct SchedulerInner<D> {
+    /// Device the actions run against
+    device: Arc<D>,
+    /// Scheduled entries
+    entries: Mutex<Vec<Entry<D>>>,
+    /// Source of entry identifiers
+    next_id: AtomicU64,
+    /// Wakes the run loop when entries change
+    changed: Notify,
+    /// Retry and location settings
+    options: SchedulerOptions,
+    /// Current local time; `Local::now` except in tests
+    clock: Clock,
+}
+
+/// Runs actions on a device at local times, on tokio
+///
+/// Clones share the same entries, so entries can be added, removed and
+/// listed from other tasks while [`LocalScheduler::run`] is running.
+///
+/// Actions receive the device as an `Arc<D>`, a [`BleLedDevice`] unless
+/// another type is given; power entries and the sleep timer need a
+/// [`BleLedDevice`].
+///
+/// ```rust,no_run
+/// # use elk_led_controller::*;
+/// # use std::sync::Arc;
+/// # async fn example(device: Arc<BleLedDevice>) -> Result<()> {
+/// let scheduler = LocalScheduler::new(device);
+/// let evening = chrono::NaiveTime::from_hms_opt(19, 0, 0).unwrap();
+/// scheduler.add("warm evening", Days::ALL, evening, |device| async move {
+///     device.set_color_temp_kelvin(2700).await?;
+///     device.set_brightness(30).await
+/// });
+/// scheduler.run(CancelHandle::new()).await;
+/// # Ok(())
+/// # }
+/// ```
+pub struct LocalScheduler<D = BleLedDevice> {
+    /// Shared state
+    inner: Arc<SchedulerInner<D>>,
+}
+
+impl<D> Clone for LocalScheduler<D> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<D: Send + Sync + 'static> LocalScheduler<D> {
+    /// Creates a scheduler with the default retry settings
+    pub fn new(device: Arc<D>) -> Self {
+        Self::with_options(device, SchedulerOptions::default())
+    }
+
+    /// Creates a scheduler with custom retry or location settings
+    pub fn with_options(device: Arc<D>, options: SchedulerOptions) -> Self {
+        Self::with_clock(device, options, Arc::new(Local::now))
+    }
+
+    /// Creates a scheduler reading the time from `clock`
+    fn with_clock(device: Arc<D>, options: SchedulerOptions, clock: Clock) -> Self {
+        Self {
+            inner: Arc::new(SchedulerInner {
+                device,
+                entries: Mutex::new(Vec::new()),
+                next_id: AtomicU64::new(1),
+                changed: Notify::new(),
+                options,
+                clock,
+            }),
+        }
+    }
+
+    /// The current local time
+    fn now(&self) -> DateTime<Local> {
+        (self.inner.clock)()
+    }
+
+    /// Schedules `action` to run at `time` on `days`
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name used in logs and [`LocalScheduler::list`]
+    /// * `days` - Days the action runs on
+    /// * `time` - Local time of day (a `NaiveTime`), or relative to sunrise or sunset
+    /// * `action` - Async closure receiving the shared device
+    pub fn add<F, Fut>(
+        &self,
+        name: impl Into<String>,
+        days: Days,
+        time: impl Into<ScheduleTime>,
+        action: F,
+    ) -> EntryId
+    where
+        F: Fn(Arc<D>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        self.push_entry(name.into(), days, time.into(), false, action)
+    }
+
+    /// Adds an entry and wakes the run loop
+    fn push_entry<F, Fut>(
+        &self,
+        name: String,
+        days: Days,
+        time: ScheduleTime,
+        run_on_start: bool,
+        action: F,
+    ) -> EntryId
+    where
+        F: Fn(Arc<D>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let id = EntryId(self.inner.next_id.fetch_add(1, Ordering::Relaxed));
+        info!("Scheduling {} '{}' at {} on {}", id, name, time, days);
+        if time.is_solar() && self.inner.options.location.is_none() {
+            warn!(
+                "'{}' is relative to the sun but no location is set; it won't run",
+                name
+            );
+        }
+
+        let action: Action<D> = Arc::new(move |device| Box::pin(action(device)));
+        self.inner.entries.lock().push(Entry {
+            id,
+            name,
+            days,
+            time,
+            run_on_start,
+            action,
+        });
+        self.inner.changed.notify_one();
+        id
+    }
+
+    /// Removes an entry, returning whether it existed
+    pub fn remove(&self, id: EntryId) -> bool {
+        let removed = {
+            let mut entries = self.inner.entries.lock();
+            let before = entries.len();
+            entries.retain(|entry| entry.id != id);
+            entries.len() != before
+        };
+
+        if removed {
+            info!("Removed scheduled entry {}", id);
+            self.inner.changed.notify_one();
+        }
+        removed
+    }
+
+    /// Lists the scheduled entries in the order they were added
+    pub fn list(&self) -> Vec<EntryInfo> {
+        let now = self.now();
+        self.inner
+            .entries
+            .lock()
+            .iter()
+            .map(|entry| EntryInfo {
+                id: entry.id,
+                name: entry.name.clone(),
+                days: entry.days,
+                time: entry.time,
+                next_run: next_occurrence(entry.days, entry.time, self.inner.options.location, now),
+            })
+            .collect()
+    }
+
+    /// Runs due entries until `cancel` is triggered
+    ///
+    /// Each action runs in its own task, so a slow or retrying action does
+    /// not delay the others.
+    #[instrument(skip(self, cancel))]
+    pub async fn run(&self, cancel: CancelHandle) {
+        info!("Local scheduler started");
+        let startup: Vec<(String, Action<D>)> = self
+            .inner
+            .entries
+            .lock()
+            .iter()
+            .filter(|entry| entry.run_on_start)
+            .map(|entry| (entry.name.clone(), entry.action.clone()))
+            .collect();
+        for (name, action) in startup {
+            self.spawn_action(name, action);
+        }
+
+        let mut last_fired: Option<DateTime<Local>> = None;
+
+        while !cancel.is_cancelled() {
+            // Register for changes before looking at the entries so none is missed
+            let changed = self.inner.changed.notified();
+
+            let now = self.now();
+            let after = last_fired.map_or(now, |fired| fired.max(now));
+            let due = self.next_due(after);
+
+            let wait = match &due {
+                Some((when, _)) => (*when - now).to_std().unwrap_or(Duration::ZERO),
+                // Nothing scheduled, wait for an entry to be added
+                None => Duration::MAX,
+            };
+
+            tokio::select! {
+                _ = tokio::time::sleep(wait), if due.is_some() => {}
+                _ = changed => {
+                    debug!("Scheduled entries changed");
+                    continue;
+                }
+                _ = cancel.cancelled() => break,
+            }
+
+            if let Some((when, entries)) = due {
+                last_fired = Some(when);
+                for (name, action) in entries {
+                    self.spawn_action(name, action);
+                }
+            }
+        }
+
+        info!("Local scheduler stopped");
+    }
+
+    /// Finds the earliest run time after `after` and every entry due then
+    fn next_due(&self, after: DateTime<Local>) -> Option<DueEntries<D>> {
+        let entries = self.inner.entries.lock();
+        let mut due: Option<DueEntries<D>> = None;
+
+        for entry in entries.iter() {
+            let location = self.inner.options.location;
+            let Some(when) = next_occurrence(entry.days, entry.time, location, after) else {
+                continue;
+            };
+            let item = (entry.name.clone(), entry.action.clone());
+            match &mut due {
+                Some((earliest, items)) if when == *earliest => items.push(item),
+                Some((earliest, _)) if when > *earliest => {}
+                _ => due = Some((when, vec![item])),
+            }
+        }
+        due
+    }
+
+    /// Runs an action in its own task, retrying on failure
+    fn spawn_action(&self, name: String, action: Action<D>) {
+        let device = self.inner.device.clone();
+        let options = self.inner.options;
+
+        tokio::spawn(async move {
+            info!("Running scheduled action '{}'", name);
+            for attempt in 0..=options.retries {
+                match action(device.clone()).await {
+                    Ok(()) => return,
+                    Err(e) if attempt < options.retries => {
+                        warn!(
+                            "Scheduled action '{}' failed (attempt {}/{}): {}",
+                            name,
+                            attempt + 1,
+                            options.retries + 1,
+                            e
+                        );
+                        tokio::time::sleep(options.retry_delay).await;
+                    }
+                    Err(e) => error!("Scheduled action '{}' failed permanently: {}", name, e),
+                }
+            }
+        });
+    }
+}
+
+impl LocalScheduler {
+    /// Schedules powering the device on or off at `time` on `days`
+    ///
+    /// With [`PowerStrategy::Host`] this is a plain entry sending the power
+    /// command. With [`PowerStrategy::Device`] the scheduler writes the time
+    /// into the device's own on/off schedule when it starts and again every
+    /// day, which keeps sunrise and sunset times current.
+    pub fn add_power(
+        &self,
+        name: impl Into<String>,
+        days: Days,
+        time: impl Into<ScheduleTime>,
+        on: bool,
+        strategy: PowerStrategy,
+    ) -> EntryId {
+        let name = name.into();
+        let time = time.into();
+
+        match strategy {
+            PowerStrategy::Host => {
+                self.push_entry(name, days, time, false, move |device| async move {
+                    if on {
+                        device.power_on().await
+                    } else {
+                        device.power_off().await
+                    }
+                })
+            }
+            PowerStrategy::Device => {
+                let location = self.inner.options.location;
+                let clock = self.inner.clock.clone();
+                self.push_entry(
+                    name,
+                    Days::ALL,
+                    REPROGRAM_TIME.into(),
+                    true,
+                    move |device| {
+                        let today = clock().date_naive();
+                        async move {
+                            let Some(when) = time.on_date(today, location) else {
+                                warn!("No {} today, leaving the device schedule unchanged", time);
+                                return Ok(());
+                            };
+                            let (hour, minute) = (when.hour() as u8, when.minute() as u8);
+                            if on {
+                                device.set_schedule_on(days, hour, minute, true).await
+                            } else {
+                                device.set_schedule_off(days, hour, minute, true).await
+                            }
+                        }
+                    },
+                )
+            }
+        }
+    }
+
+    /// Sets the device's sleep timer and clears it from the device schedule
+    /// after it fired, see [`BleLedDevice::sleep_timer`]
+    ///
+    /// Unlike scheduled entries this doesn't need [`LocalScheduler::run`].
+    pub async fn sleep_timer(&self, duration: Duration) -> Result<SleepTimer> {
+        let device = self.inner.device.clone();
+        let timer = device.sleep_timer(duration).await?;
+
+        let wait = (timer.off_at() - Local::now()).to_std().unwrap_or_default();
+        tokio::spawn(async move {
+            // Leave the device a minute to act on the schedule
+            tokio::time::sleep(wait + Duration::from_secs(60)).await;
+            if let Err(e) = device.clear_sleep_timer(timer).await {
+                warn!("Failed to clear the expired sleep timer: {}", e);
+            }
+        });
+        Ok(timer)
+    }
+}
+
+/// Next local time strictly after `after` that falls on `days` at `time`
+///
+/// Times skipped by a DST change run at the first valid time after the gap,
+/// and times repeated by a DST change run on their first occurrence.
+pub(crate) fn next_occurrence(
+    days: Days,
+    time: ScheduleTime,
+    location: Option<Location>,
+    after: DateTime<Local>,
+) -> Option<DateTime<Local>> {
+    if days.is_empty() {
+        return None;
+    }
+
+    let today = after.date_naive();
+    // Eight days so today's time, if already past, comes round again next week
+    (0..=7)
+        .map(|offset| today + ChronoDuration::days(offset))
+        .filter(|date| days.contains(Days::from_weekday(date.weekday())))
+        .filter_map(|date| resolve_local(time.on_date(date, location)?))
+        .find(|when| *when > after)
+}
+
+/// Maps a wall-clock time to a local instant, handling DST gaps and overlaps
+fn resolve_local(naive: NaiveDateTime) -> Option<DateTime<Local>> {
+    Local.from_local_datetime(&naive).earliest().or_else(|| {
+        // The time doesn't exist on this day (clocks went forward); DST gaps
+        // are at most an hour, so the time an hour later is valid
+        Local
+            .from_local_datetime(&(naive + ChronoDuration::hours(1)))
+            .earliest()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+    use std::sync::atomic::AtomicU32;
+
+    /// Stands in for the device, counting the actions run against it
+    #[derive(Default)]
+    struct Runs(AtomicU32);
+
+    impl Runs {
+        fn count(&self) -> u32 {
+            self.0.load(Ordering::SeqCst)
+        }
+    }
+
+    fn local(day: u32, hour: u32, minute: u32, second: u32) -> DateTime<Local> {
+        let naive = NaiveDate::from_ymd_opt(2025, 6, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, second)
+            .unwrap();
+        Local.from_local_datetime(&naive).unwrap()
+    }
+
+    /// A scheduler whose clock starts at `start` and then follows tokio's
+    /// paused clock
+    fn scheduler(start: DateTime<Local>, options: SchedulerOptions) -> LocalScheduler<Runs> {
+        let origin = tokio::time::Instant::now();
+        let clock: Clock =
+            Arc::new(move || start + ChronoDuration::from_std(origin.elapsed()).unwrap());
+        LocalScheduler::with_clock(Arc::new(Runs::default()), options, clock)
+    }
+
+    fn counting(runs: Arc<Runs>) -> BoxFuture<'static, Result<()>> {
+        runs.0.fetch_add(1, Ordering::SeqCst);
+        Box::pin(async { Ok(()) })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn runs_entries_when_the_clock_reaches_them() {
+        // Monday 16 June 2025, 30 seconds before the entry
+        let scheduler = scheduler(local(16, 8, 29, 30), SchedulerOptions::default());
+        let time = NaiveTime::from_hms_opt(8, 30, 0).unwrap();
+        scheduler.add("daily", Days::ALL, time, counting);
+
+        let cancel = CancelHandle::new();
+        let task = tokio::spawn({
+            let scheduler = scheduler.clone();
+            let cancel = cancel.clone();
+            async move { scheduler.run(cancel).await }
+        });
+        let runs = scheduler.inner.device.clone();
+
+        tokio::time::sleep(Duration::from_secs(29)).await;
+        assert_eq!(runs.count(), 0);
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert_eq!(runs.count(), 1);
+
+        // Once a day, not again within the same minute
+        tokio::time::sleep(Duration::from_secs(23 * 3600)).await;
+        assert_eq!(runs.count(), 1);
+        tokio::time::sleep(Duration::from_secs(3600)).await;
+        assert_eq!(runs.count(), 2);
+
+        cancel.cancel();
+        task.await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn list_reports_the_next_run_from_the_clock() {
+        // Friday 20 June 2025, a minute before midnight
+        let scheduler = scheduler(local(20, 23, 59, 0), SchedulerOptions::default());
+        scheduler.add("weekdays", Days::WEEKDAYS, NaiveTime::MIN, counting);
+        scheduler.add("never", Days::NONE, NaiveTime::MIN, counting);
+
+        let entries = scheduler.list();
+        // Saturday and Sunday are skipped
+        assert_eq!(entries[0].next_run, Some(local(23, 0, 0, 0)));
+        assert_eq!(entries[1].next_run, None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_failing_actions() {
+        let options = SchedulerOptions {
+            retries: 3,
+            retry_delay: Duration::from_secs(10),
+            location: None,
+        };
+        let scheduler = scheduler(local(16, 8, 29, 59), options);
+        let time = NaiveTime::from_hms_opt(8, 30, 0).unwrap();
+        // Fails twice, as while the device reconnects, then succeeds
+        scheduler.add("flaky", Days::ALL, time, |runs: Arc<Runs>| {
+            let attempt = runs.0.fetch_add(1, Ordering::SeqCst);
+            async move {
+                if attempt < 2 {
+                    Err(Error::NoCompatibleDevice)
+                } else {
+                    Ok(())
+                }
+            }
+        });
+
+        let cancel = CancelHandle::new();
+        let task = tokio::spawn({
+            let scheduler = scheduler.clone();
+            let cancel = cancel.clone();
+            async move { scheduler.run(cancel).await }
+        });
+        let runs = scheduler.inner.device.clone();
+
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert_eq!(runs.count(), 1);
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        assert_eq!(runs.count(), 3);
+
+        cancel.cancel();
+        task.await.unwrap();
+    }
+}
@@ -0,0 +1,98 @@
+/*!
+ # Effect shuffle
+
+ Cycles the strip through firmware effects in random order, for ambient
+ lighting that keeps changing.
+*/
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::time::Duration;
+use tracing::{debug, info, instrument};
+
+use crate::{BleLedDevice, CancelHandle, Effect, Error, Result};
+
+impl BleLedDevice {
+    /// Runs effects from `effects` in shuffled order until `cancel` is triggered
+    ///
+    /// The list is reshuffled every time it has been played through, and the
+    /// same effect never runs twice in a row (unless it is the only one).
+    ///
+    /// # Arguments
+    ///
+    /// * `effects` - Effects to pick from, e.g. [`Effect::all_crossfades`]
+    /// * `dwell` - How long each effect runs
+    /// * `speed` - Effect speed (0-100) set for every effect
+    /// * `cancel` - Handle that stops the shuffle
+    #[instrument(skip(self, effects, cancel))]
+    pub async fn run_effect_shuffle(
+        &self,
+        effects: Vec<Effect>,
+        dwell: Duration,
+        speed: u8,
+        cancel: CancelHandle,
+    ) -> Result<()> {
+        if effects.is_empty() {
+            return Err(Error::General(
+                "Effect shuffle needs at least one effect".to_string(),
+            ));
+        }
+
+        info!("Shuffling {} effects every {:?}", effects.len(), dwell);
+
+        // thread_rng isn't Send, and the rng lives across awaits
+        let mut rng = StdRng::from_entropy();
+        let mut order = effects;
+        let mut last: Option<Effect> = None;
+
+        while !cancel.is_cancelled() {
+            order.shuffle(&mut rng);
+
+            // Don't repeat the previous round's last effect back-to-back
+            if order.len() > 1 && last == Some(order[0]) {
+                let swap_with = rng.gen_range(1..order.len());
+                order.swap(0, swap_with);
+            }
+
+            for effect in order.iter().copied() {
+                if cancel.is_cancelled() {
+                    break;
+                }
+
+                debug!("Shuffle switching to {}", effect);
+                self.set_effect(effect).await?;
+                self.set_effect_speed(speed).await?;
+                last = Some(effect);
+
+                tokio::select! {
+                    _ = tokio::time::sleep(dwell) => {}
+                    _ = cancel.cancelled() => break,
+                }
+            }
+        }
+
+        info!("Effect shuffle cancelled");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The shuffle must be spawnable on a multi-threaded runtime; this only
+    /// has to compile
+    #[test]
+    fn shuffle_future_is_send() {
+        fn spawn_shuffle(device: &'static BleLedDevice) {
+            tokio::spawn(device.run_effect_shuffle(
+                Vec::new(),
+                Duration::ZERO,
+                50,
+                CancelHandle::new(),
+            ));
+        }
+        let _ = spawn_shuffle;
+    }
+}
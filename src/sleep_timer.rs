@@ -0,0 +1,129 @@
+/*!
+ # Sleep timer
+
+ Turns the strip off after a delay. The firmware has no countdown command,
+ so the timer is a one-off entry in the device's off schedule, which keeps
+ working when the host disconnects. The schedule repeats weekly, so it has
+ to be cleared once it has fired, see [`BleLedDevice::cancel_sleep_timer`].
+*/
+
+use chrono::{DateTime, Datelike, Local, Timelike};
+use std::time::Duration;
+use tracing::{info, instrument};
+
+use crate::{BleLedDevice, Days, Error, Result};
+
+/// Longest supported delay in minutes; the device schedule repeats weekly
+const MAX_SLEEP_MINUTES: u32 = 7 * 24 * 60 - 1;
+
+/// A pending sleep timer, as returned by [`BleLedDevice::sleep_timer`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SleepTimer {
+    /// When the device turns off
+    off_at: DateTime<Local>,
+}
+
+impl SleepTimer {
+    /// When the device turns off (whole minutes, the schedule's resolution)
+    pub fn off_at(&self) -> DateTime<Local> {
+        self.off_at
+    }
+
+    /// The day the schedule entry is programmed for
+    fn day(&self) -> Days {
+        Days::from_weekday(self.off_at.weekday())
+    }
+}
+
+impl BleLedDevice {
+    /// Turns the device off after `duration`
+    ///
+    /// The off time is rounded up to the next whole minute and programmed as
+    /// the device's off schedule for that day, replacing any off schedule set
+    /// before. Clear it with [`BleLedDevice::cancel_sleep_timer`] once it has
+    /// fired, or it turns the device off again a week later;
+    /// `LocalScheduler::sleep_timer` does that automatically.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ValueOutOfRange` for durations of a week or longer.
+    #[instrument(skip(self))]
+    pub async fn sleep_timer(&self, duration: Duration) -> Result<SleepTimer> {
+        let minutes = duration.as_secs().div_ceil(60);
+        if minutes > MAX_SLEEP_MINUTES as u64 {
+            return Err(Error::ValueOutOfRange(
+                minutes.min(u32::MAX as u64) as u32,
+                0,
+                MAX_SLEEP_MINUTES,
+            ));
+        }
+
+        let off_at = round_up_to_minute(Local::now() + chrono::Duration::minutes(minutes as i64));
+        let timer = SleepTimer { off_at };
+        self.set_schedule_off(
+            timer.day(),
+            off_at.hour() as u8,
+            off_at.minute() as u8,
+            true,
+        )
+        .await?;
+
+        *self.sleep_timer.lock() = Some(timer);
+        info!("Sleep timer set, turning off at {}", off_at.format("%H:%M"));
+        Ok(timer)
+    }
+
+    /// Cancels the sleep timer, returning whether one was pending
+    ///
+    /// Also call this after the timer fired, to remove the one-off entry
+    /// from the device's weekly schedule.
+    #[instrument(skip(self))]
+    pub async fn cancel_sleep_timer(&self) -> Result<bool> {
+        let Some(timer) = self.sleep_timer.lock().take() else {
+            return Ok(false);
+        };
+
+        let off_at = timer.off_at;
+        if let Err(e) = self
+            .set_schedule_off(
+                timer.day(),
+                off_at.hour() as u8,
+                off_at.minute() as u8,
+                false,
+            )
+            .await
+        {
+            // Keep the timer so cancelling can be retried
+            *self.sleep_timer.lock() = Some(timer);
+            return Err(e);
+        }
+
+        info!("Sleep timer cancelled");
+        Ok(true)
+    }
+
+    /// The pending sleep timer, if any
+    pub fn pending_sleep_timer(&self) -> Option<SleepTimer> {
+        *self.sleep_timer.lock()
+    }
+
+    /// Clears `timer` from the device if it is still the pending one
+    pub(crate) async fn clear_sleep_timer(&self, timer: SleepTimer) -> Result<()> {
+        if self.pending_sleep_timer() == Some(timer) {
+            self.cancel_sleep_timer().await?;
+        }
+        Ok(())
+    }
+}
+
+/// The next whole minute at or after `time`
+fn round_up_to_minute(time: DateTime<Local>) -> DateTime<Local> {
+    let truncated = time
+        - chrono::Duration::nanoseconds(time.nanosecond() as i64)
+        - chrono::Duration::seconds(time.second() as i64);
+    if truncated == time {
+        time
+    } else {
+        truncated + chrono::Duration::minutes(1)
+    }
+}
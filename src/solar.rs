@@ -0,0 +1,132 @@
+/*!
+ # Sunrise and sunset times
+
+ Solar calculations for scheduling relative to sunrise and sunset, using the
+ NOAA approximation. Everything is computed locally; no network access is
+ needed. Results are accurate to within a few minutes between the polar
+ circles.
+*/
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use std::f64::consts::PI;
+
+/// Zenith angle of the sun's center at sunrise and sunset, in degrees
+///
+/// 90° plus refraction and the sun's apparent radius.
+const SUNRISE_ZENITH: f64 = 90.833;
+
+/// Geographic position used for solar calculations
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Location {
+    /// Latitude in degrees, positive north
+    pub latitude: f64,
+    /// Longitude in degrees, positive east
+    pub longitude: f64,
+}
+
+impl Location {
+    /// Creates a location from latitude and longitude in degrees
+    pub const fn new(latitude: f64, longitude: f64) -> Self {
+        Self {
+            latitude,
+            longitude,
+        }
+    }
+
+    /// Sunrise and sunset at this location on `date`, see [`sunrise_sunset`]
+    pub fn sunrise_sunset(&self, date: NaiveDate) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        sunrise_sunset(self.latitude, self.longitude, date)
+    }
+}
+
+/// Sunrise and sunset in UTC at the given position on `date`
+///
+/// Returns `None` during polar day or polar night, when the sun doesn't
+/// rise or set on that date.
+///
+/// # Arguments
+///
+/// * `lat` - Latitude in degrees, positive north
+/// * `lon` - Longitude in degrees, positive east
+/// * `date` - Calendar date (UTC)
+pub fn sunrise_sunset(
+    lat: f64,
+    lon: f64,
+    date: NaiveDate,
+) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let days_in_year = if date.leap_year() { 366.0 } else { 365.0 };
+    // Fractional year in radians, evaluated at noon
+    let gamma = 2.0 * PI / days_in_year * (date.ordinal0() as f64);
+
+    // Equation of time in minutes
+    let eqtime = 229.18
+        * (0.000075 + 0.001868 * gamma.cos()
+            - 0.032077 * gamma.sin()
+            - 0.014615 * (2.0 * gamma).cos()
+            - 0.040849 * (2.0 * gamma).sin());
+
+    // Solar declination in radians
+    let decl = 0.006918 - 0.399912 * gamma.cos() + 0.070257 * gamma.sin()
+        - 0.006758 * (2.0 * gamma).cos()
+        + 0.000907 * (2.0 * gamma).sin()
+        - 0.002697 * (3.0 * gamma).cos()
+        + 0.00148 * (3.0 * gamma).sin();
+
+    let lat_rad = lat.to_radians();
+    let cos_hour_angle = SUNRISE_ZENITH.to_radians().cos() / (lat_rad.cos() * decl.cos())
+        - lat_rad.tan() * decl.tan();
+    if !(-1.0..=1.0).contains(&cos_hour_angle) {
+        return None;
+    }
+    let hour_angle = cos_hour_angle.acos().to_degrees();
+
+    // Minutes after UTC midnight; 4 minutes per degree of longitude
+    let sunrise = 720.0 - 4.0 * (lon + hour_angle) - eqtime;
+    let sunset = 720.0 - 4.0 * (lon - hour_angle) - eqtime;
+
+    let midnight = date.and_hms_opt(0, 0, 0)?.and_utc();
+    let at = |minutes: f64| midnight + Duration::seconds((minutes * 60.0).round() as i64);
+    Some((at(sunrise), at(sunset)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    /// Asserts `actual` is within two minutes of `hour:minute` UTC on `date`
+    fn assert_near(actual: DateTime<Utc>, date: NaiveDate, hour: u32, minute: u32) {
+        let expected = Utc.from_utc_datetime(&date.and_hms_opt(hour, minute, 0).unwrap());
+        let error = (actual - expected).num_seconds().abs();
+        assert!(error <= 120, "{} is not near {}", actual, expected);
+    }
+
+    #[test]
+    fn london_midsummer() {
+        // NOAA: sunrise 04:43 BST, sunset 21:21 BST
+        let date = NaiveDate::from_ymd_opt(2024, 6, 21).unwrap();
+        let (sunrise, sunset) = sunrise_sunset(51.5074, -0.1278, date).unwrap();
+        assert_near(sunrise, date, 3, 43);
+        assert_near(sunset, date, 20, 21);
+    }
+
+    #[test]
+    fn new_york_midwinter() {
+        // NOAA: sunrise 07:16 EST, sunset 16:32 EST
+        let date = NaiveDate::from_ymd_opt(2024, 12, 21).unwrap();
+        let (sunrise, sunset) = Location::new(40.7128, -74.0060)
+            .sunrise_sunset(date)
+            .unwrap();
+        assert_near(sunrise, date, 12, 16);
+        assert_near(sunset, date, 21, 32);
+    }
+
+    #[test]
+    fn no_sunrise_in_polar_night_or_day() {
+        // Tromsø
+        let winter = NaiveDate::from_ymd_opt(2024, 12, 21).unwrap();
+        let summer = NaiveDate::from_ymd_opt(2024, 6, 21).unwrap();
+        assert_eq!(sunrise_sunset(69.6492, 18.9553, winter), None);
+        assert_eq!(sunrise_sunset(69.6492, 18.9553, summer), None);
+    }
+}